@@ -23,11 +23,22 @@ const BINARY: &str = env!("CARGO_BIN_NAME");
 fn load_plugins_metadata() -> Vec<PluginMetadata> {
     // plugins that work on every target
     let mut plugins = static_plugins![
+        plugin_aggregation::AggregationPlugin,
+        plugin_consumer_enrichment::ConsumerEnrichmentPlugin,
         plugin_csv::CsvPlugin,
         plugin_influxdb::InfluxDbPlugin,
         plugin_mongodb::MongoDbPlugin,
+        plugin_normalize_units::NormalizeUnitsPlugin,
+        plugin_otlp::OtlpPlugin,
+        plugin_parquet::ParquetPlugin,
+        plugin_prometheus::PrometheusPlugin,
+        plugin_rate::RatePlugin,
+        plugin_relabel::RelabelPlugin,
         plugin_relay::client::RelayClientPlugin,
         plugin_relay::server::RelayServerPlugin,
+        plugin_resample::ResamplePlugin,
+        plugin_self_monitoring::SelfMonitoringPlugin,
+        plugin_sqlite::SqlitePlugin,
     ];
 
     // plugins that only work on Linux
@@ -42,6 +53,7 @@ fn load_plugins_metadata() -> Vec<PluginMetadata> {
             plugin_perf::PerfPlugin,
             plugin_procfs::ProcfsPlugin,
             plugin_nvidia::NvidiaPlugin,
+            plugin_hwmon::HwmonPlugin,
         ]);
     }
 
@@ -122,7 +134,7 @@ fn main() -> anyhow::Result<()> {
 
     // begin the creation of the pipeline (we have some settings to apply to it)
     let mut pipeline = pipeline::Builder::new();
-    apply_pipeline_settings(&args, &config, &mut pipeline);
+    apply_pipeline_settings(&args, &config, &mut pipeline).context("invalid pipeline settings")?;
 
     // start Alumet with the pipeline and plugins
     let agent = agent::Builder::from_pipeline(plugins, pipeline)
@@ -231,7 +243,11 @@ fn run_command_no_measurement(args: &cli::Cli, _config: &GeneralConfig, plugins:
 }
 
 /// Setup the measurement pipeline according to CLI args and config file.
-fn apply_pipeline_settings(args: &cli::Cli, config: &GeneralConfig, pipeline: &mut pipeline::Builder) {
+fn apply_pipeline_settings(
+    args: &cli::Cli,
+    config: &GeneralConfig,
+    pipeline: &mut pipeline::Builder,
+) -> anyhow::Result<()> {
     // config file
     if let Some(max_update_interval) = config.max_update_interval {
         pipeline.trigger_constraints_mut().max_update_interval = max_update_interval.into_inner();
@@ -239,6 +255,16 @@ fn apply_pipeline_settings(args: &cli::Cli, config: &GeneralConfig, pipeline: &m
     if let Some(source_channel_size) = config.source_channel_size {
         *pipeline.source_channel_size() = source_channel_size;
     }
+    for route in &config.routes {
+        pipeline.add_output_route(route.to_output_route()?);
+    }
+    if let Some(output_retry) = &config.output_retry {
+        *pipeline.output_retry_policy_mut() = output_retry.to_retry_policy();
+    }
+    for overflow in &config.output_overflow {
+        let (outputs, policy) = overflow.to_overflow_policy()?;
+        pipeline.set_output_overflow_policy(outputs, policy);
+    }
 
     // cli arguments
     if let Some(max_update_interval) = args.common.max_update_interval {
@@ -251,6 +277,7 @@ fn apply_pipeline_settings(args: &cli::Cli, config: &GeneralConfig, pipeline: &m
         // the "exec" command requires event-based source trigger
         pipeline.trigger_constraints_mut().allow_manual_trigger = true;
     }
+    Ok(())
 }
 
 /// Parses the config overrides provided on the command line, and merges them into a single table.
@@ -484,8 +511,14 @@ mod cli {
 /// and to write the default configuration to the TOML config file,
 /// therefore the structs derive [`serde::Deserialize`] and [`serde::Serialize`].
 mod config {
-    use std::time::Duration;
+    use std::{path::PathBuf, str::FromStr, time::Duration};
 
+    use alumet::pipeline::{
+        elements::output::{OverflowPolicy, RetryPolicy, SpillConfig},
+        matching::{NamePattern, OutputSelector},
+        routing::{MeasurementFilter, OutputRoute},
+    };
+    use anyhow::Context;
     use serde::{Deserialize, Serialize};
 
     /// General config options, which are not specific to a particular plugin.
@@ -493,5 +526,120 @@ mod config {
     pub struct GeneralConfig {
         pub max_update_interval: Option<humantime_serde::Serde<Duration>>,
         pub source_channel_size: Option<usize>,
+        /// Restrictions on the measurements that the outputs receive.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub routes: Vec<RouteConfig>,
+        /// How the outputs handle non-fatal errors.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub output_retry: Option<OutputRetryConfig>,
+        /// What the outputs do when they cannot keep up with the measurements.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub output_overflow: Vec<OutputOverflowConfig>,
+    }
+
+    /// Overflow policy of some outputs.
+    #[derive(Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct OutputOverflowConfig {
+        /// The outputs, for instance `influxdb` or `csv/out/*`.
+        pub outputs: String,
+        pub policy: OverflowPolicyConfig,
+    }
+
+    #[derive(Deserialize, Serialize, Clone, Copy)]
+    #[serde(rename_all = "snake_case")]
+    pub enum OverflowPolicyConfig {
+        Block,
+        DropOldest,
+        DropNewest,
+        Spill,
+    }
+
+    impl OutputOverflowConfig {
+        pub fn to_overflow_policy(&self) -> anyhow::Result<(OutputSelector, OverflowPolicy)> {
+            let outputs = OutputSelector::from_str(&self.outputs)
+                .with_context(|| format!("invalid output selector in overflow policy: {}", self.outputs))?;
+            let policy = match self.policy {
+                OverflowPolicyConfig::Block => OverflowPolicy::Block,
+                OverflowPolicyConfig::DropOldest => OverflowPolicy::DropOldest,
+                OverflowPolicyConfig::DropNewest => OverflowPolicy::DropNewest,
+                OverflowPolicyConfig::Spill => OverflowPolicy::Spill,
+            };
+            Ok((outputs, policy))
+        }
+    }
+
+    /// Retries of the outputs, and storage of the measurements that they fail to write.
+    #[derive(Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct OutputRetryConfig {
+        /// Maximum number of attempts to write a buffer of measurements.
+        pub max_attempts: u32,
+        #[serde(with = "humantime_serde")]
+        pub initial_backoff: Duration,
+        #[serde(with = "humantime_serde")]
+        pub max_backoff: Duration,
+        /// Directory where the measurements that cannot be written are stored until the output recovers.
+        /// If it is not set, these measurements are dropped.
+        ///
+        /// The spilled measurements are not replayed after a restart of the agent: when it starts,
+        /// they are moved to a `stale-<timestamp>` subdirectory.
+        pub spill_directory: Option<PathBuf>,
+        /// Maximum size of the spill queue of each output, in bytes.
+        #[serde(default = "default_spill_max_bytes")]
+        pub spill_max_bytes: u64,
+    }
+
+    fn default_spill_max_bytes() -> u64 {
+        64 * 1024 * 1024
+    }
+
+    impl OutputRetryConfig {
+        pub fn to_retry_policy(&self) -> RetryPolicy {
+            RetryPolicy {
+                max_attempts: self.max_attempts,
+                initial_backoff: self.initial_backoff,
+                max_backoff: self.max_backoff,
+                spill: self.spill_directory.as_ref().map(|directory| SpillConfig {
+                    directory: directory.clone(),
+                    max_bytes: self.spill_max_bytes,
+                }),
+            }
+        }
+    }
+
+    /// Restricts the measurements that some outputs receive.
+    #[derive(Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct RouteConfig {
+        /// The outputs, for instance `influxdb` or `csv/out/*`.
+        pub outputs: String,
+        /// Accepted metrics, for instance `rapl_*`.
+        #[serde(default)]
+        pub metrics: Vec<String>,
+        #[serde(default)]
+        pub resource_kinds: Vec<String>,
+        #[serde(default)]
+        pub consumer_kinds: Vec<String>,
+    }
+
+    impl RouteConfig {
+        pub fn to_output_route(&self) -> anyhow::Result<OutputRoute> {
+            let outputs = OutputSelector::from_str(&self.outputs)
+                .with_context(|| format!("invalid output selector in route: {}", self.outputs))?;
+            let metrics = self
+                .metrics
+                .iter()
+                .map(|m| NamePattern::from_str(m).with_context(|| format!("invalid metric pattern in route: {m}")))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Ok(OutputRoute {
+                outputs,
+                filter: MeasurementFilter {
+                    metrics,
+                    resource_kinds: self.resource_kinds.clone(),
+                    consumer_kinds: self.consumer_kinds.clone(),
+                },
+            })
+        }
     }
 }
@@ -0,0 +1,119 @@
+//! Compares the representations of the attributes of the measurement points:
+//! - `String`: every point owns a copy of each string
+//! - `Shared`: the strings are allocated once and shared between the points
+//! - `Interned`: like `Shared`, but the strings are looked up in an `AttributeInterner`
+
+use std::{sync::Arc, time::UNIX_EPOCH};
+
+use alumet::{
+    measurement::{
+        interning::AttributeInterner, AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp,
+        WrappedMeasurementValue,
+    },
+    metrics::RawMetricId,
+    resources::{Resource, ResourceConsumer},
+};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+const N_POINTS: usize = 1000;
+
+/// Attributes of a Kubernetes pod, as in the k8s probe of the cgroupv2 plugin.
+const POD: [(&str, &str); 4] = [
+    ("uid", "6d5f1c8e-7a2b-4c3d-9e8f-0a1b2c3d4e5f"),
+    ("name", "my-application-deployment-7c9f8d6b5-x2k4p"),
+    ("namespace", "production-namespace"),
+    ("node", "worker-node-042.cluster.example.com"),
+];
+
+fn point(attributes: Vec<(&'static str, AttributeValue)>) -> MeasurementPoint {
+    MeasurementPoint::new_untyped(
+        Timestamp::from(UNIX_EPOCH),
+        RawMetricId::from_u64(0),
+        Resource::LocalMachine,
+        ResourceConsumer::LocalMachine,
+        WrappedMeasurementValue::U64(42),
+    )
+    .with_attr_vec(attributes)
+}
+
+fn string_attributes() -> Vec<(&'static str, AttributeValue)> {
+    POD.iter()
+        .map(|(k, v)| (*k, AttributeValue::String(v.to_string())))
+        .collect()
+}
+
+fn shared_attributes() -> Vec<(&'static str, AttributeValue)> {
+    POD.iter()
+        .map(|(k, v)| (*k, AttributeValue::Shared(Arc::from(*v))))
+        .collect()
+}
+
+fn buffer(attributes: &[(&'static str, AttributeValue)]) -> MeasurementBuffer {
+    let mut buf = MeasurementBuffer::with_capacity(N_POINTS);
+    for _ in 0..N_POINTS {
+        buf.push(point(attributes.to_vec()));
+    }
+    buf
+}
+
+fn create_points(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_points");
+
+    group.bench_function("String", |b| {
+        b.iter(|| {
+            let mut buf = MeasurementBuffer::with_capacity(N_POINTS);
+            for _ in 0..N_POINTS {
+                buf.push(point(string_attributes()));
+            }
+            black_box(buf)
+        })
+    });
+
+    let shared = shared_attributes();
+    group.bench_function("Shared", |b| b.iter(|| black_box(buffer(&shared))));
+
+    let interner = AttributeInterner::new();
+    group.bench_function("Interned", |b| {
+        b.iter(|| {
+            let mut buf = MeasurementBuffer::with_capacity(N_POINTS);
+            for _ in 0..N_POINTS {
+                let attributes = POD.iter().map(|(k, v)| (*k, interner.value(v))).collect();
+                buf.push(point(attributes));
+            }
+            black_box(buf)
+        })
+    });
+
+    group.finish();
+}
+
+fn clone_buffer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clone_buffer");
+
+    let buf = buffer(&string_attributes());
+    group.bench_function("String", |b| b.iter(|| black_box(buf.clone())));
+
+    let buf = buffer(&shared_attributes());
+    group.bench_function("Shared", |b| b.iter(|| black_box(buf.clone())));
+
+    group.finish();
+}
+
+fn drop_buffer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("drop_buffer");
+
+    let buf = buffer(&string_attributes());
+    group.bench_function("String", |b| {
+        b.iter_batched(|| buf.clone(), drop, BatchSize::SmallInput)
+    });
+
+    let buf = buffer(&shared_attributes());
+    group.bench_function("Shared", |b| {
+        b.iter_batched(|| buf.clone(), drop, BatchSize::SmallInput)
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, create_points, clone_buffer, drop_buffer);
+criterion_main!(benches);
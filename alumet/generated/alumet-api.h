@@ -13,6 +13,9 @@
 typedef enum WrappedMeasurementType {
   WrappedMeasurementType_F64,
   WrappedMeasurementType_U64,
+  WrappedMeasurementType_I64,
+  WrappedMeasurementType_Bool,
+  WrappedMeasurementType_Histogram,
 } WrappedMeasurementType;
 
 /**
@@ -133,9 +136,24 @@ typedef struct FfiConsumerId {
   uint8_t bytes[56];
 } FfiConsumerId;
 
+/**
+ * A view of a [`Histogram`], borrowed from a measurement point.
+ *
+ * `bounds` points to `n_bounds` values and `counts` to `n_bounds + 1` values.
+ */
+typedef struct FfiHistogram {
+  uintptr_t n_bounds;
+  const double *bounds;
+  const uint64_t *counts;
+  double sum;
+} FfiHistogram;
+
 typedef enum FfiMeasurementValue_Tag {
   FfiMeasurementValue_U64,
   FfiMeasurementValue_F64,
+  FfiMeasurementValue_I64,
+  FfiMeasurementValue_Bool,
+  FfiMeasurementValue_Histogram,
 } FfiMeasurementValue_Tag;
 
 typedef struct FfiMeasurementValue {
@@ -147,6 +165,15 @@ typedef struct FfiMeasurementValue {
     struct {
       double f64;
     };
+    struct {
+      int64_t i64;
+    };
+    struct {
+      bool bool_;
+    };
+    struct {
+      struct FfiHistogram histogram;
+    };
   };
 } FfiMeasurementValue;
 
@@ -290,6 +317,34 @@ struct MeasurementPoint *mpoint_new_f64(struct Timestamp timestamp,
                                         struct FfiConsumerId consumer,
                                         double value);
 
+struct MeasurementPoint *mpoint_new_i64(struct Timestamp timestamp,
+                                        struct RawMetricId metric,
+                                        struct FfiResourceId resource,
+                                        struct FfiConsumerId consumer,
+                                        int64_t value);
+
+struct MeasurementPoint *mpoint_new_bool(struct Timestamp timestamp,
+                                         struct RawMetricId metric,
+                                         struct FfiResourceId resource,
+                                         struct FfiConsumerId consumer,
+                                         bool value);
+
+/**
+ * Creates a point whose value is a histogram.
+ *
+ * `bounds` must point to `n_bounds` values and `counts` to `n_bounds + 1` values, see [`Histogram::from_parts`].
+ * The arrays are copied. Returns a null pointer if one of the arrays is null (even if `n_bounds` is zero),
+ * or if the histogram is invalid.
+ */
+struct MeasurementPoint *mpoint_new_histogram(struct Timestamp timestamp,
+                                              struct RawMetricId metric,
+                                              struct FfiResourceId resource,
+                                              struct FfiConsumerId consumer,
+                                              uintptr_t n_bounds,
+                                              const double *bounds,
+                                              const uint64_t *counts,
+                                              double sum);
+
 /**
  * Free a MeasurementPoint.
  * Do **not** call this function after pushing a point with [`mbuffer_push`] or [`maccumulator_push`].
@@ -1,10 +1,10 @@
-use std::{borrow::Cow, time::SystemTime};
+use std::time::SystemTime;
 
 use libc::c_void;
 
 use crate::{
     measurement::{
-        AttributeValue, MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue,
+        AttributeValue, Histogram, MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue,
     },
     metrics::RawMetricId,
     resources::{Resource, ResourceConsumer},
@@ -81,6 +81,78 @@ pub extern "C" fn mpoint_new_f64(
     )
 }
 
+#[no_mangle]
+pub extern "C" fn mpoint_new_i64(
+    timestamp: Timestamp,
+    metric: RawMetricId,
+    resource: FfiResourceId,
+    consumer: FfiConsumerId,
+    value: i64,
+) -> *mut MeasurementPoint {
+    mpoint_new(
+        timestamp,
+        metric,
+        resource,
+        consumer,
+        WrappedMeasurementValue::I64(value),
+    )
+}
+
+#[no_mangle]
+pub extern "C" fn mpoint_new_bool(
+    timestamp: Timestamp,
+    metric: RawMetricId,
+    resource: FfiResourceId,
+    consumer: FfiConsumerId,
+    value: bool,
+) -> *mut MeasurementPoint {
+    mpoint_new(
+        timestamp,
+        metric,
+        resource,
+        consumer,
+        WrappedMeasurementValue::Bool(value),
+    )
+}
+
+/// Creates a point whose value is a histogram.
+///
+/// `bounds` must point to `n_bounds` values and `counts` to `n_bounds + 1` values, see [`Histogram::from_parts`].
+/// The arrays are copied. Returns a null pointer if one of the arrays is null (even if `n_bounds` is zero),
+/// or if the histogram is invalid.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn mpoint_new_histogram(
+    timestamp: Timestamp,
+    metric: RawMetricId,
+    resource: FfiResourceId,
+    consumer: FfiConsumerId,
+    n_bounds: usize,
+    bounds: *const f64,
+    counts: *const u64,
+    sum: f64,
+) -> *mut MeasurementPoint {
+    if bounds.is_null() || counts.is_null() {
+        log::error!("mpoint_new_histogram: bounds and counts must not be null");
+        return std::ptr::null_mut();
+    }
+    let bounds = unsafe { std::slice::from_raw_parts(bounds, n_bounds) }.to_vec();
+    let counts = unsafe { std::slice::from_raw_parts(counts, n_bounds + 1) }.to_vec();
+    match Histogram::from_parts(bounds, counts, sum) {
+        Ok(h) => mpoint_new(
+            timestamp,
+            metric,
+            resource,
+            consumer,
+            WrappedMeasurementValue::Histogram(h),
+        ),
+        Err(e) => {
+            log::error!("mpoint_new_histogram: {e}");
+            std::ptr::null_mut()
+        }
+    }
+}
+
 /// Free a MeasurementPoint.
 /// Do **not** call this function after pushing a point with [`mbuffer_push`] or [`maccumulator_push`].
 #[no_mangle]
@@ -92,8 +164,7 @@ pub extern "C" fn mpoint_free(point: *mut MeasurementPoint) {
 /// Internal: C binding to [`MeasurementPoint::add_attr`].
 fn mpoint_attr(point: *mut MeasurementPoint, key: AStr, value: AttributeValue) {
     let point = unsafe { &mut *point }; // not Box::from_raw because we don't want to take ownership of the point
-    let key = Cow::Owned(key.to_string());
-    point.add_attr(key, value);
+    point.add_attr(key.to_string(), value);
 }
 
 /// Generates a C-compatible function around mpoint_attr, for a specific type of attribute.
@@ -163,12 +234,35 @@ pub extern "C" fn mpoint_consumer_id(point: &MeasurementPoint) -> AString {
 pub enum FfiMeasurementValue {
     U64(u64),
     F64(f64),
+    I64(i64),
+    Bool(bool),
+    Histogram(FfiHistogram),
 }
+
+/// A view of a [`Histogram`], borrowed from a measurement point.
+///
+/// `bounds` points to `n_bounds` values and `counts` to `n_bounds + 1` values.
+#[repr(C)]
+pub struct FfiHistogram {
+    pub n_bounds: usize,
+    pub bounds: *const f64,
+    pub counts: *const u64,
+    pub sum: f64,
+}
+
 impl From<&WrappedMeasurementValue> for FfiMeasurementValue {
     fn from(value: &WrappedMeasurementValue) -> Self {
         match value {
             WrappedMeasurementValue::F64(x) => FfiMeasurementValue::F64(*x),
             WrappedMeasurementValue::U64(x) => FfiMeasurementValue::U64(*x),
+            WrappedMeasurementValue::I64(x) => FfiMeasurementValue::I64(*x),
+            WrappedMeasurementValue::Bool(x) => FfiMeasurementValue::Bool(*x),
+            WrappedMeasurementValue::Histogram(h) => FfiMeasurementValue::Histogram(FfiHistogram {
+                n_bounds: h.bounds().len(),
+                bounds: h.bounds().as_ptr(),
+                counts: h.counts().as_ptr(),
+                sum: h.sum(),
+            }),
         }
     }
 }
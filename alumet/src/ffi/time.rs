@@ -33,7 +33,7 @@ impl From<Timestamp> for crate::measurement::Timestamp {
 
 impl From<crate::measurement::Timestamp> for Timestamp {
     fn from(value: crate::measurement::Timestamp) -> Self {
-        Timestamp::from(value.0)
+        Timestamp::from(SystemTime::from(value))
     }
 }
 
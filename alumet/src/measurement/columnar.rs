@@ -0,0 +1,662 @@
+//! Columnar representation of measurements.
+//!
+//! A [`MeasurementBuffer`] stores a list of [`MeasurementPoint`]s, and each point owns its resource,
+//! consumer and attributes. For sources that produce many points at a high frequency, for instance
+//! hundreds of counters every millisecond, copying these for each point is costly.
+//!
+//! A [`ColumnarBuffer`] stores the same data in columns: one column of timestamps, one of metrics,
+//! one of values, and one column of references to the resources, consumers and attributes,
+//! which are stored only once in dictionaries. The dictionaries are kept when the buffer is
+//! [cleared](ColumnarBuffer::clear) or [taken](ColumnarBuffer::take_points), so that a source
+//! can register its resources once and reuse the references at every poll.
+//!
+//! The metrics do not need a dictionary: a [`RawMetricId`] is already a reference to the
+//! metric registry.
+//!
+//! # In the pipeline
+//!
+//! A source can push a columnar buffer to its [`MeasurementAccumulator`](super::MeasurementAccumulator)
+//! with [`push_columnar`](super::MeasurementAccumulator::push_columnar). The points are not converted:
+//! the [`MeasurementBuffer`] carries the columnar batch through the pipeline, and the transforms and outputs
+//! that [accept columnar batches](crate::pipeline::Transform::accepts_columnar) can read and modify it
+//! with [`MeasurementBuffer::columnar`] and [`MeasurementBuffer::columnar_mut`].
+//!
+//! The batches are converted to rows, with [`MeasurementBuffer::materialize`], before the buffer is
+//! given to the first transform or output that does not accept them. Existing transforms and outputs
+//! therefore keep working without modification. In any case, [`MeasurementBuffer::len`] and
+//! [`MeasurementBuffer::iter`] cover the rows and the columnar batches.
+//!
+//! # Example
+//!
+//! ```
+//! use alumet::measurement::{columnar::{AttributesRef, ColumnarBuffer}, MeasurementBuffer, Timestamp, WrappedMeasurementValue};
+//! use alumet::metrics::RawMetricId;
+//! use alumet::resources::{Resource, ResourceConsumer};
+//! # let my_metric = RawMetricId::from_u64(0);
+//!
+//! let mut batch = ColumnarBuffer::new();
+//! // register the resources and consumers once
+//! let cpu0 = batch.resource(Resource::CpuCore { id: 0 });
+//! let machine = batch.consumer(ResourceConsumer::LocalMachine);
+//!
+//! // then push the values
+//! for value in [10, 20, 30] {
+//!     let value = WrappedMeasurementValue::U64(value);
+//!     batch.push_untyped(Timestamp::now(), my_metric, value, cpu0, machine, AttributesRef::EMPTY);
+//! }
+//!
+//! // iterate on the points without converting them
+//! let sum: u64 = batch.iter().filter_map(|p| p.value.as_f64()).map(|v| v as u64).sum();
+//! assert_eq!(sum, 60);
+//!
+//! // convert the batch to a regular buffer
+//! let buffer = MeasurementBuffer::from(batch);
+//! assert_eq!(buffer.len(), 3);
+//! ```
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use smallvec::SmallVec;
+
+use super::{
+    AttributeKey, AttributeValue, MeasurementBuffer, MeasurementPoint, MeasurementType, Timestamp,
+    WrappedMeasurementValue,
+};
+use crate::{
+    metrics::{RawMetricId, TypedMetricId},
+    resources::{Resource, ResourceConsumer},
+};
+
+/// Reference to a resource stored in the dictionary of a [`ColumnarBuffer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceRef(u32);
+
+/// Reference to a consumer stored in the dictionary of a [`ColumnarBuffer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConsumerRef(u32);
+
+/// Reference to a set of attributes stored in the dictionary of a [`ColumnarBuffer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AttributesRef(u32);
+
+impl AttributesRef {
+    /// The empty set of attributes, which exists in every buffer.
+    pub const EMPTY: AttributesRef = AttributesRef(0);
+}
+
+type AttributeSet = SmallVec<[(AttributeKey, AttributeValue); 4]>;
+
+/// A buffer of measurements stored in columns.
+///
+/// The references returned by [`resource`](Self::resource), [`consumer`](Self::consumer) and
+/// [`attributes`](Self::attributes) are only valid for the buffer that returned them, and for
+/// the buffers obtained from it with [`take_points`](Self::take_points) or [`Clone::clone`].
+#[derive(Clone)]
+pub struct ColumnarBuffer {
+    /// Shared with the buffers obtained with `take_points`, copied on write.
+    dicts: Arc<Dictionaries>,
+
+    // columns
+    timestamps: Vec<Timestamp>,
+    metrics: Vec<RawMetricId>,
+    values: Vec<WrappedMeasurementValue>,
+    resource_column: Vec<ResourceRef>,
+    consumer_column: Vec<ConsumerRef>,
+    attributes_column: Vec<AttributesRef>,
+}
+
+#[derive(Clone)]
+struct Dictionaries {
+    resources: Vec<Resource>,
+    resource_refs: HashMap<Resource, ResourceRef>,
+    consumers: Vec<ResourceConsumer>,
+    consumer_refs: HashMap<ResourceConsumer, ConsumerRef>,
+    attribute_sets: Vec<AttributeSet>,
+    /// References to the attribute sets, by hash of the set.
+    attribute_refs: HashMap<u64, SmallVec<[AttributesRef; 1]>>,
+}
+
+/// A measurement point of a [`ColumnarBuffer`], which borrows its data from the buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct ColumnarPoint<'a> {
+    pub timestamp: Timestamp,
+    pub metric: RawMetricId,
+    pub value: &'a WrappedMeasurementValue,
+    pub resource: &'a Resource,
+    pub consumer: &'a ResourceConsumer,
+    attributes: &'a [(AttributeKey, AttributeValue)],
+}
+
+impl ColumnarPoint<'_> {
+    /// Iterates on the attributes of the point.
+    pub fn attributes(&self) -> impl Iterator<Item = (&str, &AttributeValue)> {
+        self.attributes.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Copies the data into a new measurement point.
+    pub fn to_point(&self) -> MeasurementPoint {
+        let mut point = MeasurementPoint::new_untyped(
+            self.timestamp,
+            self.metric,
+            self.resource.clone(),
+            self.consumer.clone(),
+            self.value.clone(),
+        );
+        point.attributes = SmallVec::from(self.attributes);
+        point
+    }
+}
+
+impl ColumnarBuffer {
+    /// Constructs a new empty buffer.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Constructs a new buffer with enough capacity for `capacity` points.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            dicts: Arc::new(Dictionaries {
+                resources: Vec::new(),
+                resource_refs: HashMap::new(),
+                consumers: Vec::new(),
+                consumer_refs: HashMap::new(),
+                attribute_sets: vec![AttributeSet::new()],
+                attribute_refs: HashMap::new(),
+            }),
+            timestamps: Vec::with_capacity(capacity),
+            metrics: Vec::with_capacity(capacity),
+            values: Vec::with_capacity(capacity),
+            resource_column: Vec::with_capacity(capacity),
+            consumer_column: Vec::with_capacity(capacity),
+            attributes_column: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of measurement points in the buffer.
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    /// Returns true if this buffer contains no measurement point.
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    /// Removes all the measurement points, but keeps the dictionaries.
+    ///
+    /// The references obtained before remain valid.
+    pub fn clear(&mut self) {
+        self.timestamps.clear();
+        self.metrics.clear();
+        self.values.clear();
+        self.resource_column.clear();
+        self.consumer_column.clear();
+        self.attributes_column.clear();
+    }
+
+    /// Moves the measurement points to a new buffer, which shares the dictionaries of this buffer.
+    ///
+    /// Afterwards, this buffer is empty, but keeps its dictionaries: the references obtained before remain valid.
+    /// Unlike a clone, this does not copy the columns.
+    pub fn take_points(&mut self) -> ColumnarBuffer {
+        let capacity = self.len();
+        ColumnarBuffer {
+            dicts: self.dicts.clone(),
+            timestamps: std::mem::replace(&mut self.timestamps, Vec::with_capacity(capacity)),
+            metrics: std::mem::replace(&mut self.metrics, Vec::with_capacity(capacity)),
+            values: std::mem::replace(&mut self.values, Vec::with_capacity(capacity)),
+            resource_column: std::mem::replace(&mut self.resource_column, Vec::with_capacity(capacity)),
+            consumer_column: std::mem::replace(&mut self.consumer_column, Vec::with_capacity(capacity)),
+            attributes_column: std::mem::replace(&mut self.attributes_column, Vec::with_capacity(capacity)),
+        }
+    }
+
+    /// Returns a reference to the given resource, adding it to the dictionary if needed.
+    pub fn resource(&mut self, resource: Resource) -> ResourceRef {
+        if let Some(r) = self.dicts.resource_refs.get(&resource) {
+            return *r;
+        }
+        let dicts = Arc::make_mut(&mut self.dicts);
+        let r = ResourceRef(dict_index(dicts.resources.len()));
+        dicts.resources.push(resource.clone());
+        dicts.resource_refs.insert(resource, r);
+        r
+    }
+
+    /// Returns a reference to the given consumer, adding it to the dictionary if needed.
+    pub fn consumer(&mut self, consumer: ResourceConsumer) -> ConsumerRef {
+        if let Some(c) = self.dicts.consumer_refs.get(&consumer) {
+            return *c;
+        }
+        let dicts = Arc::make_mut(&mut self.dicts);
+        let c = ConsumerRef(dict_index(dicts.consumers.len()));
+        dicts.consumers.push(consumer.clone());
+        dicts.consumer_refs.insert(consumer, c);
+        c
+    }
+
+    /// Returns a reference to the given set of attributes, adding it to the dictionary if needed.
+    ///
+    /// Two sets are the same if they contain the same attributes in the same order.
+    pub fn attributes<K: Into<AttributeKey>>(&mut self, attributes: Vec<(K, AttributeValue)>) -> AttributesRef {
+        self.intern_attributes(attributes.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    fn intern_attributes(&mut self, attributes: AttributeSet) -> AttributesRef {
+        if attributes.is_empty() {
+            return AttributesRef::EMPTY;
+        }
+        let hash = hash_attributes(&attributes);
+        if let Some(candidates) = self.dicts.attribute_refs.get(&hash) {
+            let existing = candidates
+                .iter()
+                .find(|a| same_attributes(&self.dicts.attribute_sets[a.0 as usize], &attributes));
+            if let Some(a) = existing {
+                return *a;
+            }
+        }
+        let dicts = Arc::make_mut(&mut self.dicts);
+        let a = AttributesRef(dict_index(dicts.attribute_sets.len()));
+        dicts.attribute_sets.push(attributes);
+        dicts.attribute_refs.entry(hash).or_default().push(a);
+        a
+    }
+
+    /// Returns the resource that corresponds to the reference.
+    pub fn get_resource(&self, r: ResourceRef) -> &Resource {
+        &self.dicts.resources[r.0 as usize]
+    }
+
+    /// Returns the consumer that corresponds to the reference.
+    pub fn get_consumer(&self, c: ConsumerRef) -> &ResourceConsumer {
+        &self.dicts.consumers[c.0 as usize]
+    }
+
+    /// Returns the attributes that correspond to the reference.
+    pub fn get_attributes(&self, a: AttributesRef) -> impl Iterator<Item = (&str, &AttributeValue)> {
+        self.dicts.attribute_sets[a.0 as usize]
+            .iter()
+            .map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Adds a measurement to the buffer.
+    pub fn push<T: MeasurementType>(
+        &mut self,
+        timestamp: Timestamp,
+        metric: TypedMetricId<T>,
+        value: T::T,
+        resource: ResourceRef,
+        consumer: ConsumerRef,
+        attributes: AttributesRef,
+    ) {
+        self.push_untyped(
+            timestamp,
+            metric.0,
+            T::wrapped_value(value),
+            resource,
+            consumer,
+            attributes,
+        );
+    }
+
+    /// Adds a measurement to the buffer, without checking the type of the value.
+    pub fn push_untyped(
+        &mut self,
+        timestamp: Timestamp,
+        metric: RawMetricId,
+        value: WrappedMeasurementValue,
+        resource: ResourceRef,
+        consumer: ConsumerRef,
+        attributes: AttributesRef,
+    ) {
+        self.timestamps.push(timestamp);
+        self.metrics.push(metric);
+        self.values.push(value);
+        self.resource_column.push(resource);
+        self.consumer_column.push(consumer);
+        self.attributes_column.push(attributes);
+    }
+
+    /// Adds a measurement point to the buffer.
+    ///
+    /// The resource, consumer and attributes of the point are added to the dictionaries if needed.
+    pub fn push_point(&mut self, point: MeasurementPoint) {
+        let resource = self.resource(point.resource);
+        let consumer = self.consumer(point.consumer);
+        let attributes = self.intern_attributes(point.attributes);
+        self.push_untyped(
+            point.timestamp,
+            point.metric,
+            point.value,
+            resource,
+            consumer,
+            attributes,
+        );
+    }
+
+    /// Returns the column of timestamps.
+    pub fn timestamps(&self) -> &[Timestamp] {
+        &self.timestamps
+    }
+
+    /// Returns the column of metrics.
+    pub fn metrics(&self) -> &[RawMetricId] {
+        &self.metrics
+    }
+
+    /// Returns the column of values.
+    pub fn values(&self) -> &[WrappedMeasurementValue] {
+        &self.values
+    }
+
+    /// Returns the column of values, which can be modified.
+    pub fn values_mut(&mut self) -> &mut [WrappedMeasurementValue] {
+        &mut self.values
+    }
+
+    /// Returns the column of resource references.
+    pub fn resource_refs(&self) -> &[ResourceRef] {
+        &self.resource_column
+    }
+
+    /// Returns the column of consumer references.
+    pub fn consumer_refs(&self) -> &[ConsumerRef] {
+        &self.consumer_column
+    }
+
+    /// Returns the column of attribute references.
+    pub fn attributes_refs(&self) -> &[AttributesRef] {
+        &self.attributes_column
+    }
+
+    /// Returns the point at the given index, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<ColumnarPoint<'_>> {
+        (index < self.len()).then(|| ColumnarPoint {
+            timestamp: self.timestamps[index],
+            metric: self.metrics[index],
+            value: &self.values[index],
+            resource: &self.dicts.resources[self.resource_column[index].0 as usize],
+            consumer: &self.dicts.consumers[self.consumer_column[index].0 as usize],
+            attributes: &self.dicts.attribute_sets[self.attributes_column[index].0 as usize],
+        })
+    }
+
+    /// Iterates on the points of the buffer, without copying them.
+    pub fn iter(&self) -> impl Iterator<Item = ColumnarPoint<'_>> {
+        (0..self.len()).map(|i| self.get(i).unwrap())
+    }
+
+    /// Copies the points of the buffer into `buffer`.
+    pub fn copy_to_rows(&self, buffer: &mut MeasurementBuffer) {
+        buffer.reserve(self.len());
+        for point in self.iter() {
+            buffer.push(point.to_point());
+        }
+    }
+
+    /// Moves the points of the buffer into `rows`, without copying the values.
+    pub(crate) fn move_to_rows(self, rows: &mut Vec<MeasurementPoint>) {
+        rows.reserve(self.len());
+        let columns = self.timestamps.into_iter().zip(self.metrics).zip(self.values);
+        let refs = self
+            .resource_column
+            .into_iter()
+            .zip(self.consumer_column)
+            .zip(self.attributes_column);
+        for (((timestamp, metric), value), ((r, c), a)) in columns.zip(refs) {
+            let mut point = MeasurementPoint::new_untyped(
+                timestamp,
+                metric,
+                self.dicts.resources[r.0 as usize].clone(),
+                self.dicts.consumers[c.0 as usize].clone(),
+                value,
+            );
+            point.attributes = self.dicts.attribute_sets[a.0 as usize].clone();
+            rows.push(point);
+        }
+    }
+}
+
+impl Default for ColumnarBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for ColumnarBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ColumnarBuffer")
+            .field("len", &self.len())
+            .field("resources", &self.dicts.resources.len())
+            .field("consumers", &self.dicts.consumers.len())
+            .field("attribute_sets", &self.dicts.attribute_sets.len())
+            .finish()
+    }
+}
+
+impl From<MeasurementBuffer> for ColumnarBuffer {
+    fn from(value: MeasurementBuffer) -> Self {
+        let mut res = ColumnarBuffer::with_capacity(value.len());
+        for point in value {
+            res.push_point(point);
+        }
+        res
+    }
+}
+
+impl From<&ColumnarBuffer> for MeasurementBuffer {
+    fn from(value: &ColumnarBuffer) -> Self {
+        let mut res = MeasurementBuffer::with_capacity(value.len());
+        value.copy_to_rows(&mut res);
+        res
+    }
+}
+
+impl From<ColumnarBuffer> for MeasurementBuffer {
+    fn from(value: ColumnarBuffer) -> Self {
+        let mut points = Vec::new();
+        value.move_to_rows(&mut points);
+        MeasurementBuffer::from(points)
+    }
+}
+
+/// Converts the length of a dictionary to the index of its next element.
+fn dict_index(len: usize) -> u32 {
+    u32::try_from(len).expect("too many entries in the dictionary of the ColumnarBuffer")
+}
+
+/// Hashes a set of attributes, consistently with [`same_attributes`].
+fn hash_attributes(attributes: &[(AttributeKey, AttributeValue)]) -> u64 {
+    let mut hasher = fxhash::FxHasher64::default();
+    for (k, v) in attributes {
+        k.as_str().hash(&mut hasher);
+        match v {
+            AttributeValue::F64(x) => x.to_bits().hash(&mut hasher),
+            AttributeValue::U64(x) => x.hash(&mut hasher),
+            AttributeValue::Bool(x) => x.hash(&mut hasher),
+            s => s.as_str().hash(&mut hasher),
+        }
+    }
+    hasher.finish()
+}
+
+/// Checks whether two sets of attributes are equal, in the same order.
+fn same_attributes(a: &[(AttributeKey, AttributeValue)], b: &[(AttributeKey, AttributeValue)]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|((ka, va), (kb, vb))| {
+            ka == kb
+                && match (va, vb) {
+                    (AttributeValue::F64(x), AttributeValue::F64(y)) => x.to_bits() == y.to_bits(),
+                    (AttributeValue::U64(x), AttributeValue::U64(y)) => x == y,
+                    (AttributeValue::Bool(x), AttributeValue::Bool(y)) => x == y,
+                    (x, y) => matches!((x.as_str(), y.as_str()), (Some(x), Some(y)) if x == y),
+                }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{AttributesRef, ColumnarBuffer};
+    use crate::{
+        measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    fn rows() -> MeasurementBuffer {
+        let point = |t: u64, metric: u64, core: u32, value: u64| {
+            MeasurementPoint::new_untyped(
+                Timestamp::from(UNIX_EPOCH + Duration::from_millis(t)),
+                RawMetricId::from_u64(metric),
+                Resource::CpuCore { id: core },
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(value),
+            )
+        };
+        MeasurementBuffer::from(vec![
+            point(0, 0, 0, 1).with_attr("domain", "pp0"),
+            point(0, 0, 1, 2).with_attr("domain", "pp0"),
+            point(1, 1, 0, 3).with_attr("domain", AttributeValue::String(String::from("pp1"))),
+            point(1, 1, 1, 4),
+        ])
+    }
+
+    fn describe(points: impl Iterator<Item = MeasurementPoint>) -> Vec<String> {
+        points
+            .map(|p| {
+                let attrs: Vec<String> = p.attributes().map(|(k, v)| format!("{k}={v}")).collect();
+                format!(
+                    "{:?} {} {:?} {:?} {} {}",
+                    p.timestamp,
+                    p.metric.as_u64(),
+                    p.resource,
+                    p.consumer,
+                    p.value,
+                    attrs.join(",")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let columns = ColumnarBuffer::from(rows());
+        assert_eq!(columns.len(), 4);
+        assert_eq!(columns.resource_refs()[0], columns.resource_refs()[2]);
+        assert_eq!(columns.consumer_refs()[0], columns.consumer_refs()[3]);
+        assert_eq!(columns.attributes_refs()[0], columns.attributes_refs()[1]);
+        assert_eq!(columns.attributes_refs()[3], AttributesRef::EMPTY);
+        assert_eq!(
+            columns
+                .get(2)
+                .unwrap()
+                .attributes()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect::<Vec<_>>(),
+            vec!["domain=pp1"]
+        );
+        assert!(columns.get(4).is_none());
+
+        let back = MeasurementBuffer::from(columns);
+        assert_eq!(describe(back.into_iter()), describe(rows().into_iter()));
+    }
+
+    #[test]
+    fn reuse_dictionaries() {
+        let mut columns = ColumnarBuffer::new();
+        let core = columns.resource(Resource::CpuCore { id: 3 });
+        let consumer = columns.consumer(ResourceConsumer::Process { pid: 42 });
+        let attrs = columns.attributes(vec![("kind", AttributeValue::Str("test"))]);
+        assert_eq!(columns.resource(Resource::CpuCore { id: 3 }), core);
+
+        for round in 0..3 {
+            columns.clear();
+            for i in 0..10 {
+                columns.push_untyped(
+                    Timestamp::now(),
+                    RawMetricId::from_u64(round),
+                    WrappedMeasurementValue::U64(i),
+                    core,
+                    consumer,
+                    attrs,
+                );
+            }
+            assert_eq!(columns.len(), 10);
+        }
+        for v in columns.values_mut() {
+            *v = WrappedMeasurementValue::F64(v.as_f64().unwrap() / 2.0);
+        }
+
+        let rows = MeasurementBuffer::from(&columns);
+        assert_eq!(rows.len(), 10);
+        let p = rows.iter().last().unwrap();
+        assert_eq!(p.metric.as_u64(), 2);
+        assert_eq!(p.resource, Resource::CpuCore { id: 3 });
+        assert_eq!(p.consumer, ResourceConsumer::Process { pid: 42 });
+        assert_eq!(p.value.to_string(), "4.5");
+        assert_eq!(p.attributes_keys().collect::<Vec<_>>(), vec!["kind"]);
+        assert_eq!(columns.get_attributes(attrs).count(), 1);
+    }
+
+    #[test]
+    fn deduplicate_attributes() {
+        let mut columns = ColumnarBuffer::new();
+        let a = columns.attributes(vec![("domain", AttributeValue::Str("pp0"))]);
+        for _ in 0..3 {
+            columns.clear();
+            let same = columns.attributes(vec![("domain", AttributeValue::String(String::from("pp0")))]);
+            assert_eq!(same, a);
+            for point in rows() {
+                columns.push_point(point);
+            }
+        }
+        let b = columns.attributes(vec![("domain", AttributeValue::Str("pp1"))]);
+        assert_ne!(a, b);
+        assert_eq!(columns.attributes_refs()[0], a);
+        assert_eq!(columns.attributes_refs()[2], b);
+        // empty, pp0 and pp1
+        assert_eq!(columns.dicts.attribute_sets.len(), 3);
+    }
+
+    #[test]
+    fn batches_in_buffer() {
+        let mut columns = ColumnarBuffer::new();
+        let mut buffer = MeasurementBuffer::new();
+        for round in 0..2 {
+            for point in rows() {
+                columns.push_point(point);
+            }
+            let resources = columns.dicts.resources.len();
+            buffer.as_accumulator().push_columnar(&mut columns);
+            // the points are moved, the dictionaries are kept
+            assert!(columns.is_empty());
+            assert_eq!(columns.dicts.resources.len(), resources);
+            assert_eq!(buffer.columnar().len(), round + 1);
+        }
+        buffer.push(rows().into_iter().next().unwrap());
+        assert_eq!(buffer.len(), 9);
+
+        // iterating covers the rows and the batches, in this order, and keeps the batches
+        let expected: Vec<MeasurementPoint> = rows().into_iter().take(1).chain(rows()).chain(rows()).collect();
+        assert_eq!(buffer.iter().count(), 9);
+        assert_eq!(describe(buffer.iter().cloned()), describe(expected.clone().into_iter()));
+        assert_eq!(buffer.columnar().len(), 2);
+
+        // modifying the batches is visible in the next iteration
+        buffer.columnar_mut()[1].values_mut()[0] = WrappedMeasurementValue::U64(42);
+        let modified = buffer.iter().nth(5).unwrap();
+        assert!(matches!(modified.value, WrappedMeasurementValue::U64(42)));
+        buffer.columnar_mut()[1].values_mut()[0] = expected[5].value.clone();
+
+        // converting to rows keeps the order of the batches, after the existing rows
+        buffer.materialize();
+        assert!(buffer.columnar().is_empty());
+        assert_eq!(buffer.len(), 9);
+        assert_eq!(describe(buffer.into_iter()), describe(expected.into_iter()));
+    }
+}
@@ -0,0 +1,117 @@
+//! Interning of attribute strings.
+//!
+//! Some attributes are repeated in many measurement points: the name of a pod, of a process, of a job...
+//! Storing them as [`AttributeValue::String`] copies the string every time a point is created or cloned.
+//! An [`AttributeInterner`] stores each distinct string once, in a shared [`Arc<str>`],
+//! so that the points only hold a pointer to it.
+//!
+//! # Example
+//!
+//! ```
+//! use alumet::measurement::{AttributeValue, interning::AttributeInterner};
+//!
+//! let interner = AttributeInterner::new();
+//! let a = interner.value("my-pod");
+//! let b = interner.value(&String::from("my-pod"));
+//! // a and b share the same string
+//! match (a, b) {
+//!     (AttributeValue::Shared(a), AttributeValue::Shared(b)) => assert!(std::sync::Arc::ptr_eq(&a, &b)),
+//!     _ => unreachable!(),
+//! }
+//! ```
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use super::{AttributeKey, AttributeValue};
+
+/// A table of shared strings, for the keys and values of attributes.
+///
+/// The interner can be shared between multiple threads.
+/// The strings stay in the table until they are [purged](Self::purge).
+#[derive(Debug, Default)]
+pub struct AttributeInterner {
+    strings: Mutex<HashSet<Arc<str>>>,
+}
+
+impl AttributeInterner {
+    /// Creates an empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared string that is equal to `s`, and adds it to the table if it is not there yet.
+    pub fn intern(&self, s: &str) -> Arc<str> {
+        let mut strings = self.strings.lock().unwrap_or_else(PoisonError::into_inner);
+        match strings.get(s) {
+            Some(shared) => shared.clone(),
+            None => {
+                let shared: Arc<str> = Arc::from(s);
+                strings.insert(shared.clone());
+                shared
+            }
+        }
+    }
+
+    /// Returns an attribute key that shares the string `s`.
+    pub fn key(&self, s: &str) -> AttributeKey {
+        AttributeKey::from(self.intern(s))
+    }
+
+    /// Returns an attribute value that shares the string `s`.
+    pub fn value(&self, s: &str) -> AttributeValue {
+        AttributeValue::Shared(self.intern(s))
+    }
+
+    /// Returns the number of strings in the table.
+    pub fn len(&self) -> usize {
+        self.strings.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    /// Returns `true` if the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the strings that are not used anymore, i.e. that are only referenced by the table,
+    /// and returns how many strings have been removed.
+    ///
+    /// Call this regularly if the strings change over time, for instance if they are the names
+    /// of short-lived processes, to prevent the table from growing forever.
+    pub fn purge(&self) -> usize {
+        let mut strings = self.strings.lock().unwrap_or_else(PoisonError::into_inner);
+        let len_before = strings.len();
+        strings.retain(|s| Arc::strong_count(s) > 1);
+        len_before - strings.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::AttributeInterner;
+    use crate::measurement::AttributeValue;
+
+    #[test]
+    fn intern_and_purge() {
+        let interner = AttributeInterner::new();
+        let a = interner.intern("pod-a");
+        let a2 = interner.intern("pod-a");
+        assert!(Arc::ptr_eq(&a, &a2));
+
+        let b = interner.value("pod-b");
+        assert!(matches!(&b, AttributeValue::Shared(s) if s.as_ref() == "pod-b"));
+        let key = interner.key("namespace");
+        assert_eq!(key.as_str(), "namespace");
+        assert_eq!(interner.len(), 3);
+
+        // "pod-a" is still used, the others are not
+        drop((a2, b, key));
+        assert_eq!(interner.purge(), 2);
+        assert_eq!(interner.len(), 1);
+        assert!(Arc::ptr_eq(&a, &interner.intern("pod-a")));
+    }
+}
@@ -0,0 +1,1110 @@
+//! Measurement points and buffers.
+//!
+//! Each step of the Alumet pipeline reads, produces or modifies timeseries data points,
+//! each represented as a [`MeasurementPoint`].
+//! This is usually done through a [`MeasurementBuffer`] (for transforms and outputs)
+//! or a [`MeasurementAccumulator`] (for sources).
+//!
+//! # Producing measurements
+//!
+//! Assuming that you have a `buffer: &mut MeasurementBuffer` (or `MeasurementAccumulator`),
+//! you can produce new measurements like this:
+//! ```no_run
+//! use alumet::measurement::{MeasurementBuffer, MeasurementPoint};
+//! use alumet::resources::{Resource, ResourceConsumer};
+//!
+//! # let buffer = MeasurementBuffer::new();
+//! # let my_metric: alumet::metrics::TypedMetricId<u64> = todo!();
+//! # let timestamp = todo!();
+//! buffer.push(MeasurementPoint::new(
+//!     timestamp, // timestamp, provided by Alumet as a parameter of [Source::poll]
+//!     my_metric, // a TypedMetricId that you obtained from [AlumetPluginStart::create_metric]
+//!     Resource::CpuPackage { id: 0 }, // the resource that you are measuring
+//!     ResourceConsumer::LocalMachine, // the thing that consumes the resource (here the "local machine" means "no consumer, we monitor the entire cpu package")
+//!     1234, // the measurement value
+//! ));
+//! ```
+//!
+//! # Attributes
+//!
+//! Measurement points can carry attributes, which are key-value pairs.
+//! Keys and string values that are repeated across many points, such as the name of a pod,
+//! should be shared instead of copied: use static strings when possible,
+//! or [`AttributeValue::Shared`] and an [`AttributeInterner`](interning::AttributeInterner) otherwise.
+//!
+//! # Columnar buffers
+//!
+//! Sources that produce many points at a high frequency can store them in a
+//! [`ColumnarBuffer`](columnar::ColumnarBuffer), which stores the resources, consumers and attributes only once,
+//! and then push the whole buffer to the [`MeasurementAccumulator`].
+
+use core::fmt;
+use fxhash::FxBuildHasher;
+use smallvec::SmallVec;
+use std::borrow::{Borrow, Cow};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, UNIX_EPOCH};
+use std::{collections::HashMap, fmt::Display, time::SystemTime};
+
+use crate::resources::ResourceConsumer;
+
+use super::metrics::{RawMetricId, TypedMetricId};
+use super::resources::Resource;
+
+pub mod columnar;
+pub mod interning;
+
+/// A value that has been measured at a given point in time.
+///
+/// Measurement points may also have attributes.
+/// Only certain types of values and attributes are allowed, see [`MeasurementType`] and [`AttributeValue`].
+#[derive(Clone)]
+pub struct MeasurementPoint {
+    /// The metric that has been measured.
+    pub metric: RawMetricId,
+
+    /// The time of the measurement.
+    pub timestamp: Timestamp,
+
+    /// The measured value.
+    pub value: WrappedMeasurementValue,
+
+    /// The resource this measurement is about: CPU socket, GPU, process, ...
+    ///
+    /// The `resource` and the `consumer` specify which object has been measured.
+    pub resource: Resource,
+
+    /// The consumer of the resource: process, container, ...
+    ///
+    /// This gives additional information about the perimeter of the measurement.
+    /// For instance, we can measure the total CPU usage of the node,
+    /// or the usage of the CPU by a particular process.
+    pub consumer: ResourceConsumer,
+
+    /// Additional attributes on the measurement point.
+    ///
+    /// Not public because we could change how they are stored later (in fact it has already changed multiple times).
+    /// Uses  [`SmallVec`] to avoid allocations if the number of attributes is small.
+    attributes: SmallVec<[(AttributeKey, AttributeValue); 4]>,
+}
+
+/// A measurement of a clock.
+///
+/// A timestamp always contains the wall-clock time, as a [`SystemTime`], which is what the outputs write.
+/// It can also contain the time of a [monotonic clock](MonotonicTime), captured at the same instant.
+/// Unlike the wall clock, the monotonic clock is not affected by the adjustments of the system time
+/// (for instance by NTP), hence it should be preferred to compute the time elapsed between two measurements,
+/// see [`Timestamp::elapsed_since`].
+///
+/// Two timestamps are equal if they have the same wall-clock time.
+#[derive(Clone, Copy)]
+pub struct Timestamp {
+    wall: SystemTime,
+    monotonic: Option<MonotonicTime>,
+}
+
+/// The clock of a [`MonotonicTime`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClockDomain {
+    /// `CLOCK_MONOTONIC`: does not count the time during which the system is suspended.
+    Monotonic,
+    /// `CLOCK_BOOTTIME`: like `Monotonic`, but includes the time during which the system is suspended.
+    Boottime,
+}
+
+/// A measurement of a monotonic clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MonotonicTime {
+    domain: ClockDomain,
+    /// Time since the (unspecified) origin of the clock.
+    since_origin: Duration,
+}
+
+impl MeasurementPoint {
+    /// Creates a new `MeasurementPoint` without attributes.
+    ///
+    /// Use [`with_attr`](Self::with_attr) or [`with_attr_vec`](Self::with_attr_vec)
+    /// to attach arbitrary attributes to the point.
+    pub fn new<T: MeasurementType>(
+        timestamp: Timestamp,
+        metric: TypedMetricId<T>,
+        resource: Resource,
+        consumer: ResourceConsumer,
+        value: T::T,
+    ) -> MeasurementPoint {
+        Self::new_untyped(timestamp, metric.0, resource, consumer, T::wrapped_value(value))
+    }
+
+    /// Creates a new `MeasurementPoint` without attributes, using an untyped metric.
+    /// Prefer to use [`MeasurementPoint::new`] with a typed metric instead.
+    ///
+    /// Use [`with_attr`](Self::with_attr) or [`with_attr_vec`](Self::with_attr_vec)
+    /// to attach arbitrary attributes to the point.
+    pub fn new_untyped(
+        timestamp: Timestamp,
+        metric: RawMetricId,
+        resource: Resource,
+        consumer: ResourceConsumer,
+        value: WrappedMeasurementValue,
+    ) -> MeasurementPoint {
+        MeasurementPoint {
+            metric,
+            timestamp,
+            value,
+            resource,
+            consumer,
+            attributes: SmallVec::new(),
+        }
+    }
+
+    /// Returns the number of attributes attached to this measurement point.
+    pub fn attributes_len(&self) -> usize {
+        self.attributes.len()
+    }
+
+    /// Iterates on the attributes attached to the measurement point.
+    pub fn attributes(&self) -> impl Iterator<Item = (&str, &AttributeValue)> {
+        self.attributes.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Iterates on the keys of the attributes that are attached to the point.
+    pub fn attributes_keys(&self) -> impl Iterator<Item = &str> {
+        self.attributes.iter().map(|(k, _v)| k.as_str())
+    }
+
+    /// Sets an attribute on this measurement point.
+    /// If an attribute with the same key already exists, its value is replaced.
+    pub fn add_attr<K: Into<AttributeKey>, V: Into<AttributeValue>>(&mut self, key: K, value: V) {
+        self.attributes.push((key.into(), value.into()));
+    }
+
+    /// Removes an attribute from this measurement point, and returns its value, if any.
+    pub fn remove_attr(&mut self, key: &str) -> Option<AttributeValue> {
+        let i = self.attributes.iter().position(|(k, _)| k.as_str() == key)?;
+        Some(self.attributes.remove(i).1)
+    }
+
+    /// Sets an attribute on this measurement point, and returns self to allow for method chaining.
+    /// If an attribute with the same key already exists, its value is replaced.
+    pub fn with_attr<K: Into<AttributeKey>, V: Into<AttributeValue>>(mut self, key: K, value: V) -> Self {
+        self.add_attr(key, value);
+        self
+    }
+
+    /// Attaches multiple attributes to this measurement point, from a [`Vec`].
+    /// Existing attributes with conflicting keys are replaced.
+    pub fn with_attr_vec<K: Into<AttributeKey>>(mut self, attributes: Vec<(K, AttributeValue)>) -> Self {
+        self.attributes
+            .extend(attributes.into_iter().map(|(k, v)| (k.into(), v)));
+        self
+    }
+
+    /// Attaches multiple attributes to this measurement point, from a [`HashMap`].
+    /// Existing attributes with conflicting keys are replaced.
+    pub fn with_attr_map<K: Into<AttributeKey>>(
+        mut self,
+        attributes: HashMap<K, AttributeValue, FxBuildHasher>,
+    ) -> Self {
+        let converted = attributes.into_iter().map(|(k, v)| (k.into(), v));
+        if self.attributes.is_empty() {
+            self.attributes = converted.collect();
+        } else {
+            self.attributes.extend(converted);
+        }
+        self
+    }
+}
+
+impl Timestamp {
+    /// Returns a `Timestamp` representing the current system time.
+    ///
+    /// The time of a monotonic clock is also captured:
+    /// `CLOCK_BOOTTIME` on Linux, `CLOCK_MONOTONIC` on other systems.
+    pub fn now() -> Self {
+        let wall = SystemTime::now();
+        let domain = if cfg!(target_os = "linux") {
+            ClockDomain::Boottime
+        } else {
+            ClockDomain::Monotonic
+        };
+        Self {
+            wall,
+            monotonic: MonotonicTime::now(domain),
+        }
+    }
+
+    /// Creates a timestamp from the number of seconds and nanoseconds since the Unix epoch.
+    ///
+    /// `secs` can be negative for the times before the epoch, `nanos` are always added.
+    /// Returns `None` if the time cannot be represented or if `nanos` is not less than one billion.
+    pub fn from_unix_timestamp(secs: i64, nanos: u32) -> Option<Self> {
+        if nanos >= 1_000_000_000 {
+            return None;
+        }
+        let wall = if secs >= 0 {
+            UNIX_EPOCH.checked_add(Duration::new(secs as u64, nanos))?
+        } else {
+            UNIX_EPOCH
+                .checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+                .checked_add(Duration::from_nanos(u64::from(nanos)))?
+        };
+        Some(Self::from(wall))
+    }
+
+    /// Returns the number of seconds and nanoseconds since the Unix epoch.
+    ///
+    /// For the times before the epoch, the number of seconds is negative, and the nanoseconds
+    /// are counted forward: -1.5s is represented as `(-2, 500_000_000)`.
+    pub fn to_unix_timestamp(&self) -> (i64, u32) {
+        match self.wall.duration_since(UNIX_EPOCH) {
+            Ok(t) => (t.as_secs() as i64, t.subsec_nanos()),
+            Err(e) => {
+                let t = e.duration();
+                let (secs, nanos) = (-(t.as_secs() as i64), t.subsec_nanos());
+                if nanos == 0 {
+                    (secs, 0)
+                } else {
+                    (secs - 1, 1_000_000_000 - nanos)
+                }
+            }
+        }
+    }
+
+    /// Returns the number of nanoseconds since the Unix epoch, which is negative before the epoch.
+    ///
+    /// The result saturates at the bounds of `i64`, that is, around the years 1677 and 2262.
+    pub fn to_unix_nanos(&self) -> i64 {
+        let (secs, nanos) = self.to_unix_timestamp();
+        let nanos = i128::from(secs) * 1_000_000_000 + i128::from(nanos);
+        nanos.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64
+    }
+
+    /// Returns the wall-clock time.
+    pub fn wall_clock(&self) -> SystemTime {
+        self.wall
+    }
+
+    /// Returns the time of the monotonic clock, if it has been captured.
+    pub fn monotonic(&self) -> Option<MonotonicTime> {
+        self.monotonic
+    }
+
+    /// Returns a copy of the timestamp with the given monotonic time.
+    pub fn with_monotonic(self, monotonic: MonotonicTime) -> Self {
+        Self {
+            monotonic: Some(monotonic),
+            ..self
+        }
+    }
+
+    /// Returns the time elapsed between `earlier` and `self`.
+    ///
+    /// If both timestamps contain the time of the same monotonic clock, it is used.
+    /// Otherwise, the wall-clock times are compared.
+    /// Returns `None` if `earlier` is after `self`.
+    pub fn elapsed_since(&self, earlier: &Timestamp) -> Option<Duration> {
+        match (self.monotonic, earlier.monotonic) {
+            (Some(now), Some(earlier)) if now.domain == earlier.domain => {
+                now.since_origin.checked_sub(earlier.since_origin)
+            }
+            _ => self.wall.duration_since(earlier.wall).ok(),
+        }
+    }
+}
+
+impl PartialEq for Timestamp {
+    fn eq(&self, other: &Self) -> bool {
+        self.wall == other.wall
+    }
+}
+
+impl Eq for Timestamp {}
+
+impl From<SystemTime> for Timestamp {
+    fn from(value: SystemTime) -> Self {
+        Self {
+            wall: value,
+            monotonic: None,
+        }
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(value: Timestamp) -> Self {
+        value.wall
+    }
+}
+
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.wall.fmt(f)
+    }
+}
+
+impl MonotonicTime {
+    /// Reads the current time of a monotonic clock.
+    ///
+    /// Returns `None` if the clock is not available on this system.
+    pub fn now(domain: ClockDomain) -> Option<Self> {
+        let clock_id = match domain {
+            ClockDomain::Monotonic => libc::CLOCK_MONOTONIC,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ClockDomain::Boottime => libc::CLOCK_BOOTTIME,
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            ClockDomain::Boottime => return None,
+        };
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        // SAFETY: ts is a valid pointer to a timespec
+        let res = unsafe { libc::clock_gettime(clock_id, &mut ts) };
+        if res != 0 {
+            return None;
+        }
+        Some(Self {
+            domain,
+            since_origin: Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32),
+        })
+    }
+
+    /// Creates a monotonic time from its clock and the time since the origin of the clock.
+    pub fn new(domain: ClockDomain, since_origin: Duration) -> Self {
+        Self { domain, since_origin }
+    }
+
+    /// Returns the clock that has been read.
+    pub fn domain(&self) -> ClockDomain {
+        self.domain
+    }
+
+    /// Returns the time since the origin of the clock, which is unspecified (usually the boot of the system).
+    pub fn since_origin(&self) -> Duration {
+        self.since_origin
+    }
+}
+
+/// Trait implemented by types that are accepted as measurement values.
+pub trait MeasurementType {
+    type T;
+
+    fn wrapped_value(v: Self::T) -> WrappedMeasurementValue;
+    fn wrapped_type() -> WrappedMeasurementType;
+}
+impl MeasurementType for u64 {
+    type T = u64;
+
+    fn wrapped_value(v: Self::T) -> WrappedMeasurementValue {
+        WrappedMeasurementValue::U64(v)
+    }
+
+    fn wrapped_type() -> WrappedMeasurementType {
+        WrappedMeasurementType::U64
+    }
+}
+impl MeasurementType for f64 {
+    type T = f64;
+
+    fn wrapped_value(v: Self::T) -> WrappedMeasurementValue {
+        WrappedMeasurementValue::F64(v)
+    }
+
+    fn wrapped_type() -> WrappedMeasurementType {
+        WrappedMeasurementType::F64
+    }
+}
+impl MeasurementType for i64 {
+    type T = i64;
+
+    fn wrapped_value(v: Self::T) -> WrappedMeasurementValue {
+        WrappedMeasurementValue::I64(v)
+    }
+
+    fn wrapped_type() -> WrappedMeasurementType {
+        WrappedMeasurementType::I64
+    }
+}
+impl MeasurementType for bool {
+    type T = bool;
+
+    fn wrapped_value(v: Self::T) -> WrappedMeasurementValue {
+        WrappedMeasurementValue::Bool(v)
+    }
+
+    fn wrapped_type() -> WrappedMeasurementType {
+        WrappedMeasurementType::Bool
+    }
+}
+impl MeasurementType for Histogram {
+    type T = Histogram;
+
+    fn wrapped_value(v: Self::T) -> WrappedMeasurementValue {
+        WrappedMeasurementValue::Histogram(v)
+    }
+
+    fn wrapped_type() -> WrappedMeasurementType {
+        WrappedMeasurementType::Histogram
+    }
+}
+
+/// Enum of the possible measurement types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum WrappedMeasurementType {
+    F64,
+    U64,
+    I64,
+    Bool,
+    Histogram,
+}
+impl fmt::Display for WrappedMeasurementType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// A measurement value of any supported measurement type.
+#[derive(Debug, Clone)]
+pub enum WrappedMeasurementValue {
+    F64(f64),
+    U64(u64),
+    I64(i64),
+    Bool(bool),
+    Histogram(Histogram),
+}
+
+impl WrappedMeasurementValue {
+    pub fn measurement_type(&self) -> WrappedMeasurementType {
+        match self {
+            WrappedMeasurementValue::F64(_) => WrappedMeasurementType::F64,
+            WrappedMeasurementValue::U64(_) => WrappedMeasurementType::U64,
+            WrappedMeasurementValue::I64(_) => WrappedMeasurementType::I64,
+            WrappedMeasurementValue::Bool(_) => WrappedMeasurementType::Bool,
+            WrappedMeasurementValue::Histogram(_) => WrappedMeasurementType::Histogram,
+        }
+    }
+
+    /// Converts the value to a float, if it is a number.
+    ///
+    /// Booleans are converted to `0.0` (false) or `1.0` (true).
+    /// Histograms cannot be converted and return `None`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            WrappedMeasurementValue::F64(x) => Some(*x),
+            WrappedMeasurementValue::U64(x) => Some(*x as f64),
+            WrappedMeasurementValue::I64(x) => Some(*x as f64),
+            WrappedMeasurementValue::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+            WrappedMeasurementValue::Histogram(_) => None,
+        }
+    }
+}
+
+impl Display for WrappedMeasurementValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WrappedMeasurementValue::F64(x) => write!(f, "{x}"),
+            WrappedMeasurementValue::U64(x) => write!(f, "{x}"),
+            WrappedMeasurementValue::I64(x) => write!(f, "{x}"),
+            WrappedMeasurementValue::Bool(x) => write!(f, "{x}"),
+            WrappedMeasurementValue::Histogram(h) => write!(f, "{h}"),
+        }
+    }
+}
+
+/// A distribution of measured values, for instance the latencies of some requests.
+///
+/// The values are counted in buckets, delimited by increasing upper bounds.
+/// The bucket `i` contains the values `v` such that `bounds[i-1] < v <= bounds[i]`.
+/// There is always one more bucket than bounds: the last one contains the values
+/// that are greater than the last bound.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+/// Error returned when the parts of a [`Histogram`] are inconsistent.
+#[derive(Debug)]
+pub struct InvalidHistogramError(&'static str);
+
+impl std::error::Error for InvalidHistogramError {}
+impl Display for InvalidHistogramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid histogram: {}", self.0)
+    }
+}
+
+impl Histogram {
+    /// Creates an empty histogram with the given bucket bounds.
+    ///
+    /// The bounds must be finite and sorted in strictly increasing order.
+    pub fn new(bounds: Vec<f64>) -> Result<Self, InvalidHistogramError> {
+        let counts = vec![0; bounds.len() + 1];
+        Self::from_parts(bounds, counts, 0.0)
+    }
+
+    /// Creates a histogram from its bounds, the number of values in each bucket
+    /// and the sum of the values.
+    ///
+    /// `counts` must contain exactly one more element than `bounds`.
+    pub fn from_parts(bounds: Vec<f64>, counts: Vec<u64>, sum: f64) -> Result<Self, InvalidHistogramError> {
+        if counts.len() != bounds.len() + 1 {
+            return Err(InvalidHistogramError("there must be one more count than bounds"));
+        }
+        if bounds.iter().any(|b| !b.is_finite()) {
+            return Err(InvalidHistogramError("the bounds must be finite"));
+        }
+        if bounds.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(InvalidHistogramError("the bounds must be strictly increasing"));
+        }
+        Ok(Self { bounds, counts, sum })
+    }
+
+    /// Adds a value to the histogram.
+    pub fn record(&mut self, value: f64) {
+        let i = self.bounds.partition_point(|b| *b < value);
+        self.counts[i] += 1;
+        self.sum += value;
+    }
+
+    /// The upper bounds of the buckets, except the last one (which is infinite).
+    pub fn bounds(&self) -> &[f64] {
+        &self.bounds
+    }
+
+    /// The number of values in each bucket.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// The sum of all the values.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// The total number of values.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Iterates on the buckets, as pairs `(upper_bound, count)`.
+    ///
+    /// The upper bound of the last bucket is [`f64::INFINITY`].
+    pub fn buckets(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        self.bounds
+            .iter()
+            .copied()
+            .chain(std::iter::once(f64::INFINITY))
+            .zip(self.counts.iter().copied())
+    }
+}
+
+impl Display for Histogram {
+    /// Formats the histogram as `count=N sum=S buckets=[b1:n1 b2:n2 ... +Inf:n]`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "count={} sum={} buckets=[", self.count(), self.sum)?;
+        for (i, (bound, count)) in self.buckets().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            if bound == f64::INFINITY {
+                write!(f, "+Inf:{count}")?;
+            } else {
+                write!(f, "{bound}:{count}")?;
+            }
+        }
+        f.write_str("]")
+    }
+}
+
+/// The key of an attribute.
+///
+/// Most keys are static strings, which are never copied. Other keys are stored in a shared string,
+/// which is cheap to clone: cloning a measurement point does not copy its keys.
+#[derive(Clone)]
+pub struct AttributeKey(KeyRepr);
+
+#[derive(Clone)]
+enum KeyRepr {
+    Static(&'static str),
+    Shared(Arc<str>),
+}
+
+impl AttributeKey {
+    /// Returns the key as a string slice.
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            KeyRepr::Static(s) => s,
+            KeyRepr::Shared(s) => s,
+        }
+    }
+}
+
+impl Deref for AttributeKey {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for AttributeKey {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for AttributeKey {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for AttributeKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for AttributeKey {}
+
+impl Hash for AttributeKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for AttributeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for AttributeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&'static str> for AttributeKey {
+    fn from(value: &'static str) -> Self {
+        AttributeKey(KeyRepr::Static(value))
+    }
+}
+
+impl From<String> for AttributeKey {
+    fn from(value: String) -> Self {
+        AttributeKey(KeyRepr::Shared(value.into()))
+    }
+}
+
+impl From<Arc<str>> for AttributeKey {
+    fn from(value: Arc<str>) -> Self {
+        AttributeKey(KeyRepr::Shared(value))
+    }
+}
+
+impl From<Cow<'static, str>> for AttributeKey {
+    fn from(value: Cow<'static, str>) -> Self {
+        match value {
+            Cow::Borrowed(s) => AttributeKey::from(s),
+            Cow::Owned(s) => AttributeKey::from(s),
+        }
+    }
+}
+
+/// An attribute value of any supported attribute type.
+#[derive(Debug, Clone)]
+pub enum AttributeValue {
+    F64(f64),
+    U64(u64),
+    Bool(bool),
+    /// A borrowed string attribute.
+    ///
+    /// If you can use `AttributeValue::Str` instead of `AttributeValue::String`,
+    /// do it: it will save a memory allocation.
+    Str(&'static str),
+    /// A shared string attribute.
+    ///
+    /// Cloning it does not copy the string. Use it for the values that are repeated
+    /// in many measurement points, such as the name of a pod.
+    /// See [`AttributeInterner`](interning::AttributeInterner).
+    Shared(Arc<str>),
+    String(String),
+}
+
+impl AttributeValue {
+    /// Returns the value as a string slice, if it is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AttributeValue::Str(s) => Some(s),
+            AttributeValue::Shared(s) => Some(s),
+            AttributeValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeValue::F64(x) => write!(f, "{x}"),
+            AttributeValue::U64(x) => write!(f, "{x}"),
+            AttributeValue::Bool(x) => write!(f, "{x}"),
+            AttributeValue::Str(str) => f.write_str(str),
+            AttributeValue::Shared(str) => f.write_str(str),
+            AttributeValue::String(str) => f.write_str(str),
+        }
+    }
+}
+
+impl From<f64> for AttributeValue {
+    fn from(value: f64) -> Self {
+        AttributeValue::F64(value)
+    }
+}
+
+impl From<u64> for AttributeValue {
+    fn from(value: u64) -> Self {
+        AttributeValue::U64(value)
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        AttributeValue::Bool(value)
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+impl From<&'static str> for AttributeValue {
+    fn from(value: &'static str) -> Self {
+        AttributeValue::Str(value)
+    }
+}
+
+impl From<Arc<str>> for AttributeValue {
+    fn from(value: Arc<str>) -> Self {
+        AttributeValue::Shared(value)
+    }
+}
+
+/// A `MeasurementBuffer` stores measured data points.
+/// Unlike a [`MeasurementAccumulator`], the buffer allows to modify the measurements.
+///
+/// The points are stored as rows ([`MeasurementPoint`]), and possibly in some columnar batches
+/// ([`ColumnarBuffer`](columnar::ColumnarBuffer)), which are not converted to rows until needed.
+/// Both are part of the buffer: [`len`](Self::len) counts all the points and [`iter`](Self::iter)
+/// returns all of them, the rows first. The methods that modify the points, like [`iter_mut`](Self::iter_mut)
+/// and [`retain`](Self::retain), [materialize](Self::materialize) the buffer automatically.
+#[derive(Clone)]
+pub struct MeasurementBuffer {
+    points: Vec<MeasurementPoint>,
+    columns: Vec<columnar::ColumnarBuffer>,
+    /// Rows of the columnar batches, built on the first call to `iter` and invalidated when the batches change.
+    column_rows: OnceLock<Vec<MeasurementPoint>>,
+}
+
+impl MeasurementBuffer {
+    /// Constructs a new buffer.
+    pub fn new() -> MeasurementBuffer {
+        MeasurementBuffer {
+            points: Vec::new(),
+            columns: Vec::new(),
+            column_rows: OnceLock::new(),
+        }
+    }
+
+    /// Constructs a new buffer with at least the specified capacity (allocated on construction).
+    pub fn with_capacity(capacity: usize) -> MeasurementBuffer {
+        MeasurementBuffer {
+            points: Vec::with_capacity(capacity),
+            columns: Vec::new(),
+            column_rows: OnceLock::new(),
+        }
+    }
+
+    /// Returns true if this buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of measurement points in the buffer, including the columnar batches.
+    pub fn len(&self) -> usize {
+        self.points.len() + self.columns.iter().map(|c| c.len()).sum::<usize>()
+    }
+
+    /// Returns the points that are not in a columnar batch.
+    pub(crate) fn rows(&self) -> &[MeasurementPoint] {
+        &self.points
+    }
+
+    /// Adds a columnar batch to the buffer, without converting it to rows.
+    pub fn push_columnar(&mut self, columns: columnar::ColumnarBuffer) {
+        if !columns.is_empty() {
+            self.column_rows.take();
+            self.columns.push(columns);
+        }
+    }
+
+    /// Returns the columnar batches of the buffer.
+    pub fn columnar(&self) -> &[columnar::ColumnarBuffer] {
+        &self.columns
+    }
+
+    /// Returns the columnar batches of the buffer, which can be modified.
+    pub fn columnar_mut(&mut self) -> &mut [columnar::ColumnarBuffer] {
+        self.column_rows.take();
+        &mut self.columns
+    }
+
+    /// Converts the columnar batches to rows, which are placed after the existing rows.
+    ///
+    /// Does nothing if the buffer contains no columnar batch.
+    pub fn materialize(&mut self) {
+        match self.column_rows.take() {
+            Some(mut rows) if !self.columns.is_empty() => {
+                // the batches have already been converted by `iter`
+                self.points.append(&mut rows);
+                self.columns.clear();
+            }
+            _ => {
+                for columns in self.columns.drain(..) {
+                    columns.move_to_rows(&mut self.points);
+                }
+            }
+        }
+    }
+
+    /// Reserves capacity for at least `additional` more elements.
+    /// See [`Vec::reserve`].
+    pub fn reserve(&mut self, additional: usize) {
+        self.points.reserve(additional);
+    }
+
+    /// Adds a measurement to the buffer.
+    /// The measurement points are *not* automatically deduplicated by the buffer.
+    pub fn push(&mut self, point: MeasurementPoint) {
+        self.points.push(point);
+    }
+
+    /// Merges another buffer into this buffer.
+    /// All the measurement points of `other` are moved to `self`.
+    pub fn merge(&mut self, other: &mut MeasurementBuffer) {
+        self.points.append(&mut other.points);
+        if !other.columns.is_empty() {
+            self.column_rows.take();
+            other.column_rows.take();
+            self.columns.append(&mut other.columns);
+        }
+    }
+
+    /// Clears the buffer, removing all the measurements.
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.columns.clear();
+        self.column_rows.take();
+    }
+
+    /// Retains only the measurements that satisfy the predicate.
+    /// See [`Vec::retain`].
+    ///
+    /// The columnar batches are converted to rows first.
+    pub fn retain(&mut self, f: impl FnMut(&MeasurementPoint) -> bool) {
+        self.materialize();
+        self.points.retain(f);
+    }
+
+    /// Creates an iterator on the measurements.
+    ///
+    /// The points of the columnar batches are included, after the rows. They are converted to rows
+    /// the first time that the buffer is iterated, but the batches are kept: the transforms and outputs
+    /// that [accept columnar batches](crate::pipeline::Transform::accepts_columnar) should rather read
+    /// them with [`columnar`](Self::columnar).
+    pub fn iter(&self) -> impl Iterator<Item = &MeasurementPoint> {
+        self.into_iter()
+    }
+
+    /// Creates an iterator that allows to modify the measurements.
+    ///
+    /// The columnar batches are converted to rows first.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut MeasurementPoint> {
+        self.materialize();
+        self.points.iter_mut()
+    }
+
+    /// Returns a `MeasurementAccumulator` that will push all measurements to this buffer.
+    pub fn as_accumulator(&mut self) -> MeasurementAccumulator {
+        MeasurementAccumulator(self)
+    }
+}
+
+impl Default for MeasurementBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> IntoIterator for &'a MeasurementBuffer {
+    type Item = &'a MeasurementPoint;
+    type IntoIter = std::iter::Chain<std::slice::Iter<'a, MeasurementPoint>, std::slice::Iter<'a, MeasurementPoint>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let column_rows: &[MeasurementPoint] = if self.columns.is_empty() {
+            &[]
+        } else {
+            self.column_rows.get_or_init(|| {
+                let mut rows = Vec::with_capacity(self.len() - self.points.len());
+                for columns in &self.columns {
+                    rows.extend(columns.iter().map(|p| p.to_point()));
+                }
+                rows
+            })
+        };
+        self.points.iter().chain(column_rows)
+    }
+}
+
+impl IntoIterator for MeasurementBuffer {
+    type Item = MeasurementPoint;
+    type IntoIter = std::vec::IntoIter<MeasurementPoint>;
+
+    /// Converts the columnar batches to rows, and iterates on all the points.
+    fn into_iter(mut self) -> Self::IntoIter {
+        self.materialize();
+        self.points.into_iter()
+    }
+}
+
+impl FromIterator<MeasurementPoint> for MeasurementBuffer {
+    fn from_iter<T: IntoIterator<Item = MeasurementPoint>>(iter: T) -> Self {
+        Self {
+            points: Vec::from_iter(iter),
+            columns: Vec::new(),
+            column_rows: OnceLock::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for MeasurementBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MeasurementBuffer")
+            .field("len", &self.len())
+            .field("columnar_batches", &self.columns.len())
+            .finish()
+    }
+}
+
+impl From<Vec<MeasurementPoint>> for MeasurementBuffer {
+    fn from(value: Vec<MeasurementPoint>) -> Self {
+        MeasurementBuffer {
+            points: value,
+            columns: Vec::new(),
+            column_rows: OnceLock::new(),
+        }
+    }
+}
+
+/// An accumulator stores measured data points.
+/// Unlike a [`MeasurementBuffer`], the accumulator only allows to [`push`](MeasurementAccumulator::push) new points, not to modify them.
+pub struct MeasurementAccumulator<'a>(&'a mut MeasurementBuffer);
+
+impl<'a> MeasurementAccumulator<'a> {
+    /// Adds a new measurement to this accumulator.
+    /// The measurement points are not deduplicated by the accumulator.
+    pub fn push(&mut self, point: MeasurementPoint) {
+        self.0.push(point)
+    }
+
+    /// Adds all the measurements of a columnar buffer to this accumulator.
+    ///
+    /// The points are moved out of `columns`, as a columnar batch that goes through the pipeline
+    /// without being converted to rows (see [`columnar`]). `columns` keeps its dictionaries:
+    /// reuse it, and the references obtained from it, in the next poll.
+    pub fn push_columnar(&mut self, columns: &mut columnar::ColumnarBuffer) {
+        self.0.push_columnar(columns.take_points())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{ClockDomain, Histogram, MonotonicTime, Timestamp};
+
+    #[test]
+    fn unix_timestamp() {
+        let t = Timestamp::from(UNIX_EPOCH + Duration::new(12, 345));
+        assert_eq!(t.to_unix_timestamp(), (12, 345));
+        assert_eq!(t.to_unix_nanos(), 12_000_000_345);
+
+        // before the epoch
+        let t = Timestamp::from(UNIX_EPOCH - Duration::from_millis(1500));
+        assert_eq!(t.to_unix_timestamp(), (-2, 500_000_000));
+        assert_eq!(t.to_unix_nanos(), -1_500_000_000);
+        assert_eq!(Timestamp::from_unix_timestamp(-2, 500_000_000), Some(t));
+        let t = Timestamp::from(UNIX_EPOCH - Duration::from_secs(3));
+        assert_eq!(t.to_unix_timestamp(), (-3, 0));
+        assert_eq!(Timestamp::from_unix_timestamp(-3, 0), Some(t));
+
+        assert_eq!(Timestamp::from_unix_timestamp(0, 1_000_000_000), None);
+    }
+
+    #[test]
+    fn elapsed() {
+        let mono = |secs| MonotonicTime::new(ClockDomain::Boottime, Duration::from_secs(secs));
+        let t0 = Timestamp::from(UNIX_EPOCH + Duration::from_secs(100));
+        let t1 = Timestamp::from(UNIX_EPOCH + Duration::from_secs(90));
+        // only the wall clock
+        assert_eq!(t1.elapsed_since(&t0), None);
+        assert_eq!(t0.elapsed_since(&t1), Some(Duration::from_secs(10)));
+
+        // the wall clock has been adjusted, but not the monotonic clock
+        let t0 = t0.with_monotonic(mono(5));
+        let t1 = t1.with_monotonic(mono(7));
+        assert_eq!(t1.elapsed_since(&t0), Some(Duration::from_secs(2)));
+        assert_eq!(t0.elapsed_since(&t1), None);
+
+        // different clocks, fall back to the wall clock
+        let t2 = t1.with_monotonic(MonotonicTime::new(ClockDomain::Monotonic, Duration::from_secs(1)));
+        assert_eq!(t2.elapsed_since(&t0), None);
+    }
+
+    #[test]
+    fn now_has_monotonic_clock() {
+        let t0 = Timestamp::now();
+        let t1 = Timestamp::now();
+        assert!(t0.monotonic().is_some());
+        assert!(t1.elapsed_since(&t0).is_some());
+    }
+
+    #[test]
+    fn histogram() {
+        let mut h = Histogram::new(vec![1.0, 10.0, 100.0]).unwrap();
+        for v in [0.5, 1.0, 5.0, 50.0, 500.0, 1000.0] {
+            h.record(v);
+        }
+        assert_eq!(h.counts(), &[2, 1, 1, 2]);
+        assert_eq!(h.count(), 6);
+        assert_eq!(h.sum(), 1556.5);
+        assert_eq!(
+            h.buckets().collect::<Vec<_>>(),
+            vec![(1.0, 2), (10.0, 1), (100.0, 1), (f64::INFINITY, 2)]
+        );
+        assert_eq!(h.to_string(), "count=6 sum=1556.5 buckets=[1:2 10:1 100:1 +Inf:2]");
+    }
+
+    #[test]
+    fn invalid_histogram() {
+        assert!(Histogram::new(vec![1.0, 1.0]).is_err());
+        assert!(Histogram::new(vec![2.0, 1.0]).is_err());
+        assert!(Histogram::new(vec![f64::NAN]).is_err());
+        assert!(Histogram::from_parts(vec![1.0], vec![1], 0.0).is_err());
+        assert!(Histogram::from_parts(vec![], vec![3], 1.5).is_ok());
+    }
+}
@@ -5,6 +5,8 @@
 //! - a description
 //! - a type of measured value
 //! - a measurement unit
+//! - a kind, which tells how the values evolve over time (see [`MetricKind`])
+//! - optional static labels, which apply to every measurement of the metric
 //!
 //! This information is stored in the [`Metric`] struct.
 //!
@@ -20,6 +22,8 @@
 //! Metrics can only be registered during the plugin startup phase.
 //! To register new metrics, use [`AlumetPluginStart::create_metric`](crate::plugin::AlumetPluginStart::create_metric)
 //! or [`AlumetPluginStart::create_metric_untyped`](crate::plugin::AlumetPluginStart::create_metric).
+//! These functions create gauges. To create another kind of metric, use
+//! [`AlumetPluginStart::create_metric_with_kind`](crate::plugin::AlumetPluginStart::create_metric_with_kind).
 //! You can then pass the id around.
 //!
 //! # Example
@@ -64,6 +68,48 @@ pub struct Metric {
     pub value_type: WrappedMeasurementType,
     /// Unit that applies to all the measurements of this metric.
     pub unit: PrefixedUnit,
+    /// How the measured values evolve over time.
+    pub kind: MetricKind,
+    /// Static labels that apply to all the measurements of this metric, as `(key, value)` pairs.
+    ///
+    /// Outputs can use them to describe the metric, for instance as Prometheus labels or InfluxDB tags.
+    pub labels: Vec<(String, String)>,
+}
+
+/// The kind of a metric, which tells how its values evolve over time.
+///
+/// Outputs use it to choose the right representation of the measurements,
+/// for instance a Prometheus counter or an OpenTelemetry sum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MetricKind {
+    /// A value that can go up and down, like a temperature or a power.
+    #[default]
+    Gauge,
+    /// A cumulative value that only increases, except when it is reset.
+    /// For instance, the total number of bytes sent by a network interface.
+    Counter,
+    /// The increase of a counter since the previous measurement.
+    /// For instance, the energy consumed since the last polling of the source.
+    Delta,
+}
+
+impl MetricKind {
+    /// Returns `true` if the metric measures something that accumulates over time,
+    /// that is, if it is a [`Counter`](MetricKind::Counter) or a [`Delta`](MetricKind::Delta).
+    pub fn is_monotonic(&self) -> bool {
+        matches!(self, MetricKind::Counter | MetricKind::Delta)
+    }
+}
+
+impl fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            MetricKind::Gauge => "gauge",
+            MetricKind::Counter => "counter",
+            MetricKind::Delta => "delta",
+        };
+        f.write_str(s)
+    }
 }
 
 /// Trait for both typed and untyped metric ids.
@@ -247,7 +293,7 @@ impl MetricRegistry {
     /// same name as `m` already exists in the registry.
     ///
     /// Instead, it:
-    /// 1. Checks whether `m` and the conflicting metric are "equal" (same name, same unit, same type of value).
+    /// 1. Checks whether `m` and the conflicting metric are "equal" (same name, same unit, same type of value, same kind).
     /// 2. If `m` is different, `register_infallible` uses the `dedup_suffix` to generate a new, unique name for `m`,
     /// and registers it under that name.
     pub(crate) fn register_infallible(&mut self, m: Metric, dedup_suffix: &str) -> RawMetricId {
@@ -257,6 +303,7 @@ impl MetricRegistry {
             // Information needed to compare metrics.
             let unit = metric.unit.clone();
             let value_type = metric.value_type.clone();
+            let kind = metric.kind;
 
             // The metric name is modified by this function.
             let mut buf = &mut metric.name;
@@ -264,7 +311,11 @@ impl MetricRegistry {
             // First try: simply append the suffix with an underscore
             write!(&mut buf, "_{dedup_suffix}").expect("dedup_suffix should be writable to metric name");
             match reg.by_name(buf) {
-                Some((id, existing)) if existing.unit == unit && existing.value_type == value_type => id,
+                Some((id, existing))
+                    if existing.unit == unit && existing.value_type == value_type && existing.kind == kind =>
+                {
+                    id
+                }
                 Some((_id, _conflict)) => {
                     // Second try: append "_2"
                     buf.push_str("_2");
@@ -273,7 +324,7 @@ impl MetricRegistry {
                     let mut existing = reg.by_name(buf);
                     while existing.is_some() {
                         let (id, other) = existing.unwrap();
-                        if other.unit == unit && other.value_type == value_type {
+                        if other.unit == unit && other.value_type == value_type && other.kind == kind {
                             // identical to the existing metric, stop here
                             return id;
                         }
@@ -292,7 +343,7 @@ impl MetricRegistry {
         let name = &m.name;
         if let Some(conflict_id) = self.metrics_by_name.get(name) {
             let conflict = &self.metrics_by_id[conflict_id];
-            if conflict.unit == m.unit && conflict.value_type == m.value_type {
+            if conflict.unit == m.unit && conflict.value_type == m.value_type && conflict.kind == m.kind {
                 // If the conflicting metric is the same, it's ok.
                 *conflict_id
             } else {
@@ -366,7 +417,11 @@ impl fmt::Display for MetricCreationError {
 
 #[cfg(test)]
 mod tests {
-    use crate::{measurement::WrappedMeasurementType, metrics::Metric, units::Unit};
+    use crate::{
+        measurement::WrappedMeasurementType,
+        metrics::{Metric, MetricKind},
+        units::Unit,
+    };
 
     use super::MetricRegistry;
 
@@ -380,6 +435,8 @@ mod tests {
                 description: "...".to_owned(),
                 value_type: WrappedMeasurementType::U64,
                 unit: Unit::Watt.into(),
+                kind: MetricKind::Gauge,
+                labels: vec![],
             })
             .unwrap();
         metrics
@@ -388,6 +445,8 @@ mod tests {
                 description: "abcd".to_owned(),
                 value_type: WrappedMeasurementType::F64,
                 unit: Unit::Volt.into(),
+                kind: MetricKind::Gauge,
+                labels: vec![],
             })
             .unwrap_err(); // error is expected
         assert_eq!(metrics.len(), 1);
@@ -403,6 +462,8 @@ mod tests {
                 description: "".to_owned(),
                 value_type: WrappedMeasurementType::U64,
                 unit: Unit::Watt.into(),
+                kind: MetricKind::Gauge,
+                labels: vec![],
             })
             .unwrap();
         let metric_id2 = metrics
@@ -411,6 +472,8 @@ mod tests {
                 description: "".to_owned(),
                 value_type: WrappedMeasurementType::F64,
                 unit: Unit::Watt.into(),
+                kind: MetricKind::Gauge,
+                labels: vec![],
             })
             .unwrap();
         assert_eq!(metrics.len(), 2);
@@ -443,6 +506,8 @@ mod tests {
                     description: "...".to_owned(),
                     value_type: WrappedMeasurementType::U64,
                     unit: Unit::Watt.into(),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
                 },
                 "suffix",
             );
@@ -456,6 +521,8 @@ mod tests {
                     description: "...".to_owned(),
                     value_type: WrappedMeasurementType::U64,
                     unit: Unit::Watt.into(),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
                 },
                 "suffix",
             );
@@ -470,6 +537,8 @@ mod tests {
                     description: "abcd".to_owned(),
                     value_type: WrappedMeasurementType::F64,
                     unit: Unit::Volt.into(),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
                 },
                 "suffix",
             );
@@ -485,6 +554,8 @@ mod tests {
                     description: "abcd".to_owned(),
                     value_type: WrappedMeasurementType::F64,
                     unit: Unit::Volt.into(),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
                 },
                 "suffix",
             );
@@ -500,6 +571,8 @@ mod tests {
                     description: "xyz".to_owned(),
                     value_type: WrappedMeasurementType::U64,
                     unit: Unit::Volt.into(),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
                 },
                 "suffix",
             );
@@ -517,6 +590,8 @@ mod tests {
                     description: "not the same".to_owned(),
                     value_type: WrappedMeasurementType::U64,
                     unit: Unit::Second.into(),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
                 },
                 "suffix",
             );
@@ -536,6 +611,8 @@ mod tests {
                     description: "not the same".to_owned(),
                     value_type: WrappedMeasurementType::U64,
                     unit: Unit::Second.into(),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
                 },
                 "suffix",
             );
@@ -547,4 +624,28 @@ mod tests {
             assert_eq!(id4_bis, id4);
         }
     }
+
+    #[test]
+    fn register_infallible_kind() {
+        let mut metrics = MetricRegistry::new();
+        let metric = |kind: MetricKind| Metric {
+            name: "energy".to_owned(),
+            description: "".to_owned(),
+            value_type: WrappedMeasurementType::F64,
+            unit: Unit::Joule.into(),
+            kind,
+            labels: vec![(String::from("domain"), String::from("package"))],
+        };
+        let delta = metrics.register_infallible(metric(MetricKind::Delta), "suffix");
+        let delta_bis = metrics.register_infallible(metric(MetricKind::Delta), "suffix");
+        assert_eq!(delta, delta_bis);
+
+        // same name, unit and type, but not the same kind: the metric is deduplicated
+        let counter = metrics.register_infallible(metric(MetricKind::Counter), "suffix");
+        assert_ne!(counter, delta);
+        let (_, m) = metrics.by_name("energy_suffix").unwrap();
+        assert_eq!(m.kind, MetricKind::Counter);
+        assert_eq!(m.labels, vec![(String::from("domain"), String::from("package"))]);
+        assert_eq!(metrics.by_id(&delta).unwrap().kind, MetricKind::Delta);
+    }
 }
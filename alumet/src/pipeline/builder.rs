@@ -1,11 +1,16 @@
 //! Construction of measurement pipelines.
+use std::sync::Arc;
 use std::time::Duration;
 
 use super::elements::{output, source, transform};
 use super::registry::listener::MetricListenerBuilder;
 use super::registry::{MetricReader, MetricSender};
 use crate::pipeline::registry::MetricRegistryControl;
+use crate::pipeline::telemetry::PipelineTelemetry;
 use crate::pipeline::util::channel;
+use crate::pipeline::util::matching::OutputSelector;
+use crate::pipeline::util::routing::OutputRoute;
+use crate::resources::{consumers::ConsumerResolver, topology::Topology, Resource};
 use crate::{measurement::MeasurementBuffer, metrics::MetricRegistry};
 
 use super::util::naming::PluginName;
@@ -16,11 +21,7 @@ use super::{
 };
 use anyhow::Context;
 use tokio::time::error::Elapsed;
-use tokio::{
-    runtime::Runtime,
-    sync::{broadcast, mpsc},
-    task::JoinHandle,
-};
+use tokio::{runtime::Runtime, sync::mpsc, task::JoinHandle};
 use tokio_util::sync::CancellationToken;
 
 /// A running measurement pipeline.
@@ -29,6 +30,8 @@ pub struct MeasurementPipeline {
     _rt_priority: Option<Runtime>,
     control_handle: AnonymousControlHandle,
     metrics: (MetricSender, MetricReader),
+    consumers: Arc<ConsumerResolver>,
+    telemetry: PipelineTelemetry,
     pipeline_control_task: JoinHandle<()>,
     metrics_control_task: JoinHandle<()>,
 }
@@ -42,6 +45,24 @@ pub struct Builder {
     transforms: Vec<(PluginName, Box<dyn transform::builder::TransformBuilder>)>,
     outputs: Vec<(PluginName, output::builder::OutputBuilder)>,
 
+    /// Restrictions on the measurements that the outputs receive.
+    output_routes: Vec<OutputRoute>,
+
+    /// How the outputs handle non-fatal errors.
+    output_retry_policy: output::RetryPolicy,
+
+    /// What to do when some outputs lag behind.
+    output_overflow_policies: Vec<(OutputSelector, output::OverflowPolicy)>,
+
+    /// Links between resources, added to the topology discovered on build.
+    resource_links: Vec<(Resource, Resource)>,
+
+    /// Owners of the resource consumers, shared with the plugins and the transforms.
+    consumers: Arc<ConsumerResolver>,
+
+    /// Counters about the operation of the pipeline, shared with the plugins.
+    telemetry: PipelineTelemetry,
+
     /// Constraints to apply to the TriggerSpec of managed sources.
     trigger_constraints: TriggerConstraints,
 
@@ -64,6 +85,12 @@ impl Builder {
             sources: Vec::new(),
             transforms: Vec::new(),
             outputs: Vec::new(),
+            output_routes: Vec::new(),
+            output_retry_policy: output::RetryPolicy::default(),
+            output_overflow_policies: Vec::new(),
+            resource_links: Vec::new(),
+            consumers: Arc::new(ConsumerResolver::new()),
+            telemetry: PipelineTelemetry::default(),
             trigger_constraints: TriggerConstraints::default(),
             source_channel_size: DEFAULT_CHAN_BUF_SIZE,
             metrics: MetricRegistry::new(),
@@ -88,6 +115,26 @@ impl Builder {
         &mut self.source_channel_size
     }
 
+    /// Returns a mutable reference to the policy that blocking outputs apply when they fail to write
+    /// their measurements with a non-fatal error.
+    ///
+    /// See [`output::RetryPolicy`].
+    pub fn output_retry_policy_mut(&mut self) -> &mut output::RetryPolicy {
+        &mut self.output_retry_policy
+    }
+
+    /// Sets what the selected outputs do when they cannot keep up with the rate of the measurements.
+    ///
+    /// Each output has a queue that can store as many buffers as the [`source_channel_size`](Self::source_channel_size).
+    /// When the queue is full, the [`output::OverflowPolicy`] applies. By default, the oldest measurements are dropped.
+    /// If several policies match an output, the last one wins.
+    ///
+    /// [`OverflowPolicy::Spill`](output::OverflowPolicy::Spill) requires to configure a spill directory in the
+    /// [`output_retry_policy_mut`](Self::output_retry_policy_mut).
+    pub fn set_output_overflow_policy(&mut self, outputs: OutputSelector, policy: output::OverflowPolicy) {
+        self.output_overflow_policies.push((outputs, policy));
+    }
+
     /// Registers a listener that will be notified of the metrics that are created while the pipeline is running,
     /// with a dedicated builder.
     pub fn add_metric_listener_builder(&mut self, plugin: PluginName, builder: Box<dyn MetricListenerBuilder>) {
@@ -113,6 +160,37 @@ impl Builder {
         self.outputs.push((plugin, builder))
     }
 
+    /// Restricts the measurements that are sent to some outputs.
+    ///
+    /// By default, every output receives every measurement.
+    /// See [`OutputRoute`] for more information.
+    pub fn add_output_route(&mut self, route: OutputRoute) {
+        self.output_routes.push(route)
+    }
+
+    /// Adds a link between two resources to the topology that is available to transforms.
+    ///
+    /// The topology of the CPUs and NUMA nodes is discovered automatically, this is useful
+    /// for the other relationships, for instance between a device and its NUMA node.
+    /// See [`Topology`].
+    pub fn add_resource_link(&mut self, parent: Resource, child: Resource) {
+        self.resource_links.push((parent, child))
+    }
+
+    /// Returns the resolver that maps resource consumers to their owners.
+    ///
+    /// Plugins can register the owners of control groups in it, before or while the pipeline is running.
+    pub fn consumer_resolver(&self) -> Arc<ConsumerResolver> {
+        self.consumers.clone()
+    }
+
+    /// Returns an access to the counters that the pipeline keeps about its own operation.
+    ///
+    /// The counters are only updated once the pipeline has been built.
+    pub fn telemetry(&self) -> PipelineTelemetry {
+        self.telemetry.clone()
+    }
+
     /// Sets the number of non-high-priority threads to use.
     ///
     /// # Default
@@ -179,6 +257,9 @@ impl Builder {
         // Token to shutdown the remaining parts of the pipeline, after the elements have been stopped.
         let pipeline_shutdown_finalize = CancellationToken::new();
 
+        // --- Transforms, which can register new metrics ---
+        let transforms = transform::TransformControl::build_transforms(self.transforms, &mut self.metrics)?;
+
         // --- Metric registry (one for the entire pipeline) ---
         // Note: We can modify it without sending a message thanks to MetricAccess::write().
         let mut registry_control = MetricRegistryControl::new(self.metrics);
@@ -201,6 +282,7 @@ impl Builder {
 
         let mut output_control;
         let transform_control;
+        let output_stats = self.telemetry.output_stats();
 
         if self.outputs.is_empty() {
             log::warn!("No output has been registered. A dummy output will be added to make the pipeline work, but you probably want to add a true output.");
@@ -209,14 +291,23 @@ impl Builder {
             self.outputs.push((no_plugin, builder));
         }
 
-        if self.outputs.len() == 1 && self.transforms.is_empty() {
+        if self.outputs.len() == 1 && transforms.is_empty() && self.output_overflow_policies.is_empty() {
             // OPTIMIZATION: there is only one output and no transform,
             // we can connect the inputs directly to the output.
+            // The output applies backpressure to the sources, which is why it is disabled when an overflow policy is set.
             log::info!("Only one output and no transform, using a simplified and optimized measurement pipeline.");
 
             // Outputs
             let out_rx_provider = channel::ReceiverProvider::from(in_rx);
-            output_control = output::OutputControl::new(out_rx_provider, rt_handle.clone(), metrics_r.clone());
+            output_control = output::OutputControl::new(
+                out_rx_provider,
+                self.output_routes,
+                self.output_retry_policy,
+                self.output_overflow_policies,
+                output_stats.clone(),
+                rt_handle.clone(),
+                metrics_r.clone(),
+            );
             output_control
                 .blocking_create_outputs(self.outputs)
                 .context("output creation failed")?;
@@ -224,23 +315,41 @@ impl Builder {
             // No transforms
             transform_control = transform::TransformControl::empty();
         } else {
-            // Broadcast queue: transforms -> outputs
-            let out_tx = broadcast::Sender::<MeasurementBuffer>::new(self.source_channel_size);
+            // Fan-out queues: transforms -> outputs
+            let out_tx = channel::FanoutSender::new(self.source_channel_size);
 
             // Outputs
             let out_rx_provider = channel::ReceiverProvider::from(out_tx.clone());
-            output_control = output::OutputControl::new(out_rx_provider, rt_handle.clone(), metrics_r.clone());
+            output_control = output::OutputControl::new(
+                out_rx_provider,
+                self.output_routes,
+                self.output_retry_policy,
+                self.output_overflow_policies,
+                output_stats.clone(),
+                rt_handle.clone(),
+                metrics_r.clone(),
+            );
             output_control
                 .blocking_create_outputs(self.outputs)
                 .context("output creation failed")?;
 
             // Transforms
+            let mut topology = Topology::from_sysfs().unwrap_or_else(|e| {
+                log::warn!("Could not discover the topology of the machine, it will be incomplete: {e:#}");
+                Topology::new()
+            });
+            for (parent, child) in self.resource_links {
+                topology.add_link(parent, child);
+            }
             transform_control = transform::TransformControl::with_transforms(
-                self.transforms,
+                transforms,
                 metrics_r.clone(),
+                Arc::new(topology),
+                self.consumers.clone(),
                 in_rx,
                 out_tx,
                 rt_handle,
+                &self.telemetry,
             )?;
         };
 
@@ -252,6 +361,7 @@ impl Builder {
             rt_handle.clone(),
             rt_priority.as_ref().unwrap_or(&rt_normal).handle().clone(),
             (metrics_r.clone(), metrics_tx.clone()),
+            self.telemetry.clone(),
         );
         source_control
             .blocking_create_sources(self.sources)
@@ -267,6 +377,8 @@ impl Builder {
             _rt_priority: rt_priority,
             control_handle,
             metrics: (metrics_tx, metrics_r),
+            consumers: self.consumers,
+            telemetry: self.telemetry,
             pipeline_control_task: control_join,
             metrics_control_task: metrics_join,
         })
@@ -320,6 +432,21 @@ impl MeasurementPipeline {
         self.metrics.0.clone()
     }
 
+    /// Returns the resolver that maps resource consumers to their owners.
+    pub fn consumer_resolver(&self) -> Arc<ConsumerResolver> {
+        self.consumers.clone()
+    }
+
+    /// Returns an access to the statistics of the outputs, such as the number of retries.
+    pub fn output_stats(&self) -> output::OutputStatsReader {
+        self.telemetry.output_stats()
+    }
+
+    /// Returns an access to the counters that the pipeline keeps about its own operation.
+    pub fn telemetry(&self) -> PipelineTelemetry {
+        self.telemetry.clone()
+    }
+
     /// Returns a handle to the non-high-priority tokio async runtime.
     ///
     /// This handle can be used to start asynchronous tasks that will be cancelled when
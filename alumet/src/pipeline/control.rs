@@ -1,11 +1,13 @@
 //! On-the-fly modification of the pipeline.
 use super::elements::source::CreateManyMessage;
 use super::elements::{output, source, transform};
+use super::matching::SourceSelector;
 use super::{trigger, PluginName, Source};
 
 use thiserror::Error;
 use tokio::runtime;
 use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
@@ -134,6 +136,21 @@ impl AnonymousControlHandle {
         }
     }
 
+    /// Retrieves the status of the sources that match the selector, including their current poll interval.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pipeline has been shut down.
+    pub async fn inspect_sources(&self, selector: SourceSelector) -> Result<Vec<source::SourceStatus>, ControlError> {
+        let (reply, rx) = oneshot::channel();
+        let message = ControlMessage::Source(source::ControlMessage::Inspect(source::InspectMessage {
+            selector,
+            reply,
+        }));
+        self.send(message).await?;
+        rx.await.map_err(|_| ControlError::Shutdown)
+    }
+
     /// Requests the pipeline to shut down.
     pub fn shutdown(&self) {
         self.shutdown.cancel()
@@ -1,10 +1,13 @@
 //! Implementation and control of output tasks.
 
+use std::collections::VecDeque;
 use std::future::Future;
 use std::ops::ControlFlow;
+use std::path::PathBuf;
 use std::pin::Pin;
-use std::sync::atomic::Ordering;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 
 use anyhow::Context;
 use builder::{
@@ -12,15 +15,18 @@ use builder::{
     OutputBuilder,
 };
 use control_state::SingleOutputController;
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use tokio::runtime;
 use tokio::task::{JoinError, JoinSet};
 
 use crate::measurement::MeasurementBuffer;
 use crate::metrics::MetricRegistry;
-use crate::pipeline::util::channel::{self, RecvError};
+use crate::pipeline::telemetry::as_nanos;
+use crate::pipeline::util::channel::{self, OverflowHandle, RecvError};
 use crate::pipeline::util::matching::OutputSelector;
 use crate::pipeline::util::naming::{NameGenerator, OutputName};
+use crate::pipeline::util::routing::{OutputFilter, OutputRoute};
+use crate::pipeline::util::spill::SpillQueue;
 use crate::pipeline::util::stream::{ControlledStream, SharedStreamState};
 use crate::pipeline::PluginName;
 
@@ -31,12 +37,22 @@ use super::error::WriteError;
 pub trait Output: Send {
     /// Writes the measurements to the output.
     fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError>;
+
+    /// Returns `true` if the output handles the [columnar batches](MeasurementBuffer::columnar) of the buffer.
+    ///
+    /// By default, the batches are converted to rows before `write` is called.
+    fn accepts_columnar(&self) -> bool {
+        false
+    }
 }
 
 /// An asynchronous stream of measurements, to be used by an asynchronous output.
+///
+/// The columnar batches are converted to rows before being sent to the stream.
 pub struct AsyncOutputStream(pub Pin<Box<dyn Stream<Item = Result<MeasurementBuffer, StreamRecvError>> + Send>>); // TODO make opaque?
 
 pub type StreamRecvError = channel::StreamRecvError;
+pub use channel::OverflowPolicy;
 pub type BoxedAsyncOutput = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>;
 
 /// Shared data that can be accessed by outputs.
@@ -44,6 +60,158 @@ pub struct OutputContext<'a> {
     pub metrics: &'a MetricRegistry,
 }
 
+/// How blocking outputs handle non-fatal errors ([`WriteError::CanRetry`]).
+///
+/// The write is retried with an exponential backoff, up to `max_attempts` times.
+/// If it still fails, the measurements are spilled to the disk, if `spill` is set, or dropped.
+/// Spilled measurements are replayed in order once the output recovers.
+///
+/// While a write is waiting to be retried, the output keeps receiving measurements, in order not to
+/// slow down the rest of the pipeline. They are kept in memory, behind the failed write, up to
+/// [`MAX_DELAYED_BUFFERS`] buffers. Beyond that, the failed write is given up (and spilled, if possible),
+/// or the oldest delayed buffer is dropped if there is no spill queue.
+///
+/// The policy does not apply to asynchronous outputs: they receive a stream of measurements and are
+/// responsible for their own retries. Their measurements are never spilled.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts to write a buffer of measurements.
+    pub max_attempts: u32,
+    /// Time to wait after the first failure.
+    pub initial_backoff: Duration,
+    /// Maximum time to wait between two attempts. The backoff doubles after each failure, up to this value.
+    pub max_backoff: Duration,
+    /// Where to store the measurements that could not be written, if anywhere.
+    pub spill: Option<SpillConfig>,
+}
+
+/// Configuration of the on-disk queues that store the measurements that could not be written.
+#[derive(Debug, Clone)]
+pub struct SpillConfig {
+    /// Directory that contains the queues. Each output has its own subdirectory.
+    ///
+    /// The measurements that are still spilled when Alumet stops cannot be replayed by the next run,
+    /// because the metric ids are not stable between two runs. When the pipeline starts, they are
+    /// moved to a `stale-<timestamp>` subdirectory of the queue, and a warning reports how many
+    /// measurement points have been left behind.
+    pub directory: PathBuf,
+    /// Maximum size of the queue of each output, in bytes.
+    ///
+    /// When the queue is full, the new measurements are dropped.
+    pub max_bytes: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            spill: None,
+        }
+    }
+}
+
+/// Statistics about the delivery of measurements to an output.
+///
+/// Unless stated otherwise, the numbers are counted in buffers of measurements, not in individual points.
+/// The retries and the replays only apply to blocking outputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputStats {
+    /// Number of writes that have failed with a non-fatal error.
+    pub retries: u64,
+    /// Number of buffers that have been spilled to the disk, because the output was failing or lagging.
+    pub spilled: u64,
+    /// Number of buffers that have been written to the output after a failure.
+    pub replayed: u64,
+    /// Number of buffers that have been lost, because they could not be written nor spilled,
+    /// or because of the [`OverflowPolicy`] of the output.
+    pub dropped: u64,
+    /// Number of measurement points in the lost buffers.
+    pub dropped_points: u64,
+    /// Number of buffers that are currently in the spill queue of a failing output.
+    pub pending: u64,
+    /// Number of buffers that are waiting in the queue of the output, before being written.
+    ///
+    /// This is only known when the output has its own queue, i.e. when the pipeline has
+    /// several outputs, or some transforms.
+    pub queued: u64,
+    /// Number of calls to [`Output::write`], including the failed ones.
+    pub writes: u64,
+    /// Total time spent in [`Output::write`].
+    pub write_time: Duration,
+}
+
+#[derive(Default)]
+struct OutputCounters {
+    retries: AtomicU64,
+    spilled: AtomicU64,
+    replayed: AtomicU64,
+    dropped: AtomicU64,
+    dropped_points: AtomicU64,
+    pending: AtomicU64,
+    writes: AtomicU64,
+    write_time_ns: AtomicU64,
+    /// The queue that holds the measurements until the output can receive them.
+    overflow: Option<OverflowHandle>,
+}
+
+impl OutputCounters {
+    fn snapshot(&self) -> OutputStats {
+        let mut stats = OutputStats {
+            retries: self.retries.load(Ordering::Relaxed),
+            spilled: self.spilled.load(Ordering::Relaxed),
+            replayed: self.replayed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            dropped_points: self.dropped_points.load(Ordering::Relaxed),
+            pending: self.pending.load(Ordering::Relaxed),
+            queued: 0,
+            writes: self.writes.load(Ordering::Relaxed),
+            write_time: Duration::from_nanos(self.write_time_ns.load(Ordering::Relaxed)),
+        };
+        if let Some(handle) = &self.overflow {
+            let overflow = handle.counters();
+            stats.queued = handle.len() as u64;
+            stats.spilled += overflow.spilled_buffers.load(Ordering::Relaxed);
+            stats.dropped += overflow.dropped_buffers.load(Ordering::Relaxed);
+            stats.dropped_points += overflow.dropped_points.load(Ordering::Relaxed);
+        }
+        stats
+    }
+
+    fn count_dropped(&self, measurements: &MeasurementBuffer) {
+        self.count_dropped_spilled(1, measurements.len());
+    }
+
+    fn count_dropped_spilled(&self, buffers: usize, points: usize) {
+        self.dropped.fetch_add(buffers as u64, Ordering::Relaxed);
+        self.dropped_points.fetch_add(points as u64, Ordering::Relaxed);
+    }
+}
+
+/// Gives access to the [`OutputStats`] of the outputs of a pipeline.
+#[derive(Clone, Default)]
+pub struct OutputStatsReader(Arc<Mutex<Vec<NamedCounters>>>);
+
+type NamedCounters = (OutputName, Arc<OutputCounters>);
+
+impl OutputStatsReader {
+    /// Returns the current statistics of each output.
+    pub fn read(&self) -> Vec<(OutputName, OutputStats)> {
+        let outputs = self.0.lock().unwrap();
+        outputs.iter().map(|(name, c)| (name.clone(), c.snapshot())).collect()
+    }
+
+    fn register(&self, name: OutputName, overflow: Option<OverflowHandle>) -> Arc<OutputCounters> {
+        let counters = Arc::new(OutputCounters {
+            overflow,
+            ..Default::default()
+        });
+        self.0.lock().unwrap().push((name, counters.clone()));
+        counters
+    }
+}
+
 pub(crate) struct OutputControl {
     tasks: TaskManager,
     names: NameGenerator,
@@ -57,6 +225,15 @@ struct TaskManager {
 
     rx_provider: channel::ReceiverProvider,
 
+    /// Restrictions on the measurements that the outputs receive.
+    routes: Vec<OutputRoute>,
+
+    /// How blocking outputs handle non-fatal errors.
+    retry: RetryPolicy,
+    /// What to do when the outputs lag behind. For each output, the last matching policy applies.
+    overflow: Vec<(OutputSelector, OverflowPolicy)>,
+    stats: OutputStatsReader,
+
     /// Handle of the "normal" async runtime. Used for creating new outputs.
     rt_normal: runtime::Handle,
 
@@ -66,6 +243,10 @@ struct TaskManager {
 impl OutputControl {
     pub fn new(
         rx_provider: channel::ReceiverProvider,
+        routes: Vec<OutputRoute>,
+        retry: RetryPolicy,
+        overflow: Vec<(OutputSelector, OverflowPolicy)>,
+        stats: OutputStatsReader,
         rt_normal: runtime::Handle,
         metrics: registry::MetricReader,
     ) -> Self {
@@ -74,6 +255,10 @@ impl OutputControl {
                 spawned_tasks: JoinSet::new(),
                 controllers: Vec::new(),
                 rx_provider,
+                routes,
+                retry,
+                overflow,
+                stats,
                 rt_normal,
                 metrics: metrics.clone(),
             },
@@ -165,6 +350,22 @@ impl TaskManager {
         // Create the necessary context.
         let rx = self.rx_provider.get(); // to receive measurements
         let metrics = self.metrics.clone(); // to read metric definitions
+        let filter = OutputFilter::for_output(&self.routes, &reg.name).map(Arc::new);
+        let overflow = match &rx {
+            channel::ReceiverEnum::Fanout(rx) => self.configure_overflow(rx.overflow_handle(), &reg.name)?,
+            channel::ReceiverEnum::Single(_) => None,
+        };
+
+        // Open the queue that stores the measurements that cannot be written.
+        let spill = match &self.retry.spill {
+            Some(config) => {
+                let dir = config.directory.join(reg.name.to_string().replace('/', "_"));
+                let queue = SpillQueue::open(&dir, config.max_bytes)
+                    .with_context(|| format!("could not open the spill queue of {} in {dir:?}", reg.name))?;
+                Some(queue)
+            }
+            None => None,
+        };
 
         // Create and store the task controller.
         let config = Arc::new(control_state::SharedOutputConfig::new());
@@ -174,16 +375,30 @@ impl TaskManager {
 
         // Put the output in a Mutex to overcome the lack of tokio::spawn_scoped.
         let guarded_output = Arc::new(Mutex::new(reg.output));
+        let delivery = Delivery {
+            counters: self.stats.register(reg.name.clone(), overflow),
+            name: reg.name,
+            output: guarded_output,
+            metrics_r: metrics,
+            filter,
+            policy: self.retry.clone(),
+            spill,
+            next_replay: None,
+            replay_backoff: self.retry.initial_backoff,
+            retry: None,
+            delayed: VecDeque::new(),
+            finishing: false,
+        };
 
         // Spawn the task on the runtime.
         match rx {
             // Specialize on the kind of receiver at compile-time (for performance).
-            channel::ReceiverEnum::Broadcast(rx) => {
-                let task = run_blocking_output(reg.name, guarded_output, rx, metrics, shared_config);
+            channel::ReceiverEnum::Fanout(rx) => {
+                let task = run_blocking_output(delivery, rx, shared_config);
                 self.spawned_tasks.spawn_on(task, &self.rt_normal);
             }
             channel::ReceiverEnum::Single(rx) => {
-                let task = run_blocking_output(reg.name, guarded_output, rx, metrics, shared_config);
+                let task = run_blocking_output(delivery, rx, shared_config);
                 self.spawned_tasks.spawn_on(task, &self.rt_normal);
             }
         }
@@ -203,20 +418,70 @@ impl TaskManager {
         >(
             stream: S,
         ) -> (AsyncOutputStream, Arc<SharedStreamState>) {
+            let stream = stream.map(|item| {
+                item.map(|mut measurements| {
+                    measurements.materialize();
+                    measurements
+                })
+            });
             let stream = Box::pin(ControlledStream::new(stream));
             let state = stream.state();
             (AsyncOutputStream(stream), state)
         }
 
-        // For async outputs, we need to build the stream first
+        /// Filters the measurements of the stream, once the filter is known.
+        fn filter_stream<S: Stream<Item = Result<MeasurementBuffer, channel::StreamRecvError>> + Send + 'static>(
+            stream: S,
+            filter: Arc<OnceLock<Option<OutputFilter>>>,
+            metrics: registry::MetricReader,
+        ) -> impl Stream<Item = Result<MeasurementBuffer, channel::StreamRecvError>> + Send + 'static {
+            stream
+                .then(move |item| {
+                    let filter = filter.clone();
+                    let metrics = metrics.clone();
+                    async move {
+                        match (item, filter.get()) {
+                            (Ok(mut measurements), Some(Some(filter))) => {
+                                // the filter returns rows
+                                measurements.materialize();
+                                Ok(filter.apply(&measurements, &*metrics.read().await))
+                            }
+                            (item, _) => item,
+                        }
+                    }
+                })
+                .filter(|item| std::future::ready(!matches!(item, Ok(measurements) if measurements.is_empty())))
+        }
+
+        // For async outputs, we need to build the stream first.
+        // The name of the output, hence its filter, is only known after it has been built.
         let rx = self.rx_provider.get();
-        let (stream, state) = match rx {
-            channel::ReceiverEnum::Broadcast(receiver) => box_controlled_stream(receiver.into_stream()),
-            channel::ReceiverEnum::Single(receiver) => box_controlled_stream(receiver.into_stream()),
+        let overflow_handle = match &rx {
+            channel::ReceiverEnum::Fanout(receiver) => Some(receiver.overflow_handle()),
+            channel::ReceiverEnum::Single(_) => None,
+        };
+        let filter = Arc::new(OnceLock::new());
+        let (stream, state) = if self.routes.is_empty() {
+            match rx {
+                channel::ReceiverEnum::Fanout(receiver) => box_controlled_stream(receiver.into_stream()),
+                channel::ReceiverEnum::Single(receiver) => box_controlled_stream(receiver.into_stream()),
+            }
+        } else {
+            let stream = match rx {
+                channel::ReceiverEnum::Fanout(receiver) => receiver.into_stream().boxed(),
+                channel::ReceiverEnum::Single(receiver) => receiver.into_stream().boxed(),
+            };
+            box_controlled_stream(filter_stream(stream, filter.clone(), self.metrics.clone()))
         };
 
         // Create the output
         let reg = builder(ctx, stream).context("output creation failed")?;
+        let _ = filter.set(OutputFilter::for_output(&self.routes, &reg.name));
+        let overflow = match overflow_handle {
+            Some(handle) => self.configure_overflow(handle, &reg.name)?,
+            None => None,
+        };
+        self.stats.register(reg.name.clone(), overflow);
 
         // Create and store the task controller
         let control = SingleOutputController::Async(state);
@@ -228,6 +493,33 @@ impl TaskManager {
         Ok(())
     }
 
+    /// Applies the overflow policy of the output to its queue, and gives the handle back.
+    fn configure_overflow(&self, handle: OverflowHandle, name: &OutputName) -> anyhow::Result<Option<OverflowHandle>> {
+        let policy = self
+            .overflow
+            .iter()
+            .rev()
+            .find(|(selector, _)| selector.matches(name))
+            .map(|(_, policy)| *policy)
+            .unwrap_or_default();
+        let spill = if policy == OverflowPolicy::Spill {
+            let config = self.retry.spill.as_ref().with_context(|| {
+                format!("the overflow policy of output {name} is to spill to the disk, but no spill directory has been configured")
+            })?;
+            let dir = config
+                .directory
+                .join(name.to_string().replace('/', "_"))
+                .join("overflow");
+            let queue = SpillQueue::open(&dir, config.max_bytes)
+                .with_context(|| format!("could not open the overflow queue of {name} in {dir:?}"))?;
+            Some(queue)
+        } else {
+            None
+        };
+        handle.set_policy(policy, spill);
+        Ok(Some(handle))
+    }
+
     fn reconfigure(&mut self, msg: ControlMessage) {
         for (name, output_config) in &mut self.controllers {
             if msg.selector.matches(name) {
@@ -289,44 +581,25 @@ impl From<u8> for TaskState {
 }
 
 async fn run_blocking_output<Rx: channel::MeasurementReceiver>(
-    name: OutputName,
-    guarded_output: Arc<Mutex<Box<dyn Output>>>,
+    mut delivery: Delivery,
     mut rx: Rx,
-    metrics_reader: registry::MetricReader,
     config: Arc<control_state::SharedOutputConfig>,
 ) -> anyhow::Result<()> {
-    /// If `measurements` is an `Ok`, build an [`OutputContext`] and call `output.write(&measurements, &ctx)`.
+    /// If `measurements` is an `Ok`, write them to the output with [`Delivery::deliver`].
     /// Otherwise, handle the error.
     async fn write_measurements(
-        name: &OutputName,
-        output: Arc<Mutex<Box<dyn Output>>>,
-        metrics_r: registry::MetricReader,
+        delivery: &mut Delivery,
         maybe_measurements: Result<MeasurementBuffer, channel::RecvError>,
     ) -> anyhow::Result<ControlFlow<()>> {
+        let name = &delivery.name;
         match maybe_measurements {
             Ok(measurements) => {
                 log::trace!("writing {} measurements to {name}", measurements.len());
-                let res = tokio::task::spawn_blocking(move || {
-                    let ctx = OutputContext {
-                        metrics: &metrics_r.blocking_read(),
-                    };
-                    output.lock().unwrap().write(&measurements, &ctx)
-                })
-                .await?;
-                match res {
-                    Ok(()) => Ok(ControlFlow::Continue(())),
-                    Err(WriteError::CanRetry(e)) => {
-                        log::error!("Non-fatal error when writing to {name} (will retry): {e:#}");
-                        Ok(ControlFlow::Continue(()))
-                    }
-                    Err(WriteError::Fatal(e)) => {
-                        log::error!("Fatal error when writing to {name} (will stop running): {e:?}");
-                        Err(e.context(format!("fatal error when writing to {name}")))
-                    }
-                }
+                delivery.deliver(measurements).await?;
+                Ok(ControlFlow::Continue(()))
             }
             Err(channel::RecvError::Lagged(n)) => {
-                log::warn!("Output {name} is too slow, it lost the oldest {n} messages.");
+                log::warn!("Output {name} is too slow, {n} buffers of measurements have been dropped.");
                 Ok(ControlFlow::Continue(()))
             }
             Err(channel::RecvError::Closed) => {
@@ -340,6 +613,8 @@ async fn run_blocking_output<Rx: channel::MeasurementReceiver>(
     let mut receive = true;
     let mut finish = false;
     loop {
+        let next_replay = delivery.next_replay;
+        let next_retry = delivery.retry.as_ref().map(|r| r.at);
         tokio::select! {
             _ = config_change.notified() => {
                 let new_state = config.atomic_state.load(Ordering::Relaxed);
@@ -351,6 +626,7 @@ async fn run_blocking_output<Rx: channel::MeasurementReceiver>(
                         receive = false;
                     }
                     TaskState::StopNow => {
+                        delivery.abandon_retry();
                         break; // stop the output and ignore the remaining data
                     }
                     TaskState::StopFinish => {
@@ -359,20 +635,31 @@ async fn run_blocking_output<Rx: channel::MeasurementReceiver>(
                     }
                 }
             },
+            // While a failed write waits to be retried, the new measurements are delayed (see Delivery::deliver).
             measurements = rx.recv(), if receive => {
-                let res = write_measurements(&name, guarded_output.clone(), metrics_reader.clone(), measurements).await?;
+                let res = write_measurements(&mut delivery, measurements).await?;
                 if res.is_break() {
-                    finish = false; // just in case
+                    finish = true; // the channel is closed, but the spill queue may not be empty
                     break
                 }
             }
+            _ = tokio::time::sleep_until(next_retry.unwrap_or_else(tokio::time::Instant::now)), if receive && next_retry.is_some() => {
+                delivery.retry_pending().await?;
+            }
+            _ = tokio::time::sleep_until(next_replay.unwrap_or_else(tokio::time::Instant::now)), if receive && next_replay.is_some() && next_retry.is_none() => {
+                // Try to write the measurements that have been spilled while the output was failing.
+                delivery.replay().await?;
+            }
         }
     }
 
     if finish {
         // Write the last measurements, ignore any lag (the latter is done in write_measurements).
         // This is useful when Alumet is stopped, to ensure that we don't discard any data.
+        // Failed writes are not retried anymore, in order not to delay the shutdown.
+        delivery.start_finishing().await?;
         loop {
+            let name = &delivery.name;
             log::trace!("{name} finishing...");
             let received = rx.recv().await;
             log::trace!(
@@ -383,16 +670,346 @@ async fn run_blocking_output<Rx: channel::MeasurementReceiver>(
                     Err(RecvError::Lagged(n)) => format!("Err(Lagged({n}))"),
                 }
             );
-            let res = write_measurements(&name, guarded_output.clone(), metrics_reader.clone(), received).await?;
+            let res = write_measurements(&mut delivery, received).await?;
             if res.is_break() {
                 break;
             }
         }
+        delivery.finish().await?;
     }
 
     Ok(())
 }
 
+/// Maximum number of buffers that a blocking output keeps in memory while a failed write waits to be retried.
+pub const MAX_DELAYED_BUFFERS: usize = 64;
+
+/// Writes measurements to a blocking output, with retries and spilling.
+///
+/// When the output returns a [`WriteError::CanRetry`], the write is retried with an exponential backoff,
+/// according to the [`RetryPolicy`]. If the output keeps failing, the measurements are spilled to a bounded
+/// on-disk queue (if enabled), and the next measurements are appended to this queue, in order not to wait
+/// for the output. The queue is replayed, in order, once the output recovers.
+struct Delivery {
+    name: OutputName,
+    output: Arc<Mutex<Box<dyn Output>>>,
+    metrics_r: registry::MetricReader,
+    filter: Option<Arc<OutputFilter>>,
+    policy: RetryPolicy,
+    spill: Option<SpillQueue>,
+    counters: Arc<OutputCounters>,
+    /// When to try to replay the spill queue, if it is not empty.
+    next_replay: Option<tokio::time::Instant>,
+    replay_backoff: Duration,
+    /// The failed write that will be retried, if any.
+    /// The backoff is awaited by the output task, in order to keep handling the control messages.
+    retry: Option<PendingRetry>,
+    /// The measurements received while `retry` is pending, to write after it.
+    delayed: VecDeque<MeasurementBuffer>,
+    /// If true, failed writes are not retried: the output is stopping.
+    finishing: bool,
+}
+
+struct PendingRetry {
+    measurements: MeasurementBuffer,
+    attempt: u32,
+    backoff: Duration,
+    at: tokio::time::Instant,
+}
+
+impl Delivery {
+    /// Writes the measurements to the output, or spills them if the output is failing.
+    ///
+    /// If the output has a filter, only the measurements that it accepts are written.
+    async fn deliver(&mut self, mut measurements: MeasurementBuffer) -> anyhow::Result<()> {
+        let measurements = match &self.filter {
+            Some(filter) => {
+                measurements.materialize();
+                filter.apply(&measurements, &*self.metrics_r.read().await)
+            }
+            None => measurements,
+        };
+        if measurements.is_empty() {
+            return Ok(());
+        }
+        self.deliver_filtered(measurements).await
+    }
+
+    /// Writes measurements that have already been filtered.
+    async fn deliver_filtered(&mut self, measurements: MeasurementBuffer) -> anyhow::Result<()> {
+        if self.retry.is_some() {
+            // A failed write is waiting to be retried: keep the order by writing the measurements after it.
+            self.delay(measurements);
+            return Ok(());
+        }
+
+        if self.spill.as_ref().is_some_and(|q| !q.is_empty()) {
+            // The output is failing: keep the order of the measurements by writing them after the spilled ones.
+            self.spill_or_drop(measurements);
+            if self.next_replay.is_some_and(|t| t <= tokio::time::Instant::now()) {
+                self.replay().await?;
+            }
+            return Ok(());
+        }
+
+        self.attempt(measurements, 1, self.policy.initial_backoff).await
+    }
+
+    /// Writes the measurements to the output. If the write fails, schedules a retry
+    /// (see [`retry_pending`](Self::retry_pending)), or spills the measurements after the last attempt.
+    async fn attempt(
+        &mut self,
+        measurements: MeasurementBuffer,
+        attempt: u32,
+        backoff: Duration,
+    ) -> anyhow::Result<()> {
+        let (measurements, res) = self.write(measurements).await?;
+        match res {
+            Ok(()) => Ok(()),
+            Err(WriteError::Fatal(e)) => Err(self.fatal(e)),
+            Err(WriteError::CanRetry(e)) => {
+                self.counters.retries.fetch_add(1, Ordering::Relaxed);
+                if attempt >= self.policy.max_attempts || self.finishing {
+                    log::error!(
+                        "Non-fatal error when writing to {} (attempt {attempt}, giving up): {e:#}",
+                        self.name
+                    );
+                    self.spill_or_drop(measurements);
+                    self.replay_backoff = self.policy.initial_backoff;
+                    self.schedule_replay();
+                } else {
+                    log::warn!(
+                        "Non-fatal error when writing to {} (attempt {attempt}, will retry in {backoff:?}): {e:#}",
+                        self.name
+                    );
+                    self.retry = Some(PendingRetry {
+                        measurements,
+                        attempt,
+                        backoff,
+                        at: tokio::time::Instant::now() + backoff,
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Retries the failed write, once its backoff has elapsed.
+    async fn retry_pending(&mut self) -> anyhow::Result<()> {
+        let Some(retry) = self.retry.take() else {
+            return Ok(());
+        };
+        let backoff = (retry.backoff * 2).min(self.policy.max_backoff);
+        self.attempt(retry.measurements, retry.attempt + 1, backoff).await?;
+        // Write the measurements that have been received in the meantime, until the output fails again.
+        while self.retry.is_none() {
+            let Some(measurements) = self.delayed.pop_front() else {
+                break;
+            };
+            self.deliver_filtered(measurements).await?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the measurements in memory until the pending retry is done.
+    fn delay(&mut self, measurements: MeasurementBuffer) {
+        self.delayed.push_back(measurements);
+        if self.delayed.len() <= MAX_DELAYED_BUFFERS {
+            return;
+        }
+        if self.spill.is_some() {
+            // Free the memory without changing the order: give up the failed write and spill everything.
+            log::warn!(
+                "Output {} is still failing and too many measurements are waiting, spilling them now.",
+                self.name
+            );
+            self.spill_delayed();
+            self.replay_backoff = self.policy.initial_backoff;
+            self.schedule_replay();
+        } else if let Some(oldest) = self.delayed.pop_front() {
+            log::error!(
+                "Output {} is still failing and too many measurements are waiting, dropping {} of them.",
+                self.name,
+                oldest.len()
+            );
+            self.counters.count_dropped(&oldest);
+        }
+    }
+
+    /// Spills (or drops) the failed write and the measurements that are waiting behind it, in order.
+    fn spill_delayed(&mut self) {
+        if let Some(retry) = self.retry.take() {
+            self.spill_or_drop(retry.measurements);
+        }
+        while let Some(measurements) = self.delayed.pop_front() {
+            self.spill_or_drop(measurements);
+        }
+    }
+
+    /// Stops retrying: the failed write is attempted one last time, without waiting for its backoff.
+    async fn start_finishing(&mut self) -> anyhow::Result<()> {
+        self.finishing = true;
+        self.retry_pending().await
+    }
+
+    /// Gives up the failed write, if any, without trying again.
+    fn abandon_retry(&mut self) {
+        if self.retry.is_some() {
+            log::warn!("Output {} is stopping, a failed write will not be retried.", self.name);
+            self.spill_delayed();
+        }
+    }
+
+    /// Writes the oldest spilled measurements to the output, until the spill queue is empty
+    /// or the output fails again.
+    async fn replay(&mut self) -> anyhow::Result<()> {
+        let Some(mut queue) = self.spill.take() else {
+            return Ok(());
+        };
+        let res = self.replay_queue(&mut queue).await;
+        self.spill = Some(queue);
+        res
+    }
+
+    async fn replay_queue(&mut self, queue: &mut SpillQueue) -> anyhow::Result<()> {
+        let mut replayed = 0;
+        while !queue.is_empty() {
+            let measurements = match queue.front() {
+                Ok(m) => m.expect("the queue should not be empty"),
+                Err(e) => {
+                    log::error!(
+                        "Could not read the spill queue of {}, dropping a buffer: {e}",
+                        self.name
+                    );
+                    self.counters.count_dropped_spilled(1, queue.front_points());
+                    self.pop_spilled(queue);
+                    continue;
+                }
+            };
+            let (_, res) = self.write(measurements).await?;
+            match res {
+                Ok(()) => {
+                    replayed += 1;
+                    self.counters.replayed.fetch_add(1, Ordering::Relaxed);
+                    self.pop_spilled(queue);
+                }
+                Err(WriteError::Fatal(e)) => return Err(self.fatal(e)),
+                Err(WriteError::CanRetry(e)) => {
+                    self.counters.retries.fetch_add(1, Ordering::Relaxed);
+                    self.replay_backoff = (self.replay_backoff * 2).min(self.policy.max_backoff);
+                    log::warn!(
+                        "Output {} is still failing, {} spilled buffers will be retried in {:?}: {e:#}",
+                        self.name,
+                        queue.len(),
+                        self.replay_backoff
+                    );
+                    self.schedule_replay();
+                    return Ok(());
+                }
+            }
+        }
+        if replayed > 0 {
+            log::info!(
+                "Output {} has recovered, {replayed} spilled buffers have been written.",
+                self.name
+            );
+        }
+        self.next_replay = None;
+        Ok(())
+    }
+
+    /// Makes a last attempt to write the spilled measurements, before the output stops.
+    async fn finish(&mut self) -> anyhow::Result<()> {
+        if self.spill.as_ref().is_some_and(|q| !q.is_empty()) {
+            self.replay().await?;
+        }
+        if let Some(queue) = self.spill.as_ref().filter(|q| !q.is_empty()) {
+            log::error!(
+                "Output {} is stopping, {} spilled buffers could not be written and are lost.",
+                self.name,
+                queue.len()
+            );
+            self.counters.count_dropped_spilled(queue.len(), queue.total_points());
+            self.counters.pending.store(0, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Calls `output.write(&measurements, &ctx)` on a thread that is allowed to block,
+    /// and gives back the measurements.
+    async fn write(
+        &self,
+        measurements: MeasurementBuffer,
+    ) -> anyhow::Result<(MeasurementBuffer, Result<(), WriteError>)> {
+        let output = self.output.clone();
+        let metrics_r = self.metrics_r.clone();
+        let counters = self.counters.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            let mut measurements = measurements;
+            let metrics = metrics_r.blocking_read();
+            let ctx = OutputContext { metrics: &metrics };
+            let mut output = output.lock().unwrap();
+            if !output.accepts_columnar() {
+                measurements.materialize();
+            }
+            let start = std::time::Instant::now();
+            let res = output.write(&measurements, &ctx);
+            counters.writes.fetch_add(1, Ordering::Relaxed);
+            counters
+                .write_time_ns
+                .fetch_add(as_nanos(start.elapsed()), Ordering::Relaxed);
+            (measurements, res)
+        })
+        .await?;
+        Ok(res)
+    }
+
+    fn spill_or_drop(&mut self, mut measurements: MeasurementBuffer) {
+        let Some(queue) = &mut self.spill else {
+            log::error!(
+                "Dropping {} measurements that could not be written to {}.",
+                measurements.len(),
+                self.name
+            );
+            self.counters.count_dropped(&measurements);
+            return;
+        };
+        measurements.materialize();
+        match queue.push(&measurements) {
+            Ok(()) => {
+                self.counters.spilled.fetch_add(1, Ordering::Relaxed);
+                self.counters.pending.store(queue.len() as u64, Ordering::Relaxed);
+            }
+            Err(e) => {
+                log::error!(
+                    "Dropping {} measurements that could not be written to {}: {e}",
+                    measurements.len(),
+                    self.name
+                );
+                self.counters.count_dropped(&measurements);
+            }
+        }
+    }
+
+    fn pop_spilled(&self, queue: &mut SpillQueue) {
+        if let Err(e) = queue.pop_front() {
+            log::warn!("Could not delete a file of the spill queue in {:?}: {e}", queue.dir());
+        }
+        self.counters.pending.store(queue.len() as u64, Ordering::Relaxed);
+    }
+
+    fn schedule_replay(&mut self) {
+        if self.spill.is_some() {
+            self.next_replay = Some(tokio::time::Instant::now() + self.replay_backoff);
+        }
+    }
+
+    fn fatal(&self, e: anyhow::Error) -> anyhow::Error {
+        log::error!("Fatal error when writing to {} (will stop running): {e:?}", self.name);
+        e.context(format!("fatal error when writing to {}", self.name))
+    }
+}
+
 async fn run_async_output(name: OutputName, output: BoxedAsyncOutput) -> anyhow::Result<()> {
     output.await.map_err(|e| {
         log::error!("Error when asynchronously writing to {name} (will stop running): {e:?}");
@@ -5,18 +5,21 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use builder::BuildContext;
 use tokio::runtime;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::oneshot;
 use tokio::task::{JoinError, JoinSet};
 use tokio_util::sync::CancellationToken;
 
 use super::error::PollError;
 use crate::measurement::{MeasurementAccumulator, MeasurementBuffer, Timestamp};
 use crate::pipeline::registry;
+use crate::pipeline::telemetry::{PipelineTelemetry, SourceCounters};
 use crate::pipeline::trigger::{Trigger, TriggerConstraints, TriggerReason, TriggerSpec};
 use crate::pipeline::util::matching::SourceSelector;
 use crate::pipeline::util::naming::{NameGenerator, PluginName, SourceName};
@@ -65,6 +68,9 @@ struct TaskManager {
 
     /// Handle of the "priority" async runtime. Used for creating new sources.
     rt_priority: runtime::Handle,
+
+    /// Counters of the managed sources, for self-monitoring.
+    telemetry: PipelineTelemetry,
 }
 
 impl SourceControl {
@@ -75,7 +81,9 @@ impl SourceControl {
         rt_normal: runtime::Handle,
         rt_priority: runtime::Handle,
         metrics: (registry::MetricReader, registry::MetricSender),
+        telemetry: PipelineTelemetry,
     ) -> Self {
+        telemetry.set_source_channel(&in_tx);
         Self {
             tasks: TaskManager {
                 spawned_tasks: JoinSet::new(),
@@ -85,6 +93,7 @@ impl SourceControl {
                 in_tx,
                 rt_normal,
                 rt_priority,
+                telemetry,
             },
             names: NameGenerator::new(),
             metrics,
@@ -152,6 +161,7 @@ impl SourceControl {
             ControlMessage::CreateOne(msg) => self.create_sources(msg.plugin, vec![msg.builder]).await?,
             ControlMessage::CreateMany(msg) => self.create_sources(msg.plugin, msg.builders).await?,
             ControlMessage::TriggerManually(msg) => self.tasks.trigger_manually(msg),
+            ControlMessage::Inspect(msg) => self.tasks.inspect(msg),
         }
         Ok(())
     }
@@ -231,7 +241,8 @@ impl TaskManager {
                 log::trace!("new controller initialized");
 
                 // Create the future (async task).
-                let source_task = run_managed(reg.name, reg.source, self.in_tx.clone(), config);
+                let counters = self.telemetry.register_source(reg.name.clone());
+                let source_task = run_managed(reg.name, reg.source, self.in_tx.clone(), config, counters);
                 log::trace!("source task created");
 
                 // Spawn the future (execute the async task on the thread pool)
@@ -287,6 +298,20 @@ impl TaskManager {
         }
         log::trace!("TriggerMessage matched {matches} sources.");
     }
+
+    fn inspect(&self, msg: InspectMessage) {
+        let statuses = self
+            .controllers
+            .iter()
+            .filter(|(name, _)| msg.selector.matches(name))
+            .map(|(name, source_controller)| SourceStatus {
+                name: name.clone(),
+                poll_interval: source_controller.poll_interval(),
+            })
+            .collect();
+        // the requester may have given up, ignore the error
+        let _ = msg.reply.send(statuses);
+    }
 }
 
 pub mod builder {
@@ -524,6 +549,10 @@ pub enum ControlMessage {
     /// and processes it. Sources must be configured to accept manual trigger, otherwise this message
     /// will do nothing.
     TriggerManually(TriggerMessage),
+    /// Retrieves the status of some source(s).
+    ///
+    /// See [`crate::pipeline::control::AnonymousControlHandle::inspect_sources`] for a high-level API.
+    Inspect(InspectMessage),
 }
 
 #[derive(Debug)]
@@ -551,6 +580,25 @@ pub struct TriggerMessage {
     pub selector: SourceSelector,
 }
 
+#[derive(Debug)]
+pub struct InspectMessage {
+    /// Which source(s) to inspect.
+    pub selector: SourceSelector,
+    /// Receives the status of the selected sources.
+    pub reply: oneshot::Sender<Vec<SourceStatus>>,
+}
+
+/// The status of a source, as returned by [`ControlMessage::Inspect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceStatus {
+    pub name: SourceName,
+    /// The interval between two polls that is currently in effect.
+    ///
+    /// This is `None` for autonomous sources and for the managed sources that are not triggered
+    /// by a time interval. The interval of an adaptive trigger changes over time.
+    pub poll_interval: Option<Duration>,
+}
+
 /// A command to send to a managed [`Source`].
 #[derive(Debug)]
 pub enum ConfigureCommand {
@@ -589,9 +637,10 @@ impl From<u8> for TaskState {
 
 mod task_controller {
     use std::sync::{
-        atomic::{AtomicU8, Ordering},
+        atomic::{AtomicU64, AtomicU8, Ordering},
         Arc, Mutex,
     };
+    use std::time::Duration;
 
     use tokio::sync::Notify;
     use tokio_util::sync::CancellationToken;
@@ -621,6 +670,16 @@ mod task_controller {
         pub atomic_state: AtomicU8,
         pub new_trigger: Mutex<Option<Trigger>>,
         pub manual_trigger: Option<ManualTrigger>,
+        /// Poll interval currently in effect, in nanoseconds, or zero if none.
+        pub poll_interval: AtomicU64,
+    }
+
+    impl SharedSourceConfig {
+        /// Publishes the interval of the current trigger, so that it can be inspected by the control loop.
+        pub fn set_poll_interval(&self, interval: Option<Duration>) {
+            let nanos = interval.map_or(0, |d| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX));
+            self.poll_interval.store(nanos, Ordering::Relaxed);
+        }
     }
 
     pub fn new_managed(initial_trigger: Trigger) -> (SingleSourceController, Arc<SharedSourceConfig>) {
@@ -628,6 +687,7 @@ mod task_controller {
         let config = Arc::new(SharedSourceConfig {
             change_notifier: Notify::new(),
             atomic_state: AtomicU8::new(TaskState::Run as u8),
+            poll_interval: AtomicU64::new(0),
             new_trigger: Mutex::new(Some(initial_trigger)),
             manual_trigger,
         });
@@ -663,6 +723,16 @@ mod task_controller {
             }
         }
 
+        pub fn poll_interval(&self) -> Option<Duration> {
+            match self {
+                SingleSourceController::Managed(shared) => {
+                    let nanos = shared.poll_interval.load(Ordering::Relaxed);
+                    (nanos != 0).then(|| Duration::from_nanos(nanos))
+                }
+                SingleSourceController::Autonomous(_) => None,
+            }
+        }
+
         pub fn trigger_now(&mut self) {
             match self {
                 SingleSourceController::Managed(shared) => {
@@ -681,6 +751,7 @@ pub(crate) async fn run_managed(
     mut source: Box<dyn Source>,
     tx: mpsc::Sender<MeasurementBuffer>,
     config: Arc<task_controller::SharedSourceConfig>,
+    counters: Arc<SourceCounters>,
 ) -> anyhow::Result<()> {
     /// Flushes the measurement and returns a new buffer.
     fn flush(buffer: MeasurementBuffer, tx: &mpsc::Sender<MeasurementBuffer>, name: &SourceName) -> MeasurementBuffer {
@@ -727,6 +798,7 @@ pub(crate) async fn run_managed(
         .unwrap()
         .take()
         .expect("the Trigger must be set before starting the source");
+    config.set_poll_interval(trigger.interval);
     log::trace!("source {source_name} got initial config");
 
     // Store measurements in this buffer, and replace it every `flush_rounds` rounds.
@@ -737,12 +809,16 @@ pub(crate) async fn run_managed(
     // by the control loop.
     let config_change = &config.change_notifier;
 
+    // Time of the previous poll, to measure the jitter of the trigger.
+    let mut last_poll: Option<Instant> = None;
+
     // main loop
     let mut i = 1usize;
     'run: loop {
-        // Wait for the trigger. It can return for two reasons:
+        // Wait for the trigger. It can return for three reasons:
         // - "normal case": the underlying mechanism (e.g. timer) triggers <- this is the most likely case
         // - "interrupt case": the underlying mechanism was idle (e.g. sleeping) but a new command arrived
+        // - "finished case": the underlying mechanism will never trigger again (e.g. end of stream)
         let reason = trigger
             .next(config_change)
             .await
@@ -751,9 +827,30 @@ pub(crate) async fn run_managed(
         let mut update;
         match reason {
             TriggerReason::Triggered => {
+                // measure the jitter, i.e. how far we are from the expected polling interval
+                let poll_start = Instant::now();
+                if let (Some(prev), Some(interval)) = (last_poll, trigger.interval) {
+                    counters.record_interval(poll_start - prev, interval);
+                }
+                last_poll = Some(poll_start);
+
                 // poll the source
                 let timestamp = Timestamp::now();
-                match source.poll(&mut buffer.as_accumulator(), timestamp) {
+                let prev_length = buffer.len();
+                let (prev_rows, prev_batches) = (buffer.rows().len(), buffer.columnar().len());
+                let res = source.poll(&mut buffer.as_accumulator(), timestamp);
+                counters.record_poll(poll_start.elapsed(), buffer.len().saturating_sub(prev_length));
+                let rows = buffer.rows()[prev_rows..]
+                    .iter()
+                    .map(|p| (p.metric, &p.resource, &p.consumer, &p.value));
+                let batches = buffer.columnar()[prev_batches..]
+                    .iter()
+                    .flat_map(|c| c.iter().map(|p| (p.metric, p.resource, p.consumer, p.value)));
+                trigger.observe(rows.chain(batches));
+                if let Err(e) = &res {
+                    counters.count_error(e);
+                }
+                match res {
                     Ok(()) => (),
                     Err(PollError::NormalStop) => {
                         log::info!("Source {source_name} stopped itself.");
@@ -778,20 +875,39 @@ pub(crate) async fn run_managed(
                 // only update on some rounds, for performance reasons.
                 update = (i % trigger.config.update_rounds) == 0;
                 i = i.wrapping_add(1);
+
+                // adaptive triggers change their interval, and their rounds, on update rounds
+                if update {
+                    let prev_flush_rounds = trigger.config.flush_rounds;
+                    if trigger.adapt() {
+                        log::debug!(
+                            "{source_name} now polls every {:?}",
+                            trigger.interval.unwrap_or_default()
+                        );
+                        config.set_poll_interval(trigger.interval);
+                        adapt_buffer_after_trigger_change(&mut buffer, prev_flush_rounds, trigger.config.flush_rounds);
+                    }
+                }
             }
             TriggerReason::Interrupted => {
                 // interrupted because of a new command, forcibly update the command (see below)
                 update = true;
             }
+            TriggerReason::Finished => {
+                log::info!("The trigger of source {source_name} has finished, the source will stop.");
+                break 'run; // stop polling
+            }
         };
 
         while update {
             let new_state = config.atomic_state.load(Ordering::Relaxed);
             let new_trigger = config.new_trigger.lock().unwrap().take();
             if let Some(t) = new_trigger {
+                last_poll = None; // the new trigger has its own timing
                 let prev_flush_rounds = trigger.config.flush_rounds;
                 let new_flush_rounds = t.config.flush_rounds;
                 trigger = t;
+                config.set_poll_interval(trigger.interval);
                 adapt_buffer_after_trigger_change(&mut buffer, prev_flush_rounds, new_flush_rounds);
             }
             match new_state.into() {
@@ -800,6 +916,7 @@ pub(crate) async fn run_managed(
                 }
                 TaskState::Pause => {
                     config_change.notified().await; // wait for the config to change
+                    last_poll = None; // the pause is not jitter
                 }
                 TaskState::Stop => {
                     break 'run; // stop polling
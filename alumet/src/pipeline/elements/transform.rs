@@ -2,20 +2,20 @@
 
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::Context;
 use builder::BuildContext;
 use tokio::task::JoinError;
-use tokio::{
-    runtime,
-    sync::{broadcast, mpsc},
-    task::JoinHandle,
-};
+use tokio::{runtime, sync::mpsc, task::JoinHandle};
 
 use super::error::TransformError;
+use crate::pipeline::telemetry::{PipelineTelemetry, TransformCounters};
+use crate::pipeline::util::channel::FanoutSender;
 use crate::pipeline::util::matching::TransformSelector;
 use crate::pipeline::util::naming::{NameGenerator, TransformName};
 use crate::pipeline::PluginName;
+use crate::resources::{consumers::ConsumerResolver, topology::Topology};
 use crate::{measurement::MeasurementBuffer, metrics::MetricRegistry, pipeline::registry::MetricReader};
 
 /// Transforms measurements (arbitrary transformation).
@@ -32,11 +32,22 @@ pub trait Transform: Send {
     /// - add new measurements
     /// - modify the measurement points
     fn apply(&mut self, measurements: &mut MeasurementBuffer, ctx: &TransformContext) -> Result<(), TransformError>;
+
+    /// Returns `true` if the transform handles the [columnar batches](MeasurementBuffer::columnar) of the buffer.
+    ///
+    /// By default, the batches are converted to rows before `apply` is called.
+    fn accepts_columnar(&self) -> bool {
+        false
+    }
 }
 
 /// Shared data that can be accessed by transforms.
 pub struct TransformContext<'a> {
     pub metrics: &'a MetricRegistry,
+    /// Links between the resources of the machine, for instance between CPU cores and packages.
+    pub topology: &'a Topology,
+    /// Owners of the resource consumers, for instance the cgroup of a process, or the pod of a cgroup.
+    pub consumers: &'a ConsumerResolver,
 }
 
 /// Controls the transforms of a measurement pipeline.
@@ -57,30 +68,49 @@ impl TransformControl {
         Self { tasks: None }
     }
 
-    pub fn with_transforms(
+    /// Builds the transforms, in order.
+    ///
+    /// This happens before the [`MetricRegistry`] is shared with the rest of the pipeline,
+    /// so that the transforms can register the metrics they produce.
+    pub fn build_transforms(
         transforms: Vec<(PluginName, Box<dyn builder::TransformBuilder>)>,
+        metrics: &mut MetricRegistry,
+    ) -> anyhow::Result<Vec<builder::TransformRegistration>> {
+        let mut namegen = NameGenerator::new();
+        transforms
+            .into_iter()
+            .map(|(plugin, builder)| {
+                let mut ctx = BuildContext {
+                    metrics: &mut *metrics,
+                    namegen: namegen.plugin_namespace(&plugin),
+                };
+                builder(&mut ctx)
+                    .context("transform creation failed")
+                    .inspect_err(|e| log::error!("Error in transform creation requested by plugin {plugin}: {e:#}"))
+            })
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_transforms(
+        transforms: Vec<builder::TransformRegistration>,
         metrics: MetricReader,
+        topology: Arc<Topology>,
+        consumers: Arc<ConsumerResolver>,
         rx: mpsc::Receiver<MeasurementBuffer>,
-        tx: broadcast::Sender<MeasurementBuffer>,
+        tx: FanoutSender,
         rt_normal: &runtime::Handle,
+        telemetry: &PipelineTelemetry,
     ) -> anyhow::Result<Self> {
-        let built: anyhow::Result<Vec<builder::TransformRegistration>> = {
-            let metrics_r = metrics.blocking_read();
-            let mut namegen = NameGenerator::new();
-            transforms
-                .into_iter()
-                .map(|(plugin, builder)| {
-                    let mut ctx = BuildContext {
-                        metrics: &metrics_r,
-                        namegen: namegen.plugin_namespace(&plugin),
-                    };
-                    builder(&mut ctx)
-                        .context("transform creation failed")
-                        .inspect_err(|e| log::error!("Error in transform creation requested by plugin {plugin}: {e:#}"))
-                })
-                .collect()
-        };
-        let tasks = TaskManager::spawn(built?, metrics.clone(), rx, tx, rt_normal);
+        // Count the time spent in each transform, for self-monitoring.
+        let transforms = transforms
+            .into_iter()
+            .map(|reg| {
+                let counters = telemetry.register_transform(reg.name.clone());
+                (reg, counters)
+            })
+            .collect();
+        let tasks = TaskManager::spawn(transforms, metrics.clone(), topology, consumers, rx, tx, rt_normal);
         Ok(Self { tasks: Some(tasks) })
     }
 
@@ -120,23 +150,33 @@ impl TransformControl {
 
 impl TaskManager {
     pub fn spawn(
-        transforms: Vec<builder::TransformRegistration>,
+        transforms: Vec<(builder::TransformRegistration, Arc<TransformCounters>)>,
         metrics_r: MetricReader,
+        topology: Arc<Topology>,
+        consumers: Arc<ConsumerResolver>,
         rx: mpsc::Receiver<MeasurementBuffer>,
-        tx: broadcast::Sender<MeasurementBuffer>,
+        tx: FanoutSender,
         rt_normal: &runtime::Handle,
     ) -> Self {
         let mut active_bitset: u64 = 0;
         let mut names_by_bitset_position = Vec::with_capacity(transforms.len());
 
-        for (i, reg) in transforms.iter().enumerate() {
+        for (i, (reg, _)) in transforms.iter().enumerate() {
             active_bitset |= 1 << i;
             names_by_bitset_position.push(reg.name.clone());
         }
 
         // Start the transforms task.
         let active_bitset = Arc::new(AtomicU64::new(active_bitset));
-        let task = run_all_in_order(transforms, rx, tx, active_bitset.clone(), metrics_r);
+        let task = run_all_in_order(
+            transforms,
+            rx,
+            tx,
+            active_bitset.clone(),
+            metrics_r,
+            topology,
+            consumers,
+        );
         let task_handle = rt_normal.spawn(task);
         Self {
             task_handle,
@@ -165,7 +205,7 @@ impl TaskManager {
 
 pub mod builder {
     use crate::{
-        metrics::{Metric, MetricRegistry, RawMetricId},
+        metrics::{Metric, MetricCreationError, MetricRegistry, RawMetricId},
         pipeline::util::naming::{PluginElementNamespace, TransformName},
     };
 
@@ -201,7 +241,7 @@ pub mod builder {
     }
 
     pub(super) struct BuildContext<'a> {
-        pub(super) metrics: &'a MetricRegistry,
+        pub(super) metrics: &'a mut MetricRegistry,
         pub(super) namegen: &'a mut PluginElementNamespace,
     }
 
@@ -209,6 +249,15 @@ pub mod builder {
     pub trait TransformBuildContext {
         /// Retrieves a metric by its name.
         fn metric_by_name(&self, name: &str) -> Option<(RawMetricId, &Metric)>;
+        /// Returns the metrics that have been registered so far.
+        fn metrics(&self) -> &MetricRegistry;
+        /// Registers a new metric, for instance to store the values computed by the transform.
+        ///
+        /// The transforms are built before the pipeline starts, hence the metric exists before any
+        /// measurement reaches the transform, and the outputs know it when they are built.
+        /// Like the metrics created in [`AlumetPluginStart`](crate::plugin::AlumetPluginStart),
+        /// it is not reported to the metric listeners.
+        fn create_metric(&mut self, metric: Metric) -> Result<RawMetricId, MetricCreationError>;
         /// Generates a name for the transform.
         fn transform_name(&mut self, name: &str) -> TransformName;
     }
@@ -218,6 +267,14 @@ pub mod builder {
             self.metrics.by_name(name)
         }
 
+        fn metrics(&self) -> &MetricRegistry {
+            self.metrics
+        }
+
+        fn create_metric(&mut self, metric: Metric) -> Result<RawMetricId, MetricCreationError> {
+            self.metrics.register(metric)
+        }
+
         fn transform_name(&mut self, name: &str) -> TransformName {
             TransformName(self.namegen.insert_deduplicate(name))
         }
@@ -240,17 +297,19 @@ pub enum TaskState {
 }
 
 async fn run_all_in_order(
-    mut transforms: Vec<builder::TransformRegistration>,
+    mut transforms: Vec<(builder::TransformRegistration, Arc<TransformCounters>)>,
     mut rx: mpsc::Receiver<MeasurementBuffer>,
-    tx: broadcast::Sender<MeasurementBuffer>,
+    tx: FanoutSender,
     active_flags: Arc<AtomicU64>,
     metrics_reader: MetricReader,
+    topology: Arc<Topology>,
+    consumers: Arc<ConsumerResolver>,
 ) -> anyhow::Result<()> {
     log::trace!(
         "Running transforms: {}",
         transforms
             .iter()
-            .map(|reg| reg.name.to_string())
+            .map(|(reg, _)| reg.name.to_string())
             .collect::<Vec<_>>()
             .join(", ")
     );
@@ -259,31 +318,45 @@ async fn run_all_in_order(
             // Update the list of active transforms.
             let current_flags = active_flags.load(Ordering::Relaxed);
 
-            // Build the transform context.
-            // This will block the publication of any modification to the MetricRegistry until the context is dropped.
-            let metrics = &metrics_reader.read().await;
-            let ctx = TransformContext { metrics };
-
-            // Run the enabled transforms. If one of them fails, the ability to continue running depends on the error type.
-            for (i, t) in &mut transforms.iter_mut().enumerate() {
-                let t_flag = 1 << i;
-                if current_flags & t_flag != 0 {
-                    let builder::TransformRegistration { name, transform } = t;
-                    match transform.apply(&mut measurements, &ctx) {
-                        Ok(()) => (),
-                        Err(TransformError::UnexpectedInput(e)) => {
-                            log::error!("Transform {name} received unexpected measurements: {e:#}");
+            {
+                // Build the transform context.
+                // This will block the publication of any modification to the MetricRegistry until the context is dropped.
+                let metrics = &metrics_reader.read().await;
+                let ctx = TransformContext {
+                    metrics,
+                    topology: &topology,
+                    consumers: &consumers,
+                };
+
+                // Run the enabled transforms. If one of them fails, the ability to continue running depends on the error type.
+                for (i, (t, counters)) in &mut transforms.iter_mut().enumerate() {
+                    let t_flag = 1 << i;
+                    if current_flags & t_flag != 0 {
+                        let builder::TransformRegistration { name, transform } = t;
+                        if !transform.accepts_columnar() {
+                            measurements.materialize();
                         }
-                        Err(TransformError::Fatal(e)) => {
-                            log::error!("Fatal error in transform {name} (this breaks the transform task!): {e:?}");
-                            return Err(e.context(format!("fatal error in transform {name}")));
+                        let start = Instant::now();
+                        let res = transform.apply(&mut measurements, &ctx);
+                        counters.record_run(start.elapsed(), res.is_err());
+                        match res {
+                            Ok(()) => (),
+                            Err(TransformError::UnexpectedInput(e)) => {
+                                log::error!("Transform {name} received unexpected measurements: {e:#}");
+                            }
+                            Err(TransformError::Fatal(e)) => {
+                                log::error!("Fatal error in transform {name} (this breaks the transform task!): {e:?}");
+                                return Err(e.context(format!("fatal error in transform {name}")));
+                            }
                         }
                     }
                 }
             }
 
             // Send the results to the outputs.
+            // The context has been dropped before, in order not to lock the MetricRegistry while waiting for slow outputs.
             tx.send(measurements)
+                .await
                 .context("could not send the measurements from transforms to the outputs")?;
         } else {
             log::debug!("The channel connected to the transform step has been closed, the transforms will stop.");
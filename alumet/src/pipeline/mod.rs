@@ -14,6 +14,7 @@ pub mod builder;
 pub mod control;
 pub mod elements;
 pub mod registry;
+pub mod telemetry;
 pub mod trigger;
 mod util;
 
@@ -23,5 +24,7 @@ pub use elements::transform::Transform;
 
 pub use builder::Builder;
 pub use builder::MeasurementPipeline;
+pub use util::alignment;
 pub use util::matching;
 pub use util::naming::{ElementKind, PluginName};
+pub use util::routing;
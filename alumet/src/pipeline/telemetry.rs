@@ -0,0 +1,248 @@
+//! Self-monitoring of the measurement pipeline.
+//!
+//! While it runs, the pipeline counts what its elements do: how long the sources take to be polled,
+//! how far their polling drifts from their trigger, how long the transforms take, how many measurements
+//! the outputs write, drop or retry, and how full the channels between the elements are.
+//!
+//! [`PipelineTelemetry`] gives access to these counters. They are cumulative: to obtain a rate or
+//! an average latency, read them periodically and compute the difference between two readings.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::measurement::MeasurementBuffer;
+use crate::pipeline::elements::error::PollError;
+use crate::pipeline::elements::output::{OutputStats, OutputStatsReader};
+use crate::pipeline::util::naming::{OutputName, SourceName, TransformName};
+
+/// Gives access to the counters that the pipeline keeps about its own operation.
+///
+/// Cloning a `PipelineTelemetry` is cheap, the clones share the same counters.
+#[derive(Clone, Default)]
+pub struct PipelineTelemetry(Arc<Registry>);
+
+#[derive(Default)]
+struct Registry {
+    sources: Mutex<Vec<(SourceName, Arc<SourceCounters>)>>,
+    transforms: Mutex<Vec<(TransformName, Arc<TransformCounters>)>>,
+    outputs: OutputStatsReader,
+    /// A weak sender does not prevent the channel from closing when the sources stop.
+    source_channel: OnceLock<mpsc::WeakSender<MeasurementBuffer>>,
+}
+
+/// Statistics about the polling of a managed source.
+///
+/// Autonomous sources are not polled by the pipeline, they have no statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceStats {
+    /// Number of calls to [`Source::poll`](super::Source::poll).
+    pub polls: u64,
+    /// Total time spent in `poll`.
+    pub poll_time: Duration,
+    /// Number of polls for which the jitter has been measured.
+    ///
+    /// The jitter is only measured for sources that are triggered at a regular interval,
+    /// between two consecutive polls.
+    pub timed_polls: u64,
+    /// Sum of the absolute differences between the time elapsed since the previous poll
+    /// and the interval of the trigger.
+    pub jitter: Duration,
+    /// Number of measurement points produced by the source.
+    pub points: u64,
+    /// Number of polls that have failed with [`PollError::CanRetry`].
+    pub retryable_errors: u64,
+    /// Number of polls that have failed with [`PollError::Fatal`].
+    pub fatal_errors: u64,
+    /// Number of polls that have returned [`PollError::NormalStop`].
+    pub normal_stops: u64,
+}
+
+/// Statistics about the execution of a transform.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransformStats {
+    /// Number of calls to [`Transform::apply`](super::Transform::apply).
+    pub runs: u64,
+    /// Total time spent in `apply`.
+    pub run_time: Duration,
+    /// Number of calls that have failed.
+    pub errors: u64,
+}
+
+/// Occupancy of a channel between two steps of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// Number of buffers of measurements that are waiting in the channel.
+    pub queued: usize,
+    /// Maximum number of buffers that the channel can hold.
+    pub capacity: usize,
+}
+
+impl PipelineTelemetry {
+    /// Returns the current statistics of each managed source.
+    pub fn sources(&self) -> Vec<(SourceName, SourceStats)> {
+        let sources = self.0.sources.lock().unwrap();
+        sources.iter().map(|(name, c)| (name.clone(), c.snapshot())).collect()
+    }
+
+    /// Returns the current statistics of each transform.
+    pub fn transforms(&self) -> Vec<(TransformName, TransformStats)> {
+        let transforms = self.0.transforms.lock().unwrap();
+        transforms
+            .iter()
+            .map(|(name, c)| (name.clone(), c.snapshot()))
+            .collect()
+    }
+
+    /// Returns the current statistics of each output.
+    pub fn outputs(&self) -> Vec<(OutputName, OutputStats)> {
+        self.0.outputs.read()
+    }
+
+    /// Returns the occupancy of the channel that carries the measurements of all the sources.
+    ///
+    /// Returns `None` if the pipeline has not started yet, or if the channel has been closed.
+    pub fn source_channel(&self) -> Option<ChannelStats> {
+        let tx = self.0.source_channel.get()?.upgrade()?;
+        Some(ChannelStats {
+            queued: tx.max_capacity() - tx.capacity(),
+            capacity: tx.max_capacity(),
+        })
+    }
+
+    pub(crate) fn output_stats(&self) -> OutputStatsReader {
+        self.0.outputs.clone()
+    }
+
+    pub(crate) fn set_source_channel(&self, tx: &mpsc::Sender<MeasurementBuffer>) {
+        let _ = self.0.source_channel.set(tx.downgrade());
+    }
+
+    pub(crate) fn register_source(&self, name: SourceName) -> Arc<SourceCounters> {
+        let counters = Arc::new(SourceCounters::default());
+        self.0.sources.lock().unwrap().push((name, counters.clone()));
+        counters
+    }
+
+    pub(crate) fn register_transform(&self, name: TransformName) -> Arc<TransformCounters> {
+        let counters = Arc::new(TransformCounters::default());
+        self.0.transforms.lock().unwrap().push((name, counters.clone()));
+        counters
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SourceCounters {
+    polls: AtomicU64,
+    poll_time_ns: AtomicU64,
+    timed_polls: AtomicU64,
+    jitter_ns: AtomicU64,
+    points: AtomicU64,
+    retryable_errors: AtomicU64,
+    fatal_errors: AtomicU64,
+    normal_stops: AtomicU64,
+}
+
+impl SourceCounters {
+    /// Records a call to `poll`, which took `duration` and produced `points` measurement points.
+    pub fn record_poll(&self, duration: Duration, points: usize) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.poll_time_ns.fetch_add(as_nanos(duration), Ordering::Relaxed);
+        self.points.fetch_add(points as u64, Ordering::Relaxed);
+    }
+
+    /// Records the time elapsed between two polls, for a source that is expected to be polled every `interval`.
+    pub fn record_interval(&self, elapsed: Duration, interval: Duration) {
+        let jitter = if elapsed > interval {
+            elapsed - interval
+        } else {
+            interval - elapsed
+        };
+        self.timed_polls.fetch_add(1, Ordering::Relaxed);
+        self.jitter_ns.fetch_add(as_nanos(jitter), Ordering::Relaxed);
+    }
+
+    pub fn count_error(&self, error: &PollError) {
+        let counter = match error {
+            PollError::CanRetry(_) => &self.retryable_errors,
+            PollError::Fatal(_) => &self.fatal_errors,
+            PollError::NormalStop => &self.normal_stops,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> SourceStats {
+        SourceStats {
+            polls: self.polls.load(Ordering::Relaxed),
+            poll_time: Duration::from_nanos(self.poll_time_ns.load(Ordering::Relaxed)),
+            timed_polls: self.timed_polls.load(Ordering::Relaxed),
+            jitter: Duration::from_nanos(self.jitter_ns.load(Ordering::Relaxed)),
+            points: self.points.load(Ordering::Relaxed),
+            retryable_errors: self.retryable_errors.load(Ordering::Relaxed),
+            fatal_errors: self.fatal_errors.load(Ordering::Relaxed),
+            normal_stops: self.normal_stops.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct TransformCounters {
+    runs: AtomicU64,
+    run_time_ns: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl TransformCounters {
+    /// Records a call to `apply`, which took `duration`.
+    pub fn record_run(&self, duration: Duration, failed: bool) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.run_time_ns.fetch_add(as_nanos(duration), Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> TransformStats {
+        TransformStats {
+            runs: self.runs.load(Ordering::Relaxed),
+            run_time: Duration::from_nanos(self.run_time_ns.load(Ordering::Relaxed)),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Converts a duration to nanoseconds, saturating at `u64::MAX` (about 584 years).
+pub(crate) fn as_nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SourceCounters;
+    use crate::pipeline::elements::error::PollError;
+
+    #[test]
+    fn source_counters() {
+        let counters = SourceCounters::default();
+        counters.record_poll(Duration::from_micros(30), 4);
+        counters.record_poll(Duration::from_micros(50), 2);
+        counters.record_interval(Duration::from_millis(12), Duration::from_millis(10));
+        counters.record_interval(Duration::from_millis(9), Duration::from_millis(10));
+        counters.count_error(&PollError::CanRetry(anyhow::anyhow!("oops")));
+        counters.count_error(&PollError::NormalStop);
+
+        let stats = counters.snapshot();
+        assert_eq!(stats.polls, 2);
+        assert_eq!(stats.poll_time, Duration::from_micros(80));
+        assert_eq!(stats.points, 6);
+        assert_eq!(stats.timed_polls, 2);
+        assert_eq!(stats.jitter, Duration::from_millis(3));
+        assert_eq!(stats.retryable_errors, 1);
+        assert_eq!(stats.fatal_errors, 0);
+        assert_eq!(stats.normal_stops, 1);
+    }
+}
@@ -1,14 +1,20 @@
 //! Source triggers.
+//!
+//! A managed [`Source`](super::Source) is polled each time its trigger fires. The trigger can be:
+//! - a time interval, see [`builder::time_interval`]
+//! - a file descriptor that becomes readable, like an eventfd or a netlink socket, see [`builder::fd_readable`]
+//! - a change to a file or directory, see [`builder::file_change`]
+//! - an arbitrary asynchronous stream, see [`builder::stream`]
+//! - a time interval that adapts to the activity, see [`builder::adaptive`]
 
 use std::sync::Arc;
 use std::time::Duration;
 use std::{fmt, time};
-use std::{future::Future, pin::Pin};
 
+use futures::stream::BoxStream;
 use tokio::sync::Notify;
 
-/// A boxed future, from the `futures` crate.
-pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+use super::util::adaptive::{AdaptiveInterval, AdaptiveSpec, SeriesValue};
 
 /// The output of a SourceTrigger.
 pub type SourceTriggerOutput = Result<(), std::io::Error>;
@@ -28,7 +34,11 @@ pub struct TriggerSpec {
 /// Controls when the [`Source`](super::Source) is polled for measurements.
 pub(crate) struct Trigger {
     pub config: TriggerConfig,
+    /// The expected time between two polls, if the trigger is periodic.
+    pub interval: Option<Duration>,
     inner: TriggerImpl,
+    /// Chooses the interval of an adaptive trigger.
+    adaptive: Option<AdaptiveInterval>,
 }
 
 enum TriggerImpl {
@@ -52,6 +62,24 @@ pub(crate) struct TriggerConfig {
     pub update_rounds: usize,
 }
 
+/// Estimates the activity that makes an [adaptive trigger](builder::adaptive) poll faster.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdaptivePolicy {
+    /// Poll faster when the measured values change.
+    ///
+    /// The activity is the largest relative change of a value between two consecutive polls,
+    /// for each series of measurements (same metric, resource and consumer).
+    /// For instance, `threshold: 0.1` means that a change of more than 10% is an activity.
+    ValueChange { threshold: f64 },
+
+    /// Poll faster when the system is busy.
+    ///
+    /// The activity is the fraction of time during which the CPUs of the system have been busy,
+    /// between 0 and 1. For instance, `threshold: 0.5` means that a CPU usage above 50% is an activity.
+    #[cfg(target_os = "linux")]
+    SystemLoad { threshold: f64 },
+}
+
 /// Constraints that can be applied to a [`TriggerSpec`] after its construction.
 pub struct TriggerConstraints {
     /// Sets the maximum interval between two updates of the commands processed by
@@ -72,7 +100,12 @@ pub mod builder {
     use core::fmt;
     use std::time::{Duration, Instant};
 
-    use super::{TriggerConfig, TriggerMechanismSpec, TriggerSpec};
+    use futures::{Stream, StreamExt};
+
+    use super::{
+        AdaptivePolicy, AdaptiveSpec, SourceTriggerOutput, StreamFactory, TriggerConfig, TriggerMechanismSpec,
+        TriggerSpec,
+    };
 
     /// Returns a builder for a source trigger that polls the source at regular intervals.
     ///
@@ -225,6 +258,259 @@ pub mod builder {
             })
         }
     }
+
+    /// Returns a builder for a source trigger that polls the source each time `fd` becomes readable.
+    ///
+    /// This is useful to react to kernel events: an eventfd, a netlink socket, an inotify instance, etc.
+    /// The trigger works on a duplicate of the file descriptor, it is up to the source to read the
+    /// pending data. The trigger fires when _new_ data arrives, not while some data remains unread.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use alumet::pipeline::trigger;
+    /// # let eventfd: std::os::fd::OwnedFd = todo!();
+    ///
+    /// let trigger_config = trigger::builder::fd_readable(&eventfd)
+    ///     .flush_rounds(10)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[cfg(unix)]
+    pub fn fd_readable(fd: impl std::os::fd::AsFd) -> EventTriggerBuilder {
+        let mechanism = fd
+            .as_fd()
+            .try_clone_to_owned()
+            .map(|fd| TriggerMechanismSpec::FdReadable(std::sync::Arc::new(fd)));
+        EventTriggerBuilder::new(mechanism)
+    }
+
+    /// Returns a builder for a source trigger that polls the source each time the file
+    /// (or the content of the directory) at `path` changes.
+    ///
+    /// The path must exist when the trigger is created. If it is deleted or replaced, the trigger
+    /// watches the new file at the same path, and fails if there is none.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use alumet::pipeline::trigger;
+    ///
+    /// // poll the source when a cgroup is created or removed
+    /// let trigger_config = trigger::builder::file_change("/sys/fs/cgroup/system.slice")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn file_change(path: impl Into<std::path::PathBuf>) -> EventTriggerBuilder {
+        EventTriggerBuilder::new(Ok(TriggerMechanismSpec::FileChange(path.into())))
+    }
+
+    /// Returns a builder for a source trigger that polls the source each time a stream yields an item.
+    ///
+    /// The stream is created by calling `new_stream` when the trigger is created, that is, when the source
+    /// is created and each time its trigger is reconfigured. If the stream yields an error, the source stops
+    /// with this error. If the stream ends, the source stops normally.
+    ///
+    /// # Example
+    /// ```
+    /// use alumet::pipeline::trigger;
+    /// use futures::StreamExt;
+    ///
+    /// let trigger_config = trigger::builder::stream(|| futures::stream::repeat(()).take(10).map(Ok))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn stream<F, S>(new_stream: F) -> EventTriggerBuilder
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Stream<Item = SourceTriggerOutput> + Send + 'static,
+    {
+        let factory = StreamFactory(std::sync::Arc::new(move || new_stream().boxed()));
+        EventTriggerBuilder::new(Ok(TriggerMechanismSpec::Stream(factory)))
+    }
+
+    /// Returns a builder for a source trigger that polls the source at an interval between `min` and `max`,
+    /// which adapts to the activity.
+    ///
+    /// The trigger starts at the `min` interval. Every `update_interval`, it estimates the activity according to
+    /// the `policy`: if the activity exceeds the threshold of the policy, the interval decreases proportionally
+    /// to the activity, otherwise it slowly increases.
+    ///
+    /// The current interval of a source can be inspected with the control API,
+    /// see [`AnonymousControlHandle::inspect_sources`](crate::pipeline::control::AnonymousControlHandle::inspect_sources).
+    ///
+    /// # Example
+    /// ```
+    /// use alumet::pipeline::trigger::{self, AdaptivePolicy};
+    /// use std::time::Duration;
+    ///
+    /// // poll every 1ms when the values change by more than 5%, and up to every second when they don't
+    /// let trigger_config = trigger::builder::adaptive(
+    ///     Duration::from_millis(1),
+    ///     Duration::from_secs(1),
+    ///     AdaptivePolicy::ValueChange { threshold: 0.05 },
+    /// )
+    /// .flush_interval(Duration::from_secs(1))
+    /// .build()
+    /// .unwrap();
+    /// ```
+    pub fn adaptive(min: Duration, max: Duration, policy: AdaptivePolicy) -> AdaptiveTriggerBuilder {
+        AdaptiveTriggerBuilder {
+            spec: AdaptiveSpec {
+                min,
+                max,
+                flush_interval: Duration::ZERO,
+                update_interval: max,
+                policy,
+            },
+            manual_trigger: false,
+            realtime_priority: false,
+        }
+    }
+
+    /// Builder for a source trigger that polls the source at an interval that adapts to the activity.
+    ///
+    /// The number of flush and update rounds is computed from the flush and update intervals,
+    /// each time the poll interval changes.
+    pub struct AdaptiveTriggerBuilder {
+        spec: AdaptiveSpec,
+        manual_trigger: bool,
+        realtime_priority: bool,
+    }
+
+    impl AdaptiveTriggerBuilder {
+        /// Flush the measurement after, at most, the given duration.
+        ///
+        /// By default, the measurements are flushed after each poll.
+        pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+            self.spec.flush_interval = flush_interval;
+            self
+        }
+
+        /// Evaluate the activity and adapt the interval after, at most, the given duration.
+        ///
+        /// By default, this is the maximum interval.
+        pub fn update_interval(mut self, update_interval: Duration) -> Self {
+            self.spec.update_interval = update_interval;
+            self
+        }
+
+        /// Signals that the pipeline should run the source on a thread with a high scheduling priority.
+        ///
+        /// See [`TimeTriggerBuilder::realtime_priority`].
+        pub fn realtime_priority(mut self) -> Self {
+            self.realtime_priority = true;
+            self
+        }
+
+        pub fn allow_manual_trigger(mut self) -> Self {
+            self.manual_trigger = true;
+            self
+        }
+
+        /// Builds the trigger.
+        pub fn build(mut self) -> Result<TriggerSpec, Error> {
+            if self.spec.min.is_zero() {
+                return Err(Error::InvalidConfig(String::from(
+                    "the minimum interval must be non-zero",
+                )));
+            }
+            if self.spec.min > self.spec.max {
+                return Err(Error::InvalidConfig(format!(
+                    "the minimum interval ({:?}) must not be greater than the maximum interval ({:?})",
+                    self.spec.min, self.spec.max
+                )));
+            }
+            let threshold = match self.spec.policy {
+                AdaptivePolicy::ValueChange { threshold } => threshold,
+                #[cfg(target_os = "linux")]
+                AdaptivePolicy::SystemLoad { threshold } => threshold,
+            };
+            if !(threshold > 0.0 && threshold.is_finite()) {
+                return Err(Error::InvalidConfig(format!(
+                    "the threshold of the adaptive policy must be positive, not {threshold}"
+                )));
+            }
+            // automatically enable `realtime_priority` in some cases, like time triggers
+            if self.spec.min <= Duration::from_millis(3) {
+                self.realtime_priority = true;
+            }
+
+            let (flush_rounds, update_rounds) = self.spec.rounds(self.spec.min);
+            Ok(TriggerSpec {
+                mechanism: TriggerMechanismSpec::Adaptive(Box::new(self.spec)),
+                // The interval can grow up to `max`: always interrupt the trigger to apply the commands quickly.
+                interruptible: true,
+                allow_manual_trigger: self.manual_trigger,
+                use_realtime_priority: self.realtime_priority,
+                config: TriggerConfig {
+                    flush_rounds,
+                    update_rounds,
+                },
+            })
+        }
+    }
+
+    /// Builder for a source trigger that polls the source when an event occurs.
+    ///
+    /// Unlike time triggers, event triggers are always interrupted by the commands sent to the source,
+    /// because the next event may never come.
+    pub struct EventTriggerBuilder {
+        mechanism: Result<TriggerMechanismSpec, std::io::Error>,
+        config: TriggerConfig,
+        manual_trigger: bool,
+        realtime_priority: bool,
+    }
+
+    impl EventTriggerBuilder {
+        fn new(mechanism: Result<TriggerMechanismSpec, std::io::Error>) -> Self {
+            Self {
+                mechanism,
+                config: TriggerConfig {
+                    flush_rounds: 1,
+                    update_rounds: 1,
+                },
+                manual_trigger: false,
+                realtime_priority: false,
+            }
+        }
+
+        /// Flush the measurements every `flush_rounds` polls.
+        ///
+        /// The measurements are flushed when the source stops, but not in between: if the events are rare,
+        /// keep this number low.
+        pub fn flush_rounds(mut self, flush_rounds: usize) -> Self {
+            self.config.flush_rounds = flush_rounds;
+            self
+        }
+
+        /// Signals that the pipeline should run the source on a thread with a high scheduling priority.
+        ///
+        /// See [`TimeTriggerBuilder::realtime_priority`].
+        pub fn realtime_priority(mut self) -> Self {
+            self.realtime_priority = true;
+            self
+        }
+
+        pub fn allow_manual_trigger(mut self) -> Self {
+            self.manual_trigger = true;
+            self
+        }
+
+        /// Builds the trigger.
+        pub fn build(self) -> Result<TriggerSpec, Error> {
+            let mechanism = self.mechanism.map_err(Error::Io)?;
+            if self.config.flush_rounds == 0 {
+                return Err(Error::InvalidConfig(String::from("flush_rounds must be non-zero")));
+            }
+            Ok(TriggerSpec {
+                mechanism,
+                interruptible: true,
+                allow_manual_trigger: self.manual_trigger,
+                use_realtime_priority: self.realtime_priority,
+                config: self.config,
+            })
+        }
+    }
 }
 
 pub(crate) mod private_impl {
@@ -237,9 +523,18 @@ pub(crate) mod private_impl {
                     super::TriggerMechanismSpec::TimeInterval(_, duration_a),
                     super::TriggerMechanismSpec::TimeInterval(_, duration_b),
                 ) => duration_a == duration_b,
-                (super::TriggerMechanismSpec::Future(_f1), super::TriggerMechanismSpec::Future(_f2)) => {
-                    true // how to std::ptr::eq on this?
+                #[cfg(unix)]
+                (super::TriggerMechanismSpec::FdReadable(fd_a), super::TriggerMechanismSpec::FdReadable(fd_b)) => {
+                    std::sync::Arc::ptr_eq(fd_a, fd_b)
+                }
+                #[cfg(target_os = "linux")]
+                (super::TriggerMechanismSpec::FileChange(path_a), super::TriggerMechanismSpec::FileChange(path_b)) => {
+                    path_a == path_b
                 }
+                (super::TriggerMechanismSpec::Stream(f_a), super::TriggerMechanismSpec::Stream(f_b)) => {
+                    std::sync::Arc::ptr_eq(&f_a.0, &f_b.0)
+                }
+                (super::TriggerMechanismSpec::Adaptive(a), super::TriggerMechanismSpec::Adaptive(b)) => a == b,
                 _ => false,
             }
         }
@@ -270,6 +565,15 @@ impl TriggerSpec {
         if constraints.allow_manual_trigger {
             self.allow_manual_trigger = constraints.allow_manual_trigger;
         }
+        if let TriggerMechanismSpec::Adaptive(spec) = &mut self.mechanism {
+            // The adaptive trigger is always interruptible, but the number of update rounds is computed
+            // from the spec each time the interval changes: constrain the spec itself.
+            if spec.update_interval > constraints.max_update_interval {
+                spec.update_interval = constraints.max_update_interval;
+                let (_, update_rounds) = spec.rounds(spec.min);
+                self.config.update_rounds = update_rounds;
+            }
+        }
         if !self.interruptible {
             let max_update_interval = constraints.max_update_interval;
 
@@ -315,6 +619,8 @@ impl Default for TriggerConstraints {
 pub(crate) enum TriggerReason {
     Triggered,
     Interrupted,
+    /// The trigger will never fire again, for instance because its stream has ended.
+    Finished,
 }
 
 pub struct ManualTrigger(Arc<Notify>);
@@ -327,6 +633,14 @@ impl ManualTrigger {
 
 impl Trigger {
     pub fn new(spec: TriggerSpec) -> Result<Self, std::io::Error> {
+        let (interval, adaptive) = match &spec.mechanism {
+            TriggerMechanismSpec::TimeInterval(_, period) => (Some(*period), None),
+            TriggerMechanismSpec::Adaptive(adaptive) => {
+                let adaptive = AdaptiveInterval::new(AdaptiveSpec::clone(adaptive));
+                (Some(adaptive.current()), Some(adaptive))
+            }
+            _ => (None, None),
+        };
         let mechanism = TriggerMechanism::try_from(spec.mechanism)?;
         let inner = if spec.allow_manual_trigger {
             TriggerImpl::WithManualTrigger(mechanism, spec.interruptible, Arc::new(Notify::new()))
@@ -337,10 +651,45 @@ impl Trigger {
         };
         Ok(Self {
             config: spec.config,
+            interval,
             inner,
+            adaptive,
         })
     }
 
+    /// Observes the measurements produced by the last poll, if the trigger is adaptive.
+    pub(crate) fn observe<'a>(&mut self, points: impl Iterator<Item = SeriesValue<'a>>) {
+        if let Some(adaptive) = &mut self.adaptive {
+            adaptive.observe(points);
+        }
+    }
+
+    /// Adapts the poll interval to the activity observed since the previous call, if the trigger is adaptive.
+    ///
+    /// Returns `true` if the interval has changed. In that case, `interval` and `config` are updated.
+    pub fn adapt(&mut self) -> bool {
+        let Some(adaptive) = &mut self.adaptive else {
+            return false;
+        };
+        let Some(new_interval) = adaptive.adapt() else {
+            return false;
+        };
+        let (flush_rounds, update_rounds) = adaptive.spec().rounds(new_interval);
+        self.config.flush_rounds = flush_rounds;
+        self.config.update_rounds = update_rounds;
+        self.interval = Some(new_interval);
+
+        let mechanism = match &mut self.inner {
+            TriggerImpl::Simple(m) | TriggerImpl::Interruptible(m) | TriggerImpl::WithManualTrigger(m, _, _) => m,
+        };
+        if let TriggerMechanism::Adaptive { next, period } = mechanism {
+            // the next tick is one (new) period after the previous one
+            *next = *next - *period + new_interval;
+            *period = new_interval;
+        }
+        true
+    }
+
     pub fn manual_trigger(&self) -> Option<ManualTrigger> {
         match &self.inner {
             TriggerImpl::WithManualTrigger(_, _, notify) => Some(ManualTrigger(notify.clone())),
@@ -353,18 +702,14 @@ impl Trigger {
         match &mut self.inner {
             TriggerImpl::Simple(mechanism) => {
                 // Simple case: wait for the trigger to wake up
-                mechanism.next().await?;
-                Ok(TriggerReason::Triggered)
+                Ok(mechanism.next().await?)
             }
             TriggerImpl::Interruptible(mechanism) => {
                 // wait for the first of two futures: normal trigger or "interruption"
                 tokio::select! {
                     biased; // don't choose the branch randomly (for performance)
 
-                    res = mechanism.next() => {
-                        res?;
-                        Ok(TriggerReason::Triggered)
-                    },
+                    res = mechanism.next() => Ok(res?),
                     _ = interrupt.notified() => {
                         Ok(TriggerReason::Interrupted)
                     }
@@ -374,10 +719,7 @@ impl Trigger {
                 tokio::select! {
                     biased;
 
-                    res = mechanism.next() => {
-                        res?;
-                        Ok(TriggerReason::Triggered)
-                    },
+                    res = mechanism.next() => Ok(res?),
                     _ = interrupt.notified(), if *interruptible => {
                         Ok(TriggerReason::Interrupted)
                     }
@@ -398,8 +740,22 @@ impl Trigger {
 #[derive(Debug, Clone)]
 enum TriggerMechanismSpec {
     TimeInterval(time::Instant, time::Duration),
-    #[allow(dead_code)]
-    Future(fn() -> BoxFuture<'static, SourceTriggerOutput>),
+    #[cfg(unix)]
+    FdReadable(Arc<std::os::fd::OwnedFd>),
+    #[cfg(target_os = "linux")]
+    FileChange(std::path::PathBuf),
+    Stream(StreamFactory),
+    Adaptive(Box<AdaptiveSpec>),
+}
+
+/// Creates the stream of a [`TriggerMechanism::Stream`].
+#[derive(Clone)]
+struct StreamFactory(Arc<dyn Fn() -> BoxStream<'static, SourceTriggerOutput> + Send + Sync>);
+
+impl fmt::Debug for StreamFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StreamFactory")
+    }
 }
 
 /// The possible trigger mechanisms.
@@ -416,11 +772,30 @@ enum TriggerMechanism {
     #[allow(dead_code)]
     TokioSleep(tokio::time::Instant, tokio::time::Duration),
 
-    /// A trigger based on an arbitrary [`Future`] that is returned on demand
-    /// by a function `f`.
+    /// A trigger based on the readiness of a file descriptor.
+    ///
+    /// The source is polled each time the file descriptor becomes readable.
+    #[cfg(unix)]
+    FdReadable(tokio::io::unix::AsyncFd<std::os::fd::OwnedFd>),
+
+    /// A trigger based on inotify.
+    ///
+    /// The source is polled each time the watched file changes.
+    #[cfg(target_os = "linux")]
+    FileChange(inotify::FileWatch),
+
+    /// A trigger based on an arbitrary [`Stream`](futures::Stream).
     ///
-    /// The source is polled each time `f().await` returns.
-    Future(fn() -> BoxFuture<'static, SourceTriggerOutput>),
+    /// The source is polled each time the stream yields an item.
+    Stream(BoxStream<'static, SourceTriggerOutput>),
+
+    /// A trigger based on [`tokio::time::sleep_until`], with a period that can change.
+    ///
+    /// The period is chosen by an [`AdaptiveInterval`].
+    Adaptive {
+        next: tokio::time::Instant,
+        period: tokio::time::Duration,
+    },
 }
 
 impl TryFrom<TriggerMechanismSpec> for TriggerMechanism {
@@ -440,30 +815,61 @@ impl TryFrom<TriggerMechanismSpec> for TriggerMechanism {
                     TriggerMechanism::TokioSleep(at.into(), duration.into())
                 }
             }
-            TriggerMechanismSpec::Future(f) => TriggerMechanism::Future(f),
+            #[cfg(unix)]
+            TriggerMechanismSpec::FdReadable(fd) => {
+                // Use a duplicate of the fd: the same fd cannot be registered twice, but the
+                // trigger can be created again when the source is reconfigured.
+                TriggerMechanism::FdReadable(tokio::io::unix::AsyncFd::new(fd.try_clone()?)?)
+            }
+            #[cfg(target_os = "linux")]
+            TriggerMechanismSpec::FileChange(path) => TriggerMechanism::FileChange(inotify::FileWatch::new(path)?),
+            TriggerMechanismSpec::Stream(factory) => TriggerMechanism::Stream((factory.0)()),
+            TriggerMechanismSpec::Adaptive(spec) => TriggerMechanism::Adaptive {
+                next: tokio::time::Instant::now(),
+                period: spec.min,
+            },
         })
     }
 }
 
 impl TriggerMechanism {
-    pub async fn next(&mut self) -> Result<(), std::io::Error> {
+    pub async fn next(&mut self) -> Result<TriggerReason, std::io::Error> {
         use tokio_stream::StreamExt;
 
         match self {
             #[cfg(target_os = "linux")]
             TriggerMechanism::Timerfd(interval) => {
                 interval.next().await.unwrap()?;
-                Ok(())
             }
             TriggerMechanism::TokioSleep(start, period) => {
                 let start = *start;
                 let now = tokio::time::Instant::now();
                 let deadline = if start > now { start } else { now + *period };
                 tokio::time::sleep_until(deadline).await;
-                Ok(())
             }
-            TriggerMechanism::Future(f) => f().await,
+            #[cfg(unix)]
+            TriggerMechanism::FdReadable(fd) => {
+                let mut guard = fd.readable().await?;
+                // Reading the data is the job of the source, wait for new data next time.
+                guard.clear_ready();
+            }
+            #[cfg(target_os = "linux")]
+            TriggerMechanism::FileChange(watch) => watch.next().await?,
+            TriggerMechanism::Stream(stream) => match stream.next().await {
+                Some(res) => res?,
+                None => return Ok(TriggerReason::Finished),
+            },
+            TriggerMechanism::Adaptive { next, period } => {
+                tokio::time::sleep_until(*next).await;
+                let now = tokio::time::Instant::now();
+                *next += *period;
+                if *next < now {
+                    // too late, skip the missed ticks
+                    *next = now + *period;
+                }
+            }
         }
+        Ok(TriggerReason::Triggered)
     }
 }
 
@@ -473,16 +879,142 @@ impl fmt::Debug for TriggerMechanism {
             #[cfg(target_os = "linux")]
             Self::Timerfd(_) => f.write_str("Timerfd trigger"),
             Self::TokioSleep(_, _) => f.write_str("TokioSleep trigger"),
-            Self::Future(_) => f.write_str("Future trigger"),
+            #[cfg(unix)]
+            Self::FdReadable(_) => f.write_str("FdReadable trigger"),
+            #[cfg(target_os = "linux")]
+            Self::FileChange(_) => f.write_str("FileChange trigger"),
+            Self::Stream(_) => f.write_str("Stream trigger"),
+            Self::Adaptive { .. } => f.write_str("Adaptive trigger"),
         }
     }
 }
 
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    use tokio::io::unix::AsyncFd;
+
+    /// Events that count as a change of the watched file.
+    const WATCH_MASK: u32 = libc::IN_MODIFY
+        | libc::IN_ATTRIB
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_DELETE_SELF
+        | libc::IN_MOVE_SELF;
+
+    /// Events after which the watch is no longer attached to the path.
+    const DETACH_MASK: u32 = libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_IGNORED;
+
+    /// Size of the fixed part of an `inotify_event`, before the optional file name.
+    const EVENT_HEADER_SIZE: usize = std::mem::size_of::<libc::inotify_event>();
+
+    /// Watches a file or a directory with inotify.
+    pub struct FileWatch {
+        inotify: AsyncFd<OwnedFd>,
+        path: CString,
+        wd: libc::c_int,
+    }
+
+    impl FileWatch {
+        pub fn new(path: PathBuf) -> io::Result<Self> {
+            let path = CString::new(path.as_os_str().as_bytes())?;
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let mut watch = Self {
+                inotify: AsyncFd::new(fd)?,
+                path,
+                wd: -1,
+            };
+            watch.add_watch()?;
+            Ok(watch)
+        }
+
+        fn add_watch(&mut self) -> io::Result<()> {
+            let fd = self.inotify.get_ref().as_raw_fd();
+            let wd = unsafe { libc::inotify_add_watch(fd, self.path.as_ptr(), WATCH_MASK) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.wd = wd;
+            Ok(())
+        }
+
+        /// Waits for the next change.
+        ///
+        /// All the pending events are consumed: multiple changes that happen at the same time
+        /// only wake up the trigger once.
+        pub async fn next(&mut self) -> io::Result<()> {
+            let mut detached = false;
+            loop {
+                let mut guard = self.inotify.readable().await?;
+                match guard.try_io(|fd| read_events(fd.get_ref().as_raw_fd(), &mut detached)) {
+                    Ok(Ok(())) => (),
+                    Ok(Err(e)) => return Err(e),
+                    Err(_would_block) => continue,
+                }
+                // drain the remaining events, if any
+                while let Ok(res) = guard.try_io(|fd| read_events(fd.get_ref().as_raw_fd(), &mut detached)) {
+                    res?;
+                }
+                break;
+            }
+            if detached {
+                // The file has been deleted or replaced: watch the new file at the same path.
+                // The old watch may already be removed by the kernel, ignore the result.
+                unsafe { libc::inotify_rm_watch(self.inotify.get_ref().as_raw_fd(), self.wd) };
+                self.add_watch()?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Reads a batch of inotify events from `fd`, and checks whether the watch has been detached.
+    fn read_events(fd: libc::c_int, detached: &mut bool) -> io::Result<()> {
+        let mut buf = [0u8; 4096];
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let events = &buf[..n as usize];
+        let mut offset = 0;
+        while offset + EVENT_HEADER_SIZE <= events.len() {
+            // struct inotify_event { int wd; uint32_t mask; uint32_t cookie; uint32_t len; char name[]; }
+            let header = &events[offset..offset + EVENT_HEADER_SIZE];
+            let mask = u32::from_ne_bytes(header[4..8].try_into().unwrap());
+            let name_len = u32::from_ne_bytes(header[12..16].try_into().unwrap()) as usize;
+            if mask & DETACH_MASK != 0 {
+                *detached = true;
+            }
+            offset += EVENT_HEADER_SIZE + name_len;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
 
-    use super::{builder, TriggerConstraints, TriggerMechanismSpec};
+    use tokio::sync::Notify;
+
+    use crate::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{builder, AdaptivePolicy, Trigger, TriggerConstraints, TriggerMechanismSpec, TriggerReason};
 
     #[test]
     fn trigger_auto_config() {
@@ -585,5 +1117,118 @@ mod tests {
         assert!(trigger.interruptible);
         assert_eq!(trigger.config.flush_rounds, 5);
         assert_eq!(trigger.config.update_rounds, 1);
+
+        // by default, the adaptive trigger updates at its maximum interval, which is too long here
+        let mut trigger = builder::adaptive(
+            Duration::from_millis(100),
+            Duration::from_secs(10),
+            AdaptivePolicy::ValueChange { threshold: 0.1 },
+        )
+        .build()
+        .unwrap();
+        assert_eq!(trigger.config.update_rounds, 100);
+        trigger.constrain(&constraints);
+        assert!(
+            matches!(&trigger.mechanism, TriggerMechanismSpec::Adaptive(spec) if spec.update_interval == Duration::from_secs(2))
+        );
+        assert_eq!(trigger.config.update_rounds, 20);
+
+        // the constraint still holds when the interval adapts
+        let mut trigger = Trigger::new(trigger).unwrap();
+        let point = MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(1.0),
+        );
+        trigger.observe(std::iter::once((
+            point.metric,
+            &point.resource,
+            &point.consumer,
+            &point.value,
+        )));
+        assert!(!trigger.adapt());
+        trigger.observe(std::iter::once((
+            point.metric,
+            &point.resource,
+            &point.consumer,
+            &point.value,
+        )));
+        assert!(trigger.adapt());
+        let interval = trigger.interval.unwrap();
+        assert!(interval > Duration::from_millis(100));
+        assert_eq!(
+            trigger.config.update_rounds,
+            (Duration::from_secs(2).as_nanos() / interval.as_nanos()) as usize
+        );
+    }
+
+    /// Waits for the next tick of the trigger, with a timeout.
+    async fn next_reason(trigger: &mut Trigger) -> TriggerReason {
+        let interrupt = Notify::new();
+        tokio::time::timeout(Duration::from_secs(2), trigger.next(&interrupt))
+            .await
+            .expect("the trigger should fire")
+            .expect("the trigger should not fail")
+    }
+
+    #[tokio::test]
+    async fn stream_trigger() {
+        let spec = builder::stream(|| futures::stream::iter([Ok(()), Ok(())]))
+            .build()
+            .unwrap();
+        assert!(spec.interruptible);
+        assert_eq!(spec, spec.clone());
+
+        let mut trigger = Trigger::new(spec.clone()).unwrap();
+        assert_eq!(trigger.interval, None);
+        assert_eq!(next_reason(&mut trigger).await, TriggerReason::Triggered);
+        assert_eq!(next_reason(&mut trigger).await, TriggerReason::Triggered);
+        assert_eq!(next_reason(&mut trigger).await, TriggerReason::Finished);
+
+        // a new trigger gets a new stream
+        let mut trigger = Trigger::new(spec).unwrap();
+        assert_eq!(next_reason(&mut trigger).await, TriggerReason::Triggered);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn fd_readable_trigger() {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+        let eventfd = unsafe { OwnedFd::from_raw_fd(libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC)) };
+        let spec = builder::fd_readable(&eventfd).build().unwrap();
+        let mut trigger = Trigger::new(spec.clone()).unwrap();
+        // the trigger can be recreated, e.g. when the source is reconfigured
+        drop(Trigger::new(spec).unwrap());
+
+        let one = 1u64.to_ne_bytes();
+        let n = unsafe { libc::write(eventfd.as_raw_fd(), one.as_ptr().cast(), one.len()) };
+        assert_eq!(n, 8);
+        assert_eq!(next_reason(&mut trigger).await, TriggerReason::Triggered);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn file_change_trigger() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("watched");
+        std::fs::write(&path, "a").unwrap();
+
+        assert!(builder::file_change(dir.path().join("missing")).build().is_ok());
+        assert!(Trigger::new(builder::file_change(dir.path().join("missing")).build().unwrap()).is_err());
+
+        let mut trigger = Trigger::new(builder::file_change(&path).build().unwrap()).unwrap();
+        std::fs::write(&path, "b").unwrap();
+        assert_eq!(next_reason(&mut trigger).await, TriggerReason::Triggered);
+
+        // replace the file, the trigger should follow the path
+        let tmp = dir.path().join("tmp");
+        std::fs::write(&tmp, "c").unwrap();
+        std::fs::rename(&tmp, &path).unwrap();
+        assert_eq!(next_reason(&mut trigger).await, TriggerReason::Triggered);
+        std::fs::write(&path, "d").unwrap();
+        assert_eq!(next_reason(&mut trigger).await, TriggerReason::Triggered);
     }
 }
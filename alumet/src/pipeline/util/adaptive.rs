@@ -0,0 +1,331 @@
+//! Adaptive polling interval.
+//!
+//! An [adaptive trigger](crate::pipeline::trigger::builder::adaptive) polls its source faster when
+//! there is some activity, and slower when nothing happens. The activity is estimated by an
+//! [`AdaptivePolicy`] and evaluated every `update_rounds` polls, like the source commands.
+//!
+//! When the activity is above the threshold of the policy, the interval is divided by the ratio
+//! between the activity and the threshold: a larger change leads to a faster polling.
+//! Otherwise, the interval slowly grows back.
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use crate::measurement::WrappedMeasurementValue;
+use crate::metrics::RawMetricId;
+use crate::pipeline::trigger::AdaptivePolicy;
+use crate::resources::{Resource, ResourceConsumer};
+
+/// Growth of the interval after a round without activity.
+const GROWTH_FACTOR: f64 = 1.25;
+
+/// The bounds and policy of an adaptive trigger.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AdaptiveSpec {
+    pub min: Duration,
+    pub max: Duration,
+    pub flush_interval: Duration,
+    pub update_interval: Duration,
+    pub policy: AdaptivePolicy,
+}
+
+impl AdaptiveSpec {
+    /// Returns `(flush_rounds, update_rounds)` for the given poll interval.
+    pub fn rounds(&self, interval: Duration) -> (usize, usize) {
+        let rounds = |d: Duration| ((d.as_nanos() / interval.as_nanos().max(1)) as usize).max(1);
+        (rounds(self.flush_interval), rounds(self.update_interval))
+    }
+}
+
+/// A measured value and its series: `(metric, resource, consumer, value)`.
+///
+/// Rows and columnar batches are both observed through this view.
+pub(crate) type SeriesValue<'a> = (
+    RawMetricId,
+    &'a Resource,
+    &'a ResourceConsumer,
+    &'a WrappedMeasurementValue,
+);
+
+/// Chooses the poll interval of an adaptive trigger.
+pub(crate) struct AdaptiveInterval {
+    spec: AdaptiveSpec,
+    current: Duration,
+    probe: ActivityProbe,
+}
+
+enum ActivityProbe {
+    ValueChange {
+        threshold: f64,
+        /// Last value of each series, and the window in which it has been seen.
+        last_values: HashMap<u64, (f64, u64)>,
+        window: u64,
+        /// Maximum relative change observed in the current window, if any.
+        max_change: Option<f64>,
+    },
+    #[cfg(target_os = "linux")]
+    SystemLoad {
+        threshold: f64,
+        prev: Option<load::CpuTimes>,
+    },
+}
+
+impl AdaptiveInterval {
+    /// Creates a new adaptive interval, which starts at the minimum interval.
+    pub fn new(spec: AdaptiveSpec) -> Self {
+        let probe = match spec.policy {
+            AdaptivePolicy::ValueChange { threshold } => ActivityProbe::ValueChange {
+                threshold,
+                last_values: HashMap::new(),
+                window: 0,
+                max_change: None,
+            },
+            #[cfg(target_os = "linux")]
+            AdaptivePolicy::SystemLoad { threshold } => ActivityProbe::SystemLoad {
+                threshold,
+                prev: load::CpuTimes::read().ok(),
+            },
+        };
+        Self {
+            current: spec.min,
+            spec,
+            probe,
+        }
+    }
+
+    pub fn spec(&self) -> &AdaptiveSpec {
+        &self.spec
+    }
+
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// Observes the measurements produced by one poll of the source.
+    pub fn observe<'a>(&mut self, points: impl Iterator<Item = SeriesValue<'a>>) {
+        if let ActivityProbe::ValueChange {
+            last_values,
+            window,
+            max_change,
+            ..
+        } = &mut self.probe
+        {
+            for (metric, resource, consumer, value) in points {
+                let Some(value) = value.as_f64() else {
+                    continue;
+                };
+                let key = series_hash(metric, resource, consumer);
+                if let Some((prev, _)) = last_values.insert(key, (value, *window)) {
+                    let change = relative_change(prev, value);
+                    *max_change = Some(max_change.map_or(change, |m| m.max(change)));
+                }
+            }
+        }
+    }
+
+    /// Evaluates the activity since the previous call, and updates the interval.
+    ///
+    /// Returns the new interval if it has changed.
+    pub fn adapt(&mut self) -> Option<Duration> {
+        let activity = match &mut self.probe {
+            ActivityProbe::ValueChange {
+                threshold,
+                last_values,
+                window,
+                max_change,
+            } => {
+                // forget the series that have disappeared
+                let w = *window;
+                last_values.retain(|_, (_, seen)| *seen == w);
+                *window += 1;
+                max_change.take().map(|c| c / *threshold)
+            }
+            #[cfg(target_os = "linux")]
+            ActivityProbe::SystemLoad { threshold, prev } => match load::CpuTimes::read() {
+                Ok(now) => {
+                    let load = prev.and_then(|p| now.busy_fraction_since(&p));
+                    *prev = Some(now);
+                    load.map(|l| l / *threshold)
+                }
+                Err(e) => {
+                    log::warn!("Cannot read the system load, the adaptive interval is unchanged: {e}");
+                    None
+                }
+            },
+        };
+        // no data, no decision
+        let ratio = activity?;
+        let new_interval = next_interval(self.current, ratio, self.spec.min, self.spec.max);
+        if new_interval == self.current {
+            return None;
+        }
+        self.current = new_interval;
+        if let ActivityProbe::ValueChange { last_values, .. } = &mut self.probe {
+            // The values of delta metrics depend on the interval: start again from a clean state
+            // in order not to see this change as an activity.
+            last_values.clear();
+        }
+        Some(new_interval)
+    }
+}
+
+/// Computes the next interval, given the ratio between the activity and its threshold.
+fn next_interval(current: Duration, ratio: f64, min: Duration, max: Duration) -> Duration {
+    let new = if ratio >= 1.0 {
+        if ratio.is_finite() {
+            current.div_f64(ratio)
+        } else {
+            min
+        }
+    } else {
+        current.mul_f64(GROWTH_FACTOR)
+    };
+    new.clamp(min, max)
+}
+
+/// Returns the relative change between two values, `+inf` if the previous value is zero.
+fn relative_change(prev: f64, new: f64) -> f64 {
+    let diff = (new - prev).abs();
+    if diff == 0.0 {
+        0.0
+    } else {
+        diff / prev.abs()
+    }
+}
+
+/// Identifies the series of a measurement point, without cloning the resource and consumer.
+fn series_hash(metric: RawMetricId, resource: &Resource, consumer: &ResourceConsumer) -> u64 {
+    let mut hasher = fxhash::FxHasher64::default();
+    metric.hash(&mut hasher);
+    resource.hash(&mut hasher);
+    consumer.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(target_os = "linux")]
+mod load {
+    use std::io;
+
+    /// Cumulative CPU times of the system, from `/proc/stat`, in clock ticks.
+    #[derive(Debug, Clone, Copy)]
+    pub struct CpuTimes {
+        busy: u64,
+        total: u64,
+    }
+
+    impl CpuTimes {
+        pub fn read() -> io::Result<Self> {
+            let stat = std::fs::read_to_string("/proc/stat")?;
+            Self::parse(&stat).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid /proc/stat"))
+        }
+
+        fn parse(stat: &str) -> Option<Self> {
+            let line = stat.lines().find(|l| l.starts_with("cpu "))?;
+            let times: Vec<u64> = line
+                .split_whitespace()
+                .skip(1)
+                .map(|t| t.parse().ok())
+                .collect::<Option<_>>()?;
+            // user nice system idle iowait irq softirq steal (guest times are included in user and nice)
+            let total: u64 = times.iter().take(8).sum();
+            let idle = times.get(3)? + times.get(4).unwrap_or(&0);
+            Some(Self {
+                busy: total - idle,
+                total,
+            })
+        }
+
+        /// Returns the fraction of time the CPUs have been busy since `prev`.
+        pub fn busy_fraction_since(&self, prev: &CpuTimes) -> Option<f64> {
+            let total = self.total.checked_sub(prev.total)?;
+            let busy = self.busy.checked_sub(prev.busy)?;
+            (total > 0).then(|| busy as f64 / total as f64)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::CpuTimes;
+
+        #[test]
+        fn parse_proc_stat() {
+            let a = CpuTimes::parse("cpu  100 0 50 800 50 0 0 0 0 0\ncpu0 100 0 50 800 50 0 0 0 0 0\n").unwrap();
+            let b = CpuTimes::parse("cpu  250 0 100 1000 50 0 0 0 0 0\n").unwrap();
+            assert_eq!(b.busy_fraction_since(&a), Some(0.5));
+            assert_eq!(a.busy_fraction_since(&a), None);
+            assert!(CpuTimes::parse("intr 1 2 3").is_none());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        measurement::{MeasurementPoint, Timestamp},
+        metrics::RawMetricId,
+        pipeline::trigger::AdaptivePolicy,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{next_interval, AdaptiveInterval, AdaptiveSpec, SeriesValue};
+
+    fn point(cpu: u32, value: f64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId::from_u64(1),
+            Resource::CpuCore { id: cpu },
+            ResourceConsumer::LocalMachine,
+            crate::measurement::WrappedMeasurementValue::F64(value),
+        )
+    }
+
+    fn series(points: &[MeasurementPoint]) -> impl Iterator<Item = SeriesValue<'_>> {
+        points.iter().map(|p| (p.metric, &p.resource, &p.consumer, &p.value))
+    }
+
+    #[test]
+    fn scaling() {
+        let (min, max) = (Duration::from_millis(10), Duration::from_secs(1));
+        let ms = Duration::from_millis;
+        assert_eq!(next_interval(ms(100), 2.0, min, max), ms(50));
+        assert_eq!(next_interval(ms(100), 1000.0, min, max), min);
+        assert_eq!(next_interval(ms(100), f64::INFINITY, min, max), min);
+        assert_eq!(next_interval(ms(100), 0.5, min, max), ms(125));
+        assert_eq!(next_interval(ms(900), 0.0, min, max), max);
+    }
+
+    #[test]
+    fn value_change() {
+        let spec = AdaptiveSpec {
+            min: Duration::from_millis(10),
+            max: Duration::from_millis(100),
+            flush_interval: Duration::from_millis(50),
+            update_interval: Duration::ZERO,
+            policy: AdaptivePolicy::ValueChange { threshold: 0.1 },
+        };
+        assert_eq!(spec.rounds(Duration::from_millis(10)), (5, 1));
+        assert_eq!(spec.rounds(Duration::from_millis(100)), (1, 1));
+
+        let mut adaptive = AdaptiveInterval::new(spec);
+        assert_eq!(adaptive.current(), Duration::from_millis(10));
+
+        // nothing to compare yet
+        adaptive.observe(series(&[point(0, 10.0), point(1, 20.0)]));
+        assert_eq!(adaptive.adapt(), None);
+
+        // small changes: the interval grows
+        adaptive.observe(series(&[point(0, 10.5), point(1, 20.0)]));
+        assert_eq!(adaptive.adapt(), Some(Duration::from_micros(12500)));
+
+        // the state is reset after a change of interval
+        adaptive.observe(series(&[point(0, 10.0), point(1, 20.0)]));
+        assert_eq!(adaptive.adapt(), None);
+
+        // large change: the interval shrinks, proportionally to the change
+        adaptive.observe(series(&[point(0, 10.0), point(1, 30.0)]));
+        assert_eq!(adaptive.adapt(), Some(Duration::from_millis(10)));
+    }
+}
@@ -0,0 +1,552 @@
+//! Alignment of measurement streams on time windows.
+//!
+//! Some transforms need to combine measurements that come from different sources, for instance
+//! the energy consumed by the CPU and the CPU usage of each process. These measurements are not
+//! taken at the exact same time, and they do not arrive at the transform at the same time either:
+//! each source has its own polling interval, and its measurements can be delayed by the pipeline.
+//!
+//! An [`AlignmentBuffer`] groups the measurements of multiple streams by time window, and
+//! releases a window once it is complete, that is, when it is unlikely to receive more data.
+//! This is decided with a _watermark_: a window is closed when all the streams have produced
+//! measurements that are after the end of the window, or when the most advanced stream is
+//! ahead of the end of the window by more than the allowed lateness.
+//! The measurements that arrive for a window that has already been closed are rejected.
+//!
+//! When a window is closed, some streams may have no measurement in it. The [`MissingPolicy`]
+//! decides what to do in this case.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use alumet::measurement::{MeasurementPoint, Timestamp, WrappedMeasurementValue};
+//! use alumet::metrics::RawMetricId;
+//! use alumet::pipeline::alignment::{AlignmentBuffer, AlignmentConfig, MissingPolicy, PushError};
+//! use alumet::resources::{Resource, ResourceConsumer};
+//!
+//! #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+//! enum Stream { Energy, Usage }
+//!
+//! let config = AlignmentConfig {
+//!     window: Duration::from_secs(1),
+//!     allowed_lateness: Duration::from_secs(2),
+//!     missing: MissingPolicy::Drop,
+//! };
+//! let mut buffer = AlignmentBuffer::new(config, [Stream::Energy, Stream::Usage]);
+//!
+//! # let point = |secs: u64| MeasurementPoint::new_untyped(
+//! #     Timestamp::from(UNIX_EPOCH + Duration::from_millis(secs * 1000 + 200)),
+//! #     RawMetricId::from_u64(0),
+//! #     Resource::LocalMachine,
+//! #     ResourceConsumer::LocalMachine,
+//! #     WrappedMeasurementValue::F64(1.0),
+//! # );
+//! // push the measurements of the two streams
+//! buffer.push(Stream::Energy, point(0)).unwrap();
+//! buffer.push(Stream::Energy, point(1)).unwrap();
+//! buffer.push(Stream::Usage, point(0)).unwrap();
+//! buffer.push(Stream::Usage, point(1)).unwrap();
+//!
+//! // the first window is complete: both streams have moved to the next one
+//! let windows = buffer.pop_ready();
+//! assert_eq!(windows.len(), 1);
+//! assert_eq!(windows[0].points(&Stream::Energy).len(), 1);
+//! assert_eq!(windows[0].points(&Stream::Usage).len(), 1);
+//!
+//! // a late measurement is rejected
+//! assert_eq!(buffer.push(Stream::Usage, point(0)), Err(PushError::Late));
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    time::Duration,
+};
+
+use thiserror::Error;
+
+use crate::{
+    measurement::{MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    resources::{Resource, ResourceConsumer},
+};
+
+/// Configuration of an [`AlignmentBuffer`].
+#[derive(Debug, Clone)]
+pub struct AlignmentConfig {
+    /// Duration of the time windows.
+    ///
+    /// The windows are aligned on the Unix epoch: with a window of one second,
+    /// each window starts on a whole second.
+    pub window: Duration,
+    /// How long to wait for the streams that are behind before closing a window.
+    pub allowed_lateness: Duration,
+    /// What to do when a stream has no measurement in a window that is being closed.
+    pub missing: MissingPolicy,
+}
+
+/// What to do with a window in which some streams have no measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingPolicy {
+    /// Discard the window.
+    #[default]
+    Drop,
+    /// Estimate the measurements of the missing streams.
+    ///
+    /// Each missing measurement is linearly interpolated between the previous measurement of the same series
+    /// and the next one, if the next one has already been received. Otherwise, the previous value is repeated.
+    /// If a stream has no previous measurement, the window is discarded.
+    Interpolate,
+    /// Release the window anyway, without the missing streams.
+    EmitPartial,
+}
+
+/// Counters about the data handled by an [`AlignmentBuffer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AlignmentStats {
+    /// Number of measurements that have been rejected because their window was already closed.
+    pub late_points: u64,
+    /// Number of windows that have been discarded because some streams were missing.
+    pub dropped_windows: u64,
+    /// Number of measurements that have been estimated by interpolation.
+    pub interpolated_points: u64,
+}
+
+/// Error returned by [`AlignmentBuffer::push`].
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PushError {
+    #[error("the measurement is too late: its window has already been closed")]
+    Late,
+    #[error("the stream of the measurement is unknown")]
+    UnknownStream,
+}
+
+/// Groups the measurements of multiple streams by time window.
+///
+/// `S` identifies the streams, for instance an enum with one variant per source of data.
+/// See the [module documentation](self).
+pub struct AlignmentBuffer<S> {
+    config: AlignmentConfig,
+    window_nanos: i64,
+    lateness_nanos: i64,
+    streams: Vec<S>,
+    /// Open windows, by start time (in nanoseconds since the Unix epoch).
+    windows: BTreeMap<i64, HashMap<S, Vec<MeasurementPoint>>>,
+    /// Time of the most recent measurement of each stream.
+    latest: HashMap<S, i64>,
+    /// End of the last window that has been closed.
+    closed_until: Option<i64>,
+    /// Measurements of each stream in the last window where it was present, used for the interpolation.
+    previous: HashMap<S, Vec<MeasurementPoint>>,
+    stats: AlignmentStats,
+}
+
+/// A time window released by an [`AlignmentBuffer`].
+pub struct AlignedWindow<S> {
+    start: Timestamp,
+    end: Timestamp,
+    points: HashMap<S, Vec<MeasurementPoint>>,
+    missing: Vec<S>,
+    interpolated: Vec<S>,
+}
+
+impl<S: Clone + Eq + Hash> AlignmentBuffer<S> {
+    /// Creates a new buffer that aligns the given streams.
+    ///
+    /// # Panics
+    /// Panics if the duration of the windows is zero.
+    pub fn new(config: AlignmentConfig, streams: impl IntoIterator<Item = S>) -> Self {
+        let window_nanos = duration_nanos(config.window);
+        assert!(window_nanos > 0, "the duration of the windows must not be zero");
+        Self {
+            window_nanos,
+            lateness_nanos: duration_nanos(config.allowed_lateness),
+            config,
+            streams: streams.into_iter().collect(),
+            windows: BTreeMap::new(),
+            latest: HashMap::new(),
+            closed_until: None,
+            previous: HashMap::new(),
+            stats: AlignmentStats::default(),
+        }
+    }
+
+    /// Adds a measurement of a stream to the buffer.
+    ///
+    /// If the window of the measurement has already been closed, or if the stream is unknown,
+    /// the measurement is rejected.
+    pub fn push(&mut self, stream: S, point: MeasurementPoint) -> Result<(), PushError> {
+        if !self.streams.contains(&stream) {
+            return Err(PushError::UnknownStream);
+        }
+        let t = point.timestamp.to_unix_nanos();
+        let start = t.div_euclid(self.window_nanos) * self.window_nanos;
+        if self.closed_until.is_some_and(|closed| start < closed) {
+            self.stats.late_points += 1;
+            return Err(PushError::Late);
+        }
+        let latest = self.latest.entry(stream.clone()).or_insert(t);
+        *latest = (*latest).max(t);
+        self.windows
+            .entry(start)
+            .or_default()
+            .entry(stream)
+            .or_default()
+            .push(point);
+        Ok(())
+    }
+
+    /// Returns the current watermark: the windows that end before it can be closed.
+    ///
+    /// Returns `None` if no measurement has been received yet.
+    pub fn watermark(&self) -> Option<Timestamp> {
+        self.watermark_nanos().and_then(timestamp_from_nanos)
+    }
+
+    fn watermark_nanos(&self) -> Option<i64> {
+        let most_advanced = *self.latest.values().max()?;
+        let waited_enough = most_advanced.saturating_sub(self.lateness_nanos);
+        if self.latest.len() < self.streams.len() {
+            // some streams have never produced anything
+            return Some(waited_enough);
+        }
+        let all_advanced = *self.latest.values().min()?;
+        // all_advanced is the time of a measurement that has been received, its window is not complete yet
+        let all_advanced = all_advanced.div_euclid(self.window_nanos) * self.window_nanos;
+        Some(all_advanced.max(waited_enough))
+    }
+
+    /// Closes the windows that are complete according to the watermark, and returns them in chronological order.
+    ///
+    /// The windows that are discarded because of the [`MissingPolicy`] are not returned.
+    pub fn pop_ready(&mut self) -> Vec<AlignedWindow<S>> {
+        match self.watermark_nanos() {
+            Some(watermark) => self.close_until(watermark),
+            None => Vec::new(),
+        }
+    }
+
+    /// Closes all the windows, regardless of the watermark, and returns them in chronological order.
+    ///
+    /// This is typically called when the pipeline stops.
+    pub fn flush(&mut self) -> Vec<AlignedWindow<S>> {
+        self.close_until(i64::MAX)
+    }
+
+    /// Returns the counters of the buffer.
+    pub fn stats(&self) -> AlignmentStats {
+        self.stats
+    }
+
+    /// Returns the number of windows that are still open.
+    pub fn open_windows(&self) -> usize {
+        self.windows.len()
+    }
+
+    fn close_until(&mut self, watermark: i64) -> Vec<AlignedWindow<S>> {
+        let mut res = Vec::new();
+        while let Some(entry) = self.windows.first_entry() {
+            let start = *entry.key();
+            let end = start.saturating_add(self.window_nanos);
+            if end > watermark {
+                break;
+            }
+            let points = entry.remove();
+            self.closed_until = Some(end);
+            if let Some(window) = self.close_window(start, end, points) {
+                res.push(window);
+            }
+        }
+        res
+    }
+
+    fn close_window(
+        &mut self,
+        start: i64,
+        end: i64,
+        mut points: HashMap<S, Vec<MeasurementPoint>>,
+    ) -> Option<AlignedWindow<S>> {
+        points.retain(|_, p| !p.is_empty());
+        let mut missing: Vec<S> = self
+            .streams
+            .iter()
+            .filter(|s| !points.contains_key(s))
+            .cloned()
+            .collect();
+        let mut interpolated = Vec::new();
+
+        if !missing.is_empty() && self.config.missing == MissingPolicy::Interpolate {
+            missing.retain(|s| match self.interpolate(s, start) {
+                Some(estimated) => {
+                    self.stats.interpolated_points += estimated.len() as u64;
+                    points.insert(s.clone(), estimated);
+                    interpolated.push(s.clone());
+                    false
+                }
+                None => true,
+            });
+        }
+
+        for (s, p) in points.iter() {
+            if !interpolated.contains(s) {
+                self.previous.insert(s.clone(), p.clone());
+            }
+        }
+
+        if !missing.is_empty() && self.config.missing != MissingPolicy::EmitPartial {
+            self.stats.dropped_windows += 1;
+            return None;
+        }
+        Some(AlignedWindow {
+            start: timestamp_from_nanos(start)?,
+            end: timestamp_from_nanos(end)?,
+            points,
+            missing,
+            interpolated,
+        })
+    }
+
+    /// Estimates the measurements of a stream at the beginning of a window.
+    fn interpolate(&self, stream: &S, at: i64) -> Option<Vec<MeasurementPoint>> {
+        let previous = self.previous.get(stream)?;
+        let next: &[MeasurementPoint] = self
+            .windows
+            .values()
+            .find_map(|w| w.get(stream).filter(|p| !p.is_empty()))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let timestamp = timestamp_from_nanos(at)?;
+        let res = previous
+            .iter()
+            .map(|p| {
+                let key = SeriesKey::of(p);
+                let value = next
+                    .iter()
+                    .find(|q| SeriesKey::of(q) == key)
+                    .and_then(|q| interpolate_value(p, q, at))
+                    .unwrap_or_else(|| p.value.clone());
+                let mut estimated = p.clone();
+                estimated.timestamp = timestamp;
+                estimated.value = value;
+                estimated
+            })
+            .collect();
+        Some(res)
+    }
+}
+
+impl<S: Eq + Hash> AlignedWindow<S> {
+    /// Returns the start of the window (inclusive).
+    pub fn start(&self) -> Timestamp {
+        self.start
+    }
+
+    /// Returns the end of the window (exclusive).
+    pub fn end(&self) -> Timestamp {
+        self.end
+    }
+
+    /// Returns the measurements of a stream in this window.
+    pub fn points(&self, stream: &S) -> &[MeasurementPoint] {
+        self.points.get(stream).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Takes the measurements of a stream out of this window.
+    pub fn take(&mut self, stream: &S) -> Vec<MeasurementPoint> {
+        self.points.remove(stream).unwrap_or_default()
+    }
+
+    /// Returns `true` if every stream has measurements in this window,
+    /// possibly estimated by interpolation.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// Returns the streams that have no measurement in this window.
+    pub fn missing(&self) -> &[S] {
+        &self.missing
+    }
+
+    /// Returns the streams whose measurements have been estimated by interpolation.
+    pub fn interpolated(&self) -> &[S] {
+        &self.interpolated
+    }
+}
+
+/// Identifies a series of measurements, for the interpolation.
+#[derive(PartialEq)]
+struct SeriesKey<'a> {
+    metric: RawMetricId,
+    resource: &'a Resource,
+    consumer: &'a ResourceConsumer,
+}
+
+impl<'a> SeriesKey<'a> {
+    fn of(point: &'a MeasurementPoint) -> Self {
+        Self {
+            metric: point.metric,
+            resource: &point.resource,
+            consumer: &point.consumer,
+        }
+    }
+}
+
+/// Linearly interpolates the value of a series at time `at`, between the points `p` and `q`.
+///
+/// The value keeps the type of `p`. Returns `None` if the values cannot be interpolated.
+fn interpolate_value(p: &MeasurementPoint, q: &MeasurementPoint, at: i64) -> Option<WrappedMeasurementValue> {
+    let (tp, tq) = (p.timestamp.to_unix_nanos(), q.timestamp.to_unix_nanos());
+    if tq <= tp {
+        return None;
+    }
+    let ratio = ((at - tp) as f64 / (tq - tp) as f64).clamp(0.0, 1.0);
+    let v = match (&p.value, &q.value) {
+        (WrappedMeasurementValue::F64(a), WrappedMeasurementValue::F64(b)) => a + (b - a) * ratio,
+        (WrappedMeasurementValue::U64(a), WrappedMeasurementValue::U64(b)) => {
+            let v = *a as f64 + (*b as f64 - *a as f64) * ratio;
+            return Some(WrappedMeasurementValue::U64(v.round() as u64));
+        }
+        (WrappedMeasurementValue::I64(a), WrappedMeasurementValue::I64(b)) => {
+            let v = *a as f64 + (*b as f64 - *a as f64) * ratio;
+            return Some(WrappedMeasurementValue::I64(v.round() as i64));
+        }
+        _ => return None,
+    };
+    Some(WrappedMeasurementValue::F64(v))
+}
+
+fn duration_nanos(d: Duration) -> i64 {
+    i64::try_from(d.as_nanos()).unwrap_or(i64::MAX)
+}
+
+fn timestamp_from_nanos(nanos: i64) -> Option<Timestamp> {
+    Timestamp::from_unix_timestamp(nanos.div_euclid(1_000_000_000), nanos.rem_euclid(1_000_000_000) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{AlignmentBuffer, AlignmentConfig, MissingPolicy, PushError};
+    use crate::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Stream {
+        A,
+        B,
+    }
+
+    fn point(t_millis: u64, value: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_millis(t_millis)),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(value),
+        )
+    }
+
+    fn buffer(missing: MissingPolicy) -> AlignmentBuffer<Stream> {
+        let config = AlignmentConfig {
+            window: Duration::from_secs(1),
+            allowed_lateness: Duration::from_secs(3),
+            missing,
+        };
+        AlignmentBuffer::new(config, [Stream::A, Stream::B])
+    }
+
+    fn values(points: &[MeasurementPoint]) -> Vec<String> {
+        points.iter().map(|p| p.value.to_string()).collect()
+    }
+
+    #[test]
+    fn join_by_window() {
+        let mut buf = buffer(MissingPolicy::Drop);
+        buf.push(Stream::A, point(100, 1)).unwrap();
+        buf.push(Stream::A, point(600, 2)).unwrap();
+        buf.push(Stream::A, point(1100, 3)).unwrap();
+        // B is behind: nothing can be closed
+        assert!(buf.pop_ready().is_empty());
+
+        buf.push(Stream::B, point(900, 10)).unwrap();
+        assert!(buf.pop_ready().is_empty());
+        buf.push(Stream::B, point(1900, 20)).unwrap();
+        let windows = buf.pop_ready();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].start(), Timestamp::from(UNIX_EPOCH));
+        assert_eq!(windows[0].end(), Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)));
+        assert_eq!(values(windows[0].points(&Stream::A)), vec!["1", "2"]);
+        assert_eq!(values(windows[0].points(&Stream::B)), vec!["10"]);
+        assert!(windows[0].is_complete());
+
+        // late point
+        assert_eq!(buf.push(Stream::B, point(950, 11)), Err(PushError::Late));
+        assert_eq!(buf.stats().late_points, 1);
+
+        let windows = buf.flush();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(values(windows[0].points(&Stream::B)), vec!["20"]);
+        assert_eq!(buf.open_windows(), 0);
+    }
+
+    #[test]
+    fn missing_partner() {
+        for (policy, expected) in [
+            (MissingPolicy::Drop, None),
+            (MissingPolicy::EmitPartial, Some(vec![])),
+            (MissingPolicy::Interpolate, Some(vec!["15"])),
+        ] {
+            let mut buf = buffer(policy);
+            buf.push(Stream::A, point(0, 1)).unwrap();
+            buf.push(Stream::B, point(0, 10)).unwrap();
+            for t in 1..=5 {
+                buf.push(Stream::A, point(t * 1000, t)).unwrap();
+            }
+            // B is missing in the window [1s, 2s[
+            buf.push(Stream::B, point(2000, 20)).unwrap();
+
+            // the allowed lateness is 3s, A is at 5s: the window [1s, 2s[ is closed even though B is behind
+            let windows = buf.pop_ready();
+            assert_eq!(windows[0].start(), Timestamp::from(UNIX_EPOCH));
+            let second = windows
+                .iter()
+                .find(|w| w.start() == Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)));
+            match expected {
+                None => {
+                    assert!(second.is_none(), "{policy:?}");
+                    assert_eq!(buf.stats().dropped_windows, 1);
+                }
+                Some(b_values) => {
+                    let second = second.unwrap();
+                    assert_eq!(values(second.points(&Stream::A)), vec!["1"]);
+                    assert_eq!(values(second.points(&Stream::B)), b_values, "{policy:?}");
+                    if policy == MissingPolicy::Interpolate {
+                        assert!(second.is_complete());
+                        assert_eq!(second.interpolated(), &[Stream::B]);
+                        assert_eq!(buf.stats().interpolated_points, 1);
+                    } else {
+                        assert_eq!(second.missing(), &[Stream::B]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_stream() {
+        let mut buf = AlignmentBuffer::new(
+            AlignmentConfig {
+                window: Duration::from_secs(1),
+                allowed_lateness: Duration::ZERO,
+                missing: MissingPolicy::Drop,
+            },
+            [Stream::A],
+        );
+        assert_eq!(buf.push(Stream::B, point(0, 1)), Err(PushError::UnknownStream));
+        assert!(buf.watermark().is_none());
+    }
+}
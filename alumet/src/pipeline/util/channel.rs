@@ -1,10 +1,16 @@
 //! Abstractions over different kinds of channel.
 
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
 use futures::Stream;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{mpsc, Notify};
 
 use crate::measurement::MeasurementBuffer;
 
+use super::spill::SpillQueue;
+
 /// Trait that allows to receive measurements from different kinds of channel.
 pub trait MeasurementReceiver {
     async fn recv(&mut self) -> Result<MeasurementBuffer, RecvError>;
@@ -12,14 +18,14 @@ pub trait MeasurementReceiver {
 }
 
 pub enum ReceiverEnum {
-    Broadcast(broadcast::Receiver<MeasurementBuffer>),
+    Fanout(FanoutReceiver),
     Single(mpsc::Receiver<MeasurementBuffer>),
 }
 
 pub struct ReceiverProvider(ProviderEnum);
 
 enum ProviderEnum {
-    Broadcast(broadcast::Sender<MeasurementBuffer>),
+    Fanout(FanoutSender),
     Single(Option<mpsc::Receiver<MeasurementBuffer>>),
 }
 
@@ -36,24 +42,329 @@ pub enum StreamRecvError {
     Lagged(u64),
 }
 
+// fan-out channel
+
+/// What to do when an output does not receive its measurements fast enough, and its queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for the output to catch up.
+    ///
+    /// This blocks the transforms, and in turn the sources (backpressure):
+    /// no measurement is lost, but a slow output slows down the whole pipeline.
+    Block,
+    /// Drop the oldest measurements of the queue to make room for the new ones.
+    #[default]
+    DropOldest,
+    /// Drop the new measurements.
+    DropNewest,
+    /// Store the new measurements on the disk, and give them to the output once it has caught up.
+    ///
+    /// When the on-disk queue is full, the new measurements are dropped.
+    Spill,
+}
+
+/// Counters of the measurements that could not be put in the queue of an output.
+#[derive(Default)]
+pub struct OverflowCounters {
+    pub dropped_buffers: AtomicU64,
+    pub dropped_points: AtomicU64,
+    pub spilled_buffers: AtomicU64,
+}
+
+/// Sends measurements to multiple outputs, each with its own bounded queue.
+///
+/// Unlike a broadcast channel, where a lagging receiver silently loses the oldest messages,
+/// each queue applies an [`OverflowPolicy`] when it is full.
+pub struct FanoutSender {
+    shared: Arc<FanoutShared>,
+}
+
+struct FanoutShared {
+    queues: Mutex<Vec<Arc<OutputQueue>>>,
+    /// Number of senders, the queues are closed when it reaches zero.
+    senders: AtomicUsize,
+    capacity: usize,
+}
+
+/// Receives the measurements sent by a [`FanoutSender`].
+pub struct FanoutReceiver {
+    queue: Arc<OutputQueue>,
+}
+
+/// Allows to configure the queue of a [`FanoutReceiver`] after the receiver has been moved.
+#[derive(Clone)]
+pub struct OverflowHandle {
+    queue: Arc<OutputQueue>,
+}
+
+struct OutputQueue {
+    state: Mutex<QueueState>,
+    /// Notified when a buffer is added to the queue, or when the queue is closed.
+    not_empty: Notify,
+    /// Notified when a buffer is removed from the queue, or when the receiver is dropped.
+    not_full: Notify,
+    capacity: usize,
+    counters: Arc<OverflowCounters>,
+}
+
+struct QueueState {
+    buffers: VecDeque<MeasurementBuffer>,
+    policy: OverflowPolicy,
+    spill: Option<SpillQueue>,
+    /// Number of buffers dropped since the last call to `recv`.
+    lagged: u64,
+    /// All the senders have been dropped.
+    closed: bool,
+    /// The receiver has been dropped.
+    disconnected: bool,
+}
+
+/// Error returned by [`FanoutSender::send`] when all the receivers have been dropped.
+#[derive(Debug, thiserror::Error)]
+#[error("all the receivers have been dropped")]
+pub struct Disconnected;
+
+impl FanoutSender {
+    /// Creates a new sender, without any receiver.
+    ///
+    /// Each receiver has a queue that can hold `capacity` buffers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            shared: Arc::new(FanoutShared {
+                queues: Mutex::new(Vec::new()),
+                senders: AtomicUsize::new(1),
+                capacity,
+            }),
+        }
+    }
+
+    /// Creates a new receiver, which will get all the measurements sent after this call.
+    ///
+    /// Its queue uses the default [`OverflowPolicy`] until it is reconfigured.
+    pub fn subscribe(&self) -> FanoutReceiver {
+        let queue = Arc::new(OutputQueue {
+            state: Mutex::new(QueueState {
+                buffers: VecDeque::new(),
+                policy: OverflowPolicy::default(),
+                spill: None,
+                lagged: 0,
+                closed: false,
+                disconnected: false,
+            }),
+            not_empty: Notify::new(),
+            not_full: Notify::new(),
+            capacity: self.shared.capacity.max(1),
+            counters: Arc::new(OverflowCounters::default()),
+        });
+        self.shared.queues.lock().unwrap().push(queue.clone());
+        FanoutReceiver { queue }
+    }
+
+    /// Sends measurements to every receiver.
+    ///
+    /// Depending on the [`OverflowPolicy`] of the receivers, this may wait for them to have room in their queue.
+    pub async fn send(&self, measurements: MeasurementBuffer) -> Result<(), Disconnected> {
+        let queues = {
+            let mut queues = self.shared.queues.lock().unwrap();
+            queues.retain(|q| !q.state.lock().unwrap().disconnected);
+            queues.clone()
+        };
+        let Some((last, others)) = queues.split_last() else {
+            return Err(Disconnected);
+        };
+        for q in others {
+            q.push(measurements.clone()).await;
+        }
+        last.push(measurements).await;
+        Ok(())
+    }
+}
+
+impl Clone for FanoutSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for FanoutSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            for q in self.shared.queues.lock().unwrap().iter() {
+                q.state.lock().unwrap().closed = true;
+                q.not_empty.notify_one();
+            }
+        }
+    }
+}
+
+impl FanoutReceiver {
+    /// Returns a handle that allows to configure the queue of this receiver.
+    pub fn overflow_handle(&self) -> OverflowHandle {
+        OverflowHandle {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl Drop for FanoutReceiver {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().unwrap();
+        state.disconnected = true;
+        state.buffers.clear();
+        drop(state);
+        self.queue.not_full.notify_one();
+    }
+}
+
+impl OverflowHandle {
+    /// Sets the policy to apply when the queue is full.
+    ///
+    /// `spill` is the on-disk queue used by [`OverflowPolicy::Spill`].
+    pub fn set_policy(&self, policy: OverflowPolicy, spill: Option<SpillQueue>) {
+        let mut state = self.queue.state.lock().unwrap();
+        state.policy = policy;
+        state.spill = spill;
+        drop(state);
+        // a blocked sender may be able to continue
+        self.queue.not_full.notify_one();
+    }
+
+    pub fn counters(&self) -> Arc<OverflowCounters> {
+        self.queue.counters.clone()
+    }
+
+    /// Returns the number of buffers that are waiting in the in-memory queue.
+    pub fn len(&self) -> usize {
+        self.queue.state.lock().unwrap().buffers.len()
+    }
+}
+
+impl OutputQueue {
+    async fn push(&self, mut buffer: MeasurementBuffer) {
+        loop {
+            let notified = self.not_full.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.disconnected {
+                    return;
+                }
+                let full = state.buffers.len() >= self.capacity;
+                if state.policy == OverflowPolicy::Spill {
+                    // Once some buffers have been spilled, the next ones must follow them, in order to keep the order.
+                    if let Some(spill) = state.spill.as_mut().filter(|q| full || !q.is_empty()) {
+                        // the spill files store rows
+                        buffer.materialize();
+                        match spill.push(&buffer) {
+                            Ok(()) => {
+                                self.counters.spilled_buffers.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(e) => {
+                                log::error!("Could not spill measurements to {:?}: {e}", spill.dir());
+                                self.drop_buffer(&mut state, &buffer);
+                            }
+                        }
+                        drop(state);
+                        self.not_empty.notify_one();
+                        return;
+                    }
+                }
+                if !full {
+                    state.buffers.push_back(buffer);
+                    drop(state);
+                    self.not_empty.notify_one();
+                    return;
+                }
+                match state.policy {
+                    OverflowPolicy::Block => (), // wait below
+                    OverflowPolicy::DropOldest => {
+                        let oldest = state.buffers.pop_front().unwrap();
+                        self.drop_buffer(&mut state, &oldest);
+                        state.buffers.push_back(buffer);
+                        drop(state);
+                        self.not_empty.notify_one();
+                        return;
+                    }
+                    OverflowPolicy::DropNewest | OverflowPolicy::Spill => {
+                        self.drop_buffer(&mut state, &buffer);
+                        drop(state);
+                        self.not_empty.notify_one();
+                        return;
+                    }
+                }
+            }
+            notified.await;
+        }
+    }
+
+    fn drop_buffer(&self, state: &mut QueueState, buffer: &MeasurementBuffer) {
+        state.lagged += 1;
+        self.counters.dropped_buffers.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .dropped_points
+            .fetch_add(buffer.len() as u64, Ordering::Relaxed);
+    }
+
+    async fn pop(&self) -> Result<MeasurementBuffer, RecvError> {
+        loop {
+            let notified = self.not_empty.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.lagged > 0 {
+                    let n = std::mem::take(&mut state.lagged);
+                    return Err(RecvError::Lagged(n));
+                }
+                if let Some(buffer) = state.buffers.pop_front() {
+                    drop(state);
+                    self.not_full.notify_one();
+                    return Ok(buffer);
+                }
+                // The buffers in memory are older than the spilled ones.
+                if let Some(spill) = state.spill.as_mut().filter(|q| !q.is_empty()) {
+                    let res = spill.front();
+                    if let Err(e) = spill.pop_front() {
+                        log::warn!("Could not delete a file of the spill queue in {:?}: {e}", spill.dir());
+                    }
+                    match res {
+                        Ok(Some(buffer)) => return Ok(buffer),
+                        Ok(None) => unreachable!("the spill queue should not be empty"),
+                        Err(e) => {
+                            log::error!("Could not read the spill queue in {:?}: {e}", spill.dir());
+                            state.lagged += 1;
+                            self.counters.dropped_buffers.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                    }
+                }
+                if state.closed {
+                    return Err(RecvError::Closed);
+                }
+            }
+            notified.await;
+        }
+    }
+}
+
 // receiver implementations
 
-impl MeasurementReceiver for broadcast::Receiver<MeasurementBuffer> {
+impl MeasurementReceiver for FanoutReceiver {
     async fn recv(&mut self) -> Result<MeasurementBuffer, RecvError> {
-        broadcast::Receiver::recv(self).await.map_err(|e| match e {
-            broadcast::error::RecvError::Closed => RecvError::Closed,
-            broadcast::error::RecvError::Lagged(n) => RecvError::Lagged(n),
-        })
+        self.queue.pop().await
     }
 
     fn into_stream(self) -> impl Stream<Item = Result<MeasurementBuffer, StreamRecvError>> {
-        use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
-        use tokio_stream::StreamExt;
-
-        BroadcastStream::new(self).map(|item| {
-            item.map_err(|e| match e {
-                BroadcastStreamRecvError::Lagged(n) => StreamRecvError::Lagged(n),
-            })
+        futures::stream::unfold(self, |mut rx| async move {
+            match rx.recv().await {
+                Ok(buffer) => Some((Ok(buffer), rx)),
+                Err(RecvError::Lagged(n)) => Some((Err(StreamRecvError::Lagged(n)), rx)),
+                Err(RecvError::Closed) => None,
+            }
         })
     }
 }
@@ -77,7 +388,7 @@ impl MeasurementReceiver for mpsc::Receiver<MeasurementBuffer> {
 impl ReceiverProvider {
     pub fn get(&mut self) -> ReceiverEnum {
         match &mut self.0 {
-            ProviderEnum::Broadcast(tx) => ReceiverEnum::Broadcast(tx.subscribe()),
+            ProviderEnum::Fanout(tx) => ReceiverEnum::Fanout(tx.subscribe()),
             ProviderEnum::Single(rx) => ReceiverEnum::Single(
                 rx.take()
                     .expect("ProviderEnum::get called but the single MeasurementReceiver has already been taken"),
@@ -86,9 +397,9 @@ impl ReceiverProvider {
     }
 }
 
-impl From<broadcast::Sender<MeasurementBuffer>> for ReceiverProvider {
-    fn from(value: broadcast::Sender<MeasurementBuffer>) -> Self {
-        Self(ProviderEnum::Broadcast(value))
+impl From<FanoutSender> for ReceiverProvider {
+    fn from(value: FanoutSender) -> Self {
+        Self(ProviderEnum::Fanout(value))
     }
 }
 
@@ -97,3 +408,163 @@ impl From<mpsc::Receiver<MeasurementBuffer>> for ReceiverProvider {
         Self(ProviderEnum::Single(Some(value)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    use crate::{
+        measurement::{
+            columnar::{AttributesRef, ColumnarBuffer},
+            MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue,
+        },
+        metrics::RawMetricId,
+        pipeline::util::spill::SpillQueue,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{FanoutSender, MeasurementReceiver, OverflowPolicy, RecvError};
+
+    fn buffer(i: u64) -> MeasurementBuffer {
+        MeasurementBuffer::from(vec![MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(i),
+        )])
+    }
+
+    fn value(res: Result<MeasurementBuffer, RecvError>) -> String {
+        match res {
+            Ok(buf) => buf.iter().next().unwrap().value.to_string(),
+            Err(RecvError::Lagged(n)) => format!("lagged {n}"),
+            Err(RecvError::Closed) => String::from("closed"),
+        }
+    }
+
+    /// Sends 4 buffers to a queue of capacity 2, then receives everything.
+    async fn overflow(policy: OverflowPolicy, spill: Option<SpillQueue>) -> Vec<String> {
+        let tx = FanoutSender::new(2);
+        let mut rx = tx.subscribe();
+        rx.overflow_handle().set_policy(policy, spill);
+        for i in 0..4 {
+            tx.send(buffer(i)).await.unwrap();
+        }
+        drop(tx);
+        let mut received = Vec::new();
+        loop {
+            let v = value(rx.recv().await);
+            let closed = v == "closed";
+            received.push(v);
+            if closed {
+                return received;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_policies() {
+        assert_eq!(
+            overflow(OverflowPolicy::DropOldest, None).await,
+            vec!["lagged 2", "2", "3", "closed"]
+        );
+        assert_eq!(
+            overflow(OverflowPolicy::DropNewest, None).await,
+            vec!["lagged 2", "0", "1", "closed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn spill_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let spill = SpillQueue::open(dir.path(), 1 << 20).unwrap();
+        assert_eq!(
+            overflow(OverflowPolicy::Spill, Some(spill)).await,
+            vec!["0", "1", "2", "3", "closed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn spill_columnar_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let tx = FanoutSender::new(1);
+        let mut rx = tx.subscribe();
+        rx.overflow_handle().set_policy(
+            OverflowPolicy::Spill,
+            Some(SpillQueue::open(dir.path(), 1 << 20).unwrap()),
+        );
+
+        // the second buffer overflows, it contains a row and a columnar batch
+        tx.send(buffer(0)).await.unwrap();
+        let mut batch = ColumnarBuffer::new();
+        let machine = batch.resource(Resource::LocalMachine);
+        let consumer = batch.consumer(ResourceConsumer::LocalMachine);
+        for i in 2..5 {
+            let value = WrappedMeasurementValue::U64(i);
+            batch.push_untyped(
+                Timestamp::now(),
+                RawMetricId::from_u64(0),
+                value,
+                machine,
+                consumer,
+                AttributesRef::EMPTY,
+            );
+        }
+        let mut mixed = buffer(1);
+        mixed.push_columnar(batch);
+        tx.send(mixed).await.unwrap();
+        assert_eq!(
+            rx.overflow_handle().counters().spilled_buffers.load(Ordering::Relaxed),
+            1
+        );
+
+        assert_eq!(value(rx.recv().await), "0");
+        let Ok(spilled) = rx.recv().await else {
+            panic!("the spilled buffer should be received");
+        };
+        let values: Vec<String> = spilled.iter().map(|p| p.value.to_string()).collect();
+        assert_eq!(values, vec!["1", "2", "3", "4"]);
+    }
+
+    #[tokio::test]
+    async fn block_policy() {
+        let tx = FanoutSender::new(1);
+        let mut rx = tx.subscribe();
+        let mut other = tx.subscribe();
+        rx.overflow_handle().set_policy(OverflowPolicy::Block, None);
+        let counters = other.overflow_handle().counters();
+
+        let sender = tokio::spawn(async move {
+            for i in 0..3 {
+                tx.send(buffer(i)).await.unwrap();
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(
+            !sender.is_finished(),
+            "the sender should wait for the blocking receiver"
+        );
+        for i in 0..3 {
+            assert_eq!(value(rx.recv().await), i.to_string());
+        }
+        sender.await.unwrap();
+        assert_eq!(value(rx.recv().await), "closed");
+
+        // the other receiver has the default policy: drop the oldest
+        assert_eq!(value(other.recv().await), "lagged 2");
+        assert_eq!(value(other.recv().await), "2");
+        assert_eq!(counters.dropped_buffers.load(std::sync::atomic::Ordering::Relaxed), 2);
+        assert_eq!(counters.dropped_points.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn dropped_receiver_does_not_block() {
+        let tx = FanoutSender::new(1);
+        let rx = tx.subscribe();
+        rx.overflow_handle().set_policy(OverflowPolicy::Block, None);
+        tx.send(buffer(0)).await.unwrap();
+        drop(rx);
+        assert!(tx.send(buffer(1)).await.is_err());
+    }
+}
@@ -178,6 +178,19 @@ impl FromStr for ElementSelector {
     }
 }
 
+impl FromStr for OutputSelector {
+    type Err = SelectorParseError;
+
+    /// Parses a selector of outputs, such as `my-plugin`, `my-plugin/output/my-output` or `*/out/*`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match ElementSelector::from_str(s)? {
+            ElementSelector::Output(selector) => Ok(selector),
+            ElementSelector::Any(patterns) => Ok(OutputSelector::new(patterns)),
+            _ => Err(SelectorParseError::InvalidKind(KindParseError)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct KindParseError;
 
@@ -223,7 +236,9 @@ impl FromStr for NamePattern {
 mod tests {
     use std::str::FromStr;
 
-    use crate::pipeline::matching::{ElementSelector, NamePattern, NamePatternParseError, NamePatterns};
+    use crate::pipeline::matching::{
+        ElementSelector, NamePattern, NamePatternParseError, NamePatterns, OutputSelector,
+    };
 
     #[test]
     fn parse_name_pattern() -> anyhow::Result<()> {
@@ -309,4 +324,26 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn parse_output_selector() -> anyhow::Result<()> {
+        assert_eq!(
+            OutputSelector::from_str("my-plugin")?,
+            NamePatterns {
+                plugin: NamePattern::Exact(String::from("my-plugin")),
+                name: NamePattern::Any
+            }
+            .into()
+        );
+        assert_eq!(
+            OutputSelector::from_str("*/out/my-output")?,
+            NamePatterns {
+                plugin: NamePattern::Any,
+                name: NamePattern::Exact(String::from("my-output"))
+            }
+            .into()
+        );
+        assert!(OutputSelector::from_str("my-plugin/sources/*").is_err());
+        Ok(())
+    }
 }
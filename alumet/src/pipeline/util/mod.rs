@@ -1,7 +1,11 @@
+pub mod adaptive;
+pub mod alignment;
 pub mod channel;
 pub mod matching;
 pub mod naming;
+pub mod routing;
 pub mod scope;
+pub mod spill;
 pub mod stream;
 pub mod threading;
 
@@ -0,0 +1,166 @@
+//! Route measurements to some outputs only.
+//!
+//! By default, every output receives every measurement. An [`OutputRoute`] restricts
+//! the measurements that the selected outputs receive.
+
+use crate::{
+    measurement::{MeasurementBuffer, MeasurementPoint},
+    metrics::MetricRegistry,
+};
+
+use super::{
+    matching::{NamePattern, OutputSelector},
+    naming::OutputName,
+};
+
+/// Restricts the measurements that are sent to some outputs.
+///
+/// If several routes select the same output, the output receives the measurements
+/// that are accepted by at least one of them.
+#[derive(Debug, Clone)]
+pub struct OutputRoute {
+    /// The outputs that this route applies to.
+    pub outputs: OutputSelector,
+    /// The measurements that the outputs accept.
+    pub filter: MeasurementFilter,
+}
+
+/// Accepts or rejects measurement points based on their metric, resource and consumer.
+///
+/// An empty list accepts everything. A point must satisfy all the conditions to be accepted.
+#[derive(Debug, Clone, Default)]
+pub struct MeasurementFilter {
+    /// Patterns on the name of the metric.
+    pub metrics: Vec<NamePattern>,
+    /// Accepted kinds of resources, for instance `cpu_package`.
+    pub resource_kinds: Vec<String>,
+    /// Accepted kinds of consumers, for instance `process`.
+    pub consumer_kinds: Vec<String>,
+}
+
+impl MeasurementFilter {
+    /// Returns `true` if the point is accepted by this filter.
+    pub fn accepts(&self, point: &MeasurementPoint, metrics: &MetricRegistry) -> bool {
+        (self.resource_kinds.is_empty() || self.resource_kinds.iter().any(|k| k == point.resource.kind()))
+            && (self.consumer_kinds.is_empty() || self.consumer_kinds.iter().any(|k| k == point.consumer.kind()))
+            && (self.metrics.is_empty()
+                || metrics
+                    .by_id(&point.metric)
+                    .is_some_and(|m| self.metrics.iter().any(|pat| pat.matches(&m.name))))
+    }
+}
+
+/// The filters that apply to a particular output.
+#[derive(Debug)]
+pub(crate) struct OutputFilter(Vec<MeasurementFilter>);
+
+impl OutputFilter {
+    /// Returns the filters that apply to the output, or `None` if no route selects it.
+    pub fn for_output(routes: &[OutputRoute], name: &OutputName) -> Option<Self> {
+        let filters: Vec<MeasurementFilter> = routes
+            .iter()
+            .filter(|r| r.outputs.matches(name))
+            .map(|r| r.filter.clone())
+            .collect();
+        if filters.is_empty() {
+            None
+        } else {
+            Some(Self(filters))
+        }
+    }
+
+    /// Returns a new buffer with the measurements that the output accepts.
+    pub fn apply(&self, measurements: &MeasurementBuffer, metrics: &MetricRegistry) -> MeasurementBuffer {
+        let mut res = MeasurementBuffer::new();
+        for point in measurements.iter() {
+            if self.0.iter().any(|f| f.accepts(point, metrics)) {
+                res.push(point.clone());
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::{Metric, MetricKind, MetricRegistry, RawMetricId},
+        pipeline::util::{
+            matching::{NamePattern, OutputSelector},
+            naming::{ElementNameParts, OutputName},
+        },
+        resources::{Resource, ResourceConsumer},
+        units::Unit,
+    };
+
+    use super::{MeasurementFilter, OutputFilter, OutputRoute};
+
+    fn registry() -> (MetricRegistry, RawMetricId, RawMetricId) {
+        let mut registry = MetricRegistry::new();
+        let metric = |name: &str| Metric {
+            name: name.to_owned(),
+            description: String::new(),
+            value_type: crate::measurement::WrappedMeasurementType::F64,
+            unit: Unit::Watt.into(),
+            kind: MetricKind::Gauge,
+            labels: vec![],
+        };
+        let raw = registry.register(metric("power_raw")).unwrap();
+        let mean = registry.register(metric("power_mean")).unwrap();
+        (registry, raw, mean)
+    }
+
+    fn point(metric: RawMetricId, resource: Resource) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            metric,
+            resource,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(1.0),
+        )
+    }
+
+    #[test]
+    fn filter_output() {
+        let (registry, raw, mean) = registry();
+        let routes = vec![
+            OutputRoute {
+                outputs: OutputSelector::from_str("influxdb").unwrap(),
+                filter: MeasurementFilter {
+                    metrics: vec![NamePattern::EndWith(String::from("_mean"))],
+                    ..Default::default()
+                },
+            },
+            OutputRoute {
+                outputs: OutputSelector::from_str("influxdb/out/*").unwrap(),
+                filter: MeasurementFilter {
+                    resource_kinds: vec![String::from("dram")],
+                    ..Default::default()
+                },
+            },
+        ];
+        let buf = MeasurementBuffer::from(vec![
+            point(raw, Resource::CpuPackage { id: 0 }),
+            point(mean, Resource::CpuPackage { id: 0 }),
+            point(raw, Resource::Dram { pkg_id: 0 }),
+        ]);
+
+        let output_name = |plugin: &str, element: &str| {
+            OutputName(ElementNameParts {
+                plugin: plugin.to_owned(),
+                element: element.to_owned(),
+            })
+        };
+        let csv = output_name("csv", "out");
+        assert!(OutputFilter::for_output(&routes, &csv).is_none());
+
+        let influx = output_name("influxdb", "out");
+        let filter = OutputFilter::for_output(&routes, &influx).unwrap();
+        let filtered = filter.apply(&buf, &registry);
+        let kept: Vec<(RawMetricId, &str)> = filtered.iter().map(|p| (p.metric, p.resource.kind())).collect();
+        assert_eq!(kept, vec![(mean, "cpu_package"), (raw, "dram")]);
+    }
+}
@@ -0,0 +1,473 @@
+//! Bounded on-disk queue of measurements.
+//!
+//! When an output cannot write its measurements for a while (for instance because
+//! the database it writes to is down), the measurements are "spilled" to the disk
+//! instead of being kept in memory. They are replayed in order once the output recovers.
+//!
+//! The queue only lives as long as the pipeline: metric ids are not stable between
+//! two runs of Alumet, hence the measurements that remain on the disk after a shutdown
+//! cannot be interpreted by the next run. When the queue is reopened, they are moved to a
+//! `stale-<timestamp>` subdirectory, so that they are not overwritten and can be inspected.
+
+use std::{
+    collections::VecDeque,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    measurement::{AttributeValue, Histogram, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    resources::{Resource, ResourceConsumer},
+};
+
+/// Magic number at the beginning of each file of the queue, followed by the version of the format.
+const MAGIC: &[u8; 4] = b"ASPL";
+const FORMAT_VERSION: u8 = 1;
+const FILE_EXTENSION: &str = "spill";
+
+/// A bounded FIFO queue of [`MeasurementBuffer`], stored in a directory.
+///
+/// Each buffer is stored in its own file, named after its position in the queue.
+pub struct SpillQueue {
+    dir: PathBuf,
+    /// Maximum total size of the files, in bytes.
+    max_bytes: u64,
+    /// Files in the queue, from the oldest to the newest.
+    files: VecDeque<SpillFile>,
+    /// Sequence number of the next file.
+    next_seq: u64,
+    total_bytes: u64,
+}
+
+struct SpillFile {
+    seq: u64,
+    /// Size of the file, in bytes.
+    size: u64,
+    /// Number of measurement points in the buffer, known without reading the file.
+    points: usize,
+}
+
+/// Error returned by [`SpillQueue::push`].
+#[derive(Debug, thiserror::Error)]
+pub enum SpillError {
+    #[error("the spill queue is full ({0} bytes)")]
+    Full(u64),
+    #[error("could not write to the spill queue")]
+    Io(#[from] io::Error),
+}
+
+impl SpillQueue {
+    /// Opens a queue in the given directory, which is created if it does not exist.
+    ///
+    /// The files that have been left by a previous queue are moved out of the way, to a
+    /// `stale-<timestamp>` subdirectory (see the [module documentation](self)).
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut stale = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == FILE_EXTENSION) {
+                stale.push(path);
+            }
+        }
+        if !stale.is_empty() {
+            let millis = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let stale_dir = dir.join(format!("stale-{millis}"));
+            fs::create_dir_all(&stale_dir)?;
+            let mut points = 0;
+            for path in &stale {
+                // a file that cannot be read does not prevent the queue from being opened
+                points += read_len(path).unwrap_or(0);
+                fs::rename(path, stale_dir.join(path.file_name().unwrap()))?;
+            }
+            log::warn!(
+                "{} buffers ({points} measurement points) had been spilled to {dir:?} by a previous run of Alumet and cannot be replayed. They have been moved to {stale_dir:?}.",
+                stale.len()
+            );
+        }
+        Ok(Self {
+            dir,
+            max_bytes,
+            files: VecDeque::new(),
+            next_seq: 0,
+            total_bytes: 0,
+        })
+    }
+
+    /// The directory that contains the queue.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Number of buffers in the queue.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Number of measurement points in the oldest buffer of the queue.
+    pub fn front_points(&self) -> usize {
+        self.files.front().map_or(0, |f| f.points)
+    }
+
+    /// Number of measurement points in the queue.
+    pub fn total_points(&self) -> usize {
+        self.files.iter().map(|f| f.points).sum()
+    }
+
+    /// Appends a buffer at the end of the queue.
+    ///
+    /// If the queue is full, the buffer is not added and [`SpillError::Full`] is returned.
+    pub fn push(&mut self, buffer: &MeasurementBuffer) -> Result<(), SpillError> {
+        let bytes = encode(buffer);
+        let size = bytes.len() as u64;
+        if self.total_bytes + size > self.max_bytes {
+            return Err(SpillError::Full(self.max_bytes));
+        }
+        let seq = self.next_seq;
+        fs::write(self.path(seq), bytes)?;
+        self.next_seq += 1;
+        self.files.push_back(SpillFile {
+            seq,
+            size,
+            points: buffer.len(),
+        });
+        self.total_bytes += size;
+        Ok(())
+    }
+
+    /// Reads the oldest buffer of the queue, without removing it.
+    pub fn front(&self) -> io::Result<Option<MeasurementBuffer>> {
+        match self.files.front() {
+            Some(file) => {
+                let bytes = fs::read(self.path(file.seq))?;
+                decode(&bytes).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the oldest buffer of the queue.
+    pub fn pop_front(&mut self) -> io::Result<()> {
+        if let Some(file) = self.files.pop_front() {
+            self.total_bytes -= file.size;
+            fs::remove_file(self.path(file.seq))?;
+        }
+        Ok(())
+    }
+
+    fn path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{seq:020}.{FILE_EXTENSION}"))
+    }
+}
+
+// ---- Encoding ----
+// The format is a simple little-endian binary format, which is only read by the agent that wrote it.
+
+fn encode(buffer: &MeasurementBuffer) -> Vec<u8> {
+    let mut w = Writer(Vec::with_capacity(64 * buffer.len() + 16));
+    w.0.extend_from_slice(MAGIC);
+    w.u8(FORMAT_VERSION);
+    w.u64(buffer.len() as u64);
+    for p in buffer.iter() {
+        let (secs, nanos) = p.timestamp.to_unix_timestamp();
+        w.i64(secs);
+        w.u32(nanos);
+        w.u64(p.metric.as_u64());
+        w.str(p.resource.kind());
+        w.str(&p.resource.id_display().to_string());
+        w.str(p.consumer.kind());
+        w.str(&p.consumer.id_display().to_string());
+        match &p.value {
+            WrappedMeasurementValue::F64(v) => {
+                w.u8(0);
+                w.f64(*v);
+            }
+            WrappedMeasurementValue::U64(v) => {
+                w.u8(1);
+                w.u64(*v);
+            }
+            WrappedMeasurementValue::I64(v) => {
+                w.u8(2);
+                w.i64(*v);
+            }
+            WrappedMeasurementValue::Bool(v) => {
+                w.u8(3);
+                w.u8(*v as u8);
+            }
+            WrappedMeasurementValue::Histogram(h) => {
+                w.u8(4);
+                w.u64(h.bounds().len() as u64);
+                for b in h.bounds() {
+                    w.f64(*b);
+                }
+                for c in h.counts() {
+                    w.u64(*c);
+                }
+                w.f64(h.sum());
+            }
+        }
+        w.u64(p.attributes_len() as u64);
+        for (key, value) in p.attributes() {
+            w.str(key);
+            match value {
+                AttributeValue::F64(v) => {
+                    w.u8(0);
+                    w.f64(*v);
+                }
+                AttributeValue::U64(v) => {
+                    w.u8(1);
+                    w.u64(*v);
+                }
+                AttributeValue::Bool(v) => {
+                    w.u8(2);
+                    w.u8(*v as u8);
+                }
+                AttributeValue::Str(_) | AttributeValue::Shared(_) | AttributeValue::String(_) => {
+                    w.u8(3);
+                    w.str(value.as_str().unwrap());
+                }
+            }
+        }
+    }
+    w.0
+}
+
+/// Reads the number of measurement points of a spill file, from its header.
+fn read_len(path: &Path) -> io::Result<u64> {
+    use std::io::Read;
+
+    let mut header = [0; MAGIC.len() + 1 + 8];
+    fs::File::open(path)?.read_exact(&mut header)?;
+    let mut r = Reader(&header);
+    if r.take(MAGIC.len())? != MAGIC || r.u8()? != FORMAT_VERSION {
+        return Err(invalid("not a spill file, or unsupported version"));
+    }
+    r.u64()
+}
+
+fn decode(bytes: &[u8]) -> io::Result<MeasurementBuffer> {
+    let mut r = Reader(bytes);
+    if r.take(MAGIC.len())? != MAGIC || r.u8()? != FORMAT_VERSION {
+        return Err(invalid("not a spill file, or unsupported version"));
+    }
+    let len = r.u64()? as usize;
+    let mut buffer = MeasurementBuffer::with_capacity(len.min(bytes.len()));
+    for _ in 0..len {
+        let secs = r.i64()?;
+        let nanos = r.u32()?;
+        let timestamp = Timestamp::from_unix_timestamp(secs, nanos).ok_or_else(|| invalid("invalid timestamp"))?;
+        let metric = RawMetricId::from_u64(r.u64()?);
+        let resource = Resource::parse(r.string()?, r.string()?).map_err(|_| invalid("invalid resource"))?;
+        let consumer = ResourceConsumer::parse(r.string()?, r.string()?).map_err(|_| invalid("invalid consumer"))?;
+        let value = match r.u8()? {
+            0 => WrappedMeasurementValue::F64(r.f64()?),
+            1 => WrappedMeasurementValue::U64(r.u64()?),
+            2 => WrappedMeasurementValue::I64(r.i64()?),
+            3 => WrappedMeasurementValue::Bool(r.u8()? != 0),
+            4 => {
+                let n = r.u64()? as usize;
+                let bounds = (0..n).map(|_| r.f64()).collect::<io::Result<Vec<_>>>()?;
+                let counts = (0..=n).map(|_| r.u64()).collect::<io::Result<Vec<_>>>()?;
+                let sum = r.f64()?;
+                let h = Histogram::from_parts(bounds, counts, sum).map_err(|_| invalid("invalid histogram"))?;
+                WrappedMeasurementValue::Histogram(h)
+            }
+            _ => return Err(invalid("invalid value type")),
+        };
+        let n_attrs = r.u64()? as usize;
+        let mut attributes = Vec::with_capacity(n_attrs.min(bytes.len()));
+        for _ in 0..n_attrs {
+            let key = r.string()?;
+            let value = match r.u8()? {
+                0 => AttributeValue::F64(r.f64()?),
+                1 => AttributeValue::U64(r.u64()?),
+                2 => AttributeValue::Bool(r.u8()? != 0),
+                3 => AttributeValue::String(r.string()?),
+                _ => return Err(invalid("invalid attribute type")),
+            };
+            attributes.push((key, value));
+        }
+        let point = MeasurementPoint::new_untyped(timestamp, metric, resource, consumer, value);
+        buffer.push(point.with_attr_vec(attributes));
+    }
+    Ok(buffer)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+    fn u64(&mut self, v: u64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+    fn i64(&mut self, v: i64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+    fn f64(&mut self, v: f64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+    fn str(&mut self, s: &str) {
+        self.u64(s.len() as u64);
+        self.0.extend_from_slice(s.as_bytes());
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if n > self.0.len() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> io::Result<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+    fn u64(&mut self) -> io::Result<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+    fn i64(&mut self) -> io::Result<i64> {
+        self.array().map(i64::from_le_bytes)
+    }
+    fn f64(&mut self) -> io::Result<f64> {
+        self.array().map(f64::from_le_bytes)
+    }
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.u64()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("invalid string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::{
+        measurement::{Histogram, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{SpillError, SpillQueue};
+
+    fn buffer(i: u64) -> MeasurementBuffer {
+        let t = Timestamp::from(UNIX_EPOCH + Duration::from_millis(i * 1500));
+        MeasurementBuffer::from(vec![
+            MeasurementPoint::new_untyped(
+                t,
+                RawMetricId::from_u64(i),
+                Resource::CpuPackage { id: 1 },
+                ResourceConsumer::ControlGroup { path: "/a/b".into() },
+                WrappedMeasurementValue::U64(i),
+            )
+            .with_attr("pod", "my-pod")
+            .with_attr("ratio", 0.5)
+            .with_attr("up", true),
+            MeasurementPoint::new_untyped(
+                t,
+                RawMetricId::from_u64(100),
+                Resource::custom("fan", "a"),
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::Histogram(Histogram::from_parts(vec![1.0, 2.0], vec![1, 2, 3], 8.0).unwrap()),
+            ),
+        ])
+    }
+
+    fn summary(buf: &MeasurementBuffer) -> Vec<String> {
+        buf.iter()
+            .map(|p| {
+                let mut attrs: Vec<String> = p.attributes().map(|(k, v)| format!("{k}={v}")).collect();
+                attrs.sort();
+                format!(
+                    "{:?} {} {:?} {:?} {} {}",
+                    p.timestamp.to_unix_timestamp(),
+                    p.metric.as_u64(),
+                    p.resource,
+                    p.consumer,
+                    p.value,
+                    attrs.join(",")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fifo_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = SpillQueue::open(dir.path(), 1 << 20).unwrap();
+        assert!(queue.front().unwrap().is_none());
+        for i in 0..3 {
+            queue.push(&buffer(i)).unwrap();
+        }
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.total_points(), 3 * buffer(0).len());
+        for i in 0..3 {
+            assert_eq!(queue.front_points(), buffer(i).len());
+            let buf = queue.front().unwrap().unwrap();
+            assert_eq!(summary(&buf), summary(&buffer(i)));
+            queue.pop_front().unwrap();
+        }
+        assert!(queue.is_empty());
+        assert_eq!(queue.total_points(), 0);
+        assert_eq!(queue.total_bytes, 0);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn bounded_and_reopened() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = SpillQueue::open(dir.path(), 1 << 20).unwrap();
+        queue.push(&buffer(0)).unwrap();
+        let size = queue.total_bytes;
+
+        let mut queue = SpillQueue::open(dir.path(), size).unwrap();
+        assert!(queue.is_empty(), "stale files should not be replayed");
+        queue.push(&buffer(0)).unwrap();
+        assert!(matches!(queue.push(&buffer(1)), Err(SpillError::Full(_))));
+        assert_eq!(queue.len(), 1);
+
+        // the stale files have been kept aside
+        let stale: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.is_dir())
+            .collect();
+        assert_eq!(stale.len(), 1);
+        assert!(stale[0].file_name().unwrap().to_str().unwrap().starts_with("stale-"));
+        let files: Vec<_> = std::fs::read_dir(&stale[0])
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(super::read_len(&files[0]).unwrap(), 2);
+    }
+}
@@ -1,13 +1,15 @@
 //! Phases of the plugins lifecycle.
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use crate::metrics::MetricRegistry;
 use crate::pipeline::elements::{output, source, transform};
 use crate::pipeline::registry;
 use crate::{
     measurement::{MeasurementType, WrappedMeasurementType},
-    metrics::{Metric, MetricCreationError, RawMetricId, TypedMetricId},
+    metrics::{Metric, MetricCreationError, MetricKind, RawMetricId, TypedMetricId},
     pipeline::{self, trigger, Output, PluginName, Source, Transform},
+    resources::{consumers::ConsumerResolver, Resource},
     units::PrefixedUnit,
 };
 
@@ -40,6 +42,9 @@ impl<'a> AlumetPluginStart<'a> {
     /// Creates a new metric with a measurement type `T` (checked at compile time).
     /// Fails if a metric with the same name already exists.
     ///
+    /// The metric is a [`MetricKind::Gauge`] without static labels.
+    /// To create another kind of metric, use [`create_metric_with_kind`](Self::create_metric_with_kind).
+    ///
     /// # Example
     /// ```no_run
     /// use alumet::units::{Unit, PrefixedUnit};
@@ -61,12 +66,57 @@ impl<'a> AlumetPluginStart<'a> {
         name: impl Into<String>,
         unit: impl Into<PrefixedUnit>,
         description: impl Into<String>,
+    ) -> Result<TypedMetricId<T>, MetricCreationError> {
+        self.create_metric_with_labels(name, MetricKind::Gauge, unit, description, Vec::new())
+    }
+
+    /// Creates a new metric of the given kind, with a measurement type `T` (checked at compile time).
+    /// Fails if a metric with the same name already exists.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use alumet::units::Unit;
+    /// use alumet::metrics::{MetricKind, TypedMetricId};
+    /// # use alumet::plugin::AlumetPluginStart;
+    ///
+    /// # fn f() -> anyhow::Result<()> {
+    /// # let alumet: &mut AlumetPluginStart = todo!();
+    /// // Each measurement is the energy consumed since the previous one.
+    /// let energy: TypedMetricId<f64> = alumet
+    ///     .create_metric_with_kind("consumed_energy", MetricKind::Delta, Unit::Joule, "energy consumed since the previous measurement")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_metric_with_kind<T: MeasurementType>(
+        &mut self,
+        name: impl Into<String>,
+        kind: MetricKind,
+        unit: impl Into<PrefixedUnit>,
+        description: impl Into<String>,
+    ) -> Result<TypedMetricId<T>, MetricCreationError> {
+        self.create_metric_with_labels(name, kind, unit, description, Vec::new())
+    }
+
+    /// Creates a new metric of the given kind, with some static labels and a measurement type `T`
+    /// (checked at compile time). Fails if a metric with the same name already exists.
+    ///
+    /// The labels apply to every measurement of the metric. They are not copied to the
+    /// attributes of the measurement points: outputs read them from the [`Metric`].
+    pub fn create_metric_with_labels<T: MeasurementType>(
+        &mut self,
+        name: impl Into<String>,
+        kind: MetricKind,
+        unit: impl Into<PrefixedUnit>,
+        description: impl Into<String>,
+        labels: Vec<(String, String)>,
     ) -> Result<TypedMetricId<T>, MetricCreationError> {
         let m = Metric {
             name: name.into(),
             description: description.into(),
             value_type: T::wrapped_type(),
             unit: unit.into(),
+            kind,
+            labels,
         };
         let untyped_id = self.pipeline_builder.metrics.register(m)?;
         Ok(TypedMetricId(untyped_id, PhantomData))
@@ -90,6 +140,8 @@ impl<'a> AlumetPluginStart<'a> {
             description: description.to_owned(),
             value_type,
             unit: unit.into(),
+            kind: MetricKind::Gauge,
+            labels: vec![],
         };
         self.pipeline_builder.metrics.register(m)
     }
@@ -302,6 +354,51 @@ impl<'a> AlumetPluginStart<'a> {
         self.pipeline_builder.add_output_builder(plugin, builder);
     }
 
+    /// Adds a link between two resources to the [`Topology`](crate::resources::topology::Topology)
+    /// that transforms can query.
+    ///
+    /// The CPU packages, cores and NUMA nodes are linked automatically.
+    /// Use this method to attach other resources, for instance a GPU to its NUMA node.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use alumet::plugin::AlumetPluginStart;
+    /// use alumet::resources::Resource;
+    /// # let alumet: &mut AlumetPluginStart = todo!();
+    ///
+    /// let gpu = Resource::Gpu { bus_id: "0000:3b:00.0".into() };
+    /// alumet.add_resource_link(Resource::NumaNode { id: 0 }, gpu);
+    /// ```
+    pub fn add_resource_link(&mut self, parent: Resource, child: Resource) {
+        self.pipeline_builder.add_resource_link(parent, child);
+    }
+
+    /// Returns the resolver that maps resource consumers to their owners.
+    ///
+    /// Plugins that know which control groups belong to a higher-level consumer, such as a pod or a job,
+    /// should register these owners in the resolver, so that transforms can use them.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use alumet::plugin::AlumetPluginStart;
+    /// use alumet::resources::ResourceConsumer;
+    /// # let alumet: &mut AlumetPluginStart = todo!();
+    ///
+    /// let job = ResourceConsumer::custom("oar_job", "1234");
+    /// alumet.consumer_resolver().register_cgroup_owner("/oar/user_1234", job);
+    /// ```
+    pub fn consumer_resolver(&self) -> Arc<ConsumerResolver> {
+        self.pipeline_builder.consumer_resolver()
+    }
+
+    /// Returns an access to the counters that the pipeline keeps about its own operation,
+    /// such as the time spent polling each source.
+    ///
+    /// The counters start to be updated when the pipeline starts.
+    pub fn pipeline_telemetry(&self) -> pipeline::telemetry::PipelineTelemetry {
+        self.pipeline_builder.telemetry()
+    }
+
     /// Registers a callback that will run just after the pipeline startup.
     ///
     /// If you have some data to move to the pipeline start phase, it's easier
@@ -408,4 +505,24 @@ impl<'a> AlumetPostStart<'a> {
     pub fn async_runtime(&self) -> tokio::runtime::Handle {
         self.pipeline.async_runtime().clone()
     }
+
+    /// Returns the resolver that maps resource consumers to their owners.
+    ///
+    /// See [`AlumetPluginStart::consumer_resolver`].
+    pub fn consumer_resolver(&self) -> Arc<ConsumerResolver> {
+        self.pipeline.consumer_resolver()
+    }
+
+    /// Returns an access to the statistics of the outputs, such as the number of retries
+    /// and of measurements spilled to the disk.
+    pub fn output_stats(&self) -> pipeline::elements::output::OutputStatsReader {
+        self.pipeline.output_stats()
+    }
+
+    /// Returns an access to the counters that the pipeline keeps about its own operation.
+    ///
+    /// See [`AlumetPluginStart::pipeline_telemetry`].
+    pub fn pipeline_telemetry(&self) -> pipeline::telemetry::PipelineTelemetry {
+        self.pipeline.telemetry()
+    }
 }
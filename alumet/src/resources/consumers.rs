@@ -0,0 +1,300 @@
+//! Resolution of the consumers that own other consumers.
+//!
+//! A [`ResourceConsumer`] identifies one process or one control group, but does not say what it belongs to.
+//! The [`ConsumerResolver`] links them to higher-level consumers:
+//! - a process belongs to a control group, which is read from `/proc/<pid>/cgroup`
+//! - a control group belongs to the owners that plugins have registered, for instance
+//!   a Kubernetes pod or a job of a batch scheduler.
+//!
+//! Control groups are nested: the owners of a cgroup are also the owners of its sub-cgroups.
+//!
+//! # Example
+//!
+//! ```
+//! use alumet::resources::{consumers::ConsumerResolver, ResourceConsumer};
+//!
+//! let resolver = ConsumerResolver::new();
+//! resolver.register_cgroup_owner(
+//!     "/kubepods.slice/kubepods-pod42.slice",
+//!     ResourceConsumer::custom("k8s_pod", "default/my-pod"),
+//! );
+//!
+//! let container = ResourceConsumer::ControlGroup {
+//!     path: "/sys/fs/cgroup/kubepods.slice/kubepods-pod42.slice/cri-containerd-abcd.scope".into(),
+//! };
+//! assert_eq!(resolver.resolve(&container), vec![ResourceConsumer::custom("k8s_pod", "default/my-pod")]);
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{PoisonError, RwLock},
+};
+
+use super::ResourceConsumer;
+
+/// Where the cgroup filesystem is usually mounted.
+const CGROUP_MOUNT_POINT: &str = "/sys/fs/cgroup";
+
+/// Names of the hierarchies that exist in the cgroup filesystem with cgroup v1.
+const CGROUP_V1_HIERARCHIES: &[&str] = &[
+    "blkio",
+    "cpu",
+    "cpu,cpuacct",
+    "cpuacct",
+    "cpuacct,cpu",
+    "cpuset",
+    "devices",
+    "freezer",
+    "hugetlb",
+    "memory",
+    "net_cls",
+    "net_cls,net_prio",
+    "net_prio",
+    "perf_event",
+    "pids",
+    "rdma",
+    "systemd",
+    "unified",
+];
+
+/// Maps consumers to the consumers that own them.
+///
+/// The resolver is shared by the whole measurement pipeline, and can be updated while it is running:
+/// plugins register (and remove) the owners of control groups as they discover them.
+/// Transforms can access it through their [`TransformContext`](crate::pipeline::elements::transform::TransformContext).
+#[derive(Debug)]
+pub struct ConsumerResolver {
+    /// Owners of each cgroup, by normalized cgroup path.
+    owners: RwLock<HashMap<String, Vec<ResourceConsumer>>>,
+    /// Mount point of the procfs.
+    procfs: PathBuf,
+}
+
+impl Default for ConsumerResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConsumerResolver {
+    /// Creates a new resolver that reads the information about processes from `/proc`.
+    pub fn new() -> Self {
+        Self::with_procfs("/proc")
+    }
+
+    /// Creates a new resolver that reads the information about processes from the given directory.
+    pub fn with_procfs(procfs: impl Into<PathBuf>) -> Self {
+        Self {
+            owners: RwLock::new(HashMap::new()),
+            procfs: procfs.into(),
+        }
+    }
+
+    /// Registers an owner of a control group.
+    ///
+    /// The path of the cgroup can be relative to the root of the cgroup hierarchy (as in `/proc/<pid>/cgroup`),
+    /// or be a path in the cgroup filesystem, such as `/sys/fs/cgroup/my.slice`.
+    /// Registering the same owner multiple times has no effect.
+    pub fn register_cgroup_owner(&self, cgroup: &str, owner: ResourceConsumer) {
+        let mut owners = self.owners.write().unwrap_or_else(PoisonError::into_inner);
+        let cgroup_owners = owners.entry(cgroup_path(cgroup).to_owned()).or_default();
+        if !cgroup_owners.contains(&owner) {
+            cgroup_owners.push(owner);
+        }
+    }
+
+    /// Removes all the owners of a control group, for instance because the cgroup has been deleted.
+    pub fn remove_cgroup(&self, cgroup: &str) {
+        let mut owners = self.owners.write().unwrap_or_else(PoisonError::into_inner);
+        owners.remove(cgroup_path(cgroup));
+    }
+
+    /// Returns the owners of a control group, from the closest one to the farthest one.
+    ///
+    /// The owners of the parent cgroups are included.
+    pub fn owners_of_cgroup(&self, cgroup: &str) -> Vec<ResourceConsumer> {
+        let owners = self.owners.read().unwrap_or_else(PoisonError::into_inner);
+        let mut res = Vec::new();
+        let mut path = cgroup_path(cgroup);
+        loop {
+            if let Some(cgroup_owners) = owners.get(path) {
+                res.extend(cgroup_owners.iter().cloned());
+            }
+            match path.rsplit_once('/') {
+                Some(("", "")) | None => break,
+                Some(("", _)) => path = "/",
+                Some((parent, _)) => path = parent,
+            }
+        }
+        res
+    }
+
+    /// Returns the control group of a process, by reading `/proc/<pid>/cgroup`.
+    ///
+    /// With cgroup v2, the path of the unified hierarchy is returned. With cgroup v1, the path of the
+    /// first hierarchy is returned. If the process does not belong to any cgroup, returns `Ok(None)`.
+    pub fn cgroup_of_process(&self, pid: u32) -> io::Result<Option<String>> {
+        let content = fs::read_to_string(self.procfs.join(pid.to_string()).join("cgroup"))?;
+        Ok(parse_proc_cgroup(&content).map(str::to_owned))
+    }
+
+    /// Returns all the consumers that own the given consumer, from the closest one to the farthest one.
+    ///
+    /// For a process, this is its control group followed by the owners of the cgroup.
+    /// For a control group, these are its owners. Other consumers have no known owner.
+    pub fn resolve(&self, consumer: &ResourceConsumer) -> Vec<ResourceConsumer> {
+        match consumer {
+            ResourceConsumer::Process { pid } => match self.cgroup_of_process(*pid) {
+                Ok(Some(cgroup)) => {
+                    let mut res = self.owners_of_cgroup(&cgroup);
+                    res.insert(0, ResourceConsumer::ControlGroup { path: cgroup.into() });
+                    res
+                }
+                Ok(None) => Vec::new(),
+                Err(e) => {
+                    // the process has probably exited
+                    log::debug!("Could not find the cgroup of process {pid}: {e}");
+                    Vec::new()
+                }
+            },
+            ResourceConsumer::ControlGroup { path } => self.owners_of_cgroup(path),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Normalizes the path of a control group.
+///
+/// The mount point of the cgroup filesystem, and the name of the hierarchy with cgroup v1,
+/// are removed, so that the path is the same as in `/proc/<pid>/cgroup`.
+/// For instance, `/sys/fs/cgroup/cpuacct/oar/user_12/` becomes `/oar/user_12`.
+pub fn cgroup_path(path: &str) -> &str {
+    let mut path = path;
+    if let Some(rest) = path.strip_prefix(CGROUP_MOUNT_POINT) {
+        if rest.is_empty() || rest.starts_with('/') {
+            path = rest;
+            let hierarchy = path.trim_start_matches('/').split('/').next().unwrap_or_default();
+            if CGROUP_V1_HIERARCHIES.contains(&hierarchy) {
+                path = &path[1 + hierarchy.len()..];
+            }
+        }
+    }
+    match path.trim_end_matches('/') {
+        "" => "/",
+        p => p,
+    }
+}
+
+/// Extracts the path of the cgroup from the content of `/proc/<pid>/cgroup`.
+fn parse_proc_cgroup(content: &str) -> Option<&str> {
+    let mut first_v1 = None;
+    for line in content.lines() {
+        // each line is "hierarchy-ID:controller-list:cgroup-path"
+        let mut parts = line.splitn(3, ':');
+        let (Some(id), Some(controllers), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        if id == "0" && controllers.is_empty() {
+            return Some(path);
+        }
+        if first_v1.is_none() && !path.is_empty() {
+            first_v1 = Some(path);
+        }
+    }
+    first_v1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{cgroup_path, parse_proc_cgroup, ConsumerResolver};
+    use crate::resources::ResourceConsumer;
+
+    #[test]
+    fn normalize_path() {
+        assert_eq!(cgroup_path("/sys/fs/cgroup"), "/");
+        assert_eq!(cgroup_path("/sys/fs/cgroup/"), "/");
+        assert_eq!(cgroup_path("/sys/fs/cgroup/user.slice/"), "/user.slice");
+        assert_eq!(cgroup_path("/sys/fs/cgroup/cpuacct/oar/user_12"), "/oar/user_12");
+        assert_eq!(cgroup_path("/sys/fs/cgroup/cpu,cpuacct/oar"), "/oar");
+        assert_eq!(cgroup_path("/sys/fs/cgroup/memory"), "/");
+        assert_eq!(cgroup_path("/oar/user_12"), "/oar/user_12");
+        assert_eq!(cgroup_path("/sys/fs/cgroupfoo"), "/sys/fs/cgroupfoo");
+    }
+
+    #[test]
+    fn parse_cgroup_file() {
+        assert_eq!(
+            parse_proc_cgroup("0::/user.slice/session-2.scope\n"),
+            Some("/user.slice/session-2.scope")
+        );
+        let v1 = "12:cpuset:/oar/user_12\n11:memory:/oar/user_12\n1:name=systemd:/system.slice\n";
+        assert_eq!(parse_proc_cgroup(v1), Some("/oar/user_12"));
+        let hybrid = "1:name=systemd:/user.slice\n0::/user.slice/app.scope\n";
+        assert_eq!(parse_proc_cgroup(hybrid), Some("/user.slice/app.scope"));
+        assert_eq!(parse_proc_cgroup(""), None);
+    }
+
+    #[test]
+    fn owners() {
+        let resolver = ConsumerResolver::new();
+        let pod = ResourceConsumer::custom("k8s_pod", "pod1");
+        let job = ResourceConsumer::custom("oar_job", "12");
+        resolver.register_cgroup_owner("/sys/fs/cgroup/kubepods.slice/pod1.slice", pod.clone());
+        resolver.register_cgroup_owner("/kubepods.slice/pod1.slice", pod.clone());
+        resolver.register_cgroup_owner("/", ResourceConsumer::custom("host", "node-1"));
+        resolver.register_cgroup_owner("/sys/fs/cgroup/memory/oar/user_12", job.clone());
+
+        assert_eq!(
+            resolver.owners_of_cgroup("/kubepods.slice/pod1.slice/container.scope/cpu.stat"),
+            vec![pod.clone(), ResourceConsumer::custom("host", "node-1")]
+        );
+        assert_eq!(
+            resolver.resolve(&ResourceConsumer::ControlGroup {
+                path: "/sys/fs/cgroup/cpuacct/oar/user_12/cpuacct.usage".into()
+            }),
+            vec![job, ResourceConsumer::custom("host", "node-1")]
+        );
+        assert_eq!(
+            resolver.owners_of_cgroup("/other.slice"),
+            vec![ResourceConsumer::custom("host", "node-1")]
+        );
+
+        resolver.remove_cgroup("/kubepods.slice/pod1.slice/");
+        assert_eq!(
+            resolver.owners_of_cgroup("/kubepods.slice/pod1.slice"),
+            vec![ResourceConsumer::custom("host", "node-1")]
+        );
+        assert!(resolver.resolve(&ResourceConsumer::LocalMachine).is_empty());
+    }
+
+    #[test]
+    fn process() {
+        let procfs = tempfile::tempdir().unwrap();
+        fs::create_dir(procfs.path().join("42")).unwrap();
+        fs::write(
+            procfs.path().join("42/cgroup"),
+            "0::/kubepods.slice/pod1.slice/app.scope\n",
+        )
+        .unwrap();
+
+        let resolver = ConsumerResolver::with_procfs(procfs.path());
+        let pod = ResourceConsumer::custom("k8s_pod", "pod1");
+        resolver.register_cgroup_owner("/kubepods.slice/pod1.slice", pod.clone());
+
+        assert_eq!(
+            resolver.resolve(&ResourceConsumer::Process { pid: 42 }),
+            vec![
+                ResourceConsumer::ControlGroup {
+                    path: "/kubepods.slice/pod1.slice/app.scope".into()
+                },
+                pod
+            ]
+        );
+        // the process does not exist
+        assert!(resolver.resolve(&ResourceConsumer::Process { pid: 43 }).is_empty());
+    }
+}
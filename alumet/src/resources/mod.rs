@@ -33,15 +33,21 @@
 //!
 //! Unlike metrics and units, resources are not registered in a global registry,
 //! but created each time they are needed.
+//! However, the relationships between the resources of the machine (which cores belong to which package, etc.)
+//! are known: see the [`topology`] module.
+//! Similarly, the [`consumers`] module links consumers to their owners (process → cgroup → pod or job).
 
 use std::{borrow::Cow, fmt};
 
+pub mod consumers;
+pub mod topology;
+
 /// Alias to a static cow. It helps to avoid the allocation of Strings.
 pub type StrCow = Cow<'static, str>;
 
 /// Hardware or software entity that can be measured.
 #[non_exhaustive]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[repr(C)]
 pub enum Resource {
     /// The whole local machine, for instance the whole physical server.
@@ -52,6 +58,8 @@ pub enum Resource {
     CpuCore { id: u32 },
     /// The RAM attached to a CPU package.
     Dram { pkg_id: u32 },
+    /// A NUMA node, i.e. a group of CPU cores with their local memory.
+    NumaNode { id: u32 },
     /// A dedicated GPU.
     Gpu { bus_id: StrCow },
     /// A custom resource.
@@ -65,7 +73,7 @@ pub enum Resource {
 /// (total memory consumption, with consumer `LocalMachine`), or at the process level
 /// (process memory consumption, with consumer `Process { pid }`).
 #[non_exhaustive]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[repr(C)]
 pub enum ResourceConsumer {
     /// The whole local machine.
@@ -98,6 +106,7 @@ impl Resource {
             Resource::CpuPackage { .. } => "cpu_package",
             Resource::CpuCore { .. } => "cpu_core",
             Resource::Dram { .. } => "dram",
+            Resource::NumaNode { .. } => "numa_node",
             Resource::Gpu { .. } => "gpu",
             Resource::Custom { kind, id: _ } => kind,
         }
@@ -116,6 +125,7 @@ impl Resource {
             Resource::CpuPackage { id } => LazyDisplayable::U32(*id),
             Resource::CpuCore { id } => LazyDisplayable::U32(*id),
             Resource::Dram { pkg_id } => LazyDisplayable::U32(*pkg_id),
+            Resource::NumaNode { id } => LazyDisplayable::U32(*id),
             Resource::Gpu { bus_id } => LazyDisplayable::Str(bus_id),
             Resource::Custom { kind: _, id } => LazyDisplayable::Str(id),
         }
@@ -147,6 +157,10 @@ impl Resource {
                     let pkg_id = id.parse().map_err(|_| InvalidResourceError::InvalidId(kind))?;
                     Ok(Resource::Dram { pkg_id })
                 }
+                "numa_node" => {
+                    let id = id.parse().map_err(|_| InvalidResourceError::InvalidId(kind))?;
+                    Ok(Resource::NumaNode { id })
+                }
                 "gpu" => Ok(Resource::Gpu { bus_id: id }),
                 _ => Ok(Resource::Custom { kind, id }),
             },
@@ -195,6 +209,13 @@ impl ResourceConsumer {
     pub fn normalize(self) -> Result<Self, InvalidConsumerError> {
         match self {
             ResourceConsumer::Custom { kind, id } => match kind.as_ref() {
+                "local_machine" => {
+                    if id.is_empty() {
+                        Ok(ResourceConsumer::LocalMachine)
+                    } else {
+                        Err(InvalidConsumerError::InvalidId(kind))
+                    }
+                }
                 "process" => {
                     let pid = id.parse().map_err(|_| InvalidConsumerError::InvalidId(kind))?;
                     Ok(ResourceConsumer::Process { pid })
@@ -0,0 +1,480 @@
+//! Relationships between resources.
+//!
+//! A [`Resource`] identifies one piece of hardware, but does not say how it relates to the others:
+//! which cores belong to which CPU package, which NUMA node a GPU is attached to, etc.
+//! The [`Topology`] records these relationships as links between a parent and a child resource.
+//!
+//! The topology of the CPUs and NUMA nodes is discovered from the sysfs of Linux,
+//! see [`Topology::from_sysfs`]. Plugins can add more links, for instance to attach devices to NUMA nodes,
+//! with [`AlumetPluginStart::add_resource_link`](crate::plugin::AlumetPluginStart::add_resource_link).
+//!
+//! # Example
+//!
+//! ```
+//! use alumet::resources::{Resource, topology::Topology};
+//!
+//! let mut topology = Topology::new();
+//! topology.add_link(Resource::LocalMachine, Resource::CpuPackage { id: 0 });
+//! topology.add_link(Resource::CpuPackage { id: 0 }, Resource::CpuCore { id: 0 });
+//! topology.add_link(Resource::CpuPackage { id: 0 }, Resource::CpuCore { id: 1 });
+//!
+//! let core = Resource::CpuCore { id: 1 };
+//! assert_eq!(topology.ancestor_of_kind(&core, "cpu_package"), Some(&Resource::CpuPackage { id: 0 }));
+//! assert_eq!(topology.ancestor_of_kind(&core, "local_machine"), Some(&Resource::LocalMachine));
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::Path,
+    time::SystemTime,
+};
+
+use anyhow::Context;
+
+use super::{Resource, ResourceConsumer};
+use crate::{
+    measurement::{MeasurementPoint, WrappedMeasurementValue},
+    metrics::RawMetricId,
+};
+
+/// Parent/child links between resources.
+///
+/// A resource can have several parents: for instance, a CPU core belongs to a CPU package
+/// and to a NUMA node. Therefore, the topology is not a tree, but it contains no cycle.
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    parents: HashMap<Resource, Vec<Resource>>,
+    children: HashMap<Resource, Vec<Resource>>,
+}
+
+impl Topology {
+    /// Creates an empty topology.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Discovers the topology of the local machine from `/sys/devices/system`.
+    ///
+    /// The following links are created:
+    /// - `LocalMachine` → `CpuPackage`
+    /// - `CpuPackage` → `CpuCore` (the id of the core is the id of the logical CPU, as seen by the OS)
+    /// - `CpuPackage` → `Dram`
+    /// - `LocalMachine` → `NumaNode`, if the kernel exposes NUMA nodes
+    /// - `NumaNode` → `CpuCore`
+    pub fn from_sysfs() -> anyhow::Result<Self> {
+        Self::from_sysfs_root(Path::new("/sys/devices/system"))
+    }
+
+    /// Discovers the topology from a sysfs-like directory, which contains `cpu/` and, optionally, `node/`.
+    ///
+    /// See [`from_sysfs`](Self::from_sysfs).
+    pub fn from_sysfs_root(root: &Path) -> anyhow::Result<Self> {
+        let mut topology = Topology::new();
+
+        // CPU packages and cores
+        let cpu_dir = root.join("cpu");
+        for cpu in numbered_entries(&cpu_dir, "cpu")? {
+            // offline CPUs have no topology directory
+            let path = cpu_dir.join(format!("cpu{cpu}/topology/physical_package_id"));
+            let Ok(content) = fs::read_to_string(&path) else {
+                log::debug!("Skipping cpu{cpu}: {path:?} is not readable");
+                continue;
+            };
+            let pkg: i64 = content
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid package id in {path:?}"))?;
+            let Ok(pkg) = u32::try_from(pkg) else {
+                log::debug!("Skipping cpu{cpu}: it does not belong to any package");
+                continue;
+            };
+            let package = Resource::CpuPackage { id: pkg };
+            topology.add_link(Resource::LocalMachine, package.clone());
+            topology.add_link(package.clone(), Resource::Dram { pkg_id: pkg });
+            topology.add_link(package, Resource::CpuCore { id: cpu });
+        }
+
+        // NUMA nodes, which do not exist if the kernel has been compiled without NUMA support
+        let node_dir = root.join("node");
+        if node_dir.is_dir() {
+            for node in numbered_entries(&node_dir, "node")? {
+                let numa_node = Resource::NumaNode { id: node };
+                topology.add_link(Resource::LocalMachine, numa_node.clone());
+
+                let path = node_dir.join(format!("node{node}/cpulist"));
+                let cpulist = fs::read_to_string(&path).with_context(|| format!("failed to read {path:?}"))?;
+                for cpu in parse_cpu_list(&cpulist).with_context(|| format!("failed to parse {path:?}"))? {
+                    topology.add_link(numa_node.clone(), Resource::CpuCore { id: cpu });
+                }
+            }
+        }
+        Ok(topology)
+    }
+
+    /// Adds a link between a parent resource and one of its children.
+    ///
+    /// Adding the same link multiple times has no effect.
+    pub fn add_link(&mut self, parent: Resource, child: Resource) {
+        let children = self.children.entry(parent.clone()).or_default();
+        if children.contains(&child) {
+            return;
+        }
+        children.push(child.clone());
+        self.parents.entry(child).or_default().push(parent);
+    }
+
+    /// Returns `true` if the topology contains no link.
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Returns the direct parents of a resource.
+    pub fn parents(&self, resource: &Resource) -> &[Resource] {
+        self.parents.get(resource).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Returns the direct children of a resource.
+    pub fn children(&self, resource: &Resource) -> &[Resource] {
+        self.children.get(resource).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Finds the closest ancestor of a resource that has the given [kind](Resource::kind).
+    ///
+    /// If several ancestors of this kind are at the same distance, the one whose link
+    /// has been added first is returned.
+    pub fn ancestor_of_kind(&self, resource: &Resource, kind: &str) -> Option<&Resource> {
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<&Resource> = self.parents(resource).iter().collect();
+        while let Some(r) = queue.pop_front() {
+            if r.kind() == kind {
+                return Some(r);
+            }
+            if visited.insert(r) {
+                queue.extend(self.parents(r));
+            }
+        }
+        None
+    }
+
+    /// Returns all the descendants of a resource that have the given [kind](Resource::kind).
+    pub fn descendants_of_kind(&self, resource: &Resource, kind: &str) -> Vec<&Resource> {
+        let mut res = Vec::new();
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<&Resource> = self.children(resource).iter().collect();
+        while let Some(r) = queue.pop_front() {
+            if !visited.insert(r) {
+                continue;
+            }
+            if r.kind() == kind {
+                res.push(r);
+            }
+            queue.extend(self.children(r));
+        }
+        res
+    }
+
+    /// Sums the values of some measurement points at a higher level of the topology.
+    ///
+    /// Each point is attributed to the closest ancestor of its resource that has the given kind,
+    /// for instance `"cpu_package"` to roll up per-core values to the package level.
+    /// The values of the points that have the same metric, timestamp, consumer and attributes,
+    /// and whose resources have the same ancestor, are added together.
+    ///
+    /// The type of the values is kept if all the values of a group have the same type,
+    /// otherwise the sum is a float. Points that are not numbers (booleans and histograms),
+    /// and points whose resource has no ancestor of the given kind, are ignored.
+    ///
+    /// The returned points are in the order of the first point of each group.
+    pub fn roll_up<'a>(
+        &self,
+        points: impl IntoIterator<Item = &'a MeasurementPoint>,
+        kind: &str,
+    ) -> Vec<MeasurementPoint> {
+        let mut res: Vec<MeasurementPoint> = Vec::new();
+        let mut groups: HashMap<RollUpKey, usize> = HashMap::new();
+        for point in points {
+            if matches!(
+                point.value,
+                WrappedMeasurementValue::Bool(_) | WrappedMeasurementValue::Histogram(_)
+            ) {
+                continue;
+            }
+            let Some(ancestor) = self.ancestor_of_kind(&point.resource, kind) else {
+                continue;
+            };
+            let key = RollUpKey::of(point, ancestor);
+            match groups.get(&key) {
+                Some(&i) => {
+                    let sum = &mut res[i].value;
+                    *sum = add_values(sum, &point.value);
+                }
+                None => {
+                    let mut rolled_up = point.clone();
+                    rolled_up.resource = ancestor.clone();
+                    groups.insert(key, res.len());
+                    res.push(rolled_up);
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Returns the NUMA node that a PCI device is attached to, if any.
+///
+/// The bus id can be in the format used by the kernel (`0000:3b:00.0`) or in the longer format
+/// used by some libraries, such as NVML (`00000000:3B:00.0`).
+/// Returns `Ok(None)` if the device is not attached to a particular NUMA node.
+pub fn pci_device_numa_node(bus_id: &str) -> anyhow::Result<Option<u32>> {
+    pci_device_numa_node_in(Path::new("/sys/bus/pci/devices"), bus_id)
+}
+
+fn pci_device_numa_node_in(pci_devices: &Path, bus_id: &str) -> anyhow::Result<Option<u32>> {
+    // the kernel uses a 16-bit PCI domain, written with 4 hex digits in lowercase
+    let bus_id = bus_id.to_ascii_lowercase();
+    let bus_id = match bus_id.split_once(':') {
+        Some((domain, rest)) if domain.len() > 4 => format!("{}:{rest}", &domain[domain.len() - 4..]),
+        _ => bus_id,
+    };
+    let path = pci_devices.join(bus_id).join("numa_node");
+    let content = fs::read_to_string(&path).with_context(|| format!("failed to read {path:?}"))?;
+    let node: i64 = content
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid NUMA node in {path:?}"))?;
+    // -1 means that the device is not attached to a particular NUMA node
+    Ok(u32::try_from(node).ok())
+}
+
+/// Identifies a group of points that are rolled up together.
+#[derive(PartialEq, Eq, Hash)]
+struct RollUpKey {
+    metric: RawMetricId,
+    timestamp: SystemTime,
+    resource: Resource,
+    consumer: ResourceConsumer,
+    attributes: Vec<(String, String)>,
+}
+
+impl RollUpKey {
+    fn of(point: &MeasurementPoint, ancestor: &Resource) -> Self {
+        let mut attributes: Vec<(String, String)> =
+            point.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect();
+        attributes.sort_unstable();
+        Self {
+            metric: point.metric,
+            timestamp: SystemTime::from(point.timestamp),
+            resource: ancestor.clone(),
+            consumer: point.consumer.clone(),
+            attributes,
+        }
+    }
+}
+
+fn add_values(a: &WrappedMeasurementValue, b: &WrappedMeasurementValue) -> WrappedMeasurementValue {
+    match (a, b) {
+        (WrappedMeasurementValue::U64(a), WrappedMeasurementValue::U64(b)) => {
+            WrappedMeasurementValue::U64(a.saturating_add(*b))
+        }
+        (WrappedMeasurementValue::I64(a), WrappedMeasurementValue::I64(b)) => {
+            WrappedMeasurementValue::I64(a.saturating_add(*b))
+        }
+        // the values have been checked by roll_up, they are numbers
+        (a, b) => WrappedMeasurementValue::F64(a.as_f64().unwrap_or_default() + b.as_f64().unwrap_or_default()),
+    }
+}
+
+/// Lists the ids of the entries named `{prefix}{id}` in a directory, in ascending order.
+fn numbered_entries(dir: &Path, prefix: &str) -> anyhow::Result<Vec<u32>> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to list {dir:?}"))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if let Some(id) = name.to_str().and_then(|n| n.strip_prefix(prefix)?.parse().ok()) {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// Parses a list of CPUs like `0-3,8-11`.
+fn parse_cpu_list(cpulist: &str) -> anyhow::Result<Vec<u32>> {
+    let mut cpus = Vec::new();
+    for item in cpulist.trim().split(',').filter(|item| !item.is_empty()) {
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let start: u32 = start
+                    .parse()
+                    .with_context(|| format!("invalid cpu list item: {item}"))?;
+                let end: u32 = end.parse().with_context(|| format!("invalid cpu list item: {item}"))?;
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(item.parse().with_context(|| format!("invalid cpu list item: {item}"))?),
+        }
+    }
+    Ok(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, time::UNIX_EPOCH};
+
+    use super::{parse_cpu_list, pci_device_numa_node_in, Topology};
+    use crate::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn cpu_list() {
+        assert_eq!(parse_cpu_list("0\n").unwrap(), vec![0]);
+        assert_eq!(parse_cpu_list("0-3,8-9\n").unwrap(), vec![0, 1, 2, 3, 8, 9]);
+        assert_eq!(parse_cpu_list("1,5").unwrap(), vec![1, 5]);
+        assert_eq!(parse_cpu_list("\n").unwrap(), Vec::<u32>::new());
+        assert!(parse_cpu_list("0-a").is_err());
+    }
+
+    #[test]
+    fn sysfs() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        // 2 packages with 2 cores each, 1 offline cpu, 2 NUMA nodes
+        for (cpu, pkg) in [(0, 0), (1, 0), (2, 1), (3, 1)] {
+            write(
+                root,
+                &format!("cpu/cpu{cpu}/topology/physical_package_id"),
+                &format!("{pkg}\n"),
+            );
+        }
+        fs::create_dir_all(root.join("cpu/cpu4")).unwrap();
+        write(root, "cpu/online", "0-3\n");
+        write(root, "node/node0/cpulist", "0-1\n");
+        write(root, "node/node1/cpulist", "2-3\n");
+        write(root, "node/possible", "0-1\n");
+
+        let topology = Topology::from_sysfs_root(root).unwrap();
+        assert_eq!(
+            topology.children(&Resource::LocalMachine),
+            &[
+                Resource::CpuPackage { id: 0 },
+                Resource::CpuPackage { id: 1 },
+                Resource::NumaNode { id: 0 },
+                Resource::NumaNode { id: 1 },
+            ]
+        );
+        assert_eq!(
+            topology.children(&Resource::CpuPackage { id: 1 }),
+            &[
+                Resource::Dram { pkg_id: 1 },
+                Resource::CpuCore { id: 2 },
+                Resource::CpuCore { id: 3 },
+            ]
+        );
+        assert_eq!(
+            topology.parents(&Resource::CpuCore { id: 2 }),
+            &[Resource::CpuPackage { id: 1 }, Resource::NumaNode { id: 1 }]
+        );
+        assert!(topology.parents(&Resource::CpuCore { id: 4 }).is_empty());
+        assert_eq!(
+            topology.ancestor_of_kind(&Resource::CpuCore { id: 3 }, "numa_node"),
+            Some(&Resource::NumaNode { id: 1 })
+        );
+        assert_eq!(
+            topology.descendants_of_kind(&Resource::LocalMachine, "cpu_core"),
+            vec![
+                &Resource::CpuCore { id: 0 },
+                &Resource::CpuCore { id: 1 },
+                &Resource::CpuCore { id: 2 },
+                &Resource::CpuCore { id: 3 },
+            ]
+        );
+    }
+
+    #[test]
+    fn sysfs_without_numa() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, "cpu/cpu0/topology/physical_package_id", "0\n");
+
+        let topology = Topology::from_sysfs_root(root).unwrap();
+        assert_eq!(
+            topology.ancestor_of_kind(&Resource::CpuCore { id: 0 }, "local_machine"),
+            Some(&Resource::LocalMachine)
+        );
+        assert_eq!(
+            topology.ancestor_of_kind(&Resource::CpuCore { id: 0 }, "numa_node"),
+            None
+        );
+    }
+
+    #[test]
+    fn pci_numa_node() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, "0000:3b:00.0/numa_node", "1\n");
+        write(root, "0000:af:00.0/numa_node", "-1\n");
+
+        assert_eq!(pci_device_numa_node_in(root, "0000:3b:00.0").unwrap(), Some(1));
+        assert_eq!(pci_device_numa_node_in(root, "00000000:3B:00.0").unwrap(), Some(1));
+        assert_eq!(pci_device_numa_node_in(root, "0000:af:00.0").unwrap(), None);
+        assert!(pci_device_numa_node_in(root, "0000:00:01.0").is_err());
+    }
+
+    #[test]
+    fn roll_up() {
+        let mut topology = Topology::new();
+        for (core, pkg) in [(0, 0), (1, 0), (2, 1)] {
+            topology.add_link(Resource::CpuPackage { id: pkg }, Resource::CpuCore { id: core });
+        }
+        let t0 = Timestamp::from(UNIX_EPOCH);
+        let t1 = Timestamp::from(UNIX_EPOCH + std::time::Duration::from_secs(1));
+        let point = |t: Timestamp, metric: u64, core: u32, value: WrappedMeasurementValue| {
+            MeasurementPoint::new_untyped(
+                t,
+                RawMetricId::from_u64(metric),
+                Resource::CpuCore { id: core },
+                ResourceConsumer::LocalMachine,
+                value,
+            )
+        };
+        let points = vec![
+            point(t0, 0, 0, WrappedMeasurementValue::U64(1)),
+            point(t0, 0, 1, WrappedMeasurementValue::U64(2)),
+            point(t0, 0, 2, WrappedMeasurementValue::U64(4)),
+            point(t1, 0, 0, WrappedMeasurementValue::U64(8)),
+            point(t0, 1, 0, WrappedMeasurementValue::U64(1)),
+            point(t0, 1, 1, WrappedMeasurementValue::F64(0.5)),
+            point(t0, 2, 0, WrappedMeasurementValue::Bool(true)),
+            point(t0, 0, 9, WrappedMeasurementValue::U64(100)),
+            point(t0, 0, 1, WrappedMeasurementValue::U64(16)).with_attr("domain", "other"),
+        ];
+
+        let rolled_up = topology.roll_up(&points, "cpu_package");
+        let summary: Vec<_> = rolled_up
+            .iter()
+            .map(|p| (p.metric.as_u64(), p.resource.clone(), p.value.to_string()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, Resource::CpuPackage { id: 0 }, String::from("3")),
+                (0, Resource::CpuPackage { id: 1 }, String::from("4")),
+                (0, Resource::CpuPackage { id: 0 }, String::from("8")),
+                (1, Resource::CpuPackage { id: 0 }, String::from("1.5")),
+                (0, Resource::CpuPackage { id: 0 }, String::from("16")),
+            ]
+        );
+        assert!(matches!(rolled_up[0].value, WrappedMeasurementValue::U64(_)));
+        assert!(matches!(rolled_up[3].value, WrappedMeasurementValue::F64(_)));
+        assert!(rolled_up[4].attributes_keys().eq(["domain"]));
+    }
+}
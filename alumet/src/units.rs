@@ -141,6 +141,43 @@ impl Unit {
             prefix: scale,
         }
     }
+
+    /// Returns the quantity measured by the unit, and the scale and offset that convert a value
+    /// in this unit to the reference unit of the quantity: `reference = value * scale + offset`.
+    fn reference(&self) -> (Quantity<'_>, f64, f64) {
+        match self {
+            Unit::Unity => (Quantity::Dimensionless, 1.0, 0.0),
+            Unit::Second => (Quantity::Time, 1.0, 0.0),
+            Unit::Watt => (Quantity::Power, 1.0, 0.0),
+            Unit::Joule => (Quantity::Energy, 1.0, 0.0),
+            // 1 Wh = 3600 J
+            Unit::WattHour => (Quantity::Energy, 3600.0, 0.0),
+            Unit::Volt => (Quantity::Voltage, 1.0, 0.0),
+            Unit::Ampere => (Quantity::Current, 1.0, 0.0),
+            Unit::Hertz => (Quantity::Frequency, 1.0, 0.0),
+            Unit::DegreeCelsius => (Quantity::Temperature, 1.0, 0.0),
+            // °C = (°F - 32) × 5/9
+            Unit::DegreeFahrenheit => (Quantity::Temperature, 5.0 / 9.0, -32.0 * 5.0 / 9.0),
+            Unit::Byte => (Quantity::Information, 1.0, 0.0),
+            // A custom unit can only be converted to itself (with another prefix).
+            Unit::Custom { unique_name, .. } => (Quantity::Custom(unique_name), 1.0, 0.0),
+        }
+    }
+}
+
+/// A physical quantity, which determines the units that can be converted to each other.
+#[derive(PartialEq, Eq)]
+enum Quantity<'a> {
+    Dimensionless,
+    Time,
+    Power,
+    Energy,
+    Voltage,
+    Current,
+    Frequency,
+    Temperature,
+    Information,
+    Custom(&'a str),
 }
 
 impl Display for Unit {
@@ -218,6 +255,89 @@ impl PrefixedUnit {
     pub fn display_name(&self) -> String {
         format!("{self}")
     }
+
+    /// Computes the conversion from this unit to `target`.
+    ///
+    /// Fails if the two units do not measure the same quantity, for instance joules and watts.
+    ///
+    /// # Example
+    /// ```
+    /// use alumet::units::{Unit, PrefixedUnit};
+    ///
+    /// let conversion = PrefixedUnit::micro(Unit::Joule).conversion_to(&Unit::Joule.into()).unwrap();
+    /// assert_eq!(conversion.apply(2_000_000.0), 2.0);
+    ///
+    /// let conversion = PrefixedUnit::from(Unit::DegreeFahrenheit).conversion_to(&Unit::DegreeCelsius.into()).unwrap();
+    /// assert_eq!(conversion.apply(212.0), 100.0);
+    ///
+    /// assert!(PrefixedUnit::from(Unit::Watt).conversion_to(&Unit::Joule.into()).is_err());
+    /// ```
+    pub fn conversion_to(&self, target: &PrefixedUnit) -> Result<UnitConversion, UnitConversionError> {
+        let (quantity, scale, offset) = self.base_unit.reference();
+        let (target_quantity, target_scale, target_offset) = target.base_unit.reference();
+        if quantity != target_quantity {
+            return Err(UnitConversionError {
+                from: self.clone(),
+                to: target.clone(),
+            });
+        }
+        // Compute the power of ten in one step, to avoid rounding errors such as 1e-6 * 1e3 != 1e-3.
+        let exponent = self.prefix.exponent() - target.prefix.exponent();
+        let prefix_factor = if exponent >= 0 {
+            10f64.powi(exponent)
+        } else {
+            1.0 / 10f64.powi(-exponent)
+        };
+        let target_factor = 10f64.powi(target.prefix.exponent()) * target_scale;
+        Ok(UnitConversion {
+            factor: prefix_factor * scale / target_scale,
+            offset: (offset - target_offset) / target_factor,
+        })
+    }
+}
+
+/// A conversion between two units that measure the same quantity.
+///
+/// The converted value is `value * factor + offset`.
+/// The offset is zero, except for conversions between units that do not have the same zero, such as °C and °F.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnitConversion {
+    pub factor: f64,
+    pub offset: f64,
+}
+
+impl UnitConversion {
+    /// Converts a value.
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.factor + self.offset
+    }
+
+    /// Returns `true` if the conversion does not change the values.
+    pub fn is_identity(&self) -> bool {
+        self.factor == 1.0 && self.offset == 0.0
+    }
+}
+
+/// Error which can occur when converting a value from one unit to another.
+///
+/// This error occurs if the two units do not measure the same quantity.
+#[derive(Debug, Clone)]
+pub struct UnitConversionError {
+    pub from: PrefixedUnit,
+    pub to: PrefixedUnit,
+}
+
+impl std::error::Error for UnitConversionError {}
+
+impl Display for UnitConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Incompatible units: cannot convert {} to {}",
+            self.from.unique_name(),
+            self.to.unique_name()
+        )
+    }
 }
 
 impl From<Unit> for PrefixedUnit {
@@ -226,6 +346,23 @@ impl From<Unit> for PrefixedUnit {
     }
 }
 
+impl FromStr for PrefixedUnit {
+    // TODO more precise error type
+    type Err = anyhow::Error;
+
+    /// Parses a unit with an optional prefix, for instance `"J"`, `"mW"`, `"uJ"`, `"kW.h"` or `"milliCel"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Try the longest unit first, so that "m" alone is not taken for a prefix.
+        for (i, _) in s.char_indices() {
+            let (prefix, unit) = s.split_at(i);
+            if let (Ok(prefix), Ok(unit)) = (prefix.parse::<UnitPrefix>(), unit.parse::<Unit>()) {
+                return Ok(unit.with_prefix(prefix));
+            }
+        }
+        Err(anyhow!("Unknown or non standard unit {s}"))
+    }
+}
+
 impl Display for PrefixedUnit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}{}", self.prefix, self.base_unit)
@@ -264,6 +401,21 @@ impl UnitPrefix {
     }
 }
 
+impl UnitPrefix {
+    /// Returns the power of ten that corresponds to the prefix, for instance `-3` for milli.
+    pub fn exponent(&self) -> i32 {
+        match self {
+            UnitPrefix::Nano => -9,
+            UnitPrefix::Micro => -6,
+            UnitPrefix::Milli => -3,
+            UnitPrefix::Plain => 0,
+            UnitPrefix::Kilo => 3,
+            UnitPrefix::Mega => 6,
+            UnitPrefix::Giga => 9,
+        }
+    }
+}
+
 impl Display for UnitPrefix {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(self.display_name())
@@ -277,7 +429,7 @@ impl FromStr for UnitPrefix {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let res = match s {
             "nano" | "n" => UnitPrefix::Nano,
-            "micro" | "μ" => UnitPrefix::Micro,
+            "micro" | "μ" | "µ" | "u" => UnitPrefix::Micro,
             "milli" | "m" => UnitPrefix::Milli,
             "" => UnitPrefix::Plain,
             "kilo" | "k" => UnitPrefix::Kilo,
@@ -291,7 +443,7 @@ impl FromStr for UnitPrefix {
 
 #[cfg(test)]
 mod tests {
-    use super::{Unit, UnitPrefix};
+    use super::{PrefixedUnit, Unit, UnitPrefix};
 
     #[test]
     fn unit_serde() {
@@ -328,4 +480,97 @@ mod tests {
         assert_eq!(parse_self(UnitPrefix::Mega), UnitPrefix::Mega);
         assert_eq!(parse_self(UnitPrefix::Giga), UnitPrefix::Giga);
     }
+
+    #[test]
+    fn prefixed_unit_parse() {
+        assert_eq!("J".parse::<PrefixedUnit>().unwrap(), PrefixedUnit::from(Unit::Joule));
+        assert_eq!("mW".parse::<PrefixedUnit>().unwrap(), PrefixedUnit::milli(Unit::Watt));
+        assert_eq!("uJ".parse::<PrefixedUnit>().unwrap(), PrefixedUnit::micro(Unit::Joule));
+        assert_eq!(
+            "kW.h".parse::<PrefixedUnit>().unwrap(),
+            PrefixedUnit::kilo(Unit::WattHour)
+        );
+        assert_eq!(
+            "milliCel".parse::<PrefixedUnit>().unwrap(),
+            PrefixedUnit::milli(Unit::DegreeCelsius)
+        );
+        assert_eq!(
+            "[degF]".parse::<PrefixedUnit>().unwrap(),
+            PrefixedUnit::from(Unit::DegreeFahrenheit)
+        );
+        "kJoule".parse::<PrefixedUnit>().unwrap_err();
+        "".parse::<PrefixedUnit>().unwrap_err();
+    }
+
+    #[test]
+    fn conversion() {
+        fn convert(value: f64, from: PrefixedUnit, to: PrefixedUnit) -> f64 {
+            from.conversion_to(&to).unwrap().apply(value)
+        }
+        fn assert_approx_eq(a: f64, b: f64) {
+            assert!((a - b).abs() < 1e-9 * b.abs().max(1.0), "{a} != {b}");
+        }
+        assert_eq!(
+            convert(
+                1500.0,
+                PrefixedUnit::micro(Unit::Joule),
+                PrefixedUnit::milli(Unit::Joule)
+            ),
+            1.5
+        );
+        assert_eq!(
+            convert(2.0, PrefixedUnit::kilo(Unit::Watt), PrefixedUnit::milli(Unit::Watt)),
+            2e6
+        );
+        assert_eq!(convert(1.0, Unit::WattHour.into(), Unit::Joule.into()), 3600.0);
+        assert_approx_eq(
+            convert(
+                7200.0,
+                PrefixedUnit::kilo(Unit::Joule),
+                PrefixedUnit::kilo(Unit::WattHour),
+            ),
+            2.0,
+        );
+        assert_approx_eq(
+            convert(212.0, Unit::DegreeFahrenheit.into(), Unit::DegreeCelsius.into()),
+            100.0,
+        );
+        assert_approx_eq(
+            convert(-40.0, Unit::DegreeCelsius.into(), Unit::DegreeFahrenheit.into()),
+            -40.0,
+        );
+        assert_approx_eq(
+            convert(
+                37000.0,
+                PrefixedUnit::milli(Unit::DegreeCelsius),
+                Unit::DegreeFahrenheit.into(),
+            ),
+            98.6,
+        );
+
+        let identity = PrefixedUnit::from(Unit::Byte)
+            .conversion_to(&Unit::Byte.into())
+            .unwrap();
+        assert!(identity.is_identity());
+
+        let custom = |prefix| PrefixedUnit {
+            base_unit: Unit::Custom {
+                unique_name: String::from("req"),
+                display_name: String::from("req"),
+            },
+            prefix,
+        };
+        assert_eq!(
+            convert(3.0, custom(UnitPrefix::Kilo), custom(UnitPrefix::Plain)),
+            3000.0
+        );
+
+        let err = PrefixedUnit::from(Unit::Watt)
+            .conversion_to(&PrefixedUnit::milli(Unit::Joule))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Incompatible units: cannot convert W to milliJ");
+        PrefixedUnit::from(Unit::Unity)
+            .conversion_to(&custom(UnitPrefix::Plain))
+            .unwrap_err();
+    }
 }
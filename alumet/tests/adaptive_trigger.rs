@@ -0,0 +1,117 @@
+use std::{thread, time::Duration};
+
+use alumet::{
+    agent::{self, plugin::PluginSet},
+    measurement::{MeasurementAccumulator, MeasurementPoint, Timestamp},
+    metrics::TypedMetricId,
+    pipeline::{
+        self,
+        elements::error::PollError,
+        matching::SourceSelector,
+        trigger::{self, AdaptivePolicy},
+    },
+    plugin::{rust::AlumetPlugin, AlumetPluginStart, ConfigTable},
+    resources::{Resource, ResourceConsumer},
+    static_plugins,
+    units::Unit,
+};
+use anyhow::Context;
+
+const MIN_INTERVAL: Duration = Duration::from_millis(5);
+const MAX_INTERVAL: Duration = Duration::from_millis(40);
+
+struct TestPlugin;
+
+/// A source whose value changes a lot at each poll, or never changes.
+struct TestSource {
+    metric: TypedMetricId<u64>,
+    busy: bool,
+    polls: u64,
+}
+
+impl AlumetPlugin for TestPlugin {
+    fn name() -> &'static str {
+        "adaptive"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(TestPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let metric = alumet.create_metric::<u64>("value", Unit::Unity, "")?;
+        for busy in [true, false] {
+            let trigger = trigger::builder::adaptive(
+                MIN_INTERVAL,
+                MAX_INTERVAL,
+                AdaptivePolicy::ValueChange { threshold: 0.1 },
+            )
+            .update_interval(Duration::from_millis(10))
+            .build()?;
+            let source = TestSource { metric, busy, polls: 0 };
+            alumet.add_source(Box::new(source), trigger);
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl pipeline::Source for TestSource {
+    fn poll(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        self.polls += 1;
+        let value = if self.busy && self.polls % 2 == 0 { 100 } else { 1 };
+        m.push(MeasurementPoint::new(
+            t,
+            self.metric,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            value,
+        ));
+        Ok(())
+    }
+}
+
+#[test]
+fn interval_adapts_to_the_activity() -> anyhow::Result<()> {
+    let plugins = PluginSet::from(static_plugins![TestPlugin]);
+    let agent = agent::Builder::from_pipeline(plugins, pipeline::Builder::new())
+        .build_and_start()
+        .expect("agent should start fine");
+    let control = agent.pipeline.control_handle();
+    let rt = agent.pipeline.async_runtime().clone();
+
+    // the triggers start at the minimum interval
+    let statuses = rt.block_on(control.inspect_sources(SourceSelector::all()))?;
+    assert_eq!(statuses.len(), 2);
+    assert!(statuses.iter().all(|s| s.poll_interval == Some(MIN_INTERVAL)));
+
+    thread::sleep(Duration::from_secs(1));
+
+    // the busy source keeps polling fast, the idle one slows down
+    let statuses = rt.block_on(control.inspect_sources(SourceSelector::all()))?;
+    let intervals: Vec<_> = statuses.iter().map(|s| (s.name.to_string(), s.poll_interval)).collect();
+    assert_eq!(
+        intervals,
+        vec![
+            (String::from("adaptive/source/0"), Some(MIN_INTERVAL)),
+            (String::from("adaptive/source/1"), Some(MAX_INTERVAL)),
+        ]
+    );
+
+    control.shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+    Ok(())
+}
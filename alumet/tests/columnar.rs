@@ -0,0 +1,181 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+use alumet::{
+    agent::{self, plugin::PluginSet},
+    measurement::{
+        columnar::{AttributesRef, ColumnarBuffer, ConsumerRef, ResourceRef},
+        MeasurementAccumulator, MeasurementBuffer, Timestamp, WrappedMeasurementValue,
+    },
+    metrics::TypedMetricId,
+    pipeline::{
+        self,
+        elements::{
+            error::{PollError, TransformError, WriteError},
+            output::OutputContext,
+            transform::TransformContext,
+        },
+        trigger::TriggerSpec,
+        Output, Transform,
+    },
+    plugin::{rust::AlumetPlugin, AlumetPluginStart, ConfigTable},
+    resources::{Resource, ResourceConsumer},
+    static_plugins,
+    units::Unit,
+};
+use anyhow::Context;
+
+/// Number of points per poll.
+const POINTS: u64 = 16;
+
+/// Number of points that the transform has seen in columnar batches.
+static TRANSFORMED_IN_BATCHES: AtomicU64 = AtomicU64::new(0);
+/// Number of points that the columnar output has received in columnar batches, and outside of them.
+static COLUMNAR_BATCHES: AtomicU64 = AtomicU64::new(0);
+static COLUMNAR_ROWS: AtomicU64 = AtomicU64::new(0);
+/// Number of points, and sum of their values, received by the regular output.
+static REGULAR_ROWS: AtomicU64 = AtomicU64::new(0);
+static REGULAR_SUM: AtomicU64 = AtomicU64::new(0);
+
+struct TestPlugin;
+
+struct TestSource {
+    metric: TypedMetricId<u64>,
+    batch: ColumnarBuffer,
+    cpus: Vec<ResourceRef>,
+    machine: ConsumerRef,
+}
+
+/// Doubles the values, directly in the columns.
+struct DoubleTransform;
+
+struct ColumnarOutput;
+
+struct RegularOutput;
+
+impl AlumetPlugin for TestPlugin {
+    fn name() -> &'static str {
+        "columnar"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(TestPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "")?;
+        let mut batch = ColumnarBuffer::new();
+        let cpus = (0..POINTS as u32)
+            .map(|id| batch.resource(Resource::CpuCore { id }))
+            .collect();
+        let machine = batch.consumer(ResourceConsumer::LocalMachine);
+        let source = TestSource {
+            metric,
+            batch,
+            cpus,
+            machine,
+        };
+        alumet.add_source(Box::new(source), TriggerSpec::at_interval(Duration::from_millis(10)));
+        alumet.add_transform(Box::new(DoubleTransform));
+        alumet.add_blocking_output(Box::new(ColumnarOutput));
+        alumet.add_blocking_output(Box::new(RegularOutput));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl pipeline::Source for TestSource {
+    fn poll(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        for cpu in &self.cpus {
+            self.batch
+                .push(t, self.metric, 1, *cpu, self.machine, AttributesRef::EMPTY);
+        }
+        m.push_columnar(&mut self.batch);
+        Ok(())
+    }
+}
+
+impl Transform for DoubleTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        for batch in measurements.columnar_mut() {
+            TRANSFORMED_IN_BATCHES.fetch_add(batch.len() as u64, Ordering::Relaxed);
+            for v in batch.values_mut() {
+                if let WrappedMeasurementValue::U64(v) = v {
+                    *v *= 2;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn accepts_columnar(&self) -> bool {
+        true
+    }
+}
+
+impl Output for ColumnarOutput {
+    fn write(&mut self, m: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        let in_batches: usize = m.columnar().iter().map(|b| b.len()).sum();
+        COLUMNAR_BATCHES.fetch_add(in_batches as u64, Ordering::Relaxed);
+        COLUMNAR_ROWS.fetch_add((m.len() - in_batches) as u64, Ordering::Relaxed);
+        assert_eq!(m.iter().count(), m.len(), "iter should cover the batches");
+        Ok(())
+    }
+
+    fn accepts_columnar(&self) -> bool {
+        true
+    }
+}
+
+impl Output for RegularOutput {
+    fn write(&mut self, m: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        assert!(
+            m.columnar().is_empty(),
+            "the batches should have been converted to rows"
+        );
+        for p in m {
+            REGULAR_ROWS.fetch_add(1, Ordering::Relaxed);
+            REGULAR_SUM.fetch_add(p.value.as_f64().unwrap() as u64, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn columnar_batches_go_through_the_pipeline() -> anyhow::Result<()> {
+    let plugins = PluginSet::from(static_plugins![TestPlugin]);
+    let agent = agent::Builder::from_pipeline(plugins, pipeline::Builder::new())
+        .build_and_start()
+        .expect("agent should start fine");
+    thread::sleep(Duration::from_millis(200));
+    agent.pipeline.control_handle().shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+
+    // the transform and the columnar output only receive batches
+    let points = TRANSFORMED_IN_BATCHES.load(Ordering::Relaxed);
+    assert!(points > 0);
+    assert_eq!(points % POINTS, 0);
+    assert_eq!(COLUMNAR_BATCHES.load(Ordering::Relaxed), points);
+    assert_eq!(COLUMNAR_ROWS.load(Ordering::Relaxed), 0);
+
+    // the regular output receives rows, with the values modified by the transform
+    assert_eq!(REGULAR_ROWS.load(Ordering::Relaxed), points);
+    assert_eq!(REGULAR_SUM.load(Ordering::Relaxed), points * 2);
+    Ok(())
+}
@@ -182,6 +182,7 @@ impl Transform for TestTransform {
             res.value = match res.value {
                 f @ WrappedMeasurementValue::F64(_) => f,
                 WrappedMeasurementValue::U64(i) => WrappedMeasurementValue::F64(i as f64),
+                _ => unreachable!("the test source only produces u64 values"),
             };
             res
         }
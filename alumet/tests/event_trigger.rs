@@ -0,0 +1,118 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+use alumet::{
+    agent::{self, plugin::PluginSet},
+    measurement::{MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp},
+    metrics::TypedMetricId,
+    pipeline::{
+        self,
+        elements::{
+            error::{PollError, WriteError},
+            output::OutputContext,
+        },
+        trigger, Output,
+    },
+    plugin::{rust::AlumetPlugin, AlumetPluginStart, ConfigTable},
+    resources::{Resource, ResourceConsumer},
+    static_plugins,
+    units::Unit,
+};
+use anyhow::Context;
+use futures::StreamExt;
+
+/// Number of items in the stream that triggers the source.
+const EVENTS: u64 = 5;
+
+static POINTS_WRITTEN: AtomicU64 = AtomicU64::new(0);
+
+struct TestPlugin;
+
+struct TestSource {
+    metric: TypedMetricId<u64>,
+    polls: u64,
+}
+
+struct TestOutput;
+
+impl AlumetPlugin for TestPlugin {
+    fn name() -> &'static str {
+        "events"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(TestPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let metric = alumet.create_metric::<u64>("events", Unit::Unity, "")?;
+        let trigger = trigger::builder::stream(|| {
+            let ticks = tokio_stream::wrappers::IntervalStream::new(tokio::time::interval(Duration::from_millis(10)));
+            ticks.take(EVENTS as usize).map(|_| Ok(()))
+        })
+        .build()?;
+        alumet.add_source(Box::new(TestSource { metric, polls: 0 }), trigger);
+        alumet.add_blocking_output(Box::new(TestOutput));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl pipeline::Source for TestSource {
+    fn poll(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        self.polls += 1;
+        m.push(MeasurementPoint::new(
+            t,
+            self.metric,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            self.polls,
+        ));
+        Ok(())
+    }
+}
+
+impl Output for TestOutput {
+    fn write(&mut self, m: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        POINTS_WRITTEN.fetch_add(m.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[test]
+fn source_stops_at_the_end_of_its_stream() -> anyhow::Result<()> {
+    let plugins = PluginSet::from(static_plugins![TestPlugin]);
+    let agent = agent::Builder::from_pipeline(plugins, pipeline::Builder::new())
+        .build_and_start()
+        .expect("agent should start fine");
+    let telemetry = agent.pipeline.telemetry();
+    thread::sleep(Duration::from_millis(300));
+
+    // the source has stopped by itself, but the pipeline is still running
+    let sources = telemetry.sources();
+    assert_eq!(sources.len(), 1);
+    let (_, source) = &sources[0];
+    assert_eq!(source.polls, EVENTS);
+    assert_eq!(source.timed_polls, 0, "event triggers have no interval");
+
+    agent.pipeline.control_handle().shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+    assert_eq!(POINTS_WRITTEN.load(Ordering::Relaxed), EVENTS);
+    Ok(())
+}
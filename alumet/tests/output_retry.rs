@@ -0,0 +1,331 @@
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use alumet::{
+    agent::{self, plugin::PluginSet},
+    measurement::{MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp},
+    metrics::TypedMetricId,
+    pipeline::{
+        self,
+        elements::{
+            error::{PollError, WriteError},
+            output::{
+                builder::BlockingOutputRegistration, OutputContext, OverflowPolicy, RetryPolicy, SpillConfig,
+                MAX_DELAYED_BUFFERS,
+            },
+        },
+        matching::OutputSelector,
+        trigger::TriggerSpec,
+        Output,
+    },
+    plugin::{rust::AlumetPlugin, AlumetPluginStart, ConfigTable},
+    resources::{Resource, ResourceConsumer},
+    static_plugins,
+    units::Unit,
+};
+use anyhow::Context;
+
+/// Number of writes that fail before the output "recovers".
+const FAILED_WRITES: u64 = 10;
+
+static WRITE_CALLS: AtomicU64 = AtomicU64::new(0);
+static RECEIVED: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+static HEALTHY_POINTS: AtomicU64 = AtomicU64::new(0);
+
+struct TestPlugin;
+
+struct CountingSource {
+    metric: TypedMetricId<u64>,
+    next: u64,
+}
+
+struct FlakyOutput;
+
+/// An output that is always down.
+struct DownPlugin;
+
+struct DownOutput;
+
+/// An output that is always down, next to an output that works.
+struct DownAndHealthyPlugin;
+
+struct HealthyOutput;
+
+impl AlumetPlugin for TestPlugin {
+    fn name() -> &'static str {
+        "retry"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(TestPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let metric = alumet.create_metric::<u64>("sequence", Unit::Unity, "")?;
+        alumet.add_source(
+            Box::new(CountingSource { metric, next: 0 }),
+            TriggerSpec::at_interval(Duration::from_millis(20)),
+        );
+        alumet.add_blocking_output(Box::new(FlakyOutput));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl AlumetPlugin for DownPlugin {
+    fn name() -> &'static str {
+        "retry-down"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(DownPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let metric = alumet.create_metric::<u64>("sequence", Unit::Unity, "")?;
+        alumet.add_source(
+            Box::new(CountingSource { metric, next: 0 }),
+            TriggerSpec::at_interval(Duration::from_millis(20)),
+        );
+        alumet.add_blocking_output(Box::new(DownOutput));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl AlumetPlugin for DownAndHealthyPlugin {
+    fn name() -> &'static str {
+        "retry-down-healthy"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(DownAndHealthyPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let metric = alumet.create_metric::<u64>("sequence", Unit::Unity, "")?;
+        alumet.add_source(
+            Box::new(CountingSource { metric, next: 0 }),
+            TriggerSpec::at_interval(Duration::from_millis(2)),
+        );
+        alumet.add_blocking_output_builder(|ctx| {
+            Ok(BlockingOutputRegistration {
+                name: ctx.output_name("down"),
+                output: Box::new(DownOutput),
+            })
+        });
+        alumet.add_blocking_output_builder(|ctx| {
+            Ok(BlockingOutputRegistration {
+                name: ctx.output_name("healthy"),
+                output: Box::new(HealthyOutput),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl pipeline::Source for CountingSource {
+    fn poll(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        m.push(MeasurementPoint::new(
+            t,
+            self.metric,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            self.next,
+        ));
+        self.next += 1;
+        Ok(())
+    }
+}
+
+impl Output for FlakyOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        if WRITE_CALLS.fetch_add(1, Ordering::Relaxed) < FAILED_WRITES {
+            return Err(WriteError::CanRetry(anyhow::anyhow!("the database is down")));
+        }
+        let mut received = RECEIVED.lock().unwrap();
+        for p in measurements.iter() {
+            received.push(p.value.as_f64().unwrap() as u64);
+        }
+        Ok(())
+    }
+}
+
+impl Output for DownOutput {
+    fn write(&mut self, _measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        Err(WriteError::CanRetry(anyhow::anyhow!("the database is down")))
+    }
+}
+
+impl Output for HealthyOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        HEALTHY_POINTS.fetch_add(measurements.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[test]
+fn retry_and_spill() -> anyhow::Result<()> {
+    let spill_dir = tempfile::tempdir()?;
+    let plugins = PluginSet::from(static_plugins![TestPlugin]);
+
+    let mut pipeline_builder = pipeline::Builder::new();
+    *pipeline_builder.output_retry_policy_mut() = RetryPolicy {
+        max_attempts: 2,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(40),
+        spill: Some(SpillConfig {
+            directory: spill_dir.path().to_owned(),
+            max_bytes: 1 << 20,
+        }),
+    };
+
+    let agent = agent::Builder::from_pipeline(plugins, pipeline_builder)
+        .build_and_start()
+        .expect("agent should start fine");
+    let stats = agent.pipeline.output_stats();
+    thread::sleep(Duration::from_millis(800));
+    agent.pipeline.control_handle().shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+
+    // no measurement has been lost, and the order has been preserved
+    let received = RECEIVED.lock().unwrap();
+    assert!(received.len() > 10, "not enough measurements: {received:?}");
+    let expected: Vec<u64> = (0..received.len() as u64).collect();
+    assert_eq!(*received, expected);
+
+    let stats = stats.read();
+    assert_eq!(stats.len(), 1);
+    let (_, stats) = stats[0];
+    assert_eq!(stats.retries, FAILED_WRITES);
+    assert!(stats.spilled > 0);
+    assert_eq!(stats.replayed, stats.spilled);
+    assert_eq!(stats.dropped, 0);
+    assert_eq!(stats.pending, 0);
+    Ok(())
+}
+
+#[test]
+fn shutdown_during_backoff() -> anyhow::Result<()> {
+    let spill_dir = tempfile::tempdir()?;
+    let plugins = PluginSet::from(static_plugins![DownPlugin]);
+
+    let mut pipeline_builder = pipeline::Builder::new();
+    *pipeline_builder.output_retry_policy_mut() = RetryPolicy {
+        max_attempts: 10,
+        initial_backoff: Duration::from_secs(30),
+        max_backoff: Duration::from_secs(60),
+        spill: Some(SpillConfig {
+            directory: spill_dir.path().to_owned(),
+            max_bytes: 1 << 20,
+        }),
+    };
+
+    let agent = agent::Builder::from_pipeline(plugins, pipeline_builder)
+        .build_and_start()
+        .expect("agent should start fine");
+    let stats = agent.pipeline.output_stats();
+    thread::sleep(Duration::from_millis(200));
+
+    // the output waits for its backoff, but the shutdown does not
+    let shutdown_start = Instant::now();
+    agent.pipeline.control_handle().shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+    assert!(shutdown_start.elapsed() < Duration::from_secs(2));
+
+    // the measurements that could not be written are counted as lost
+    let stats = stats.read();
+    let (_, stats) = stats[0];
+    assert!(stats.dropped > 0);
+    assert!(stats.dropped_points >= stats.dropped);
+    assert_eq!(stats.pending, 0);
+    Ok(())
+}
+
+#[test]
+fn backoff_does_not_block_other_outputs() -> anyhow::Result<()> {
+    let plugins = PluginSet::from(static_plugins![DownAndHealthyPlugin]);
+
+    // small queues, that block the sources when they are full
+    let mut pipeline_builder = pipeline::Builder::new();
+    *pipeline_builder.source_channel_size() = 4;
+    pipeline_builder.set_output_overflow_policy(OutputSelector::from_str("*")?, OverflowPolicy::Block);
+    *pipeline_builder.output_retry_policy_mut() = RetryPolicy {
+        max_attempts: 10,
+        initial_backoff: Duration::from_secs(30),
+        max_backoff: Duration::from_secs(60),
+        spill: None,
+    };
+
+    let agent = agent::Builder::from_pipeline(plugins, pipeline_builder)
+        .build_and_start()
+        .expect("agent should start fine");
+    let stats = agent.pipeline.output_stats();
+    thread::sleep(Duration::from_millis(500));
+    agent.pipeline.control_handle().shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+
+    // the failing output keeps receiving during its backoff, hence the source is not blocked
+    let healthy = HEALTHY_POINTS.load(Ordering::Relaxed);
+    assert!(
+        healthy > 2 * MAX_DELAYED_BUFFERS as u64,
+        "not enough measurements: {healthy}"
+    );
+
+    // beyond the limit, the oldest measurements of the failing output are dropped
+    let stats = stats.read();
+    let (_, down) = stats
+        .iter()
+        .find(|(name, _)| name.to_string().ends_with("/down"))
+        .unwrap();
+    assert!(down.dropped > 0);
+    assert_eq!(down.spilled, 0);
+    Ok(())
+}
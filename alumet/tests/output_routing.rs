@@ -0,0 +1,211 @@
+use std::{
+    collections::BTreeSet,
+    str::FromStr,
+    sync::Mutex,
+    thread,
+    time::{self, Duration},
+};
+
+use alumet::{
+    agent::{self, plugin::PluginSet},
+    measurement::{
+        columnar::{AttributesRef, ColumnarBuffer, ConsumerRef, ResourceRef},
+        MeasurementAccumulator, MeasurementPoint, Timestamp,
+    },
+    metrics::TypedMetricId,
+    pipeline::{
+        self,
+        elements::{
+            error::{PollError, WriteError},
+            output::{
+                builder::{AsyncOutputRegistration, BlockingOutputRegistration},
+                OutputContext,
+            },
+        },
+        matching::{NamePattern, OutputSelector},
+        routing::{MeasurementFilter, OutputRoute},
+        trigger::TriggerSpec,
+        Output,
+    },
+    plugin::{rust::AlumetPlugin, AlumetPluginStart, ConfigTable},
+    resources::{Resource, ResourceConsumer},
+    static_plugins,
+    units::Unit,
+};
+use anyhow::Context;
+use futures::StreamExt;
+
+/// Metric names received by each output.
+static RECEIVED_ALL: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+static RECEIVED_RAW: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+static RECEIVED_ASYNC: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+struct TestPlugin;
+
+struct TestSource {
+    raw: TypedMetricId<u64>,
+    mean: TypedMetricId<u64>,
+}
+
+/// Pushes its measurements as a columnar batch.
+struct BatchSource {
+    batch: TypedMetricId<u64>,
+    columns: ColumnarBuffer,
+    machine: ResourceRef,
+    consumer: ConsumerRef,
+}
+
+struct TestOutput(&'static Mutex<BTreeSet<String>>);
+
+impl AlumetPlugin for TestPlugin {
+    fn name() -> &'static str {
+        "routing"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(TestPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let raw = alumet.create_metric::<u64>("power_raw", Unit::Watt, "")?;
+        let mean = alumet.create_metric::<u64>("power_mean", Unit::Watt, "")?;
+        alumet.add_source(
+            Box::new(TestSource { raw, mean }),
+            TriggerSpec::at_interval(Duration::from_millis(50)),
+        );
+        let batch = alumet.create_metric::<u64>("power_batch", Unit::Watt, "")?;
+        let mut columns = ColumnarBuffer::new();
+        let machine = columns.resource(Resource::LocalMachine);
+        let consumer = columns.consumer(ResourceConsumer::LocalMachine);
+        alumet.add_source(
+            Box::new(BatchSource {
+                batch,
+                columns,
+                machine,
+                consumer,
+            }),
+            TriggerSpec::at_interval(Duration::from_millis(50)),
+        );
+        alumet.add_blocking_output_builder(|ctx| {
+            Ok(BlockingOutputRegistration {
+                name: ctx.output_name("all"),
+                output: Box::new(TestOutput(&RECEIVED_ALL)),
+            })
+        });
+        alumet.add_blocking_output_builder(|ctx| {
+            Ok(BlockingOutputRegistration {
+                name: ctx.output_name("raw"),
+                output: Box::new(TestOutput(&RECEIVED_RAW)),
+            })
+        });
+        alumet.add_async_output_builder(|ctx, mut stream| {
+            let metrics = ctx.metrics_reader();
+            let output = async move {
+                while let Some(res) = stream.0.next().await {
+                    let measurements = res.map_err(|e| anyhow::anyhow!("{e:?}"))?;
+                    let metrics = metrics.read().await;
+                    let mut received = RECEIVED_ASYNC.lock().unwrap();
+                    for p in measurements.iter() {
+                        received.insert(metrics.by_id(&p.metric).unwrap().name.clone());
+                    }
+                }
+                Ok(())
+            };
+            Ok(AsyncOutputRegistration {
+                name: ctx.output_name("async"),
+                output: Box::pin(output),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl pipeline::Source for TestSource {
+    fn poll(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        for metric in [self.raw, self.mean] {
+            m.push(MeasurementPoint::new(
+                t,
+                metric,
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                1,
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl pipeline::Source for BatchSource {
+    fn poll(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        self.columns
+            .push(t, self.batch, 1, self.machine, self.consumer, AttributesRef::EMPTY);
+        m.push_columnar(&mut self.columns);
+        Ok(())
+    }
+}
+
+impl Output for TestOutput {
+    fn write(
+        &mut self,
+        measurements: &alumet::measurement::MeasurementBuffer,
+        ctx: &OutputContext,
+    ) -> Result<(), WriteError> {
+        let mut received = self.0.lock().unwrap();
+        for p in measurements.iter() {
+            received.insert(ctx.metrics.by_id(&p.metric).unwrap().name.clone());
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn output_routing() -> anyhow::Result<()> {
+    let plugins = PluginSet::from(static_plugins![TestPlugin]);
+
+    let mut pipeline_builder = pipeline::Builder::new();
+    pipeline_builder.add_output_route(OutputRoute {
+        outputs: OutputSelector::from_str("routing/out/raw")?,
+        filter: MeasurementFilter {
+            metrics: vec![NamePattern::from_str("*_raw")?],
+            ..Default::default()
+        },
+    });
+    pipeline_builder.add_output_route(OutputRoute {
+        outputs: OutputSelector::from_str("*/out/async")?,
+        filter: MeasurementFilter {
+            metrics: vec![
+                NamePattern::from_str("power_mean")?,
+                NamePattern::from_str("power_batch")?,
+            ],
+            ..Default::default()
+        },
+    });
+
+    let agent = agent::Builder::from_pipeline(plugins, pipeline_builder)
+        .build_and_start()
+        .expect("agent should start fine");
+    thread::sleep(time::Duration::from_millis(500));
+    agent.pipeline.control_handle().shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+
+    let names = |received: &Mutex<BTreeSet<String>>| received.lock().unwrap().iter().cloned().collect::<Vec<_>>();
+    assert_eq!(names(&RECEIVED_ALL), vec!["power_batch", "power_mean", "power_raw"]);
+    assert_eq!(names(&RECEIVED_RAW), vec!["power_raw"]);
+    // the columnar batches go through the filter of the async output
+    assert_eq!(names(&RECEIVED_ASYNC), vec!["power_batch", "power_mean"]);
+    Ok(())
+}
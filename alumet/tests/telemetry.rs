@@ -0,0 +1,150 @@
+use std::{thread, time::Duration};
+
+use alumet::{
+    agent::{self, plugin::PluginSet},
+    measurement::{MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp},
+    metrics::TypedMetricId,
+    pipeline::{
+        self,
+        elements::{
+            error::{PollError, TransformError, WriteError},
+            output::OutputContext,
+            transform::TransformContext,
+        },
+        trigger::TriggerSpec,
+        Output, Transform,
+    },
+    plugin::{rust::AlumetPlugin, AlumetPluginStart, ConfigTable},
+    resources::{Resource, ResourceConsumer},
+    static_plugins,
+    units::Unit,
+};
+use anyhow::Context;
+
+/// Number of measurement points produced by each poll.
+const POINTS_PER_POLL: usize = 3;
+
+struct TestPlugin;
+
+struct TestSource {
+    metric: TypedMetricId<u64>,
+    polls: u64,
+}
+
+struct SlowTransform;
+
+struct TestOutput;
+
+impl AlumetPlugin for TestPlugin {
+    fn name() -> &'static str {
+        "telemetry"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(TestPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "")?;
+        alumet.add_source(
+            Box::new(TestSource { metric, polls: 0 }),
+            TriggerSpec::at_interval(Duration::from_millis(20)),
+        );
+        alumet.add_transform(Box::new(SlowTransform));
+        alumet.add_blocking_output(Box::new(TestOutput));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl pipeline::Source for TestSource {
+    fn poll(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        self.polls += 1;
+        if self.polls % 4 == 0 {
+            return Err(PollError::CanRetry(anyhow::anyhow!("every fourth poll fails")));
+        }
+        for i in 0..POINTS_PER_POLL {
+            m.push(MeasurementPoint::new(
+                t,
+                self.metric,
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                i as u64,
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Transform for SlowTransform {
+    fn apply(&mut self, _m: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        thread::sleep(Duration::from_millis(2));
+        Ok(())
+    }
+}
+
+impl Output for TestOutput {
+    fn write(&mut self, _m: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn pipeline_telemetry() -> anyhow::Result<()> {
+    let plugins = PluginSet::from(static_plugins![TestPlugin]);
+    let agent = agent::Builder::from_pipeline(plugins, pipeline::Builder::new())
+        .build_and_start()
+        .expect("agent should start fine");
+    let telemetry = agent.pipeline.telemetry();
+    thread::sleep(Duration::from_millis(500));
+
+    let channel = telemetry.source_channel().expect("the source channel should be open");
+    assert!(channel.queued <= channel.capacity);
+
+    agent.pipeline.control_handle().shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+
+    let sources = telemetry.sources();
+    assert_eq!(sources.len(), 1);
+    let (name, source) = &sources[0];
+    assert_eq!(name.to_string(), "telemetry/source/0");
+    assert!(source.polls > 10, "not enough polls: {source:?}");
+    assert_eq!(source.timed_polls, source.polls - 1);
+    assert_eq!(source.retryable_errors, source.polls / 4);
+    assert_eq!(source.fatal_errors, 0);
+    assert_eq!(
+        source.points,
+        (source.polls - source.retryable_errors) * POINTS_PER_POLL as u64
+    );
+    // the jitter should be small compared to the interval
+    assert!(source.jitter / (source.timed_polls as u32) < Duration::from_millis(10));
+
+    let transforms = telemetry.transforms();
+    assert_eq!(transforms.len(), 1);
+    let (_, transform) = &transforms[0];
+    assert!(transform.runs > 0);
+    assert!(transform.run_time >= Duration::from_millis(2) * transform.runs as u32);
+    assert_eq!(transform.errors, 0);
+
+    let outputs = telemetry.outputs();
+    assert_eq!(outputs.len(), 1);
+    let (_, output) = &outputs[0];
+    // the buffers of the failed polls are empty, they are not written
+    assert!(output.writes > 0 && output.writes <= transform.runs);
+    assert_eq!(output.retries, 0);
+    assert_eq!(output.queued, 0);
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+use alumet::{
+    agent::{self, plugin::PluginSet},
+    measurement::{MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType},
+    metrics::{Metric, MetricKind, RawMetricId, TypedMetricId},
+    pipeline::{
+        self,
+        elements::{
+            error::{PollError, TransformError, WriteError},
+            output::{builder::BlockingOutputRegistration, OutputContext},
+            transform::{builder::TransformRegistration, TransformContext},
+        },
+        trigger::TriggerSpec,
+        Output, Transform,
+    },
+    plugin::{rust::AlumetPlugin, AlumetPluginStart, ConfigTable},
+    resources::{Resource, ResourceConsumer},
+    static_plugins,
+    units::Unit,
+};
+use anyhow::Context;
+
+static POLLED: AtomicU64 = AtomicU64::new(0);
+static WRITTEN_INPUT: AtomicU64 = AtomicU64::new(0);
+static WRITTEN_DERIVED: AtomicU64 = AtomicU64::new(0);
+
+struct TestPlugin;
+
+struct TestSource {
+    metric: TypedMetricId<u64>,
+}
+
+/// Adds a point of a metric that it has registered, for each input point.
+struct DeriveTransform {
+    input: RawMetricId,
+    derived: RawMetricId,
+}
+
+struct TestOutput {
+    input: RawMetricId,
+    derived: RawMetricId,
+}
+
+impl AlumetPlugin for TestPlugin {
+    fn name() -> &'static str {
+        "derive"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(TestPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let metric = alumet.create_metric::<u64>("input", Unit::Unity, "")?;
+        alumet.add_source(
+            Box::new(TestSource { metric }),
+            TriggerSpec::at_interval(Duration::from_millis(10)),
+        );
+        alumet.add_transform_builder(|ctx| {
+            let (input, metric) = ctx.metric_by_name("input").context("input metric not found")?;
+            let derived = Metric {
+                name: String::from("derived"),
+                kind: MetricKind::Gauge,
+                ..metric.clone()
+            };
+            let derived = ctx.create_metric(derived)?;
+            assert!(ctx
+                .create_metric(ctx.metrics().by_id(&derived).unwrap().clone())
+                .is_err());
+            Ok(TransformRegistration {
+                name: ctx.transform_name("derive"),
+                transform: Box::new(DeriveTransform { input, derived }),
+            })
+        });
+        alumet.add_blocking_output_builder(|ctx| {
+            // the outputs are built after the transforms
+            let (input, _) = ctx.metric_by_name("input").context("input metric not found")?;
+            let (derived, metric) = ctx.metric_by_name("derived").context("derived metric not found")?;
+            assert_eq!(metric.value_type, WrappedMeasurementType::U64);
+            Ok(BlockingOutputRegistration {
+                name: ctx.output_name("out"),
+                output: Box::new(TestOutput { input, derived }),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl pipeline::Source for TestSource {
+    fn poll(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        POLLED.fetch_add(1, Ordering::Relaxed);
+        m.push(MeasurementPoint::new(
+            t,
+            self.metric,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            1,
+        ));
+        Ok(())
+    }
+}
+
+impl Transform for DeriveTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, ctx: &TransformContext) -> Result<(), TransformError> {
+        assert!(ctx.metrics.by_id(&self.derived).is_some());
+        let derived: Vec<_> = measurements
+            .iter()
+            .filter(|p| p.metric == self.input)
+            .map(|p| {
+                let mut p = p.clone();
+                p.metric = self.derived;
+                p
+            })
+            .collect();
+        for p in derived {
+            measurements.push(p);
+        }
+        Ok(())
+    }
+}
+
+impl Output for TestOutput {
+    fn write(&mut self, m: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        for p in m {
+            if p.metric == self.input {
+                WRITTEN_INPUT.fetch_add(1, Ordering::Relaxed);
+            } else if p.metric == self.derived {
+                WRITTEN_DERIVED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn transform_registers_metrics_before_the_first_measurement() -> anyhow::Result<()> {
+    let plugins = PluginSet::from(static_plugins![TestPlugin]);
+    let agent = agent::Builder::from_pipeline(plugins, pipeline::Builder::new())
+        .build_and_start()
+        .expect("agent should start fine");
+    thread::sleep(Duration::from_millis(100));
+    agent.pipeline.control_handle().shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+
+    // every point, including the first ones, has been derived
+    let polled = POLLED.load(Ordering::Relaxed);
+    assert!(polled > 0);
+    assert_eq!(WRITTEN_INPUT.load(Ordering::Relaxed), polled);
+    assert_eq!(WRITTEN_DERIVED.load(Ordering::Relaxed), polled);
+    Ok(())
+}
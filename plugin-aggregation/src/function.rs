@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+/// An aggregation function, applied to all the values of a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Function {
+    Mean,
+    Min,
+    Max,
+    Sum,
+    /// The value with the most recent timestamp.
+    Last,
+    /// The number of values.
+    Count,
+    /// The median.
+    P50,
+    /// The 95th percentile.
+    P95,
+    /// The 99th percentile.
+    P99,
+}
+
+impl Function {
+    /// Returns the name of the function, as written in the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::Mean => "mean",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Sum => "sum",
+            Function::Last => "last",
+            Function::Count => "count",
+            Function::P50 => "p50",
+            Function::P95 => "p95",
+            Function::P99 => "p99",
+        }
+    }
+
+    /// Applies the function to the values of a window.
+    ///
+    /// `sorted_values` must be sorted in ascending order and must not be empty,
+    /// `last` is the value with the most recent timestamp.
+    pub fn apply(&self, sorted_values: &[f64], last: f64) -> f64 {
+        let n = sorted_values.len();
+        match self {
+            Function::Mean => sorted_values.iter().sum::<f64>() / n as f64,
+            Function::Min => sorted_values[0],
+            Function::Max => sorted_values[n - 1],
+            Function::Sum => sorted_values.iter().sum(),
+            Function::Last => last,
+            Function::Count => n as f64,
+            Function::P50 => percentile(sorted_values, 50.0),
+            Function::P95 => percentile(sorted_values, 95.0),
+            Function::P99 => percentile(sorted_values, 99.0),
+        }
+    }
+}
+
+/// Computes a percentile with the nearest-rank method.
+fn percentile(sorted_values: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted_values.len() as f64).ceil() as usize;
+    sorted_values[rank.saturating_sub(1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Function;
+
+    #[test]
+    fn functions() {
+        let values: Vec<f64> = (1..=20).map(|x| x as f64).collect();
+        let apply = |f: Function| f.apply(&values, 7.0);
+        assert_eq!(apply(Function::Mean), 10.5);
+        assert_eq!(apply(Function::Min), 1.0);
+        assert_eq!(apply(Function::Max), 20.0);
+        assert_eq!(apply(Function::Sum), 210.0);
+        assert_eq!(apply(Function::Last), 7.0);
+        assert_eq!(apply(Function::Count), 20.0);
+        assert_eq!(apply(Function::P50), 10.0);
+        assert_eq!(apply(Function::P95), 19.0);
+        assert_eq!(apply(Function::P99), 20.0);
+    }
+
+    #[test]
+    fn single_value() {
+        for f in [Function::P50, Function::P95, Function::P99, Function::Mean] {
+            assert_eq!(f.apply(&[3.0], 3.0), 3.0);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::{collections::HashMap, time::Duration};
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    metrics::{Metric, MetricKind, RawMetricId},
+    pipeline::elements::transform::builder::{TransformBuildContext, TransformRegistration},
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+    units::{PrefixedUnit, Unit},
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use function::Function;
+use transform::{Aggregation, AggregationTransform, Output, Rule};
+
+mod function;
+mod transform;
+
+pub struct AggregationPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for AggregationPlugin {
+    fn name() -> &'static str {
+        "aggregation"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config: Config = deserialize_config(config)?;
+        for rule in &config.rules {
+            if rule.window.is_zero() {
+                return Err(anyhow!(
+                    "invalid aggregation window for {:?}: it must not be zero",
+                    rule.metrics
+                ));
+            }
+            if rule.functions.is_empty() {
+                return Err(anyhow!("no aggregation function for {:?}", rule.metrics));
+            }
+        }
+        Ok(Box::new(AggregationPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        if config.rules.is_empty() {
+            log::warn!("No aggregation rule, the aggregation plugin will do nothing. Add some rules to its config.");
+            return Ok(());
+        }
+
+        // The aggregated metrics depend on the metrics that are registered by other plugins.
+        // Therefore, they are registered when the transform is built.
+        let rules = config
+            .rules
+            .iter()
+            .map(|r| Rule {
+                window: r.window,
+                functions: r.functions.clone(),
+                group_by: r.group_by.clone(),
+                keep_original: r.keep_original,
+            })
+            .collect();
+        alumet.add_transform_builder(move |ctx| {
+            let aggregations = register_aggregated_metrics(ctx, &config.rules)?;
+            Ok(TransformRegistration {
+                name: ctx.transform_name("aggregation"),
+                transform: Box::new(AggregationTransform::new(rules, aggregations)),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Registers the aggregated metrics, and returns how to compute them.
+fn register_aggregated_metrics(
+    ctx: &mut dyn TransformBuildContext,
+    rules: &[RuleConfig],
+) -> anyhow::Result<HashMap<RawMetricId, Aggregation>> {
+    let mut aggregations = HashMap::new();
+    for (rule, config) in rules.iter().enumerate() {
+        for name in &config.metrics {
+            // Find the source metric and define the aggregated metrics.
+            let (source, metric) = ctx
+                .metric_by_name(name)
+                .with_context(|| format!("metric not found: {name}"))?;
+            if metric.value_type == WrappedMeasurementType::Histogram {
+                return Err(anyhow!("metric {name} is a histogram, it cannot be aggregated"));
+            }
+            let ids = aggregated_metrics(metric, config)
+                .into_iter()
+                .map(|m| ctx.create_metric(m))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| anyhow!("could not register the metrics aggregated from {name}: {e}"))?;
+            let output = match config.output {
+                OutputMode::Metrics => Output::Metrics(ids),
+                OutputMode::Attribute => Output::Attribute(ids[0]),
+            };
+            aggregations.insert(source, Aggregation { rule, output });
+        }
+    }
+    Ok(aggregations)
+}
+
+/// Defines the metrics that hold the aggregates of a metric.
+fn aggregated_metrics(source: &Metric, rule: &RuleConfig) -> Vec<Metric> {
+    match rule.output {
+        OutputMode::Metrics => rule
+            .functions
+            .iter()
+            .map(|f| {
+                let (value_type, unit) = match f {
+                    Function::Count => (WrappedMeasurementType::U64, PrefixedUnit::from(Unit::Unity)),
+                    _ => (WrappedMeasurementType::F64, source.unit.clone()),
+                };
+                // The sum of some increments over a window is the increment over the whole window.
+                let kind = match (f, source.kind) {
+                    (Function::Sum, MetricKind::Delta) => MetricKind::Delta,
+                    _ => MetricKind::Gauge,
+                };
+                Metric {
+                    name: format!("{}_{}", source.name, f.name()),
+                    description: format!(
+                        "Aggregate ({}) of {} over windows of {}.",
+                        f.name(),
+                        source.name,
+                        humantime::format_duration(rule.window)
+                    ),
+                    value_type,
+                    unit,
+                    kind,
+                    labels: source.labels.clone(),
+                }
+            })
+            .collect(),
+        OutputMode::Attribute => vec![Metric {
+            name: format!("{}_aggregated", source.name),
+            description: format!(
+                "Aggregates of {} over windows of {}. The attribute `{}` indicates the aggregation function.",
+                source.name,
+                humantime::format_duration(rule.window),
+                transform::FUNCTION_ATTRIBUTE
+            ),
+            value_type: WrappedMeasurementType::F64,
+            unit: source.unit.clone(),
+            kind: MetricKind::Gauge,
+            labels: source.labels.clone(),
+        }],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    /// One new metric per aggregation function.
+    #[default]
+    Metrics,
+    /// One new metric for all the functions, with an attribute that indicates the function.
+    Attribute,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    /// Names of the metrics to aggregate.
+    metrics: Vec<String>,
+    /// Width of the tumbling windows.
+    #[serde(with = "humantime_serde")]
+    window: Duration,
+    functions: Vec<Function>,
+    /// Attributes that identify a group, in addition to the resource and consumer.
+    /// The other attributes are dropped.
+    #[serde(default)]
+    group_by: Vec<String>,
+    #[serde(default)]
+    output: OutputMode,
+    /// Keep the original measurements, in addition to the aggregated values.
+    #[serde(default)]
+    keep_original: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use alumet::{
+        measurement::WrappedMeasurementType,
+        metrics::{Metric, MetricKind},
+        units::{PrefixedUnit, Unit},
+    };
+
+    use super::{aggregated_metrics, Function, OutputMode, RuleConfig};
+
+    #[test]
+    fn aggregated_metrics_definition() {
+        let power = Metric {
+            name: String::from("power"),
+            description: String::new(),
+            value_type: WrappedMeasurementType::U64,
+            unit: PrefixedUnit::milli(Unit::Watt),
+            kind: MetricKind::Gauge,
+            labels: vec![],
+        };
+        let mut rule = RuleConfig {
+            metrics: vec![power.name.clone()],
+            window: Duration::from_secs(10),
+            functions: vec![Function::Mean, Function::Count],
+            group_by: Vec::new(),
+            output: OutputMode::Metrics,
+            keep_original: false,
+        };
+        let metrics = aggregated_metrics(&power, &rule);
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].name, "power_mean");
+        assert_eq!(metrics[0].unit, PrefixedUnit::milli(Unit::Watt));
+        assert_eq!(metrics[0].value_type, WrappedMeasurementType::F64);
+        assert_eq!(metrics[1].name, "power_count");
+        assert_eq!(metrics[1].unit, PrefixedUnit::from(Unit::Unity));
+        assert_eq!(metrics[1].value_type, WrappedMeasurementType::U64);
+
+        rule.output = OutputMode::Attribute;
+        let metrics = aggregated_metrics(&power, &rule);
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].name, "power_aggregated");
+        assert_eq!(metrics[0].value_type, WrappedMeasurementType::F64);
+    }
+}
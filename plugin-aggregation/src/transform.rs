@@ -0,0 +1,464 @@
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use alumet::{
+    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::{
+        elements::{error::TransformError, transform::TransformContext},
+        Transform,
+    },
+    resources::{Resource, ResourceConsumer},
+};
+
+use crate::function::Function;
+
+/// Name of the attribute that indicates the aggregation function, when
+/// the aggregates are stored in a single metric.
+pub const FUNCTION_ATTRIBUTE: &str = "aggregate";
+
+/// Parameters of the aggregation, shared by several metrics.
+pub struct Rule {
+    /// Width of the tumbling windows.
+    pub window: Duration,
+    pub functions: Vec<Function>,
+    /// Keys of the attributes that identify a group, in addition to the resource and consumer.
+    pub group_by: Vec<String>,
+    /// Keep the original measurements, in addition to the aggregated values.
+    pub keep_original: bool,
+}
+
+/// How to aggregate a metric.
+pub struct Aggregation {
+    /// Index of the rule that applies to the metric.
+    pub rule: usize,
+    pub output: Output,
+}
+
+/// Where to store the aggregated values.
+pub enum Output {
+    /// One metric per function, in the same order as the functions of the rule.
+    Metrics(Vec<RawMetricId>),
+    /// One metric for all the functions, with an attribute that indicates the function.
+    Attribute(RawMetricId),
+}
+
+/// Aggregates the values of some metrics over tumbling windows.
+pub struct AggregationTransform {
+    rules: Vec<Rule>,
+    /// The metrics to aggregate, by metric id.
+    aggregations: Arc<HashMap<RawMetricId, Aggregation>>,
+    /// The current window of each group.
+    windows: HashMap<GroupKey, Window>,
+    /// The most recent timestamp seen by each rule.
+    watermarks: Vec<Option<SystemTime>>,
+}
+
+/// Identifies a group of values that are aggregated together.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct GroupKey {
+    metric: RawMetricId,
+    resource: Resource,
+    consumer: ResourceConsumer,
+    attributes: Vec<(String, String)>,
+}
+
+/// A window that has not been closed yet.
+struct Window {
+    start: SystemTime,
+    values: Vec<f64>,
+    /// The most recent value and its timestamp.
+    last: (SystemTime, f64),
+    /// The attributes of the group.
+    attributes: Vec<(String, AttributeValue)>,
+}
+
+impl AggregationTransform {
+    pub fn new(rules: Vec<Rule>, aggregations: HashMap<RawMetricId, Aggregation>) -> Self {
+        let watermarks = vec![None; rules.len()];
+        Self {
+            rules,
+            aggregations: Arc::new(aggregations),
+            windows: HashMap::new(),
+            watermarks,
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        let aggregations = self.aggregations.clone();
+
+        let mut aggregated = Vec::new();
+        for point in measurements.iter() {
+            let Some(aggregation) = aggregations.get(&point.metric) else {
+                continue;
+            };
+            let rule = &self.rules[aggregation.rule];
+            let Some(value) = point.value.as_f64() else {
+                log::debug!("Ignoring value of {:?} that is not a number", point.metric);
+                continue;
+            };
+            let t = SystemTime::from(point.timestamp);
+            let Some(start) = window_start(t, rule.window) else {
+                log::debug!("Ignoring value of {:?} that is older than the Unix epoch", point.metric);
+                continue;
+            };
+            let watermark = &mut self.watermarks[aggregation.rule];
+            *watermark = (*watermark).max(Some(t));
+
+            match self.windows.entry(GroupKey::of(point, &rule.group_by)) {
+                Entry::Vacant(entry) => {
+                    entry.insert(Window::new(start, point, &rule.group_by, t, value));
+                }
+                Entry::Occupied(mut entry) => {
+                    let window = entry.get_mut();
+                    match start.cmp(&window.start) {
+                        Ordering::Equal => window.push(t, value),
+                        Ordering::Greater => {
+                            let next = Window::new(start, point, &rule.group_by, t, value);
+                            let closed = std::mem::replace(window, next);
+                            closed.close(entry.key(), aggregation, rule, &mut aggregated);
+                        }
+                        Ordering::Less => {
+                            log::debug!(
+                                "Ignoring late value of {:?}: its window is already closed",
+                                point.metric
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        self.close_stale_windows(&aggregations, &mut aggregated);
+
+        if self.rules.iter().any(|r| !r.keep_original) {
+            let rules = &self.rules;
+            measurements.retain(|p| aggregations.get(&p.metric).is_none_or(|a| rules[a.rule].keep_original));
+        }
+        for point in aggregated {
+            measurements.push(point);
+        }
+    }
+
+    /// Closes the windows of the groups that have stopped receiving values.
+    ///
+    /// Usually, a window is closed when a value of the same group falls into the next window.
+    /// To avoid keeping the values of a group forever, its window is also closed when another
+    /// group of the same rule has received a value that is more than one window past its end.
+    fn close_stale_windows(
+        &mut self,
+        aggregations: &HashMap<RawMetricId, Aggregation>,
+        aggregated: &mut Vec<MeasurementPoint>,
+    ) {
+        let stale: Vec<GroupKey> = self
+            .windows
+            .iter()
+            .filter(|(key, window)| {
+                let aggregation = &aggregations[&key.metric];
+                let width = self.rules[aggregation.rule].window;
+                self.watermarks[aggregation.rule].is_some_and(|t| t >= window.start + width * 2)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale {
+            let window = self.windows.remove(&key).unwrap();
+            let aggregation = &aggregations[&key.metric];
+            window.close(&key, aggregation, &self.rules[aggregation.rule], aggregated);
+        }
+    }
+}
+
+impl Transform for AggregationTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+impl GroupKey {
+    fn of(point: &MeasurementPoint, group_by: &[String]) -> Self {
+        let attributes = grouping_attributes(point, group_by)
+            .map(|(k, v)| (k.to_owned(), v.to_string()))
+            .collect();
+        Self {
+            metric: point.metric,
+            resource: point.resource.clone(),
+            consumer: point.consumer.clone(),
+            attributes,
+        }
+    }
+}
+
+impl Window {
+    fn new(start: SystemTime, point: &MeasurementPoint, group_by: &[String], t: SystemTime, value: f64) -> Self {
+        let attributes = grouping_attributes(point, group_by)
+            .map(|(k, v)| (k.to_owned(), v.clone()))
+            .collect();
+        Self {
+            start,
+            values: vec![value],
+            last: (t, value),
+            attributes,
+        }
+    }
+
+    fn push(&mut self, t: SystemTime, value: f64) {
+        self.values.push(value);
+        if t >= self.last.0 {
+            self.last = (t, value);
+        }
+    }
+
+    /// Computes the aggregates of the window.
+    /// The aggregated points have the timestamp of the start of the window.
+    fn close(mut self, key: &GroupKey, aggregation: &Aggregation, rule: &Rule, out: &mut Vec<MeasurementPoint>) {
+        self.values.sort_unstable_by(f64::total_cmp);
+        let timestamp = Timestamp::from(self.start);
+        for (i, function) in rule.functions.iter().enumerate() {
+            let value = function.apply(&self.values, self.last.1);
+            let mut attributes = self.attributes.clone();
+            let (metric, value) = match &aggregation.output {
+                Output::Metrics(ids) if *function == Function::Count => {
+                    (ids[i], WrappedMeasurementValue::U64(self.values.len() as u64))
+                }
+                Output::Metrics(ids) => (ids[i], WrappedMeasurementValue::F64(value)),
+                Output::Attribute(id) => {
+                    attributes.push((FUNCTION_ATTRIBUTE.to_owned(), AttributeValue::Str(function.name())));
+                    (*id, WrappedMeasurementValue::F64(value))
+                }
+            };
+            out.push(
+                MeasurementPoint::new_untyped(timestamp, metric, key.resource.clone(), key.consumer.clone(), value)
+                    .with_attr_vec(attributes),
+            );
+        }
+    }
+}
+
+/// Returns the attributes of the point that identify its group, in the order of `group_by`.
+fn grouping_attributes<'a>(
+    point: &'a MeasurementPoint,
+    group_by: &'a [String],
+) -> impl Iterator<Item = (&'a str, &'a AttributeValue)> {
+    group_by
+        .iter()
+        .filter_map(|key| point.attributes().find(|(k, _)| k == key))
+}
+
+/// Returns the start of the window that contains `t`.
+/// The windows are aligned on the Unix epoch.
+fn window_start(t: SystemTime, width: Duration) -> Option<SystemTime> {
+    let nanos = t.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    let start = nanos - nanos % width.as_nanos();
+    Some(UNIX_EPOCH + Duration::from_nanos(u64::try_from(start).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{Aggregation, AggregationTransform, Output, Rule};
+    use crate::function::Function;
+
+    fn power() -> RawMetricId {
+        RawMetricId::from_u64(0)
+    }
+    fn power_mean() -> RawMetricId {
+        RawMetricId::from_u64(1)
+    }
+    fn power_max() -> RawMetricId {
+        RawMetricId::from_u64(2)
+    }
+    fn power_count() -> RawMetricId {
+        RawMetricId::from_u64(3)
+    }
+    fn temperature() -> RawMetricId {
+        RawMetricId::from_u64(4)
+    }
+    fn temperature_aggregated() -> RawMetricId {
+        RawMetricId::from_u64(5)
+    }
+
+    fn transform(keep_original: bool) -> AggregationTransform {
+        let rules = vec![
+            Rule {
+                window: Duration::from_secs(10),
+                functions: vec![Function::Mean, Function::Max, Function::Count],
+                group_by: vec![String::from("domain")],
+                keep_original,
+            },
+            Rule {
+                window: Duration::from_secs(60),
+                functions: vec![Function::Min, Function::P50],
+                group_by: vec![],
+                keep_original: true,
+            },
+        ];
+        let aggregations = HashMap::from([
+            (
+                power(),
+                Aggregation {
+                    rule: 0,
+                    output: Output::Metrics(vec![power_mean(), power_max(), power_count()]),
+                },
+            ),
+            (
+                temperature(),
+                Aggregation {
+                    rule: 1,
+                    output: Output::Attribute(temperature_aggregated()),
+                },
+            ),
+        ]);
+        AggregationTransform::new(rules, aggregations)
+    }
+
+    fn point(t_millis: u64, metric: RawMetricId, pid: u32, value: f64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_millis(t_millis)),
+            metric,
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid },
+            WrappedMeasurementValue::F64(value),
+        )
+    }
+
+    /// Returns (timestamp in seconds, pid, value, attributes) of the points of the given metric.
+    fn values(buf: &MeasurementBuffer, metric: RawMetricId) -> Vec<(u64, u32, String, String)> {
+        let mut res: Vec<_> = buf
+            .iter()
+            .filter(|p| p.metric == metric)
+            .map(|p| {
+                let t = std::time::SystemTime::from(p.timestamp)
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                let pid = match p.consumer {
+                    ResourceConsumer::Process { pid } => pid,
+                    _ => unreachable!(),
+                };
+                let value = match &p.value {
+                    WrappedMeasurementValue::U64(x) => format!("{x}u"),
+                    v => v.to_string(),
+                };
+                let attrs: Vec<String> = p.attributes().map(|(k, v)| format!("{k}={v}")).collect();
+                (t, pid, value, attrs.join(","))
+            })
+            .collect();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn windows_across_buffers() {
+        let mut t = transform(false);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(1000, power(), 1, 2.0),
+            point(4000, power(), 1, 6.0),
+            point(9000, power(), 2, 10.0),
+        ]);
+        t.process(&mut buf);
+        assert!(buf.is_empty());
+
+        let mut buf = MeasurementBuffer::from(vec![point(9999, power(), 1, 1.0), point(10_000, power(), 1, 5.0)]);
+        t.process(&mut buf);
+        assert_eq!(
+            values(&buf, power_mean()),
+            vec![(0, 1, String::from("3"), String::new())]
+        );
+        assert_eq!(
+            values(&buf, power_max()),
+            vec![(0, 1, String::from("6"), String::new())]
+        );
+        assert_eq!(
+            values(&buf, power_count()),
+            vec![(0, 1, String::from("3u"), String::new())]
+        );
+        assert!(values(&buf, power()).is_empty());
+
+        // pid 2 has not been updated, its window is closed once the rule has seen a value two windows later
+        let mut buf = MeasurementBuffer::from(vec![point(20_000, power(), 1, 1.0)]);
+        t.process(&mut buf);
+        assert_eq!(
+            values(&buf, power_mean()),
+            vec![
+                (0, 2, String::from("10"), String::new()),
+                (10, 1, String::from("5"), String::new())
+            ]
+        );
+    }
+
+    #[test]
+    fn late_values_are_ignored() {
+        let mut t = transform(false);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(1000, power(), 1, 2.0),
+            point(11_000, power(), 1, 4.0),
+            point(5000, power(), 1, 100.0),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(
+            values(&buf, power_max()),
+            vec![(0, 1, String::from("2"), String::new())]
+        );
+    }
+
+    #[test]
+    fn group_by_attributes() {
+        let mut t = transform(true);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, power(), 1, 1.0)
+                .with_attr("domain", "package")
+                .with_attr("socket", 0_u64),
+            point(0, power(), 1, 3.0)
+                .with_attr("domain", "package")
+                .with_attr("socket", 1_u64),
+            point(0, power(), 1, 8.0)
+                .with_attr("domain", "dram")
+                .with_attr("socket", 0_u64),
+            point(10_000, power(), 1, 0.0).with_attr("domain", "package"),
+            point(10_000, power(), 1, 0.0).with_attr("domain", "dram"),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(values(&buf, power()).len(), 5);
+        assert_eq!(
+            values(&buf, power_mean()),
+            vec![
+                (0, 1, String::from("2"), String::from("domain=package")),
+                (0, 1, String::from("8"), String::from("domain=dram")),
+            ]
+        );
+    }
+
+    #[test]
+    fn aggregates_as_attributes() {
+        let mut t = transform(true);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, temperature(), 1, 40.0),
+            point(20_000, temperature(), 1, 45.0),
+            point(30_000, temperature(), 1, 35.0),
+            point(60_000, temperature(), 1, 50.0),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(values(&buf, temperature()).len(), 4);
+        assert_eq!(
+            values(&buf, temperature_aggregated()),
+            vec![
+                (0, 1, String::from("35"), String::from("aggregate=min")),
+                (0, 1, String::from("40"), String::from("aggregate=p50")),
+            ]
+        );
+    }
+}
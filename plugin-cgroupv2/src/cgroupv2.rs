@@ -1,5 +1,5 @@
 use alumet::{
-    metrics::{MetricCreationError, TypedMetricId},
+    metrics::{MetricCreationError, MetricKind, TypedMetricId},
     plugin::AlumetPluginStart,
     units::{PrefixedUnit, Unit},
 };
@@ -110,18 +110,21 @@ impl Metrics {
 
         Ok(Self {
             // CPU cgroup data
-            cpu_time_total: alumet.create_metric::<u64>(
+            cpu_time_total: alumet.create_metric_with_kind::<u64>(
                 "cgroup_cpu_usage_total",
+                MetricKind::Delta,
                 usec.clone(),
                 "Total CPU usage time by the cgroup",
             )?,
-            cpu_time_user_mode: alumet.create_metric::<u64>(
+            cpu_time_user_mode: alumet.create_metric_with_kind::<u64>(
                 "cgroup_cpu_usage_user",
+                MetricKind::Delta,
                 usec.clone(),
                 "CPU in user mode usage time by the cgroup",
             )?,
-            cpu_time_system_mode: alumet.create_metric::<u64>(
+            cpu_time_system_mode: alumet.create_metric_with_kind::<u64>(
                 "cgroup_cpu_usage_system",
+                MetricKind::Delta,
                 usec.clone(),
                 "CPU in system mode usage time by the cgroup",
             )?,
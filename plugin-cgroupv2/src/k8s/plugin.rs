@@ -5,13 +5,18 @@ use alumet::{
         util::CounterDiff,
         AlumetPluginStart, AlumetPostStart, ConfigTable,
     },
-    resources::ResourceConsumer,
+    resources::{consumers::ConsumerResolver, ResourceConsumer},
 };
 use anyhow::{anyhow, Context};
 use gethostname::gethostname;
 use notify::{Event, EventHandler, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, path::PathBuf, time::Duration};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use crate::{
     cgroupv2::{Metrics, CGROUP_MAX_TIME_COUNTER},
@@ -102,7 +107,13 @@ impl AlumetPlugin for K8sPlugin {
         )?;
 
         // Add as a source each pod already present
+        let consumers = alumet.consumer_resolver();
         for metric_file in final_list_metric_file {
+            if let ResourceConsumer::ControlGroup { path } = &metric_file.consumer_cpu {
+                if let Some(cgroup) = Path::new(path.as_ref()).parent() {
+                    register_pod(&consumers, cgroup, &metric_file);
+                }
+            }
             let counter_tmp_tot = CounterDiff::with_max_value(crate::cgroupv2::CGROUP_MAX_TIME_COUNTER);
             let counter_tmp_usr = CounterDiff::with_max_value(crate::cgroupv2::CGROUP_MAX_TIME_COUNTER);
             let counter_tmp_sys = CounterDiff::with_max_value(crate::cgroupv2::CGROUP_MAX_TIME_COUNTER);
@@ -128,9 +139,11 @@ impl AlumetPlugin for K8sPlugin {
         let kubernetes_api_url = self.config.kubernetes_api_url.clone();
         let hostname = self.config.hostname.to_owned();
         let token_retrieval = self.config.token_retrieval.clone();
+        let consumers = alumet.consumer_resolver();
 
         struct PodDetector {
             metrics: Metrics,
+            consumers: Arc<ConsumerResolver>,
             control_handle: ScopedControlHandle,
             poll_interval: Duration,
             kubernetes_api_url: String,
@@ -147,6 +160,19 @@ impl AlumetPlugin for K8sPlugin {
                     // The events look like the following
                     // Handle_Event: Ok(Event { kind: Create(Folder), paths: ["/sys/fs/cgroup/kubepods.slice/kubepods-besteffort.slice/TESTTTTT"], attr:tracker: None, attr:flag: None, attr:info: None, attr:source: None })
                     // Handle_Event: Ok(Event { kind: Remove(Folder), paths: ["/sys/fs/cgroup/kubepods.slice/kubepods-besteffort.slice/TESTTTTT"], attr:tracker: None, attr:flag: None, attr:info: None, attr:source: None })
+                    if let Ok(Event {
+                        kind: EventKind::Remove(notify::event::RemoveKind::Folder),
+                        paths,
+                        ..
+                    }) = &event
+                    {
+                        for path in paths {
+                            if let Some(path) = path.to_str() {
+                                detector.consumers.remove_cgroup(path);
+                            }
+                        }
+                        return Ok(());
+                    }
                     if let Ok(Event {
                         kind: EventKind::Create(notify::event::CreateKind::Folder),
                         paths,
@@ -235,6 +261,7 @@ impl AlumetPlugin for K8sPlugin {
                                     namespace: namespace.to_owned(),
                                     node: node.to_owned(),
                                 };
+                                register_pod(&detector.consumers, &path, &metric_file);
 
                                 let counter_tmp_tot = CounterDiff::with_max_value(CGROUP_MAX_TIME_COUNTER);
                                 let counter_tmp_usr = CounterDiff::with_max_value(CGROUP_MAX_TIME_COUNTER);
@@ -272,6 +299,7 @@ impl AlumetPlugin for K8sPlugin {
         }
         let handler = PodDetector {
             metrics,
+            consumers,
             control_handle,
             poll_interval,
             kubernetes_api_url,
@@ -288,6 +316,25 @@ impl AlumetPlugin for K8sPlugin {
     }
 }
 
+/// Registers the pod, and its namespace, as the owners of the pod's cgroup.
+fn register_pod(consumers: &ConsumerResolver, cgroup: &Path, metric_file: &CgroupV2MetricFile) {
+    let Some(cgroup) = cgroup.to_str() else {
+        return;
+    };
+    let pod = if metric_file.name.is_empty() {
+        metric_file.uid.clone()
+    } else {
+        metric_file.name.clone()
+    };
+    consumers.register_cgroup_owner(cgroup, ResourceConsumer::custom("k8s_pod", pod));
+    if !metric_file.namespace.is_empty() {
+        consumers.register_cgroup_owner(
+            cgroup,
+            ResourceConsumer::custom("k8s_namespace", metric_file.namespace.clone()),
+        );
+    }
+}
+
 impl Default for K8sConfig {
     fn default() -> Self {
         let root_path = PathBuf::from("/sys/fs/cgroup/kubepods.slice/");
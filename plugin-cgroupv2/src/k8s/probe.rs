@@ -8,7 +8,7 @@ use alumet::{
 use anyhow::{Context, Result};
 
 use super::utils::{gather_value, CgroupV2MetricFile};
-use crate::cgroupv2::{CgroupMeasurements, Metrics};
+use crate::cgroupv2::Metrics;
 
 pub struct K8SProbe {
     pub cgroup_v2_metric_file: CgroupV2MetricFile,
@@ -23,6 +23,8 @@ pub struct K8SProbe {
     pub memory_kernel: TypedMetricId<u64>,
     pub memory_pagetables: TypedMetricId<u64>,
     pub memory_total: TypedMetricId<u64>,
+    /// Attributes of the pod, shared by all the measurement points.
+    pub pod_attributes: [(&'static str, AttributeValue); 4],
 }
 
 impl K8SProbe {
@@ -33,7 +35,17 @@ impl K8SProbe {
         counter_sys: CounterDiff,
         counter_usr: CounterDiff,
     ) -> anyhow::Result<K8SProbe> {
+        let pod_attributes = [
+            ("uid", AttributeValue::Shared(metric_file.uid.as_str().into())),
+            ("name", AttributeValue::Shared(metric_file.name.as_str().into())),
+            (
+                "namespace",
+                AttributeValue::Shared(metric_file.namespace.as_str().into()),
+            ),
+            ("node", AttributeValue::Shared(metric_file.node.as_str().into())),
+        ];
         Ok(K8SProbe {
+            pod_attributes,
             cgroup_v2_metric_file: metric_file,
             time_tot: counter_tot,
             time_usr: counter_usr,
@@ -59,7 +71,7 @@ impl alumet::pipeline::Source for K8SProbe {
             metric_id: TypedMetricId<u64>,
             resource_consumer: ResourceConsumer,
             value_measured: u64,
-            metrics_param: &CgroupMeasurements,
+            pod_attributes: &[(&'static str, AttributeValue)],
         ) -> MeasurementPoint {
             MeasurementPoint::new(
                 timestamp,
@@ -68,10 +80,7 @@ impl alumet::pipeline::Source for K8SProbe {
                 resource_consumer,
                 value_measured,
             )
-            .with_attr("uid", AttributeValue::String(metrics_param.pod_uid.clone()))
-            .with_attr("name", AttributeValue::String(metrics_param.pod_name.clone()))
-            .with_attr("namespace", AttributeValue::String(metrics_param.namespace.clone()))
-            .with_attr("node", AttributeValue::String(metrics_param.node.clone()))
+            .with_attr_vec(pod_attributes.to_vec())
         }
 
         let mut buffer = String::new();
@@ -90,7 +99,7 @@ impl alumet::pipeline::Source for K8SProbe {
                 self.cpu_time_tot,
                 self.cgroup_v2_metric_file.consumer_cpu.clone(),
                 value_tot,
-                &metrics,
+                &self.pod_attributes,
             );
             measurements.push(p_tot);
         }
@@ -102,7 +111,7 @@ impl alumet::pipeline::Source for K8SProbe {
                 self.cpu_time_user_mode,
                 self.cgroup_v2_metric_file.consumer_cpu.clone(),
                 value_usr,
-                &metrics,
+                &self.pod_attributes,
             );
             measurements.push(p_usr);
         }
@@ -114,7 +123,7 @@ impl alumet::pipeline::Source for K8SProbe {
                 self.cpu_time_system_mode,
                 self.cgroup_v2_metric_file.consumer_cpu.clone(),
                 value_sys,
-                &metrics,
+                &self.pod_attributes,
             );
             measurements.push(p_sys);
         }
@@ -126,7 +135,7 @@ impl alumet::pipeline::Source for K8SProbe {
             self.memory_anon,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_anon_value,
-            &metrics,
+            &self.pod_attributes,
         );
         measurements.push(m_anon);
 
@@ -137,7 +146,7 @@ impl alumet::pipeline::Source for K8SProbe {
             self.memory_file,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_file_value,
-            &metrics,
+            &self.pod_attributes,
         );
         measurements.push(m_file);
 
@@ -148,7 +157,7 @@ impl alumet::pipeline::Source for K8SProbe {
             self.memory_kernel,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_kernel_value,
-            &metrics,
+            &self.pod_attributes,
         );
         measurements.push(m_ker);
 
@@ -159,7 +168,7 @@ impl alumet::pipeline::Source for K8SProbe {
             self.memory_pagetables,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_pagetables_value,
-            &metrics,
+            &self.pod_attributes,
         );
         measurements.push(m_pgt);
 
@@ -170,7 +179,7 @@ impl alumet::pipeline::Source for K8SProbe {
             self.memory_total,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_total_value,
-            &metrics,
+            &self.pod_attributes,
         );
         measurements.push(m_tot);
 
@@ -5,12 +5,17 @@ use alumet::{
         util::CounterDiff,
         AlumetPluginStart, AlumetPostStart, ConfigTable,
     },
-    resources::ResourceConsumer,
+    resources::{consumers::ConsumerResolver, ResourceConsumer},
 };
 use anyhow::{anyhow, Context};
 use notify::{Event, EventHandler, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, path::PathBuf, time::Duration};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use crate::{
     cgroupv2::{Metrics, CGROUP_MAX_TIME_COUNTER},
@@ -73,7 +78,13 @@ impl AlumetPlugin for OARPlugin {
         let final_list_metric_file = super::utils::list_all_file(&self.config.path)?;
 
         // Add as a source each pod already present
+        let consumers = alumet.consumer_resolver();
         for metric_file in final_list_metric_file {
+            if let ResourceConsumer::ControlGroup { path } = &metric_file.consumer_cpu {
+                if let Some(cgroup) = Path::new(path.as_ref()).parent() {
+                    register_job(&consumers, cgroup);
+                }
+            }
             let counter_tmp_tot: CounterDiff = CounterDiff::with_max_value(CGROUP_MAX_TIME_COUNTER);
             let counter_tmp_usr: CounterDiff = CounterDiff::with_max_value(CGROUP_MAX_TIME_COUNTER);
             let counter_tmp_sys: CounterDiff = CounterDiff::with_max_value(CGROUP_MAX_TIME_COUNTER);
@@ -97,8 +108,10 @@ impl AlumetPlugin for OARPlugin {
         // let metrics = self.metrics.clone().unwrap();
         let metrics = self.metrics.clone().with_context(|| "Metrics is not available")?;
         let poll_interval = self.config.poll_interval;
+        let consumers = alumet.consumer_resolver();
         struct PodDetector {
             metrics: Metrics,
+            consumers: Arc<ConsumerResolver>,
             control_handle: ScopedControlHandle,
             poll_interval: Duration,
         }
@@ -112,6 +125,19 @@ impl AlumetPlugin for OARPlugin {
                     // The events look like the following
                     // Handle_Event: Ok(Event { kind: Create(Folder), paths: ["/sys/fs/cgroup/kubepods.slice/kubepods-besteffort.slice/TESTTTTT"], attr:tracker: None, attr:flag: None, attr:info: None, attr:source: None })
                     // Handle_Event: Ok(Event { kind: Remove(Folder), paths: ["/sys/fs/cgroup/kubepods.slice/kubepods-besteffort.slice/TESTTTTT"], attr:tracker: None, attr:flag: None, attr:info: None, attr:source: None })
+                    if let Ok(Event {
+                        kind: EventKind::Remove(notify::event::RemoveKind::Folder),
+                        paths,
+                        ..
+                    }) = &event
+                    {
+                        for path in paths {
+                            if let Some(path) = path.to_str() {
+                                detector.consumers.remove_cgroup(path);
+                            }
+                        }
+                        return Ok(());
+                    }
                     if let Ok(Event {
                         kind: EventKind::Create(notify::event::CreateKind::Folder),
                         paths,
@@ -120,6 +146,7 @@ impl AlumetPlugin for OARPlugin {
                     {
                         for path in paths {
                             if path.is_dir() {
+                                register_job(&detector.consumers, &path);
                                 if let Some(pod_uid) = path.file_name() {
                                     let mut path_cpu = path.clone();
                                     let mut path_memory = path.clone();
@@ -201,6 +228,7 @@ impl AlumetPlugin for OARPlugin {
         let handler = PodDetector {
             control_handle,
             metrics,
+            consumers,
             poll_interval,
         };
 
@@ -213,6 +241,24 @@ impl AlumetPlugin for OARPlugin {
     }
 }
 
+/// Registers the OAR job as the owner of its cgroup, if the cgroup belongs to a job.
+///
+/// The cgroup of a job is named after the user and the id of the job, for instance `alice_1234`.
+fn register_job(consumers: &ConsumerResolver, cgroup: &Path) {
+    let (Some(cgroup), Some(name)) = (cgroup.to_str(), cgroup.file_name().and_then(|n| n.to_str())) else {
+        return;
+    };
+    if let Some(job_id) = oar_job_id(name) {
+        consumers.register_cgroup_owner(cgroup, ResourceConsumer::custom("oar_job", job_id.to_owned()));
+    }
+}
+
+/// Extracts the id of an OAR job from the name of its cgroup.
+fn oar_job_id(cgroup_name: &str) -> Option<&str> {
+    let (_user, id) = cgroup_name.rsplit_once('_')?;
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_digit())).then_some(id)
+}
+
 impl Default for OAR3Config {
     fn default() -> Self {
         let root_path = PathBuf::from("/sys/fs/cgroup/");
@@ -267,6 +313,16 @@ mod tests {
         Ok(())
     }
 
+    // Test `oar_job_id` function to find the job of a cgroup
+    #[test]
+    fn test_oar_job_id() {
+        assert_eq!(oar_job_id("alice_1234"), Some("1234"));
+        assert_eq!(oar_job_id("oar_user_42"), Some("42"));
+        assert_eq!(oar_job_id("system.slice"), None);
+        assert_eq!(oar_job_id("alice_"), None);
+        assert_eq!(oar_job_id("alice_12a"), None);
+    }
+
     // Test `stop` function to stop oar3 plugin
     #[test]
     fn test_stop() {
@@ -23,6 +23,8 @@ pub struct CgroupV2prob {
     pub memory_kernel: TypedMetricId<u64>,
     pub memory_pagetables: TypedMetricId<u64>,
     pub memory_total: TypedMetricId<u64>,
+    /// Name of the job, shared by all the measurement points.
+    pub job_name: AttributeValue,
 }
 
 impl CgroupV2prob {
@@ -34,6 +36,7 @@ impl CgroupV2prob {
         counter_usr: CounterDiff,
     ) -> anyhow::Result<CgroupV2prob> {
         Ok(CgroupV2prob {
+            job_name: AttributeValue::Shared(metric_file.name.as_str().into()),
             cgroup_v2_metric_file: metric_file,
             time_tot: counter_tot,
             time_usr: counter_usr,
@@ -59,7 +62,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
             metric_id: TypedMetricId<u64>,
             resource_consumer: ResourceConsumer,
             value_measured: u64,
-            metrics_param: &CgroupMeasurements,
+            job_name: &AttributeValue,
         ) -> MeasurementPoint {
             MeasurementPoint::new(
                 timestamp,
@@ -68,7 +71,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
                 resource_consumer,
                 value_measured,
             )
-            .with_attr("name", AttributeValue::String(metrics_param.pod_name.clone()))
+            .with_attr("name", job_name.clone())
         }
 
         let mut buffer = String::new();
@@ -96,7 +99,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
                 self.cpu_time_tot,
                 self.cgroup_v2_metric_file.consumer_cpu.clone(),
                 value_tot,
-                &metrics,
+                &self.job_name,
             );
             measurements.push(p_tot);
         }
@@ -108,7 +111,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
                 self.cpu_time_user_mode,
                 self.cgroup_v2_metric_file.consumer_cpu.clone(),
                 value_usr,
-                &metrics,
+                &self.job_name,
             );
             measurements.push(p_usr);
         }
@@ -120,7 +123,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
                 self.cpu_time_system_mode,
                 self.cgroup_v2_metric_file.consumer_cpu.clone(),
                 value_sys,
-                &metrics,
+                &self.job_name,
             );
             measurements.push(p_sys);
         }
@@ -132,7 +135,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
             self.memory_anon,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_anon_value,
-            &metrics,
+            &self.job_name,
         );
         measurements.push(m_anon);
 
@@ -143,7 +146,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
             self.memory_file,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_file_value,
-            &metrics,
+            &self.job_name,
         );
         measurements.push(m_file);
 
@@ -154,7 +157,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
             self.memory_kernel,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_kernel_value,
-            &metrics,
+            &self.job_name,
         );
         measurements.push(m_ker);
 
@@ -165,7 +168,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
             self.memory_pagetables,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_pagetables_value,
-            &metrics,
+            &self.job_name,
         );
         measurements.push(m_pgt);
 
@@ -176,7 +179,7 @@ impl alumet::pipeline::Source for CgroupV2prob {
             self.memory_total,
             self.cgroup_v2_metric_file.consumer_memory.clone(),
             mem_total_value,
-            &metrics,
+            &self.job_name,
         );
         measurements.push(m_tot);
 
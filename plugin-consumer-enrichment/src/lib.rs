@@ -0,0 +1,63 @@
+use alumet::plugin::{
+    rust::{deserialize_config, serialize_config, AlumetPlugin},
+    AlumetPluginStart, ConfigTable,
+};
+use serde::{Deserialize, Serialize};
+
+use transform::ConsumerEnrichmentTransform;
+
+mod transform;
+
+pub struct ConsumerEnrichmentPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for ConsumerEnrichmentPlugin {
+    fn name() -> &'static str {
+        "consumer-enrichment"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(ConsumerEnrichmentPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        alumet.add_transform(Box::new(ConsumerEnrichmentTransform::new(
+            config.attribute_prefix,
+            config.resolve_processes,
+        )));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Prefix of the attributes that are added to the measurement points.
+    attribute_prefix: String,
+    /// Find the cgroup (and its owners) of the processes, by reading `/proc/<pid>/cgroup`.
+    resolve_processes: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            attribute_prefix: String::new(),
+            resolve_processes: true,
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use alumet::{
+    measurement::{AttributeValue, MeasurementBuffer},
+    pipeline::{
+        elements::{error::TransformError, transform::TransformContext},
+        Transform,
+    },
+    resources::{consumers::ConsumerResolver, ResourceConsumer},
+};
+
+/// Adds the owners of the resource consumers to the attributes of the measurement points.
+///
+/// For instance, a point about a process gets a `cgroup` attribute, and a `k8s_pod` attribute
+/// if the cgroup belongs to a Kubernetes pod. The name of each attribute is the kind of the owner.
+pub struct ConsumerEnrichmentTransform {
+    /// Prefix of the attributes.
+    attribute_prefix: String,
+    /// Resolve the cgroup of the processes.
+    resolve_processes: bool,
+}
+
+impl ConsumerEnrichmentTransform {
+    pub fn new(attribute_prefix: String, resolve_processes: bool) -> Self {
+        Self {
+            attribute_prefix,
+            resolve_processes,
+        }
+    }
+
+    fn process(&self, measurements: &mut MeasurementBuffer, resolver: &ConsumerResolver) {
+        // The consumers are resolved once per buffer, because a buffer often contains
+        // multiple points about the same consumer. Resolving a process requires to read a file.
+        let mut owners_cache: HashMap<ResourceConsumer, Vec<(String, AttributeValue)>> = HashMap::new();
+        for point in measurements.iter_mut() {
+            let resolvable = match point.consumer {
+                ResourceConsumer::Process { .. } => self.resolve_processes,
+                ResourceConsumer::ControlGroup { .. } => true,
+                _ => false,
+            };
+            if !resolvable {
+                continue;
+            }
+            let attributes = owners_cache.entry(point.consumer.clone()).or_insert_with(|| {
+                resolver
+                    .resolve(&point.consumer)
+                    .into_iter()
+                    .map(|owner| {
+                        let key = format!("{}{}", self.attribute_prefix, owner.kind());
+                        let value = AttributeValue::String(owner.id_display().to_string());
+                        (key, value)
+                    })
+                    .collect()
+            });
+            for (key, value) in attributes.iter() {
+                // Never overwrite an existing attribute, and only keep the closest owner of each kind.
+                if !point.attributes_keys().any(|k| k == key) {
+                    point.add_attr(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+impl Transform for ConsumerEnrichmentTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements, ctx.consumers);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::UNIX_EPOCH};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{consumers::ConsumerResolver, Resource, ResourceConsumer},
+    };
+
+    use super::ConsumerEnrichmentTransform;
+
+    fn buffer() -> MeasurementBuffer {
+        let point = |consumer: ResourceConsumer| {
+            MeasurementPoint::new_untyped(
+                Timestamp::from(UNIX_EPOCH),
+                RawMetricId::from_u64(0),
+                Resource::LocalMachine,
+                consumer,
+                WrappedMeasurementValue::U64(1),
+            )
+        };
+        MeasurementBuffer::from(vec![
+            point(ResourceConsumer::Process { pid: 42 }),
+            point(ResourceConsumer::ControlGroup {
+                path: "/sys/fs/cgroup/oar/alice_12/cpu.stat".into(),
+            }),
+            point(ResourceConsumer::Process { pid: 1 }).with_attr("cgroup", "kept"),
+            point(ResourceConsumer::LocalMachine),
+        ])
+    }
+
+    fn attributes(buf: &MeasurementBuffer) -> Vec<Vec<(String, String)>> {
+        buf.iter()
+            .map(|p| p.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect())
+            .collect()
+    }
+
+    fn expected(attributes: &[&[(&str, &str)]]) -> Vec<Vec<(String, String)>> {
+        attributes
+            .iter()
+            .map(|attrs| attrs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+            .collect()
+    }
+
+    fn resolver(procfs: &std::path::Path) -> ConsumerResolver {
+        for (pid, cgroup) in [(42, "/oar/alice_12/task"), (1, "/init.scope")] {
+            fs::create_dir(procfs.join(pid.to_string())).unwrap();
+            fs::write(procfs.join(format!("{pid}/cgroup")), format!("0::{cgroup}\n")).unwrap();
+        }
+        let resolver = ConsumerResolver::with_procfs(procfs);
+        resolver.register_cgroup_owner("/oar/alice_12", ResourceConsumer::custom("oar_job", "12"));
+        resolver
+    }
+
+    #[test]
+    fn enrich() {
+        let procfs = tempfile::tempdir().unwrap();
+        let resolver = resolver(procfs.path());
+        let mut buf = buffer();
+        ConsumerEnrichmentTransform::new(String::new(), true).process(&mut buf, &resolver);
+        assert_eq!(
+            attributes(&buf),
+            expected(&[
+                &[("cgroup", "/oar/alice_12/task"), ("oar_job", "12")],
+                &[("oar_job", "12")],
+                &[("cgroup", "kept")],
+                &[],
+            ])
+        );
+    }
+
+    #[test]
+    fn prefix_without_processes() {
+        let procfs = tempfile::tempdir().unwrap();
+        let resolver = resolver(procfs.path());
+        let mut buf = buffer();
+        ConsumerEnrichmentTransform::new(String::from("owner_"), false).process(&mut buf, &resolver);
+        assert_eq!(
+            attributes(&buf),
+            expected(&[&[], &[("owner_oar_job", "12")], &[("cgroup", "kept")], &[]])
+        );
+    }
+}
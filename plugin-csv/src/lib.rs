@@ -1,5 +1,6 @@
 mod csv;
 mod output;
+mod rotation;
 // TODO mod input
 
 use std::path::PathBuf;
@@ -9,6 +10,7 @@ use alumet::plugin::{
     ConfigTable,
 };
 use output::CsvOutput;
+use rotation::RotationConfig;
 use serde::{Deserialize, Serialize};
 
 pub struct CsvPlugin {
@@ -35,12 +37,13 @@ impl AlumetPlugin for CsvPlugin {
 
     fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
         let output = Box::new(CsvOutput::new(
-            &self.config.output_path,
+            self.config.output_path.clone(),
             self.config.force_flush,
             self.config.append_unit_to_metric_name,
             self.config.use_unit_display_name,
             self.config.csv_delimiter,
             self.config.csv_escaped_quote.take().unwrap_or(String::from("\"\"")),
+            self.config.rotation.clone(),
         )?);
         alumet.add_blocking_output(output);
         Ok(())
@@ -60,6 +63,8 @@ struct Config {
     use_unit_display_name: bool,
     csv_delimiter: char,
     csv_escaped_quote: Option<String>,
+    /// Rotation of the output file, disabled if not set.
+    rotation: Option<RotationConfig>,
 }
 
 impl Default for Config {
@@ -71,6 +76,7 @@ impl Default for Config {
             append_unit_to_metric_name: true,
             csv_delimiter: ';',
             csv_escaped_quote: None,
+            rotation: None,
         }
     }
 }
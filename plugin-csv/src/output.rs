@@ -1,14 +1,14 @@
 use std::{
     collections::HashSet,
     fs::File,
-    io::{self, BufWriter, Write},
-    path::Path,
+    io::{BufWriter, Write},
+    path::PathBuf,
     time::SystemTime,
 };
 
-use alumet::measurement::WrappedMeasurementValue;
 use alumet::{
     measurement::MeasurementBuffer,
+    metrics::{Metric, RawMetricId},
     pipeline::elements::{error::WriteError, output::OutputContext},
 };
 use anyhow::Context;
@@ -16,11 +16,18 @@ use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
 use crate::csv::CsvHelper;
+use crate::rotation::{RotationConfig, Rotator};
 
 pub struct CsvOutput {
-    /// The attributes that we have written to the header.
+    /// The attributes that we have written to the header, sorted by key.
     /// None if the header has not been written yet.
-    attributes_in_header: Option<HashSet<String>>,
+    attributes_in_header: Option<Vec<String>>,
+
+    /// The attributes that were not in the header, and have been written to `__late_attributes`.
+    late_attributes: HashSet<String>,
+
+    /// The attributes to include in the header of the next file, after a rotation.
+    next_header_attributes: HashSet<String>,
 
     /// parameter: do we flush after each write(measurements)?
     force_flush: bool,
@@ -29,33 +36,87 @@ pub struct CsvOutput {
     append_unit_to_metric_name: bool,
     use_unit_display_name: bool,
 
+    /// Path of the file that is being written to
+    output_path: PathBuf,
+
     /// File writer
     writer: BufWriter<File>,
 
+    /// Rotates the output file, if enabled
+    rotator: Option<Rotator>,
+
     /// CSV utility
     csv_helper: CsvHelper,
 }
 
 impl CsvOutput {
     pub fn new(
-        output_file: impl AsRef<Path>,
+        output_path: PathBuf,
         force_flush: bool,
         append_unit_to_metric_name: bool,
         use_unit_display_name: bool,
         delimiter: char,
         escaped_quote: String,
-    ) -> io::Result<Self> {
-        let writer = BufWriter::new(File::create(output_file)?);
+        rotation: Option<RotationConfig>,
+    ) -> anyhow::Result<Self> {
+        let writer = BufWriter::new(
+            File::create(&output_path).with_context(|| format!("could not create {}", output_path.display()))?,
+        );
+        let rotator = rotation
+            .map(|config| Rotator::new(config, output_path.clone()))
+            .transpose()?;
         let helper = CsvHelper::new(delimiter, escaped_quote);
         Ok(Self {
             attributes_in_header: None,
+            late_attributes: HashSet::new(),
+            next_header_attributes: HashSet::new(),
             force_flush,
             append_unit_to_metric_name,
             use_unit_display_name,
+            output_path,
             writer,
+            rotator,
             csv_helper: helper,
         })
     }
+
+    /// Rotates the output file if it is too big or too old.
+    fn rotate_if_needed(&mut self) -> anyhow::Result<()> {
+        let Some(rotator) = &self.rotator else {
+            return Ok(());
+        };
+        // Don't rotate a file that does not even have a header.
+        if self.attributes_in_header.is_none() {
+            return Ok(());
+        }
+        let size = self.writer.get_ref().metadata()?.len() + self.writer.buffer().len() as u64;
+        if rotator.should_rotate(size) {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Moves the current file away and starts a new one.
+    ///
+    /// The header of the new file will contain the attributes of the previous header,
+    /// plus the attributes that have been written to `__late_attributes`.
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        let rotator = self.rotator.as_mut().expect("rotation should be enabled");
+        let rotated_path = rotator
+            .rotate()
+            .with_context(|| format!("could not rotate {}", self.output_path.display()))?;
+        log::info!("CSV output rotated to {}", rotated_path.display());
+
+        self.writer = BufWriter::new(
+            File::create(&self.output_path)
+                .with_context(|| format!("could not create {}", self.output_path.display()))?,
+        );
+        let previous_header = self.attributes_in_header.take().unwrap_or_default();
+        self.next_header_attributes.extend(previous_header);
+        self.next_header_attributes.extend(self.late_attributes.drain());
+        Ok(())
+    }
 }
 
 fn collect_attribute_keys(buf: &MeasurementBuffer) -> HashSet<String> {
@@ -68,11 +129,24 @@ fn collect_attribute_keys(buf: &MeasurementBuffer) -> HashSet<String> {
 
 impl alumet::pipeline::Output for CsvOutput {
     fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        self.write_measurements(measurements, |id| ctx.metrics.by_id(id))?;
+        Ok(())
+    }
+}
+
+impl CsvOutput {
+    fn write_measurements<'m>(
+        &mut self,
+        measurements: &MeasurementBuffer,
+        metric_by_id: impl Fn(&RawMetricId) -> Option<&'m Metric>,
+    ) -> anyhow::Result<()> {
         if self.attributes_in_header.is_none() && !measurements.is_empty() {
-            // Collect the attributes that are present in the measurements.
+            // Collect the attributes that are present in the measurements, and the ones
+            // that were late in the previous file, if any.
             // Then, sort the keys to ensure a consistent order between calls to `CsvOutput::write`.
-            let attr_keys = collect_attribute_keys(measurements);
-            let mut attr_keys_sorted: Vec<&str> = attr_keys.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+            let mut attr_keys = collect_attribute_keys(measurements);
+            attr_keys.extend(self.next_header_attributes.drain());
+            let mut attr_keys_sorted: Vec<String> = attr_keys.into_iter().collect();
             attr_keys_sorted.sort();
 
             // Build the CSV header
@@ -86,20 +160,17 @@ impl alumet::pipeline::Output for CsvOutput {
                 "consumer_kind",
                 "consumer_id",
             ]);
-            header.extend(attr_keys_sorted);
+            header.extend(attr_keys_sorted.iter().map(|k| k.as_str()));
             header.push("__late_attributes");
 
             self.csv_helper.writeln(&mut self.writer, header)?;
 
-            self.attributes_in_header = Some(attr_keys);
+            self.attributes_in_header = Some(attr_keys_sorted);
         }
 
         for m in measurements.iter() {
             // get the full definition of the metric
-            let full_metric = ctx
-                .metrics
-                .by_id(&m.metric)
-                .with_context(|| format!("Unknown metric {:?}", m.metric))?;
+            let full_metric = metric_by_id(&m.metric).with_context(|| format!("Unknown metric {:?}", m.metric))?;
 
             // extract the metric name, appending its unit if configured so
             let metric_name = if self.append_unit_to_metric_name {
@@ -120,10 +191,7 @@ impl alumet::pipeline::Output for CsvOutput {
             // convert every field to string
             let datetime: OffsetDateTime = SystemTime::from(m.timestamp).into();
             let datetime: String = datetime.format(&Rfc3339)?;
-            let value = match m.value {
-                WrappedMeasurementValue::F64(x) => x.to_string(),
-                WrappedMeasurementValue::U64(x) => x.to_string(),
-            };
+            let value = m.value.to_string();
             let resource_kind = m.resource.kind().to_owned();
             let resource_id = m.resource.id_display().to_string();
             let consumer_kind = m.consumer.kind().to_owned();
@@ -140,22 +208,22 @@ impl alumet::pipeline::Output for CsvOutput {
                 consumer_id,
             ];
 
-            // Sort the attributes by key
-            let mut attr_sorted = m.attributes().collect::<Vec<_>>();
-            attr_sorted.sort_by_key(|(k, _)| *k);
-
             // Handle known as well as new attributes.
+            // Known attributes are written in the same order as the header, missing ones are left empty.
+            let header_attributes = self.attributes_in_header.as_ref().unwrap();
+            let mut known_attrs = vec![String::new(); header_attributes.len()];
             let mut late_attrs: String = String::new();
-            let mut known_attrs = 0;
-            for (key, value) in attr_sorted {
-                if self.attributes_in_header.as_ref().unwrap().contains(key) {
-                    // known attribute, write in the same order as the header (thanks to the sort)
-                    record.push(value.to_string());
-                    known_attrs += 1;
+            for (key, value) in m.attributes() {
+                if let Ok(i) = header_attributes.binary_search_by(|k| k.as_str().cmp(key)) {
+                    // known attribute
+                    known_attrs[i] = value.to_string();
                 } else {
                     // unknown attribute, add to the column `__late_attributes`
                     use std::fmt::Write;
 
+                    if !self.late_attributes.contains(key) {
+                        self.late_attributes.insert(key.to_owned());
+                    }
                     if !late_attrs.is_empty() {
                         late_attrs.push_str(", ");
                     }
@@ -167,9 +235,7 @@ impl alumet::pipeline::Output for CsvOutput {
                     )?;
                 }
             }
-            // Add missing attributes as empty values
-            let missing_attributes = self.attributes_in_header.as_ref().unwrap().len() - known_attrs;
-            record.extend(vec![String::from(""); missing_attributes]);
+            record.extend(known_attrs);
 
             // Push the late attributes as one value
             record.push(late_attrs);
@@ -181,6 +247,11 @@ impl alumet::pipeline::Output for CsvOutput {
             log::trace!("flushing BufWriter");
             self.writer.flush()?;
         }
+
+        // Check the size after the write, so that a file does not stay above the limit until the next write.
+        if !measurements.is_empty() {
+            self.rotate_if_needed()?;
+        }
         Ok(())
     }
 }
@@ -188,3 +259,183 @@ impl alumet::pipeline::Output for CsvOutput {
 fn escape_late_attribute(s: &str) -> String {
     s.replace('=', "\\=")
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use alumet::{
+        measurement::{
+            Histogram, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+        },
+        metrics::{Metric, MetricKind, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::CsvOutput;
+    use crate::rotation::RotationConfig;
+
+    fn point(attributes: &[(&'static str, u64)]) -> MeasurementPoint {
+        let mut point = MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(1),
+        );
+        for (key, value) in attributes {
+            point.add_attr(*key, *value);
+        }
+        point
+    }
+
+    fn header(path: &std::path::Path) -> String {
+        fs::read_to_string(path).unwrap().lines().next().unwrap().to_owned()
+    }
+
+    #[test]
+    fn rotation_rewrites_header_with_late_attributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("out.csv");
+        let rotation = RotationConfig {
+            max_file_size: None,
+            max_duration: None,
+            file_pattern: String::from("out-{seq}.csv"),
+            compress: false,
+            max_files: None,
+        };
+        let mut output = CsvOutput::new(
+            output_path.clone(),
+            true,
+            false,
+            false,
+            ';',
+            String::from("\"\""),
+            Some(rotation),
+        )
+        .unwrap();
+        let metrics = HashMap::from([(
+            RawMetricId::from_u64(0),
+            Metric {
+                name: String::from("test"),
+                description: String::new(),
+                value_type: WrappedMeasurementType::U64,
+                unit: PrefixedUnit::from(Unit::Unity),
+                kind: MetricKind::Gauge,
+                labels: vec![],
+            },
+        )]);
+
+        // "b" is not in the header of the first file, it becomes a late attribute
+        let buf1 = MeasurementBuffer::from(vec![point(&[("a", 1)])]);
+        let buf2 = MeasurementBuffer::from(vec![point(&[("a", 2), ("b", 3)])]);
+        output.write_measurements(&buf1, |id| metrics.get(id)).unwrap();
+        output.write_measurements(&buf2, |id| metrics.get(id)).unwrap();
+        output.rotate().unwrap();
+
+        // the new header contains "b", even if the new points don't have it
+        let buf3 = MeasurementBuffer::from(vec![point(&[("c", 4)])]);
+        output.write_measurements(&buf3, |id| metrics.get(id)).unwrap();
+
+        let base = "metric;timestamp;value;resource_kind;resource_id;consumer_kind;consumer_id";
+        assert_eq!(
+            header(&dir.path().join("out-0000.csv")),
+            format!("{base};a;__late_attributes")
+        );
+        assert_eq!(header(&output_path), format!("{base};a;b;c;__late_attributes"));
+
+        let content = fs::read_to_string(&output_path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].ends_with(";;;4;"), "{}", lines[1]);
+    }
+
+    #[test]
+    fn write_value_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("out.csv");
+        let mut output =
+            CsvOutput::new(output_path.clone(), true, false, false, ';', String::from("\"\""), None).unwrap();
+        let metrics = HashMap::from([(
+            RawMetricId::from_u64(0),
+            Metric {
+                name: String::from("test"),
+                description: String::new(),
+                value_type: WrappedMeasurementType::I64,
+                unit: PrefixedUnit::from(Unit::Unity),
+                kind: MetricKind::Gauge,
+                labels: vec![],
+            },
+        )]);
+
+        let mut histogram = Histogram::new(vec![1.0]).unwrap();
+        histogram.record(0.5);
+        histogram.record(2.0);
+        let values = [
+            WrappedMeasurementValue::I64(-12),
+            WrappedMeasurementValue::Bool(true),
+            WrappedMeasurementValue::Histogram(histogram),
+        ];
+        let buf: MeasurementBuffer = values
+            .into_iter()
+            .map(|v| {
+                MeasurementPoint::new_untyped(
+                    Timestamp::now(),
+                    RawMetricId::from_u64(0),
+                    Resource::LocalMachine,
+                    ResourceConsumer::LocalMachine,
+                    v,
+                )
+            })
+            .collect();
+        output.write_measurements(&buf, |id| metrics.get(id)).unwrap();
+
+        let content = fs::read_to_string(&output_path).unwrap();
+        let values: Vec<&str> = content.lines().skip(1).map(|l| l.split(';').nth(2).unwrap()).collect();
+        assert_eq!(values, vec!["-12", "true", "count=2 sum=2.5 buckets=[1:1 +Inf:1]"]);
+    }
+
+    #[test]
+    fn rotation_after_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("out.csv");
+        let rotation = RotationConfig {
+            max_file_size: Some(10),
+            max_duration: None,
+            file_pattern: String::from("out-{seq}.csv"),
+            compress: false,
+            max_files: None,
+        };
+        let mut output = CsvOutput::new(
+            output_path.clone(),
+            false,
+            false,
+            false,
+            ';',
+            String::from("\"\""),
+            Some(rotation),
+        )
+        .unwrap();
+        let metrics = HashMap::from([(
+            RawMetricId::from_u64(0),
+            Metric {
+                name: String::from("test"),
+                description: String::new(),
+                value_type: WrappedMeasurementType::U64,
+                unit: PrefixedUnit::from(Unit::Unity),
+                kind: MetricKind::Gauge,
+                labels: vec![],
+            },
+        )]);
+
+        // the file is above the limit after the first write, it is rotated immediately
+        let buf = MeasurementBuffer::from(vec![point(&[("a", 1)])]);
+        output.write_measurements(&buf, |id| metrics.get(id)).unwrap();
+        let rotated = dir.path().join("out-0000.csv");
+        assert!(rotated.exists());
+        assert_eq!(fs::read_to_string(rotated).unwrap().lines().count(), 2);
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "");
+    }
+}
@@ -0,0 +1,382 @@
+//! Rotation of the CSV files.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, OffsetDateTime};
+
+/// Placeholder for the sequence number in [`RotationConfig::file_pattern`].
+const SEQ_PLACEHOLDER: &str = "{seq}";
+/// Placeholder for the rotation date and time in [`RotationConfig::file_pattern`].
+const TIMESTAMP_PLACEHOLDER: &str = "{timestamp}";
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct RotationConfig {
+    /// Rotate the file when it reaches this size, in bytes.
+    ///
+    /// The size is checked after each write: this is a soft limit, that a file
+    /// can exceed by the size of one buffer of measurements.
+    pub max_file_size: Option<u64>,
+    /// Rotate the file when it has been written to for this duration.
+    #[serde(with = "humantime_serde")]
+    pub max_duration: Option<Duration>,
+    /// Name of the rotated files, with the placeholders `{seq}` and/or `{timestamp}`.
+    /// Relative paths are relative to the directory of the output file.
+    pub file_pattern: String,
+    /// Compress the rotated files with gzip.
+    pub compress: bool,
+    /// Maximum number of rotated files to keep. The oldest files are deleted first.
+    ///
+    /// The files that match `file_pattern` and have been left by a previous run are counted.
+    pub max_files: Option<usize>,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: Some(100 * 1024 * 1024),
+            max_duration: None,
+            file_pattern: String::from("alumet-output-{timestamp}.csv"),
+            compress: false,
+            max_files: None,
+        }
+    }
+}
+
+/// Decides when to rotate the output file, and moves it away when it's time.
+pub struct Rotator {
+    config: RotationConfig,
+    /// The file that is being written to.
+    active_path: PathBuf,
+    /// When the active file has been created.
+    opened_at: Instant,
+    /// Sequence number of the next rotated file.
+    next_seq: u64,
+    /// Rotated files, oldest first, including the ones of the previous runs.
+    rotated: VecDeque<PathBuf>,
+}
+
+impl Rotator {
+    pub fn new(config: RotationConfig, active_path: PathBuf) -> anyhow::Result<Self> {
+        if !config.file_pattern.contains(SEQ_PLACEHOLDER) && !config.file_pattern.contains(TIMESTAMP_PLACEHOLDER) {
+            return Err(anyhow!(
+                "invalid rotation pattern '{}': it must contain {SEQ_PLACEHOLDER} or {TIMESTAMP_PLACEHOLDER}",
+                config.file_pattern
+            ));
+        }
+        let mut rotator = Self {
+            config,
+            active_path,
+            opened_at: Instant::now(),
+            next_seq: 0,
+            rotated: VecDeque::new(),
+        };
+        rotator.find_previous_files();
+        Ok(rotator)
+    }
+
+    /// Looks for the files that have been rotated by a previous run, so that they are
+    /// taken into account by `max_files`, and so that the sequence numbers continue.
+    fn find_previous_files(&mut self) {
+        let pattern_path = self.rotated_dir().join(&self.config.file_pattern);
+        let (Some(dir), Some(pattern)) = (pattern_path.parent(), pattern_path.file_name()) else {
+            return;
+        };
+        let pattern = pattern.to_string_lossy().into_owned();
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let mut previous = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(seq) = match_rotated_name(&pattern, &name) else {
+                continue;
+            };
+            if path == self.active_path || !path.is_file() {
+                continue;
+            }
+            if let Some(seq) = seq {
+                self.next_seq = self.next_seq.max(seq + 1);
+            }
+            let modified = entry.metadata().and_then(|m| m.modified()).ok();
+            previous.push((modified, seq, path));
+        }
+        if !previous.is_empty() {
+            log::debug!(
+                "Found {} files rotated by a previous run in {}",
+                previous.len(),
+                dir.display()
+            );
+        }
+        previous.sort();
+        self.rotated.extend(previous.into_iter().map(|(_, _, path)| path));
+    }
+
+    /// Returns true if the active file, which has the given size, should be rotated.
+    pub fn should_rotate(&self, file_size: u64) -> bool {
+        self.config.max_file_size.is_some_and(|max| file_size >= max)
+            || self
+                .config
+                .max_duration
+                .is_some_and(|max| self.opened_at.elapsed() >= max)
+    }
+
+    /// Moves the active file to its rotated name, compresses it if configured so,
+    /// and deletes the oldest rotated files if there are too many of them.
+    ///
+    /// The active file must have been flushed and closed before calling this function.
+    /// Returns the final path of the rotated file.
+    pub fn rotate(&mut self) -> io::Result<PathBuf> {
+        let mut rotated_path = self.next_rotated_path();
+        std::fs::rename(&self.active_path, &rotated_path)?;
+        if self.config.compress {
+            rotated_path = gzip(&rotated_path)?;
+        }
+        log::debug!("Rotated {} to {}", self.active_path.display(), rotated_path.display());
+        self.rotated.push_back(rotated_path.clone());
+        self.opened_at = Instant::now();
+
+        if let Some(max_files) = self.config.max_files {
+            while self.rotated.len() > max_files {
+                let oldest = self.rotated.pop_front().unwrap();
+                log::debug!("Deleting old rotated file {}", oldest.display());
+                if let Err(e) = std::fs::remove_file(&oldest) {
+                    log::warn!("Could not delete old rotated file {}: {e}", oldest.display());
+                }
+            }
+        }
+        Ok(rotated_path)
+    }
+
+    /// Generates a path for the next rotated file, that does not exist yet.
+    fn next_rotated_path(&mut self) -> PathBuf {
+        let format = format_description!("[year][month][day]T[hour][minute][second]");
+        let now = OffsetDateTime::now_utc().format(&format).unwrap();
+        let dir = self.rotated_dir().to_owned();
+        loop {
+            let name = self
+                .config
+                .file_pattern
+                .replace(SEQ_PLACEHOLDER, &format!("{:04}", self.next_seq))
+                .replace(TIMESTAMP_PLACEHOLDER, &now);
+            self.next_seq += 1;
+            let path = dir.join(name);
+            if !path.exists() && !gz_path(&path).exists() {
+                return path;
+            }
+            if !self.config.file_pattern.contains(SEQ_PLACEHOLDER) {
+                // Only the timestamp varies, add a suffix to avoid overwriting a file.
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let name = match path.extension() {
+                    Some(ext) => format!("{stem}-{}.{}", self.next_seq, ext.to_string_lossy()),
+                    None => format!("{stem}-{}", self.next_seq),
+                };
+                let path = path.with_file_name(name);
+                if !path.exists() && !gz_path(&path).exists() {
+                    return path;
+                }
+            }
+        }
+    }
+
+    /// Directory to which `file_pattern` is relative.
+    fn rotated_dir(&self) -> &Path {
+        self.active_path.parent().unwrap_or(Path::new(""))
+    }
+}
+
+/// Length of the timestamp that replaces [`TIMESTAMP_PLACEHOLDER`], for instance `20240131T235959`.
+const TIMESTAMP_LEN: usize = 15;
+
+/// Checks whether `name` is a file name that [`Rotator::next_rotated_path`] could have generated
+/// from `pattern`, possibly compressed.
+///
+/// Returns `None` if it does not match, or `Some(seq)` where `seq` is the sequence number, if any.
+fn match_rotated_name(pattern: &str, name: &str) -> Option<Option<u64>> {
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    if let Some(seq) = match_pattern(pattern, name) {
+        return Some(seq);
+    }
+    // When only the timestamp varies, a suffix `-N` can have been added before the extension.
+    let path = Path::new(name);
+    let stem = path.file_stem()?.to_str()?;
+    let (stem, suffix) = stem.rsplit_once('-')?;
+    if suffix.is_empty() || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let unsuffixed = match path.extension() {
+        Some(ext) => format!("{stem}.{}", ext.to_str()?),
+        None => stem.to_owned(),
+    };
+    match_pattern(pattern, &unsuffixed)
+}
+
+fn match_pattern(pattern: &str, name: &str) -> Option<Option<u64>> {
+    if let Some(rest) = pattern.strip_prefix(SEQ_PLACEHOLDER) {
+        // try every possible sequence number, the shortest first
+        let digits = name.bytes().take_while(|b| b.is_ascii_digit()).count();
+        return (1..=digits).find_map(|n| {
+            let seq = name[..n].parse().ok()?;
+            match_pattern(rest, &name[n..]).map(|other| other.or(Some(seq)))
+        });
+    }
+    if let Some(rest) = pattern.strip_prefix(TIMESTAMP_PLACEHOLDER) {
+        let timestamp = name.get(..TIMESTAMP_LEN)?;
+        if !timestamp.bytes().all(|b| b.is_ascii_digit() || b == b'T') {
+            return None;
+        }
+        return match_pattern(rest, &name[TIMESTAMP_LEN..]);
+    }
+    let mut pattern_chars = pattern.chars();
+    match pattern_chars.next() {
+        None => name.is_empty().then_some(None),
+        Some(c) => match_pattern(pattern_chars.as_str(), name.strip_prefix(c)?),
+    }
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut res = path.to_owned().into_os_string();
+    res.push(".gz");
+    PathBuf::from(res)
+}
+
+/// Compresses a file with gzip and deletes the uncompressed file.
+fn gzip(path: &Path) -> io::Result<PathBuf> {
+    let compressed_path = gz_path(path);
+    let mut input = BufReader::new(File::open(path)?);
+    let mut encoder = GzEncoder::new(BufWriter::new(File::create(&compressed_path)?), Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    std::fs::remove_file(path)?;
+    Ok(compressed_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read, path::Path};
+
+    use flate2::read::GzDecoder;
+
+    use super::{match_rotated_name, RotationConfig, Rotator};
+
+    fn config(pattern: &str, compress: bool, max_files: Option<usize>) -> RotationConfig {
+        RotationConfig {
+            max_file_size: Some(10),
+            max_duration: None,
+            file_pattern: pattern.to_owned(),
+            compress,
+            max_files,
+        }
+    }
+
+    fn list_dir(dir: &Path) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(Rotator::new(config("out.csv", false, None), "out.csv".into()).is_err());
+    }
+
+    #[test]
+    fn should_rotate() {
+        let rotator = Rotator::new(config("out-{seq}.csv", false, None), "out.csv".into()).unwrap();
+        assert!(!rotator.should_rotate(9));
+        assert!(rotator.should_rotate(10));
+    }
+
+    #[test]
+    fn rotate_with_seq_and_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let active = dir.path().join("out.csv");
+        let mut rotator = Rotator::new(config("out-{seq}.csv", false, Some(2)), active.clone()).unwrap();
+        for i in 0..3 {
+            fs::write(&active, format!("file {i}")).unwrap();
+            rotator.rotate().unwrap();
+        }
+        assert_eq!(list_dir(dir.path()), vec!["out-0001.csv", "out-0002.csv"]);
+        assert_eq!(fs::read_to_string(dir.path().join("out-0002.csv")).unwrap(), "file 2");
+    }
+
+    #[test]
+    fn rotate_with_timestamp_and_gzip() {
+        let dir = tempfile::tempdir().unwrap();
+        let active = dir.path().join("out.csv");
+        let mut rotator = Rotator::new(config("out-{timestamp}.csv", true, None), active.clone()).unwrap();
+        for i in 0..2 {
+            fs::write(&active, format!("file {i}")).unwrap();
+            rotator.rotate().unwrap();
+        }
+        let files = list_dir(dir.path());
+        assert_eq!(files.len(), 2, "{files:?}");
+        assert!(files.iter().all(|f| f.starts_with("out-") && f.ends_with(".csv.gz")));
+
+        let mut contents: Vec<String> = files
+            .iter()
+            .map(|f| {
+                let mut content = String::new();
+                GzDecoder::new(fs::File::open(dir.path().join(f)).unwrap())
+                    .read_to_string(&mut content)
+                    .unwrap();
+                content
+            })
+            .collect();
+        contents.sort();
+        assert_eq!(contents, vec!["file 0", "file 1"]);
+    }
+
+    #[test]
+    fn match_rotated_names() {
+        assert_eq!(match_rotated_name("out-{seq}.csv", "out-0012.csv"), Some(Some(12)));
+        assert_eq!(match_rotated_name("out-{seq}.csv", "out-0012.csv.gz"), Some(Some(12)));
+        assert_eq!(match_rotated_name("out-{seq}.csv", "out-.csv"), None);
+        assert_eq!(match_rotated_name("out-{seq}.csv", "out.csv"), None);
+        assert_eq!(match_rotated_name("out-{seq}.csv", "other-0001.csv"), None);
+        assert_eq!(
+            match_rotated_name("out-{timestamp}.csv", "out-20240131T235959.csv"),
+            Some(None)
+        );
+        assert_eq!(
+            match_rotated_name("out-{timestamp}.csv", "out-20240131T235959-3.csv.gz"),
+            Some(None)
+        );
+        assert_eq!(match_rotated_name("out-{timestamp}.csv", "out-2024.csv"), None);
+        assert_eq!(
+            match_rotated_name("{timestamp}-{seq}.csv", "20240131T235959-7.csv"),
+            Some(Some(7))
+        );
+    }
+
+    #[test]
+    fn previous_files_are_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let active = dir.path().join("out.csv");
+        // files left by a previous run, and a file that is not ours
+        for name in ["out-0000.csv", "out-0001.csv", "notes.txt"] {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+
+        let mut rotator = Rotator::new(config("out-{seq}.csv", false, Some(2)), active.clone()).unwrap();
+        fs::write(&active, "new file").unwrap();
+        let rotated = rotator.rotate().unwrap();
+
+        // the sequence continues, and the oldest file of the previous run has been deleted
+        assert_eq!(rotated, dir.path().join("out-0002.csv"));
+        assert_eq!(list_dir(dir.path()), vec!["notes.txt", "out-0001.csv", "out-0002.csv"]);
+    }
+}
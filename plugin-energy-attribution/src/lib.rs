@@ -1,6 +1,9 @@
 use alumet::{
-    metrics::{RawMetricId, TypedMetricId},
-    pipeline::elements::transform::builder::TransformRegistration,
+    metrics::{MetricId, MetricKind, RawMetricId},
+    pipeline::{
+        alignment::{AlignmentConfig, MissingPolicy},
+        elements::transform::builder::TransformRegistration,
+    },
     plugin::{
         rust::{deserialize_config, serialize_config, AlumetPlugin},
         ConfigTable,
@@ -9,6 +12,7 @@ use alumet::{
 };
 
 use anyhow::Context;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
@@ -30,7 +34,7 @@ struct Metrics {
     // that is why they are Options.
     hardware_usage: RawMetricId,
     consumed_energy: RawMetricId,
-    pod_attributed_energy: TypedMetricId<f64>,
+    pod_attributed_energy: RawMetricId,
 }
 
 impl AlumetPlugin for EnergyAttributionPlugin {
@@ -54,14 +58,20 @@ impl AlumetPlugin for EnergyAttributionPlugin {
     fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
         // Create the energy attribution metric and add its id to the
         // transform builder's metrics list.
-        let attribution_energy_metric = alumet.create_metric(
+        let attribution_energy_metric = alumet.create_metric_with_kind::<f64>(
             "pod_attributed_energy",
+            MetricKind::Delta,
             Unit::Joule,
             "Energy consumption attributed to the pod",
         )?;
 
         let consumed_energy = self.config.consumed_energy_rapl.clone();
         let hardware_usage = self.config.hardware_usage_cgroup.clone();
+        let alignment = AlignmentConfig {
+            window: self.config.window,
+            allowed_lateness: self.config.allowed_lateness,
+            missing: self.config.missing_policy.into(),
+        };
 
         // Add the transform builder and its metrics
         alumet.add_transform_builder(move |ctx| {
@@ -76,12 +86,12 @@ impl AlumetPlugin for EnergyAttributionPlugin {
                 .with_context(|| format!("Metric not found {}", hardware_usage))?
                 .0;
             let metrics = Metrics {
-                pod_attributed_energy: attribution_energy_metric,
+                pod_attributed_energy: attribution_energy_metric.untyped_id(),
                 consumed_energy: consumed_energy_metric,
                 hardware_usage: hardware_usage_metric,
             };
 
-            let transform = Box::new(EnergyAttributionTransform::new(metrics));
+            let transform = Box::new(EnergyAttributionTransform::new(metrics, alignment.clone()));
             Ok(TransformRegistration { name, transform })
         });
         Ok(())
@@ -96,6 +106,45 @@ impl AlumetPlugin for EnergyAttributionPlugin {
 struct Config {
     consumed_energy_rapl: String,
     hardware_usage_cgroup: String,
+    /// The measurements are joined by time windows of this duration.
+    #[serde(with = "humantime_serde", default = "default_window")]
+    window: Duration,
+    /// How long to wait for the measurements of a window before attributing its energy.
+    #[serde(with = "humantime_serde", default = "default_allowed_lateness")]
+    allowed_lateness: Duration,
+    /// What to do when the energy or the usage of the pods is missing in a window.
+    #[serde(default)]
+    missing_policy: MissingPolicyConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum MissingPolicyConfig {
+    /// Do not attribute the energy of the window.
+    #[default]
+    Drop,
+    /// Estimate the missing measurements from the previous and next ones.
+    Interpolate,
+    /// Attribute what can be attributed.
+    EmitPartial,
+}
+
+impl From<MissingPolicyConfig> for MissingPolicy {
+    fn from(value: MissingPolicyConfig) -> Self {
+        match value {
+            MissingPolicyConfig::Drop => MissingPolicy::Drop,
+            MissingPolicyConfig::Interpolate => MissingPolicy::Interpolate,
+            MissingPolicyConfig::EmitPartial => MissingPolicy::EmitPartial,
+        }
+    }
+}
+
+fn default_window() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_allowed_lateness() -> Duration {
+    Duration::from_secs(5)
 }
 
 impl Default for Config {
@@ -103,6 +152,9 @@ impl Default for Config {
         Self {
             consumed_energy_rapl: String::from("rapl_consumed_energy"),
             hardware_usage_cgroup: String::from("cgroup_cpu_usage_user"),
+            window: default_window(),
+            allowed_lateness: default_allowed_lateness(),
+            missing_policy: MissingPolicyConfig::default(),
         }
     }
 }
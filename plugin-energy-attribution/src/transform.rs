@@ -1,99 +1,96 @@
-use std::{
-    collections::HashMap,
-    time::{SystemTime, UNIX_EPOCH},
-};
-
 use alumet::{
-    measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    measurement::{MeasurementBuffer, WrappedMeasurementValue},
     pipeline::{
+        alignment::{AlignedWindow, AlignmentBuffer, AlignmentConfig},
         elements::{error::TransformError, transform::TransformContext},
         Transform,
     },
     resources::Resource,
 };
 
+/// The streams of measurements that are joined by the transform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Stream {
+    /// Energy consumed by the CPU packages.
+    Energy,
+    /// Hardware usage of the pods.
+    Usage,
+}
+
 pub struct EnergyAttributionTransform {
     pub metrics: super::Metrics,
-    buffer_pod: HashMap<u64, Vec<MeasurementPoint>>,
-    buffer_rapl: HashMap<u64, MeasurementPoint>,
+    aligner: AlignmentBuffer<Stream>,
 }
+
 impl EnergyAttributionTransform {
     /// Instantiates a new EnergyAttributionTransform with its private fields initialized.
-    pub fn new(metrics: super::Metrics) -> Self {
+    pub fn new(metrics: super::Metrics, alignment: AlignmentConfig) -> Self {
         Self {
             metrics,
-            buffer_pod: HashMap::<u64, Vec<MeasurementPoint>>::new(),
-            buffer_rapl: HashMap::<u64, MeasurementPoint>::new(),
+            aligner: AlignmentBuffer::new(alignment, [Stream::Energy, Stream::Usage]),
         }
     }
 
-    /// Empties the buffers and send the energy attribution points to the MeasurementBuffer.
-    fn buffer_bouncer(&mut self, measurements: &mut alumet::measurement::MeasurementBuffer) {
-        // Retrieving the metric_id of the energy attribution.
-        // Using a nested scope to reduce the lock time.
-        let metric_id = self.metrics.pod_attributed_energy;
-
-        // If the buffers do have enough (every) MeasurementPoints,
-        // then we compute the energy attribution.
-        while self.buffer_rapl.len() >= 2 && self.buffer_pod.len() >= 2 {
-            // Get the smallest rapl id i.e. the oldest timestamp (key) present in the buffer.
-            let rapl_mini_id = self
-                .buffer_rapl
-                .keys()
-                .reduce(|x, y| if x < y { x } else { y })
-                .unwrap()
-                .clone();
-
-            // Check if the buffer_pod contains the key to prevent any panic/error bellow.
-            if !self.buffer_pod.contains_key(&rapl_mini_id) {
-                todo!("decide what to do in this case");
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        // Filling the buffers.
+        for m in measurements.iter() {
+            let stream = if m.metric == self.metrics.consumed_energy {
+                // If the metric is rapl then we keep only the cpu package ones.
+                match m.resource {
+                    Resource::CpuPackage { id: _ } => Stream::Energy,
+                    _ => continue,
+                }
+            } else if m.metric == self.metrics.hardware_usage {
+                // Else, if the metric is pod, then we keep only the ones that are prefixed with "pod".
+                if m.attributes().any(|(_, value)| value.to_string().starts_with("pod")) {
+                    Stream::Usage
+                } else {
+                    continue;
+                }
+            } else {
+                continue;
+            };
+            if let Err(e) = self.aligner.push(stream, m.clone()) {
+                log::debug!("Ignoring measurement of {stream:?} at {:?}: {e}", m.timestamp);
             }
+        }
 
-            let rapl_point = self.buffer_rapl.remove(&rapl_mini_id).unwrap();
-
-            // Compute the sum of every `total_usage_usec` for the given timestamp: `rapl_mini_id`.
-            let tot_time_sum = self
-                .buffer_pod
-                .get(&rapl_mini_id)
-                .unwrap()
-                .iter()
-                .map(|x| match x.value {
-                    WrappedMeasurementValue::F64(fx) => fx,
-                    WrappedMeasurementValue::U64(ux) => ux as f64,
-                })
-                .sum::<f64>();
-
-            // Then for every points in the buffer_pod at `rapl_mini_id`.
-            for point in self.buffer_pod.remove(&rapl_mini_id).unwrap().iter() {
-                // We extract the current tot_time as f64.
-                let cur_tot_time_f64 = match point.value {
-                    WrappedMeasurementValue::F64(fx) => fx,
-                    WrappedMeasurementValue::U64(ux) => ux as f64,
-                };
-
-                // Extract the attributes of the current point to add them
-                // to the new measurement point.
-                let point_attributes = point
-                    .attributes()
-                    .map(|(key, value)| (key.to_owned(), value.clone()))
-                    .collect();
-
-                // We create the new MeasurementPoint for the energy attribution.
-                let new_m = MeasurementPoint::new(
-                    rapl_point.timestamp,
-                    metric_id,
-                    point.resource.clone(),
-                    point.consumer.clone(),
-                    match rapl_point.value {
-                        WrappedMeasurementValue::F64(fx) => cur_tot_time_f64 / tot_time_sum * fx,
-                        WrappedMeasurementValue::U64(ux) => cur_tot_time_f64 / tot_time_sum * (ux as f64),
-                    },
-                )
-                .with_attr_vec(point_attributes);
-
-                // And finally, the MeasurementPoint is pushed to the MeasurementBuffer.
-                measurements.push(new_m.clone());
-            }
+        // Emptying the complete windows and pushing the energy attribution to the MeasurementBuffer
+        for window in self.aligner.pop_ready() {
+            self.attribute(window, measurements);
+        }
+    }
+
+    /// Computes the energy attribution of a time window.
+    fn attribute(&self, mut window: AlignedWindow<Stream>, measurements: &mut MeasurementBuffer) {
+        let energy_points = window.take(&Stream::Energy);
+        let usage_points = window.take(&Stream::Usage);
+        let Some(timestamp) = energy_points
+            .iter()
+            .map(|p| p.timestamp)
+            .max_by_key(|t| t.to_unix_nanos())
+        else {
+            // The window is partial: there is no energy to attribute.
+            return;
+        };
+
+        // Compute the energy consumed by all the CPU packages, and the sum of every `total_usage_usec`.
+        let energy = energy_points.iter().filter_map(|p| p.value.as_f64()).sum::<f64>();
+        let tot_time_sum = usage_points.iter().filter_map(|p| p.value.as_f64()).sum::<f64>();
+        if tot_time_sum <= 0.0 {
+            return;
+        }
+
+        for mut point in usage_points {
+            let Some(cur_tot_time_f64) = point.value.as_f64() else {
+                continue;
+            };
+            // We reuse the point of the pod for the energy attribution, so that its attributes
+            // (and its resource and consumer) are moved to the new point instead of being copied.
+            point.timestamp = timestamp;
+            point.metric = self.metrics.pod_attributed_energy;
+            point.value = WrappedMeasurementValue::F64(cur_tot_time_f64 / tot_time_sum * energy);
+            measurements.push(point);
         }
     }
 }
@@ -101,51 +98,110 @@ impl EnergyAttributionTransform {
 impl Transform for EnergyAttributionTransform {
     /// Applies the transform on the measurements.
     fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
-        // Retrieve the pod_id and the rapl_id.
-        // Using a nested scope to reduce the lock time.
-        let (pod_id, rapl_id) = {
-            let metrics = &self.metrics;
-
-            let pod_id = metrics.hardware_usage.as_u64();
-            let rapl_id = metrics.consumed_energy.as_u64();
+        self.process(measurements);
+        Ok(())
+    }
+}
 
-            (pod_id, rapl_id)
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        pipeline::alignment::{AlignmentConfig, MissingPolicy},
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::EnergyAttributionTransform;
+    use crate::Metrics;
+
+    fn transform(missing: MissingPolicy) -> EnergyAttributionTransform {
+        let metrics = Metrics {
+            hardware_usage: RawMetricId::from_u64(0),
+            consumed_energy: RawMetricId::from_u64(1),
+            pod_attributed_energy: RawMetricId::from_u64(2),
         };
+        let alignment = AlignmentConfig {
+            window: Duration::from_secs(1),
+            allowed_lateness: Duration::from_secs(2),
+            missing,
+        };
+        EnergyAttributionTransform::new(metrics, alignment)
+    }
 
-        // Filling the buffers.
-        for m in measurements.clone().iter() {
-            if m.metric.as_u64() == rapl_id {
-                match m.resource {
-                    // If the metric is rapl then we insert only the cpu package one in the buffer.
-                    Resource::CpuPackage { id: _ } => {
-                        let id = SystemTime::from(m.timestamp).duration_since(UNIX_EPOCH)?.as_secs();
+    fn energy(secs: u64, value: f64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_secs(secs)),
+            RawMetricId::from_u64(1),
+            Resource::CpuPackage { id: 0 },
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(value),
+        )
+    }
 
-                        self.buffer_rapl.insert(id, m.clone());
-                    }
-                    _ => continue,
-                }
-            } else if m.metric.as_u64() == pod_id {
-                // Else, if the metric is pod, then we keep only the ones that are prefixed with "pod"
-                // before inserting them in the buffer.
-                if m.attributes().any(|(_, value)| value.to_string().starts_with("pod")) {
-                    let id = SystemTime::from(m.timestamp).duration_since(UNIX_EPOCH)?.as_secs();
-                    match self.buffer_pod.get_mut(&id) {
-                        Some(vec_points) => {
-                            vec_points.push(m.clone());
-                        }
-                        None => {
-                            // If the buffer does not have any value for the current id (timestamp)
-                            // then we create the vec with its first value.
-                            self.buffer_pod.insert(id, vec![m.clone()]);
-                        }
-                    }
-                }
-            }
-        }
+    fn usage(secs: u64, pod: &'static str, value: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_millis(secs * 1000 + 100)),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::ControlGroup { path: pod.into() },
+            WrappedMeasurementValue::U64(value),
+        )
+        .with_attr("name", pod)
+    }
 
-        // Emptying the buffers and pushing the energy attribution to the MeasurementBuffer
-        self.buffer_bouncer(measurements);
+    fn attributed(buf: &MeasurementBuffer) -> Vec<(u64, String, String)> {
+        buf.iter()
+            .filter(|p| p.metric == RawMetricId::from_u64(2))
+            .map(|p| {
+                let (secs, _) = p.timestamp.to_unix_timestamp();
+                (secs as u64, p.consumer.id_display().to_string(), p.value.to_string())
+            })
+            .collect()
+    }
 
-        Ok(())
+    #[test]
+    fn attribution() {
+        let mut t = transform(MissingPolicy::Drop);
+        let mut buf = MeasurementBuffer::from(vec![
+            energy(0, 100.0),
+            usage(0, "pod-a", 1),
+            usage(0, "pod-b", 3),
+            usage(0, "other", 10),
+        ]);
+        t.process(&mut buf);
+        assert!(attributed(&buf).is_empty());
+
+        let mut buf = MeasurementBuffer::from(vec![energy(1, 50.0), usage(1, "pod-a", 1), usage(1, "pod-b", 1)]);
+        t.process(&mut buf);
+        assert_eq!(
+            attributed(&buf),
+            vec![(0, "pod-a".into(), "25".into()), (0, "pod-b".into(), "75".into())]
+        );
+    }
+
+    #[test]
+    fn missing_usage() {
+        // the usage of the pods is missing for the second 1: this used to panic
+        for (policy, expected) in [
+            (MissingPolicy::Drop, vec![]),
+            (MissingPolicy::EmitPartial, vec![]),
+            (
+                MissingPolicy::Interpolate,
+                vec![(1, "pod-a".into(), "20".into()), (1, "pod-b".into(), "60".into())],
+            ),
+        ] {
+            let mut t = transform(policy);
+            let mut buf = MeasurementBuffer::from(vec![energy(0, 100.0), usage(0, "pod-a", 1), usage(0, "pod-b", 3)]);
+            t.process(&mut buf);
+            let mut buf = MeasurementBuffer::from(vec![energy(1, 80.0), energy(2, 10.0), energy(3, 10.0)]);
+            t.process(&mut buf);
+            let mut buf = MeasurementBuffer::from(vec![energy(4, 10.0), usage(4, "pod-a", 1)]);
+            t.process(&mut buf);
+            let res: Vec<_> = attributed(&buf).into_iter().filter(|(t, _, _)| *t == 1).collect();
+            assert_eq!(res, expected, "{policy:?}");
+        }
     }
 }
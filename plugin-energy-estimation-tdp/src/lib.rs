@@ -1,5 +1,5 @@
 use alumet::{
-    metrics::{RawMetricId, TypedMetricId},
+    metrics::{MetricKind, RawMetricId, TypedMetricId},
     plugin::{
         rust::{deserialize_config, serialize_config, AlumetPlugin},
         AlumetPreStart, ConfigTable,
@@ -60,8 +60,9 @@ impl AlumetPlugin for EnergyEstimationTdpPlugin {
 
         // Create the energy attribution metric and add its id to the
         // transform plugin metrics' list.
-        metrics.pod_estimate_attributed_energy = Some(alumet.create_metric(
+        metrics.pod_estimate_attributed_energy = Some(alumet.create_metric_with_kind(
             "pod_estimate_attributed_energy",
+            MetricKind::Delta,
             Unit::Joule,
             "Energy consumption estimated to the pod",
         )?);
@@ -5,11 +5,12 @@ use std::{
 };
 
 use alumet::{
-    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint},
     pipeline::{
         elements::{error::TransformError, transform::TransformContext},
         Transform,
     },
+    units::{PrefixedUnit, Unit, UnitConversion},
 };
 
 use crate::Config;
@@ -17,12 +18,21 @@ use crate::Config;
 pub struct EnergyEstimationTdpTransform {
     pub config: Config,
     pub metrics: Arc<Mutex<super::Metrics>>,
+    /// Converts the CPU usage of the pods, in microseconds, to seconds.
+    cpu_usage_to_secs: UnitConversion,
 }
 
 impl EnergyEstimationTdpTransform {
     /// Instantiates a new EnergyAttributionTransform with its private fields initialized.
     pub fn new(config: Config, metrics: Arc<Mutex<super::Metrics>>) -> Self {
-        Self { config, metrics }
+        let cpu_usage_to_secs = PrefixedUnit::micro(Unit::Second)
+            .conversion_to(&Unit::Second.into())
+            .expect("µs can be converted to s");
+        Self {
+            config,
+            metrics,
+            cpu_usage_to_secs,
+        }
     }
 }
 
@@ -53,16 +63,15 @@ impl Transform for EnergyEstimationTdpTransform {
                 let id = SystemTime::from(point.timestamp).duration_since(UNIX_EPOCH)?.as_secs();
                 log::trace!("we get a measurement for pod with timestamp: {}", id);
 
-                let value = match point.value {
-                    WrappedMeasurementValue::F64(x) => x.to_string(),
-                    WrappedMeasurementValue::U64(x) => x.to_string(),
+                let Some(value) = point.value.as_f64() else {
+                    log::debug!("Ignoring value of {:?} that is not a number", point.metric);
+                    continue;
                 };
 
                 // from k8s plugin we get the cpu_usage_per_pod in micro second
                 // energy = cpu_usage_per_pod * nb_vcpu/nb_cpu * tdp / poll_interval
-                let mut estimated_energy = value.parse().unwrap();
-                estimated_energy = estimated_energy * self.config.nb_vcpu / self.config.nb_cpu * self.config.tdp
-                    / (1000000.0)
+                let cpu_usage = self.cpu_usage_to_secs.apply(value);
+                let estimated_energy = cpu_usage * self.config.nb_vcpu / self.config.nb_cpu * self.config.tdp
                     / (self.config.poll_interval.as_secs() as f64);
 
                 log::trace!(
@@ -0,0 +1,198 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use alumet::{
+    metrics::MetricId,
+    pipeline::trigger,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+};
+use anyhow::{anyhow, Context};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use probe::HwmonProbe;
+use sysfs::{Chip, SensorType};
+
+mod probe;
+mod sysfs;
+
+pub struct HwmonPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for HwmonPlugin {
+    fn name() -> &'static str {
+        "hwmon"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(HwmonPlugin { config }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let filter = ChannelFilter::new(&self.config)?;
+        let mut chips = sysfs::all_chips(&self.config.hwmon_path)?;
+        filter.apply(&mut chips);
+
+        let n_channels: usize = chips.iter().map(|c| c.channels.len()).sum();
+        if n_channels == 0 {
+            return Err(anyhow!(
+                "no hwmon sensor found in {} (or all of them have been filtered out)",
+                self.config.hwmon_path.display()
+            ));
+        }
+        for chip in &chips {
+            let labels: Vec<&str> = chip.channels.iter().map(|c| c.label_or_name()).collect();
+            log::info!("Found hwmon chip {}: {}", chip.id, labels.join(", "));
+        }
+
+        // Only create the metrics of the sensors that are present.
+        let mut metrics = HashMap::new();
+        for chip in &chips {
+            for channel in &chip.channels {
+                if !metrics.contains_key(&channel.sensor) {
+                    let t = channel.sensor;
+                    let metric =
+                        alumet.create_metric_with_kind::<f64>(t.metric_name(), t.kind(), t.unit(), t.description())?;
+                    metrics.insert(t, metric.untyped_id());
+                }
+            }
+        }
+
+        let probe = HwmonProbe::new(chips, &metrics)?;
+        let trigger = trigger::builder::time_interval(self.config.poll_interval)
+            .flush_interval(self.config.flush_interval)
+            .update_interval(self.config.flush_interval)
+            .build()?;
+        alumet.add_source(Box::new(probe), trigger);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Selects the chips and channels to monitor.
+struct ChannelFilter {
+    sensors: Vec<SensorType>,
+    chips: Option<Regex>,
+    labels: Option<Regex>,
+}
+
+impl ChannelFilter {
+    fn new(config: &Config) -> anyhow::Result<Self> {
+        fn regex(pattern: &Option<String>, option: &str) -> anyhow::Result<Option<Regex>> {
+            pattern
+                .as_deref()
+                .map(|p| Regex::new(p).with_context(|| format!("invalid regex for {option}: {p}")))
+                .transpose()
+        }
+        Ok(Self {
+            sensors: config.sensors.clone(),
+            chips: regex(&config.chips, "chips")?,
+            labels: regex(&config.labels, "labels")?,
+        })
+    }
+
+    /// Removes the chips and channels that do not match the filter.
+    fn apply(&self, chips: &mut Vec<Chip>) {
+        chips.retain(|chip| {
+            self.chips
+                .as_ref()
+                .is_none_or(|r| r.is_match(&chip.name) || r.is_match(&chip.id))
+        });
+        for chip in chips.iter_mut() {
+            chip.channels.retain(|channel| {
+                self.sensors.contains(&channel.sensor)
+                    && self.labels.as_ref().is_none_or(|r| r.is_match(channel.label_or_name()))
+            });
+        }
+        chips.retain(|chip| !chip.channels.is_empty());
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(with = "humantime_serde")]
+    poll_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    flush_interval: Duration,
+    /// Directory that contains the hwmon chips.
+    hwmon_path: PathBuf,
+    /// Types of sensors to read.
+    sensors: Vec<SensorType>,
+    /// Only read the chips whose name matches this regex.
+    chips: Option<String>,
+    /// Only read the channels whose label (or name, if it has no label) matches this regex.
+    labels: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            flush_interval: Duration::from_secs(5),
+            hwmon_path: PathBuf::from(sysfs::HWMON_PATH),
+            sensors: SensorType::ALL.to_vec(),
+            chips: None,
+            labels: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sysfs::{all_chips, tests::fake_hwmon, SensorType},
+        ChannelFilter, Config,
+    };
+
+    #[test]
+    fn filter() {
+        let root = tempfile::tempdir().unwrap();
+        fake_hwmon(root.path());
+        let config = Config {
+            sensors: vec![SensorType::Temperature, SensorType::Power],
+            chips: Some(String::from("^(coretemp|acpi_power)$")),
+            labels: Some(String::from("^(Package id \\d+|power1|temp1)$")),
+            ..Default::default()
+        };
+        let mut chips = all_chips(root.path()).unwrap();
+        ChannelFilter::new(&config).unwrap().apply(&mut chips);
+
+        let selected: Vec<(&str, Vec<&str>)> = chips
+            .iter()
+            .map(|c| (c.id.as_str(), c.channels.iter().map(|ch| ch.label_or_name()).collect()))
+            .collect();
+        assert_eq!(
+            selected,
+            vec![
+                ("coretemp-0", vec!["Package id 0"]),
+                ("acpi_power", vec!["power1"]),
+                ("coretemp-1", vec!["temp1"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let config = Config {
+            labels: Some(String::from("(")),
+            ..Default::default()
+        };
+        assert!(ChannelFilter::new(&config).is_err());
+    }
+}
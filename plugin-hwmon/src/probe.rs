@@ -0,0 +1,176 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek},
+};
+
+use alumet::{
+    measurement::{AttributeValue, MeasurementAccumulator, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::{elements::error::PollError, Source},
+    plugin::util::{CounterDiff, CounterDiffUpdate},
+    resources::{Resource, ResourceConsumer},
+};
+use anyhow::Context;
+
+use crate::sysfs::{Chip, SensorType};
+
+/// Reads the hwmon sensors.
+pub struct HwmonProbe {
+    channels: Vec<OpenedChannel>,
+}
+
+struct OpenedChannel {
+    file: File,
+    metric: RawMetricId,
+    resource: Resource,
+    /// Name of the channel, for instance `temp1`.
+    name: String,
+    label: Option<String>,
+    /// Overflow-correcting counter, only for the energy sensors.
+    counter: Option<CounterDiff>,
+}
+
+impl HwmonProbe {
+    pub fn new(chips: Vec<Chip>, metrics: &HashMap<SensorType, RawMetricId>) -> anyhow::Result<Self> {
+        let mut channels = Vec::new();
+        for chip in chips {
+            for channel in chip.channels {
+                let file = File::open(&channel.input_path)
+                    .with_context(|| format!("could not open {}", channel.input_path.display()))?;
+                let counter = match channel.sensor {
+                    SensorType::Energy => Some(CounterDiff::with_max_value(u64::MAX)),
+                    _ => None,
+                };
+                channels.push(OpenedChannel {
+                    file,
+                    metric: metrics[&channel.sensor],
+                    resource: Resource::custom("hwmon", chip.id.clone()),
+                    name: channel.name,
+                    label: channel.label,
+                    counter,
+                });
+            }
+        }
+        Ok(Self { channels })
+    }
+}
+
+impl Source for HwmonProbe {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        let mut buf = String::with_capacity(16);
+        for channel in &mut self.channels {
+            buf.clear();
+            let res = channel
+                .file
+                .rewind()
+                .and_then(|_| channel.file.read_to_string(&mut buf));
+            if let Err(e) = res {
+                // Some sensors are temporarily unavailable (for instance when the device is suspended).
+                log::debug!(
+                    "Could not read hwmon channel {} of {:?}: {e}",
+                    channel.name,
+                    channel.resource
+                );
+                continue;
+            }
+            let raw: i64 = match buf.trim_end().parse() {
+                Ok(raw) => raw,
+                Err(e) => {
+                    log::debug!(
+                        "Could not parse the value of hwmon channel {} of {:?}: '{}': {e}",
+                        channel.name,
+                        channel.resource,
+                        buf.trim_end()
+                    );
+                    continue;
+                }
+            };
+
+            let value = match &mut channel.counter {
+                None => raw as f64,
+                Some(counter) => match counter.update(raw as u64) {
+                    CounterDiffUpdate::FirstTime => continue,
+                    CounterDiffUpdate::Difference(diff) => diff as f64,
+                    CounterDiffUpdate::CorrectedDifference(diff) => {
+                        log::debug!("Overflow on hwmon energy counter {}", channel.name);
+                        diff as f64
+                    }
+                },
+            };
+            let mut point = MeasurementPoint::new_untyped(
+                timestamp,
+                channel.metric,
+                channel.resource.clone(),
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::F64(value),
+            )
+            .with_attr("channel", AttributeValue::String(channel.name.clone()));
+            if let Some(label) = &channel.label {
+                point = point.with_attr("label", AttributeValue::String(label.clone()));
+            }
+            measurements.push(point);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        pipeline::Source,
+        resources::Resource,
+    };
+
+    use super::HwmonProbe;
+    use crate::sysfs::{all_chips, tests::fake_hwmon, SensorType};
+
+    #[test]
+    fn poll() {
+        let root = tempfile::tempdir().unwrap();
+        fake_hwmon(root.path());
+        let chips = all_chips(root.path()).unwrap();
+        let metrics: HashMap<SensorType, RawMetricId> = SensorType::ALL
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| (t, RawMetricId::from_u64(i as u64)))
+            .collect();
+        let mut probe = HwmonProbe::new(chips, &metrics).unwrap();
+
+        let mut buf = MeasurementBuffer::new();
+        probe.poll(&mut buf.as_accumulator(), Timestamp::now()).unwrap();
+        // The energy counter needs two measurements.
+        assert_eq!(buf.len(), 7);
+        let energy = metrics[&SensorType::Energy];
+        assert!(buf.iter().all(|p| p.metric != energy));
+
+        let package = buf
+            .iter()
+            .find(|p| p.attributes().any(|(_, v)| v.to_string() == "Package id 0"))
+            .unwrap();
+        assert_eq!(package.resource, Resource::custom("hwmon", "coretemp-0"));
+        assert!(matches!(package.value, WrappedMeasurementValue::F64(x) if x == 45000.0));
+        let negative = buf
+            .iter()
+            .find(|p| p.resource == Resource::custom("hwmon", "coretemp-1"))
+            .unwrap();
+        assert!(matches!(negative.value, WrappedMeasurementValue::F64(x) if x == -5000.0));
+
+        fs::write(root.path().join("hwmon1/device/energy1_input"), "1500000\n").unwrap();
+        let mut buf = MeasurementBuffer::new();
+        probe.poll(&mut buf.as_accumulator(), Timestamp::now()).unwrap();
+        assert_eq!(buf.len(), 8);
+        let energy = buf.iter().find(|p| p.metric == energy).unwrap();
+        assert!(matches!(energy.value, WrappedMeasurementValue::F64(x) if x == 500000.0));
+
+        // An invalid value is skipped, the other channels are still measured.
+        fs::write(root.path().join("hwmon0/temp2_input"), "N/A\n").unwrap();
+        let mut buf = MeasurementBuffer::new();
+        probe.poll(&mut buf.as_accumulator(), Timestamp::now()).unwrap();
+        assert_eq!(buf.len(), 7);
+    }
+}
@@ -0,0 +1,297 @@
+//! Discovery of the hwmon chips and channels in sysfs.
+//!
+//! See <https://docs.kernel.org/hwmon/sysfs-interface.html>.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use alumet::{
+    metrics::MetricKind,
+    units::{PrefixedUnit, Unit},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+pub const HWMON_PATH: &str = "/sys/class/hwmon";
+
+/// Type of a hwmon sensor, as indicated by the prefix of its files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SensorType {
+    #[serde(rename = "power")]
+    Power,
+    #[serde(rename = "energy")]
+    Energy,
+    #[serde(rename = "in")]
+    Voltage,
+    #[serde(rename = "curr")]
+    Current,
+    #[serde(rename = "temp")]
+    Temperature,
+    #[serde(rename = "fan")]
+    Fan,
+}
+
+impl SensorType {
+    pub const ALL: [SensorType; 6] = [
+        SensorType::Power,
+        SensorType::Energy,
+        SensorType::Voltage,
+        SensorType::Current,
+        SensorType::Temperature,
+        SensorType::Fan,
+    ];
+
+    fn file_prefix(&self) -> &'static str {
+        match self {
+            SensorType::Power => "power",
+            SensorType::Energy => "energy",
+            SensorType::Voltage => "in",
+            SensorType::Current => "curr",
+            SensorType::Temperature => "temp",
+            SensorType::Fan => "fan",
+        }
+    }
+
+    pub fn metric_name(&self) -> &'static str {
+        match self {
+            SensorType::Power => "hwmon_power",
+            SensorType::Energy => "hwmon_consumed_energy",
+            SensorType::Voltage => "hwmon_voltage",
+            SensorType::Current => "hwmon_current",
+            SensorType::Temperature => "hwmon_temperature",
+            SensorType::Fan => "hwmon_fan_speed",
+        }
+    }
+
+    /// Returns the kind of the metric: the energy counters are reported as differences.
+    pub fn kind(&self) -> MetricKind {
+        match self {
+            SensorType::Energy => MetricKind::Delta,
+            _ => MetricKind::Gauge,
+        }
+    }
+
+    /// Returns the unit of the values reported by the kernel.
+    pub fn unit(&self) -> PrefixedUnit {
+        match self {
+            SensorType::Power => PrefixedUnit::micro(Unit::Watt),
+            SensorType::Energy => PrefixedUnit::micro(Unit::Joule),
+            SensorType::Voltage => PrefixedUnit::milli(Unit::Volt),
+            SensorType::Current => PrefixedUnit::milli(Unit::Ampere),
+            SensorType::Temperature => PrefixedUnit::milli(Unit::DegreeCelsius),
+            SensorType::Fan => PrefixedUnit::from(Unit::Custom {
+                unique_name: String::from("{rpm}"),
+                display_name: String::from("RPM"),
+            }),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            SensorType::Power => "Power reported by a hwmon sensor.",
+            SensorType::Energy => "Energy consumed since the previous measurement, as reported by a hwmon sensor.",
+            SensorType::Voltage => "Voltage reported by a hwmon sensor.",
+            SensorType::Current => "Current reported by a hwmon sensor.",
+            SensorType::Temperature => "Temperature reported by a hwmon sensor.",
+            SensorType::Fan => "Fan speed reported by a hwmon sensor, in revolutions per minute.",
+        }
+    }
+
+    /// Parses the name of an input file, such as `temp1_input`.
+    /// Returns the type of the sensor and the name of the channel, such as `temp1`.
+    fn parse_input_file(file_name: &str) -> Option<(SensorType, &str)> {
+        let channel = file_name.strip_suffix("_input")?;
+        SensorType::ALL.into_iter().find_map(|t| {
+            let index = channel.strip_prefix(t.file_prefix())?;
+            if !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()) {
+                Some((t, channel))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// A hardware monitoring chip, such as `coretemp` or `nvme`.
+#[derive(Debug)]
+pub struct Chip {
+    /// Name of the chip, as reported by the driver.
+    pub name: String,
+    /// Unique identifier of the chip. It is the name of the chip, unless several chips have the same name.
+    pub id: String,
+    pub channels: Vec<Channel>,
+}
+
+/// A sensor channel of a chip, such as `temp1`.
+#[derive(Debug)]
+pub struct Channel {
+    pub sensor: SensorType,
+    /// Name of the channel, such as `temp1`.
+    pub name: String,
+    /// Label of the channel, such as `Package id 0`, if the driver provides one.
+    pub label: Option<String>,
+    pub input_path: PathBuf,
+}
+
+impl Channel {
+    /// Returns the label of the channel, or its name if it has no label.
+    pub fn label_or_name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Discovers all the hwmon chips in the given directory, which is usually [`HWMON_PATH`].
+pub fn all_chips(hwmon_dir: &Path) -> anyhow::Result<Vec<Chip>> {
+    let mut entries = Vec::new();
+    for e in fs::read_dir(hwmon_dir).with_context(|| format!("could not read {}", hwmon_dir.display()))? {
+        let path = e?.path();
+        if path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with("hwmon"))
+        {
+            entries.push(path);
+        }
+    }
+    entries.sort();
+
+    let mut chips = Vec::with_capacity(entries.len());
+    for path in entries {
+        let chip = read_chip(&path).with_context(|| format!("could not read hwmon chip {}", path.display()))?;
+        chips.push(chip);
+    }
+
+    // Make the ids unique.
+    let mut name_count: HashMap<String, usize> = HashMap::new();
+    for chip in &chips {
+        *name_count.entry(chip.name.clone()).or_default() += 1;
+    }
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for chip in &mut chips {
+        if name_count[&chip.name] > 1 {
+            let n = seen.entry(chip.name.clone()).or_default();
+            chip.id = format!("{}-{n}", chip.name);
+            *n += 1;
+        }
+    }
+    Ok(chips)
+}
+
+fn read_chip(dir: &Path) -> anyhow::Result<Chip> {
+    // Old drivers put the attributes in the `device` subdirectory.
+    let dir = if dir.join("name").exists() {
+        dir.to_owned()
+    } else {
+        dir.join("device")
+    };
+    let name = read_trimmed(&dir.join("name"))?;
+
+    let mut channels = Vec::new();
+    for e in fs::read_dir(&dir)? {
+        let entry = e?;
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        let Some((sensor, channel_name)) = SensorType::parse_input_file(&file_name) else {
+            continue;
+        };
+        let label_path = dir.join(format!("{channel_name}_label"));
+        let label = if label_path.exists() {
+            Some(read_trimmed(&label_path)?)
+        } else {
+            None
+        };
+        channels.push(Channel {
+            sensor,
+            name: channel_name.to_owned(),
+            label,
+            input_path: entry.path(),
+        });
+    }
+    channels.sort_by(|a, b| (a.sensor.file_prefix(), &a.name).cmp(&(b.sensor.file_prefix(), &b.name)));
+    Ok(Chip {
+        id: name.clone(),
+        name,
+        channels,
+    })
+}
+
+fn read_trimmed(path: &Path) -> anyhow::Result<String> {
+    let content = fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    Ok(content.trim().to_owned())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{fs, path::Path};
+
+    use super::{all_chips, SensorType};
+
+    /// Creates a fake hwmon tree with three chips.
+    pub fn fake_hwmon(root: &Path) {
+        let write = |path: &str, content: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write("hwmon0/name", "coretemp\n");
+        write("hwmon0/temp1_input", "45000\n");
+        write("hwmon0/temp1_label", "Package id 0\n");
+        write("hwmon0/temp2_input", "43000\n");
+        write("hwmon0/temp2_label", "Core 0\n");
+        write("hwmon0/temp1_crit", "100000\n");
+
+        write("hwmon1/device/name", "acpi_power\n");
+        write("hwmon1/device/power1_input", "12500000\n");
+        write("hwmon1/device/energy1_input", "1000000\n");
+        write("hwmon1/device/in0_input", "12000\n");
+        write("hwmon1/device/curr1_input", "-250\n");
+        write("hwmon1/device/fan1_input", "1200\n");
+
+        write("hwmon2/name", "coretemp\n");
+        write("hwmon2/temp1_input", "-5000\n");
+    }
+
+    #[test]
+    fn parse_input_file() {
+        assert_eq!(
+            SensorType::parse_input_file("temp12_input"),
+            Some((SensorType::Temperature, "temp12"))
+        );
+        assert_eq!(
+            SensorType::parse_input_file("in0_input"),
+            Some((SensorType::Voltage, "in0"))
+        );
+        assert_eq!(SensorType::parse_input_file("temp1_label"), None);
+        assert_eq!(SensorType::parse_input_file("intrusion0_input"), None);
+        assert_eq!(SensorType::parse_input_file("power_input"), None);
+    }
+
+    #[test]
+    fn discover_chips() {
+        let root = tempfile::tempdir().unwrap();
+        fake_hwmon(root.path());
+        let chips = all_chips(root.path()).unwrap();
+
+        let ids: Vec<&str> = chips.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, vec!["coretemp-0", "acpi_power", "coretemp-1"]);
+
+        let channels: Vec<(SensorType, &str, &str)> = chips[0]
+            .channels
+            .iter()
+            .map(|c| (c.sensor, c.name.as_str(), c.label_or_name()))
+            .collect();
+        assert_eq!(
+            channels,
+            vec![
+                (SensorType::Temperature, "temp1", "Package id 0"),
+                (SensorType::Temperature, "temp2", "Core 0"),
+            ]
+        );
+
+        let channels: Vec<&str> = chips[1].channels.iter().map(|c| c.label_or_name()).collect();
+        assert_eq!(channels, vec!["curr1", "energy1", "fan1", "in0", "power1"]);
+        assert!(chips[1].channels[0].input_path.ends_with("hwmon1/device/curr1_input"));
+    }
+}
@@ -2,11 +2,7 @@
 
 use alumet::measurement::Timestamp;
 use reqwest::{header, Url};
-use std::{
-    borrow::Cow,
-    fmt::Write,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::{borrow::Cow, fmt::Write};
 
 /// Client for InfluxDB v2.
 pub struct Client {
@@ -174,10 +170,7 @@ impl LineProtocolBuilder {
     ///
     /// Must be called after `field`. Required.
     pub fn timestamp(&mut self, timestamp: Timestamp) -> &mut Self {
-        let nanoseconds = SystemTime::from(timestamp)
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
+        let nanoseconds = timestamp.to_unix_nanos();
         write!(self.buf, " {nanoseconds}").unwrap();
         self
     }
@@ -134,6 +134,17 @@ impl Output for InfluxDbOutput {
                     builder.tag(tag_key, tag_value);
                 }
             }
+            // The static labels of the metric are tags too, unless an attribute has the same key.
+            for (label_key, label_value) in &metric.labels {
+                if m.attributes().any(|(key, _)| key == label_key) {
+                    continue;
+                }
+                if RESERVED_TAGS.contains(&label_key.as_str()) {
+                    builder.tag(&format!("alumet_attribute__{label_key}"), label_value);
+                } else {
+                    builder.tag(label_key, label_value);
+                }
+            }
             // Append fields.
             for (field_key, field_value) in fields {
                 let field_key = if field_key == RESERVED_FIELD {
@@ -146,14 +157,32 @@ impl Output for InfluxDbOutput {
                     AttributeValue::U64(v) => builder.field_uint(field_key, *v),
                     AttributeValue::Bool(v) => builder.field_bool(field_key, *v),
                     AttributeValue::Str(v) => builder.field_string(field_key, v),
+                    AttributeValue::Shared(v) => builder.field_string(field_key, v),
                     AttributeValue::String(v) => builder.field_string(field_key, v),
                 };
             }
 
             // Alumet value is a field.
-            match m.value {
-                WrappedMeasurementValue::F64(v) => builder.field_float("value", v),
-                WrappedMeasurementValue::U64(v) => builder.field_uint("value", v),
+            match &m.value {
+                WrappedMeasurementValue::F64(v) => builder.field_float("value", *v),
+                WrappedMeasurementValue::U64(v) => builder.field_uint("value", *v),
+                WrappedMeasurementValue::I64(v) => builder.field_int("value", *v),
+                WrappedMeasurementValue::Bool(v) => builder.field_bool("value", *v),
+                WrappedMeasurementValue::Histogram(h) => {
+                    // Like Prometheus histograms: one field per bucket, with the cumulative count.
+                    let mut cumulative = 0;
+                    for (bound, count) in h.buckets() {
+                        cumulative += count;
+                        let key = if bound == f64::INFINITY {
+                            String::from("value_le_+Inf")
+                        } else {
+                            format!("value_le_{bound}")
+                        };
+                        builder.field_uint(&key, cumulative);
+                    }
+                    builder.field_float("value_sum", h.sum());
+                    builder.field_uint("value_count", cumulative)
+                }
             };
 
             // And the timestamp comes last.
@@ -124,6 +124,9 @@ impl Output for MongoDbOutput {
                     AttributeValue::Str(v) => {
                         doc.insert(field_key, v);
                     }
+                    AttributeValue::Shared(v) => {
+                        doc.insert(field_key, v.as_ref());
+                    }
                     AttributeValue::String(v) => {
                         doc.insert(field_key, v);
                     }
@@ -131,13 +134,41 @@ impl Output for MongoDbOutput {
             }
 
             // Append alumet value
-            match m.value {
+            match &m.value {
                 WrappedMeasurementValue::F64(v) => {
                     doc.insert("value", v.to_string());
                 }
                 WrappedMeasurementValue::U64(v) => {
                     doc.insert("value", format!("{v}u"));
                 }
+                WrappedMeasurementValue::I64(v) => {
+                    doc.insert("value", format!("{v}i"));
+                }
+                WrappedMeasurementValue::Bool(v) => {
+                    doc.insert("value", if *v { "T" } else { "F" });
+                }
+                WrappedMeasurementValue::Histogram(h) => {
+                    // Like Prometheus histograms: each bucket holds the cumulative count.
+                    let mut cumulative = 0;
+                    let mut buckets = Vec::with_capacity(h.counts().len());
+                    for (bound, count) in h.buckets() {
+                        cumulative += count;
+                        let le = if bound == f64::INFINITY {
+                            String::from("+Inf")
+                        } else {
+                            bound.to_string()
+                        };
+                        buckets.push(doc! { "le": le, "count": format!("{cumulative}u") });
+                    }
+                    doc.insert(
+                        "value",
+                        doc! {
+                            "sum": h.sum().to_string(),
+                            "count": format!("{cumulative}u"),
+                            "buckets": buckets,
+                        },
+                    );
+                }
             }
 
             // Add the timestamp
@@ -1,5 +1,4 @@
 use alumet::measurement::Timestamp;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::Config;
 
@@ -17,9 +16,6 @@ pub fn build_mongo_uri(config: &Config) -> String {
 }
 
 pub fn convert_timestamp(timestamp: Timestamp) -> String {
-    let nanoseconds = SystemTime::from(timestamp)
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
+    let nanoseconds = timestamp.to_unix_nanos();
     format!("{nanoseconds}")
 }
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    metrics::{Metric, MetricKind, RawMetricId},
+    pipeline::elements::transform::builder::{TransformBuildContext, TransformRegistration},
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+    units::PrefixedUnit,
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use transform::{Normalization, NormalizeUnitsTransform};
+
+mod transform;
+
+pub struct NormalizeUnitsPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for NormalizeUnitsPlugin {
+    fn name() -> &'static str {
+        "normalize-units"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(NormalizeUnitsPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        if config.metrics.is_empty() {
+            log::warn!(
+                "No metric to normalize, the normalize-units plugin will do nothing. Add some metrics to its config."
+            );
+            return Ok(());
+        }
+
+        // Parse the units now, to report errors as early as possible.
+        let mut targets = Vec::with_capacity(config.metrics.len());
+        for m in &config.metrics {
+            let unit: PrefixedUnit = m
+                .unit
+                .parse()
+                .with_context(|| format!("invalid target unit for metric {}", m.metric))?;
+            targets.push(unit);
+        }
+
+        // The conversion depends on the unit of the metrics, which are registered by other plugins.
+        // Therefore, the converted metrics are registered when the transform is built.
+        alumet.add_transform_builder(move |ctx| {
+            let normalizations = register_converted_metrics(ctx, &config.metrics, targets)?;
+            Ok(TransformRegistration {
+                name: ctx.transform_name("normalize-units"),
+                transform: Box::new(NormalizeUnitsTransform::new(normalizations, config.keep_original)),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Registers the converted metrics, and returns how to compute them.
+fn register_converted_metrics(
+    ctx: &mut dyn TransformBuildContext,
+    metrics: &[MetricConfig],
+    targets: Vec<PrefixedUnit>,
+) -> anyhow::Result<HashMap<RawMetricId, Normalization>> {
+    let mut normalizations = HashMap::with_capacity(metrics.len());
+    for (config, target) in metrics.iter().zip(targets) {
+        // Find the metric and define the converted metric.
+        let (source, metric) = ctx
+            .metric_by_name(&config.metric)
+            .with_context(|| format!("metric not found: {}", config.metric))?;
+        if matches!(
+            metric.value_type,
+            WrappedMeasurementType::Bool | WrappedMeasurementType::Histogram
+        ) {
+            return Err(anyhow!(
+                "metric {} has type {}, its unit cannot be converted",
+                config.metric,
+                metric.value_type
+            ));
+        }
+        let conversion = metric
+            .unit
+            .conversion_to(&target)
+            .with_context(|| format!("cannot normalize metric {}", config.metric))?;
+        // An offset only makes sense for absolute values, not for increments.
+        if conversion.offset != 0.0 && metric.kind != MetricKind::Gauge {
+            return Err(anyhow!(
+                "cannot normalize metric {}: the conversion from {} to {} has an offset, which cannot be applied to a {}",
+                config.metric,
+                metric.unit.unique_name(),
+                target.unique_name(),
+                metric.kind
+            ));
+        }
+        let converted = Metric {
+            name: config
+                .converted_metric
+                .clone()
+                .unwrap_or_else(|| format!("{}_normalized", metric.name)),
+            description: format!("{} (converted to {})", metric.description, target.display_name()),
+            value_type: WrappedMeasurementType::F64,
+            unit: target,
+            kind: metric.kind,
+            labels: metric.labels.clone(),
+        };
+        let converted_metric = ctx
+            .create_metric(converted)
+            .map_err(|e| anyhow!("could not register the metric converted from {}: {e}", config.metric))?;
+        normalizations.insert(
+            source,
+            Normalization {
+                converted_metric,
+                conversion,
+            },
+        );
+    }
+    Ok(normalizations)
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Keep the original measurements, in addition to the converted ones.
+    keep_original: bool,
+    metrics: Vec<MetricConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetricConfig {
+    /// Name of the metric to convert.
+    metric: String,
+    /// The target unit, for instance `J` or `kW.h`.
+    unit: String,
+    /// Name of the converted metric. By default, `_normalized` is appended to the name of the metric.
+    #[serde(default)]
+    converted_metric: Option<String>,
+}
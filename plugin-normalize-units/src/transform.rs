@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::{
+        elements::{error::TransformError, transform::TransformContext},
+        Transform,
+    },
+    units::UnitConversion,
+};
+
+/// How to convert the values of a metric.
+pub struct Normalization {
+    /// The metric that holds the converted values.
+    pub converted_metric: RawMetricId,
+    pub conversion: UnitConversion,
+}
+
+/// Converts the values of some metrics to another unit.
+pub struct NormalizeUnitsTransform {
+    /// The metrics to convert, by metric id.
+    normalizations: HashMap<RawMetricId, Normalization>,
+    /// Keep the original measurements, in addition to the converted ones.
+    keep_original: bool,
+}
+
+impl NormalizeUnitsTransform {
+    pub fn new(normalizations: HashMap<RawMetricId, Normalization>, keep_original: bool) -> Self {
+        Self {
+            normalizations,
+            keep_original,
+        }
+    }
+
+    fn process(&self, measurements: &mut MeasurementBuffer) {
+        let normalizations = &self.normalizations;
+        if self.keep_original {
+            let mut converted = Vec::new();
+            for point in measurements.iter() {
+                if let Some(n) = normalizations.get(&point.metric) {
+                    let mut point = point.clone();
+                    if convert(&mut point, n) {
+                        converted.push(point);
+                    }
+                }
+            }
+            for point in converted {
+                measurements.push(point);
+            }
+        } else {
+            for point in measurements.iter_mut() {
+                if let Some(n) = normalizations.get(&point.metric) {
+                    convert(point, n);
+                }
+            }
+        }
+    }
+}
+
+/// Moves the point to the converted metric, and converts its value.
+///
+/// Returns `false` if the value is not a number, in which case the point is left untouched.
+fn convert(point: &mut MeasurementPoint, normalization: &Normalization) -> bool {
+    let Some(value) = point.value.as_f64() else {
+        log::debug!("Ignoring value of {:?} that is not a number", point.metric);
+        return false;
+    };
+    point.metric = normalization.converted_metric;
+    point.value = WrappedMeasurementValue::F64(normalization.conversion.apply(value));
+    true
+}
+
+impl Transform for NormalizeUnitsTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::UNIX_EPOCH};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+
+    use super::{Normalization, NormalizeUnitsTransform};
+
+    fn transform(keep_original: bool) -> NormalizeUnitsTransform {
+        let normalizations = HashMap::from([
+            (
+                RawMetricId::from_u64(0),
+                Normalization {
+                    converted_metric: RawMetricId::from_u64(10),
+                    conversion: PrefixedUnit::micro(Unit::Joule)
+                        .conversion_to(&Unit::Joule.into())
+                        .unwrap(),
+                },
+            ),
+            (
+                RawMetricId::from_u64(1),
+                Normalization {
+                    converted_metric: RawMetricId::from_u64(11),
+                    conversion: PrefixedUnit::from(Unit::DegreeFahrenheit)
+                        .conversion_to(&Unit::DegreeCelsius.into())
+                        .unwrap(),
+                },
+            ),
+        ]);
+        NormalizeUnitsTransform::new(normalizations, keep_original)
+    }
+
+    fn buffer() -> MeasurementBuffer {
+        let point = |metric: u64, value: WrappedMeasurementValue| {
+            MeasurementPoint::new_untyped(
+                Timestamp::from(UNIX_EPOCH),
+                RawMetricId::from_u64(metric),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                value,
+            )
+            .with_attr("domain", "package")
+        };
+        MeasurementBuffer::from(vec![
+            point(0, WrappedMeasurementValue::U64(2_500_000)),
+            point(1, WrappedMeasurementValue::F64(212.0)),
+            point(2, WrappedMeasurementValue::U64(7)),
+        ])
+    }
+
+    fn values(buf: &MeasurementBuffer) -> Vec<(u64, String)> {
+        buf.iter().map(|p| (p.metric.as_u64(), p.value.to_string())).collect()
+    }
+
+    fn expected(values: &[(u64, &str)]) -> Vec<(u64, String)> {
+        values.iter().map(|(m, v)| (*m, v.to_string())).collect()
+    }
+
+    #[test]
+    fn replace() {
+        let mut buf = buffer();
+        transform(false).process(&mut buf);
+        assert_eq!(values(&buf), expected(&[(10, "2.5"), (11, "100"), (2, "7")]));
+        assert!(buf.iter().all(|p| p.attributes_keys().eq(["domain"])));
+    }
+
+    #[test]
+    fn keep_original() {
+        let mut buf = buffer();
+        transform(true).process(&mut buf);
+        assert_eq!(
+            values(&buf),
+            expected(&[(0, "2500000"), (1, "212"), (2, "7"), (10, "2.5"), (11, "100")])
+        );
+    }
+}
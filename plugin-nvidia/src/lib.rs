@@ -63,6 +63,8 @@ impl NvidiaPlugin {
     /// For Jetson edge devices, use [`start_jetson`] instead.
     #[cfg(feature = "nvml")]
     fn start_nvml(&self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
+        use alumet::resources::{topology::pci_device_numa_node, Resource};
+
         let nvml = nvml::NvmlDevices::detect(true)?;
         let stats = nvml.detection_stats();
         if stats.found_devices == 0 {
@@ -94,6 +96,17 @@ impl NvidiaPlugin {
 
         for maybe_device in nvml.devices {
             if let Some(device) = maybe_device {
+                // Attach the GPU to its NUMA node, for the transforms that need the topology.
+                match pci_device_numa_node(&device.bus_id) {
+                    Ok(Some(node)) => {
+                        let gpu = Resource::Gpu {
+                            bus_id: device.bus_id.clone().into(),
+                        };
+                        alumet.add_resource_link(Resource::NumaNode { id: node }, gpu);
+                    }
+                    Ok(None) => (),
+                    Err(e) => log::debug!("Could not find the NUMA node of NVML device {}: {e:#}", device.bus_id),
+                }
                 let source = nvml::NvmlSource::new(device, metrics.clone())?;
                 let trigger = TriggerSpec::builder(self.config.poll_interval)
                     .flush_interval(self.config.flush_interval)
@@ -2,7 +2,7 @@ use std::fmt::Display;
 use std::sync::Arc;
 
 use alumet::measurement::Timestamp;
-use alumet::metrics::MetricCreationError;
+use alumet::metrics::{MetricCreationError, MetricKind};
 use alumet::resources::ResourceConsumer;
 use alumet::units::PrefixedUnit;
 use alumet::{
@@ -216,8 +216,9 @@ pub struct Metrics {
 impl Metrics {
     pub fn new(alumet: &mut AlumetPluginStart) -> Result<Self, MetricCreationError> {
         Ok(Self {
-            total_energy_consumption: alumet.create_metric(
+            total_energy_consumption: alumet.create_metric_with_kind(
                 "nvml_energy_consumption",
+                MetricKind::Delta,
                 PrefixedUnit::milli(Unit::Joule),
                 "energy consumption by the GPU (including memory) since the previous measurement",
             )?,
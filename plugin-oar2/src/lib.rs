@@ -6,7 +6,7 @@ use alumet::{
         rust::{deserialize_config, serialize_config, AlumetPlugin},
         AlumetPluginStart, AlumetPostStart, ConfigTable,
     },
-    resources::{Resource, ResourceConsumer},
+    resources::{consumers::ConsumerResolver, Resource, ResourceConsumer},
     units::{PrefixedUnit, Unit},
 };
 use anyhow::Context;
@@ -15,7 +15,8 @@ use serde::{Deserialize, Serialize};
 use std::{
     fs::{self, File},
     io::{Read, Seek},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::Arc,
     time::Duration,
 };
 
@@ -131,6 +132,7 @@ impl AlumetPlugin for Oar2Plugin {
                 });
 
                 alumet.add_source(initial_source, TriggerSpec::at_interval(self.config.poll_interval));
+                register_job(&alumet.consumer_resolver(), &cpu_job_path, job_id);
             }
         }
         Ok(())
@@ -144,9 +146,11 @@ impl AlumetPlugin for Oar2Plugin {
         let cpu_metric = metrics.cpu_metric;
         let memory_metric = metrics.memory_metric;
         let poll_interval = self.config.poll_interval;
+        let consumers = alumet.consumer_resolver();
 
         struct JobDetector {
             config_path: PathBuf,
+            consumers: Arc<ConsumerResolver>,
             cpu_metric: TypedMetricId<u64>,
             memory_metric: TypedMetricId<u64>,
             control_handle: ScopedControlHandle,
@@ -186,6 +190,7 @@ impl AlumetPlugin for Oar2Plugin {
                                     job_id,
                                 });
 
+                                register_job(&job_detect.consumers, &cpu_path, job_id);
                                 let source_name = job_name.to_string();
 
                                 job_detect
@@ -215,6 +220,15 @@ impl AlumetPlugin for Oar2Plugin {
                             log::error!("Unable to handle event on {}: {}", path.display(), e);
                         }
                     }
+                } else if let Ok(Event {
+                    kind: EventKind::Remove(_),
+                    paths,
+                    ..
+                }) = event
+                {
+                    for path in paths.iter().filter_map(|p| p.to_str()) {
+                        self.consumers.remove_cgroup(path);
+                    }
                 } else if let Err(e) = event {
                     log::error!("watch error: {:?}", e);
                 }
@@ -223,6 +237,7 @@ impl AlumetPlugin for Oar2Plugin {
 
         let handler = JobDetector {
             config_path: config_path.clone(),
+            consumers,
             cpu_metric,
             memory_metric,
             control_handle,
@@ -243,6 +258,16 @@ impl AlumetPlugin for Oar2Plugin {
     }
 }
 
+/// Registers the OAR job as the owner of its cgroup.
+///
+/// With cgroup v1, the job has the same cgroup path in every hierarchy (`cpuacct`, `memory`...),
+/// therefore registering it once is enough.
+fn register_job(consumers: &ConsumerResolver, cgroup: &Path, job_id: u64) {
+    if let Some(cgroup) = cgroup.to_str() {
+        consumers.register_cgroup_owner(cgroup, ResourceConsumer::custom("oar_job", job_id.to_string()));
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         let mut path = PathBuf::new();
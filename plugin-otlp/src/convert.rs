@@ -0,0 +1,552 @@
+//! Conversion of Alumet measurements to OTLP messages.
+
+use std::collections::HashMap;
+
+use alumet::{
+    measurement::{
+        AttributeValue, Histogram, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType,
+        WrappedMeasurementValue,
+    },
+    metrics::{Metric, MetricKind, RawMetricId},
+    units::{PrefixedUnit, Unit, UnitPrefix},
+};
+
+use crate::proto::{
+    self, any_value, metric, number_data_point, AggregationTemporality, ExportMetricsServiceRequest,
+    HistogramDataPoint, InstrumentationScope, KeyValue, NumberDataPoint, ResourceMetrics, ScopeMetrics,
+};
+
+/// Builds OTLP requests from Alumet measurements.
+pub struct RequestBuilder {
+    /// Attributes that are added to every OTLP resource, such as `service.name`.
+    common_attributes: Vec<KeyValue>,
+    scope: InstrumentationScope,
+    /// Start of the cumulative series, in nanoseconds since the Unix epoch.
+    start_time: u64,
+    /// Timestamp of the last point of each delta series, which is the start of its next point.
+    delta_starts: HashMap<SeriesKey, u64>,
+}
+
+/// Identifies an OTLP resource: an Alumet resource and consumer.
+type ResourceKey = (String, Option<String>, String, Option<String>);
+
+/// Identifies a series: a metric of a resource, with a set of attributes.
+type SeriesKey = (ResourceKey, RawMetricId, Vec<(String, String)>);
+
+impl RequestBuilder {
+    /// Creates a new builder. The cumulative series are considered to start at `start_time`.
+    pub fn new(common_attributes: Vec<KeyValue>, scope: InstrumentationScope, start_time: Timestamp) -> Self {
+        Self {
+            common_attributes,
+            scope,
+            start_time: unix_nanos(&start_time),
+            delta_starts: HashMap::new(),
+        }
+    }
+
+    /// Converts a buffer of measurements to an OTLP export request.
+    ///
+    /// Each couple (resource, consumer) is translated to an OTLP `Resource`, which gets its
+    /// own `ResourceMetrics`. Points that belong to an unknown metric are ignored.
+    ///
+    /// The points of sums and histograms get a start time: the start of the builder for the
+    /// cumulative series, and the time of the previous point of the series for the delta series.
+    /// Hence, the buffers must be built in order.
+    pub fn build<'m>(
+        &mut self,
+        measurements: &MeasurementBuffer,
+        metric_by_id: impl Fn(&RawMetricId) -> Option<&'m Metric>,
+    ) -> ExportMetricsServiceRequest {
+        let mut resources: Vec<ResourceMetrics> = Vec::new();
+        let mut resource_index: HashMap<ResourceKey, usize> = HashMap::new();
+        let mut metric_index: HashMap<(usize, RawMetricId), usize> = HashMap::new();
+
+        for point in measurements {
+            let Some(metric) = metric_by_id(&point.metric) else {
+                log::warn!("Ignoring point with unknown metric id {:?}", point.metric);
+                continue;
+            };
+
+            // find or create the OTLP resource
+            let key: ResourceKey = (
+                point.resource.kind().to_owned(),
+                point.resource.id_string(),
+                point.consumer.kind().to_owned(),
+                point.consumer.id_string(),
+            );
+            let r = *resource_index.entry(key.clone()).or_insert_with(|| {
+                resources.push(self.new_resource_metrics(point));
+                resources.len() - 1
+            });
+            let scope_metrics = &mut resources[r].scope_metrics[0];
+
+            // find or create the OTLP metric
+            let m = *metric_index.entry((r, point.metric)).or_insert_with(|| {
+                scope_metrics.metrics.push(convert_metric(metric));
+                scope_metrics.metrics.len() - 1
+            });
+            match (scope_metrics.metrics[m].data.as_mut(), &point.value) {
+                (Some(metric::Data::Histogram(h)), WrappedMeasurementValue::Histogram(value)) => {
+                    let start = self.delta_start(key, point);
+                    h.data_points.push(convert_histogram_point(point, metric, value, start))
+                }
+                (Some(metric::Data::Gauge(g)), _) => g.data_points.extend(convert_point(point, metric, 0)),
+                (Some(metric::Data::Sum(s)), _) => {
+                    let start = if s.aggregation_temporality == AggregationTemporality::Delta as i32 {
+                        self.delta_start(key, point)
+                    } else {
+                        self.start_time
+                    };
+                    s.data_points.extend(convert_point(point, metric, start))
+                }
+                (Some(metric::Data::Histogram(_)), value) => {
+                    log::warn!("Ignoring {value:?}: metric {} is a histogram", metric.name)
+                }
+                (None, _) => unreachable!("convert_metric always sets the data"),
+            }
+        }
+        ExportMetricsServiceRequest {
+            resource_metrics: resources,
+        }
+    }
+
+    /// Returns the start time of a point of a delta series, and remembers the end of the point.
+    ///
+    /// The first point of a series starts at the start of the builder.
+    fn delta_start(&mut self, resource: ResourceKey, point: &MeasurementPoint) -> u64 {
+        let mut attributes: Vec<(String, String)> = point
+            .attributes()
+            .map(|(key, value)| (key.to_owned(), value.to_string()))
+            .collect();
+        attributes.sort();
+        let time = unix_nanos(&point.timestamp);
+        let previous = self.delta_starts.insert((resource, point.metric, attributes), time);
+        previous.unwrap_or(self.start_time).min(time)
+    }
+
+    fn new_resource_metrics(&self, point: &MeasurementPoint) -> ResourceMetrics {
+        let mut attributes = self.common_attributes.clone();
+        attributes.push(KeyValue::string("alumet.resource.kind", point.resource.kind()));
+        if let Some(id) = point.resource.id_string() {
+            attributes.push(KeyValue::string("alumet.resource.id", id));
+        }
+        attributes.push(KeyValue::string("alumet.consumer.kind", point.consumer.kind()));
+        if let Some(id) = point.consumer.id_string() {
+            attributes.push(KeyValue::string("alumet.consumer.id", id));
+        }
+        ResourceMetrics {
+            resource: Some(proto::Resource {
+                attributes,
+                dropped_attributes_count: 0,
+            }),
+            scope_metrics: vec![ScopeMetrics {
+                scope: Some(self.scope.clone()),
+                metrics: Vec::new(),
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }
+    }
+}
+
+/// Creates an OTLP metric without any data point.
+///
+/// Deltas, such as the energy consumed since the previous measurement, are exported as monotonic
+/// sums with a delta temporality, and counters as monotonic sums with a cumulative temporality.
+/// Histograms are exported as OTLP histograms, with a delta temporality.
+/// Gauges are exported as gauges.
+fn convert_metric(metric: &Metric) -> proto::Metric {
+    let data = if metric.value_type == WrappedMeasurementType::Histogram {
+        metric::Data::Histogram(proto::Histogram {
+            data_points: Vec::new(),
+            aggregation_temporality: AggregationTemporality::Delta as i32,
+        })
+    } else {
+        let sum = |temporality: AggregationTemporality| {
+            metric::Data::Sum(proto::Sum {
+                data_points: Vec::new(),
+                aggregation_temporality: temporality as i32,
+                is_monotonic: true,
+            })
+        };
+        match metric.kind {
+            MetricKind::Delta => sum(AggregationTemporality::Delta),
+            MetricKind::Counter => sum(AggregationTemporality::Cumulative),
+            MetricKind::Gauge => metric::Data::Gauge(proto::Gauge {
+                data_points: Vec::new(),
+            }),
+        }
+    };
+    proto::Metric {
+        name: metric.name.clone(),
+        description: metric.description.clone(),
+        unit: ucum_unit(&metric.unit),
+        data: Some(data),
+    }
+}
+
+/// Converts a numeric point. Returns `None` if the value is not a number.
+fn convert_point(point: &MeasurementPoint, metric: &Metric, start_time: u64) -> Option<NumberDataPoint> {
+    let value = match point.value {
+        WrappedMeasurementValue::F64(v) => number_data_point::Value::AsDouble(v),
+        WrappedMeasurementValue::U64(v) => match i64::try_from(v) {
+            Ok(v) => number_data_point::Value::AsInt(v),
+            Err(_) => number_data_point::Value::AsDouble(v as f64),
+        },
+        WrappedMeasurementValue::I64(v) => number_data_point::Value::AsInt(v),
+        WrappedMeasurementValue::Bool(v) => number_data_point::Value::AsInt(i64::from(v)),
+        WrappedMeasurementValue::Histogram(_) => {
+            log::warn!("Ignoring histogram point of non-histogram metric {:?}", point.metric);
+            return None;
+        }
+    };
+    Some(NumberDataPoint {
+        attributes: convert_attributes(point, metric),
+        start_time_unix_nano: start_time,
+        time_unix_nano: unix_nanos(&point.timestamp),
+        value: Some(value),
+    })
+}
+
+fn convert_histogram_point(
+    point: &MeasurementPoint,
+    metric: &Metric,
+    value: &Histogram,
+    start_time: u64,
+) -> HistogramDataPoint {
+    HistogramDataPoint {
+        attributes: convert_attributes(point, metric),
+        start_time_unix_nano: start_time,
+        time_unix_nano: unix_nanos(&point.timestamp),
+        count: value.count(),
+        sum: Some(value.sum()),
+        bucket_counts: value.counts().to_vec(),
+        explicit_bounds: value.bounds().to_vec(),
+    }
+}
+
+/// Converts the attributes of a point and the static labels of its metric.
+///
+/// An attribute replaces the static label that has the same key.
+fn convert_attributes(point: &MeasurementPoint, metric: &Metric) -> Vec<KeyValue> {
+    let mut res: Vec<KeyValue> = point
+        .attributes()
+        .map(|(key, value)| KeyValue::new(key, convert_attribute(value)))
+        .collect();
+    for (key, value) in &metric.labels {
+        if !res.iter().any(|kv| &kv.key == key) {
+            res.push(KeyValue::string(key, value));
+        }
+    }
+    res
+}
+
+fn convert_attribute(value: &AttributeValue) -> any_value::Value {
+    match value {
+        AttributeValue::F64(v) => any_value::Value::DoubleValue(*v),
+        AttributeValue::U64(v) => match i64::try_from(*v) {
+            Ok(v) => any_value::Value::IntValue(v),
+            Err(_) => any_value::Value::StringValue(v.to_string()),
+        },
+        AttributeValue::Bool(v) => any_value::Value::BoolValue(*v),
+        AttributeValue::Str(v) => any_value::Value::StringValue(v.to_string()),
+        AttributeValue::Shared(v) => any_value::Value::StringValue(v.to_string()),
+        AttributeValue::String(v) => any_value::Value::StringValue(v.clone()),
+    }
+}
+
+fn unix_nanos(timestamp: &Timestamp) -> u64 {
+    // OTLP timestamps are unsigned: the times before the epoch cannot be represented
+    timestamp.to_unix_nanos().max(0) as u64
+}
+
+/// Returns the case-sensitive UCUM code of a unit, as expected by OpenTelemetry.
+///
+/// See <https://ucum.org/ucum#section-Prefixes>.
+pub fn ucum_unit(unit: &PrefixedUnit) -> String {
+    let prefix = match unit.prefix {
+        UnitPrefix::Nano => "n",
+        UnitPrefix::Micro => "u",
+        UnitPrefix::Milli => "m",
+        UnitPrefix::Plain => "",
+        UnitPrefix::Kilo => "k",
+        UnitPrefix::Mega => "M",
+        UnitPrefix::Giga => "G",
+    };
+    match &unit.base_unit {
+        // A prefix cannot be applied to the unity, use a power of ten instead.
+        Unit::Unity => match unit.prefix {
+            UnitPrefix::Nano => String::from("10*-9"),
+            UnitPrefix::Micro => String::from("10*-6"),
+            UnitPrefix::Milli => String::from("10*-3"),
+            UnitPrefix::Plain => String::from("1"),
+            UnitPrefix::Kilo => String::from("10*3"),
+            UnitPrefix::Mega => String::from("10*6"),
+            UnitPrefix::Giga => String::from("10*9"),
+        },
+        base => format!("{prefix}{}", base.unique_name()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use alumet::{
+        measurement::{
+            Histogram, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+        },
+        metrics::{Metric, MetricKind, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{ucum_unit, RequestBuilder};
+    use crate::proto::{any_value, metric, number_data_point, AggregationTemporality, InstrumentationScope, KeyValue};
+
+    #[test]
+    fn units() {
+        assert_eq!(ucum_unit(&PrefixedUnit::from(Unit::Joule)), "J");
+        assert_eq!(ucum_unit(&PrefixedUnit::micro(Unit::Joule)), "uJ");
+        assert_eq!(ucum_unit(&PrefixedUnit::milli(Unit::DegreeCelsius)), "mCel");
+        assert_eq!(ucum_unit(&PrefixedUnit::kilo(Unit::WattHour)), "kW.h");
+        assert_eq!(ucum_unit(&PrefixedUnit::from(Unit::Unity)), "1");
+        assert_eq!(ucum_unit(&PrefixedUnit::milli(Unit::Unity)), "10*-3");
+    }
+
+    #[test]
+    fn build_request() {
+        let metrics = HashMap::from([
+            (
+                RawMetricId::from_u64(0),
+                Metric {
+                    name: String::from("rapl_consumed_energy"),
+                    description: String::from("Energy consumed"),
+                    value_type: WrappedMeasurementType::F64,
+                    unit: PrefixedUnit::from(Unit::Joule),
+                    kind: MetricKind::Delta,
+                    labels: vec![],
+                },
+            ),
+            (
+                RawMetricId::from_u64(1),
+                Metric {
+                    name: String::from("memory_usage"),
+                    description: String::from("Memory usage"),
+                    value_type: WrappedMeasurementType::U64,
+                    unit: PrefixedUnit::from(Unit::Byte),
+                    kind: MetricKind::Gauge,
+                    labels: vec![(String::from("memory_kind"), String::from("resident"))],
+                },
+            ),
+        ]);
+        let t = Timestamp::from(UNIX_EPOCH + Duration::from_millis(1500));
+        let energy = RawMetricId::from_u64(0);
+        let memory = RawMetricId::from_u64(1);
+        let buf = MeasurementBuffer::from(vec![
+            MeasurementPoint::new_untyped(
+                t,
+                energy,
+                Resource::CpuPackage { id: 0 },
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::F64(12.5),
+            )
+            .with_attr("domain", "package"),
+            MeasurementPoint::new_untyped(
+                t,
+                memory,
+                Resource::LocalMachine,
+                ResourceConsumer::Process { pid: 1 },
+                WrappedMeasurementValue::U64(1024),
+            ),
+            MeasurementPoint::new_untyped(
+                t,
+                energy,
+                Resource::CpuPackage { id: 0 },
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::F64(2.0),
+            ),
+            // unknown metric, ignored
+            MeasurementPoint::new_untyped(
+                t,
+                RawMetricId::from_u64(2),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(0),
+            ),
+        ]);
+
+        let mut builder = RequestBuilder::new(
+            vec![KeyValue::string("service.name", "alumet")],
+            InstrumentationScope {
+                name: String::from("alumet"),
+                version: String::from("test"),
+            },
+            Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)),
+        );
+        let request = builder.build(&buf, |id| metrics.get(id));
+        assert_eq!(request.resource_metrics.len(), 2);
+
+        let cpu = &request.resource_metrics[0];
+        let attributes = &cpu.resource.as_ref().unwrap().attributes;
+        assert_eq!(
+            attributes,
+            &vec![
+                KeyValue::string("service.name", "alumet"),
+                KeyValue::string("alumet.resource.kind", "cpu_package"),
+                KeyValue::string("alumet.resource.id", "0"),
+                KeyValue::string("alumet.consumer.kind", "local_machine"),
+            ]
+        );
+        let metrics = &cpu.scope_metrics[0].metrics;
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].name, "rapl_consumed_energy");
+        assert_eq!(metrics[0].unit, "J");
+        let Some(metric::Data::Sum(sum)) = &metrics[0].data else {
+            panic!("energy should be a sum")
+        };
+        assert_eq!(sum.aggregation_temporality, AggregationTemporality::Delta as i32);
+        assert!(sum.is_monotonic);
+        assert_eq!(sum.data_points.len(), 2);
+        assert_eq!(sum.data_points[0].time_unix_nano, 1_500_000_000);
+        assert_eq!(sum.data_points[0].value, Some(number_data_point::Value::AsDouble(12.5)));
+        assert_eq!(
+            sum.data_points[0].attributes,
+            vec![KeyValue::new(
+                "domain",
+                any_value::Value::StringValue(String::from("package"))
+            )]
+        );
+
+        let process = &request.resource_metrics[1];
+        let metrics = &process.scope_metrics[0].metrics;
+        assert_eq!(metrics[0].unit, "By");
+        let Some(metric::Data::Gauge(gauge)) = &metrics[0].data else {
+            panic!("memory usage should be a gauge")
+        };
+        assert_eq!(gauge.data_points[0].value, Some(number_data_point::Value::AsInt(1024)));
+        assert_eq!(
+            gauge.data_points[0].attributes,
+            vec![KeyValue::string("memory_kind", "resident")]
+        );
+    }
+
+    #[test]
+    fn build_histogram() {
+        let metrics = HashMap::from([(
+            RawMetricId::from_u64(0),
+            Metric {
+                name: String::from("request_latency"),
+                description: String::new(),
+                value_type: WrappedMeasurementType::Histogram,
+                unit: PrefixedUnit::milli(Unit::Second),
+                kind: MetricKind::Gauge,
+                labels: vec![],
+            },
+        )]);
+        let mut histogram = Histogram::new(vec![10.0, 100.0]).unwrap();
+        histogram.record(5.0);
+        histogram.record(50.0);
+        histogram.record(20.0);
+        let buf = MeasurementBuffer::from(vec![MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_secs(2)),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::Histogram(histogram),
+        )]);
+
+        let mut builder = RequestBuilder::new(
+            Vec::new(),
+            InstrumentationScope {
+                name: String::from("alumet"),
+                version: String::from("test"),
+            },
+            Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)),
+        );
+        let request = builder.build(&buf, |id| metrics.get(id));
+        let metrics = &request.resource_metrics[0].scope_metrics[0].metrics;
+        let Some(metric::Data::Histogram(h)) = &metrics[0].data else {
+            panic!("latency should be a histogram")
+        };
+        let point = &h.data_points[0];
+        assert_eq!(point.start_time_unix_nano, 1_000_000_000);
+        assert_eq!(point.time_unix_nano, 2_000_000_000);
+        assert_eq!(point.count, 3);
+        assert_eq!(point.sum, Some(75.0));
+        assert_eq!(point.explicit_bounds, vec![10.0, 100.0]);
+        assert_eq!(point.bucket_counts, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn start_times() {
+        let metric = |name: &str, kind| Metric {
+            name: String::from(name),
+            description: String::new(),
+            value_type: WrappedMeasurementType::U64,
+            unit: PrefixedUnit::from(Unit::Joule),
+            kind,
+            labels: vec![],
+        };
+        let metrics = HashMap::from([
+            (RawMetricId::from_u64(0), metric("energy_delta", MetricKind::Delta)),
+            (RawMetricId::from_u64(1), metric("energy_total", MetricKind::Counter)),
+        ]);
+        let point = |secs: u64, metric: u64, domain: &str| {
+            MeasurementPoint::new_untyped(
+                Timestamp::from(UNIX_EPOCH + Duration::from_secs(secs)),
+                RawMetricId::from_u64(metric),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(1),
+            )
+            .with_attr("domain", domain.to_owned())
+        };
+        let mut builder = RequestBuilder::new(
+            Vec::new(),
+            InstrumentationScope {
+                name: String::from("alumet"),
+                version: String::from("test"),
+            },
+            Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)),
+        );
+        // (start, time) of the points of each metric
+        let mut build = |buf: Vec<MeasurementPoint>| {
+            let request = builder.build(&MeasurementBuffer::from(buf), |id| metrics.get(id));
+            let metrics = &request.resource_metrics[0].scope_metrics[0].metrics;
+            let times = |i: usize| {
+                let Some(metric::Data::Sum(sum)) = &metrics[i].data else {
+                    panic!("{} should be a sum", metrics[i].name)
+                };
+                let to_secs = |nanos: u64| nanos / 1_000_000_000;
+                sum.data_points
+                    .iter()
+                    .map(|p| (to_secs(p.start_time_unix_nano), to_secs(p.time_unix_nano)))
+                    .collect::<Vec<_>>()
+            };
+            (times(0), times(1))
+        };
+
+        let (delta, total) = build(vec![
+            point(2, 0, "package"),
+            point(2, 0, "dram"),
+            point(2, 1, "package"),
+            point(3, 0, "package"),
+        ]);
+        assert_eq!(delta, vec![(1, 2), (1, 2), (2, 3)]);
+        assert_eq!(total, vec![(1, 2)]);
+
+        // the series continue across the requests
+        let (delta, total) = build(vec![
+            point(5, 0, "dram"),
+            point(5, 1, "package"),
+            point(6, 0, "package"),
+        ]);
+        assert_eq!(delta, vec![(2, 5), (3, 6)]);
+        assert_eq!(total, vec![(1, 5)]);
+    }
+}
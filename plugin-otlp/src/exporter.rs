@@ -0,0 +1,311 @@
+//! OTLP/HTTP exporter, with compression and retries.
+//!
+//! See <https://opentelemetry.io/docs/specs/otlp/#otlphttp>.
+
+use std::{io::Write, time::Duration};
+
+use anyhow::{anyhow, Context};
+use flate2::{write::GzEncoder, Compression as GzLevel};
+use prost::Message;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::proto::ExportMetricsServiceRequest;
+
+/// Compression of the request body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Gzip,
+}
+
+/// How to retry the requests that have been rejected because the collector is overloaded or unavailable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the first attempt.
+    pub max_retries: u16,
+    /// Delay before the first retry. It is doubled after each retry.
+    #[serde(with = "humantime_serde")]
+    pub initial_delay: Duration,
+    /// Maximum delay between two attempts.
+    #[serde(with = "humantime_serde")]
+    pub max_delay: Duration,
+}
+
+/// Sends metrics to an OTLP collector with the HTTP/protobuf transport.
+pub struct Exporter {
+    client: reqwest::Client,
+    endpoint: String,
+    headers: HeaderMap,
+    compression: Compression,
+    retry: RetryPolicy,
+}
+
+impl Exporter {
+    pub fn new(
+        endpoint: String,
+        headers: impl IntoIterator<Item = (String, String)>,
+        compression: Compression,
+        timeout: Duration,
+        retry: RetryPolicy,
+    ) -> anyhow::Result<Self> {
+        let mut header_map = HeaderMap::new();
+        header_map.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/x-protobuf"));
+        if compression == Compression::Gzip {
+            header_map.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        }
+        for (key, value) in headers {
+            let name = HeaderName::try_from(&key).with_context(|| format!("invalid header name: {key}"))?;
+            let value = HeaderValue::try_from(&value).with_context(|| format!("invalid value for header {key}"))?;
+            header_map.insert(name, value);
+        }
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            client,
+            endpoint,
+            headers: header_map,
+            compression,
+            retry,
+        })
+    }
+
+    /// Exports the metrics to the collector.
+    ///
+    /// If the collector responds with a retryable status code (429, 502, 503 or 504),
+    /// or if it cannot be reached, the request is sent again according to the retry policy.
+    /// The `Retry-After` header is honored when it is present. If it asks for a delay that is longer
+    /// than the maximum delay of the policy, the request is not retried: the export is part of the
+    /// output loop, which must not stall for an arbitrary amount of time.
+    pub async fn export(&self, request: &ExportMetricsServiceRequest) -> anyhow::Result<()> {
+        let body = self.encode(request)?;
+        let mut delay = self.retry.initial_delay;
+        let mut n_retries = 0;
+        loop {
+            let res = self
+                .client
+                .post(&self.endpoint)
+                .headers(self.headers.clone())
+                .body(body.clone())
+                .send()
+                .await;
+            let wait = match res {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) if is_retryable(response.status()) => {
+                    let status = response.status();
+                    let wait = retry_after(&response).unwrap_or(delay);
+                    if n_retries >= self.retry.max_retries {
+                        return Err(anyhow!("OTLP collector is still unavailable ({status}), giving up"));
+                    }
+                    if wait > self.retry.max_delay {
+                        return Err(anyhow!(
+                            "OTLP collector is unavailable ({status}) and asks to retry in {wait:?}, which is longer than the maximum delay ({:?}), giving up",
+                            self.retry.max_delay
+                        ));
+                    }
+                    log::warn!("OTLP collector responded with {status}, retrying in {wait:?}");
+                    wait
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let message = response.text().await.unwrap_or_default();
+                    return Err(anyhow!("OTLP collector rejected the metrics ({status}): {message}"));
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => {
+                    if n_retries >= self.retry.max_retries {
+                        return Err(anyhow!(e).context("OTLP collector is still unreachable, giving up"));
+                    }
+                    log::warn!("Failed to reach the OTLP collector ({e}), retrying in {delay:?}");
+                    delay
+                }
+                Err(e) => return Err(anyhow!(e).context("failed to send metrics to the OTLP collector")),
+            };
+            tokio::time::sleep(wait).await;
+            n_retries += 1;
+            delay = (delay * 2).min(self.retry.max_delay);
+        }
+    }
+
+    fn encode(&self, request: &ExportMetricsServiceRequest) -> anyhow::Result<Vec<u8>> {
+        let data = request.encode_to_vec();
+        match self.compression {
+            Compression::None => Ok(data),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), GzLevel::default());
+                encoder.write_all(&data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+/// Returns true if the request should be retried, as specified by OTLP/HTTP.
+fn is_retryable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Parses the `Retry-After` header, if it is given as a number of seconds.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(header::RETRY_AFTER)?;
+    let secs: u64 = value.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        io::Read,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use flate2::read::GzDecoder;
+    use http_body_util::{BodyExt, Full};
+    use hyper::{body::Bytes, server::conn::http1, service::service_fn, Request, Response, StatusCode};
+    use hyper_util::rt::TokioIo;
+    use prost::Message;
+    use tokio::net::TcpListener;
+
+    use super::{Compression, Exporter, RetryPolicy};
+    use crate::proto::{ExportMetricsServiceRequest, KeyValue, Resource, ResourceMetrics};
+
+    /// What the mock collector has received.
+    #[derive(Default)]
+    struct Received {
+        attempts: usize,
+        requests: Vec<ExportMetricsServiceRequest>,
+    }
+
+    /// Starts a mock collector that answers with the given status codes, then 200.
+    ///
+    /// If `retry_after` is set, the errors carry a `Retry-After` header with this number of seconds.
+    async fn mock_collector(statuses: Vec<u16>, retry_after: Option<u64>) -> (String, Arc<Mutex<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(Received::default()));
+        let state = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let state = state.clone();
+                let statuses = statuses.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        let state = state.clone();
+                        let statuses = statuses.clone();
+                        async move {
+                            assert_eq!(req.uri().path(), "/v1/metrics");
+                            assert_eq!(req.headers()["content-type"], "application/x-protobuf");
+                            assert_eq!(req.headers()["content-encoding"], "gzip");
+                            let body = req.into_body().collect().await.unwrap().to_bytes();
+                            let mut data = Vec::new();
+                            GzDecoder::new(&body[..]).read_to_end(&mut data).unwrap();
+                            let request = ExportMetricsServiceRequest::decode(&data[..]).unwrap();
+
+                            let mut state = state.lock().unwrap();
+                            let status = statuses.get(state.attempts).copied().unwrap_or(200);
+                            state.attempts += 1;
+                            if status == 200 {
+                                state.requests.push(request);
+                            }
+                            let mut response = Response::builder().status(StatusCode::from_u16(status).unwrap());
+                            if let (Some(secs), false) = (retry_after, status == 200) {
+                                response = response.header("retry-after", secs.to_string());
+                            }
+                            let response = response.body(Full::new(Bytes::new())).unwrap();
+                            Ok::<_, Infallible>(response)
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        (format!("http://{addr}/v1/metrics"), received)
+    }
+
+    fn exporter(endpoint: String, max_retries: u16) -> Exporter {
+        let retry = RetryPolicy {
+            max_retries,
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+        };
+        Exporter::new(endpoint, [], Compression::Gzip, Duration::from_secs(5), retry).unwrap()
+    }
+
+    fn request() -> ExportMetricsServiceRequest {
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(Resource {
+                    attributes: vec![KeyValue::string("service.name", "alumet")],
+                    dropped_attributes_count: 0,
+                }),
+                scope_metrics: Vec::new(),
+                schema_url: String::new(),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn export_gzip() {
+        let (endpoint, received) = mock_collector(vec![], None).await;
+        exporter(endpoint, 0).export(&request()).await.unwrap();
+        let received = received.lock().unwrap();
+        assert_eq!(received.attempts, 1);
+        assert_eq!(received.requests, vec![request()]);
+    }
+
+    #[tokio::test]
+    async fn retry_on_unavailable() {
+        let (endpoint, received) = mock_collector(vec![503, 429], None).await;
+        exporter(endpoint, 3).export(&request()).await.unwrap();
+        let received = received.lock().unwrap();
+        assert_eq!(received.attempts, 3);
+        assert_eq!(received.requests, vec![request()]);
+    }
+
+    #[tokio::test]
+    async fn give_up_after_max_retries() {
+        let (endpoint, received) = mock_collector(vec![503, 503, 503], None).await;
+        let res = exporter(endpoint, 1).export(&request()).await;
+        assert!(res.is_err());
+        assert_eq!(received.lock().unwrap().attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn honor_retry_after() {
+        let (endpoint, received) = mock_collector(vec![503], Some(0)).await;
+        exporter(endpoint, 3).export(&request()).await.unwrap();
+        assert_eq!(received.lock().unwrap().attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn give_up_when_retry_after_is_too_long() {
+        let (endpoint, received) = mock_collector(vec![503], Some(3600)).await;
+        let start = std::time::Instant::now();
+        let res = exporter(endpoint, 3).export(&request()).await;
+        assert!(res.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(received.lock().unwrap().attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn no_retry_on_bad_request() {
+        let (endpoint, received) = mock_collector(vec![400], None).await;
+        let res = exporter(endpoint, 3).export(&request()).await;
+        assert!(res.is_err());
+        assert_eq!(received.lock().unwrap().attempts, 1);
+    }
+}
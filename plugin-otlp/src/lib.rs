@@ -0,0 +1,202 @@
+use std::{collections::HashMap, time::Duration};
+
+use alumet::{
+    measurement::{MeasurementBuffer, Timestamp},
+    pipeline::{
+        elements::output::{
+            builder::{AsyncOutputBuildContext, AsyncOutputRegistration},
+            AsyncOutputStream, StreamRecvError,
+        },
+        registry::MetricReader,
+    },
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+};
+use anyhow::anyhow;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::time::MissedTickBehavior;
+
+use convert::RequestBuilder;
+use exporter::{Compression, Exporter, RetryPolicy};
+use proto::{InstrumentationScope, KeyValue};
+
+mod convert;
+mod exporter;
+mod proto;
+
+/// Exports the measurements to an OpenTelemetry collector, with the OTLP/HTTP protocol.
+pub struct OtlpPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for OtlpPlugin {
+    fn name() -> &'static str {
+        "otlp"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(OtlpPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        if config.flush_interval.is_zero() {
+            return Err(anyhow!("flush_interval must not be zero"));
+        }
+
+        let exporter = Exporter::new(
+            config.endpoint,
+            config.headers,
+            config.compression,
+            config.timeout,
+            config.retry,
+        )?;
+        let request_builder = RequestBuilder::new(
+            vec![KeyValue::string("service.name", config.service_name)],
+            InstrumentationScope {
+                name: String::from("alumet"),
+                version: String::from(alumet::VERSION),
+            },
+            Timestamp::now(),
+        );
+        let batch = BatchConfig {
+            max_batch_size: config.max_batch_size,
+            flush_interval: config.flush_interval,
+        };
+
+        alumet.add_async_output_builder(move |ctx: &mut dyn AsyncOutputBuildContext, stream| {
+            let output = run_output(stream, ctx.metrics_reader(), exporter, request_builder, batch);
+            Ok(AsyncOutputRegistration {
+                name: ctx.output_name("exporter"),
+                output: Box::pin(output),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct BatchConfig {
+    max_batch_size: usize,
+    flush_interval: Duration,
+}
+
+/// Receives the measurements and exports them by batches, until the stream ends.
+///
+/// A batch is exported when it contains at least `max_batch_size` points, or when
+/// `flush_interval` has elapsed, whichever comes first.
+async fn run_output(
+    mut stream: AsyncOutputStream,
+    metrics: MetricReader,
+    exporter: Exporter,
+    mut request_builder: RequestBuilder,
+    batch: BatchConfig,
+) -> anyhow::Result<()> {
+    let mut pending = MeasurementBuffer::new();
+    let mut flush_timer = tokio::time::interval(batch.flush_interval);
+    flush_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            received = stream.0.next() => match received {
+                Some(Ok(mut measurements)) => {
+                    pending.merge(&mut measurements);
+                    if pending.len() >= batch.max_batch_size {
+                        export_batch(std::mem::take(&mut pending), &metrics, &mut request_builder, &exporter).await;
+                        flush_timer.reset();
+                    }
+                }
+                Some(Err(StreamRecvError::Lagged(n))) => {
+                    log::warn!("OTLP output is too slow, {n} buffers have been lost");
+                }
+                Some(Err(e)) => {
+                    log::error!("OTLP output failed to receive measurements: {e:?}");
+                }
+                None => break,
+            },
+            _ = flush_timer.tick() => {
+                if !pending.is_empty() {
+                    export_batch(std::mem::take(&mut pending), &metrics, &mut request_builder, &exporter).await;
+                }
+            }
+        }
+    }
+
+    // Export what remains before stopping.
+    if !pending.is_empty() {
+        export_batch(pending, &metrics, &mut request_builder, &exporter).await;
+    }
+    Ok(())
+}
+
+/// Exports a batch of measurements. Errors are logged and the batch is dropped.
+async fn export_batch(
+    measurements: MeasurementBuffer,
+    metrics: &MetricReader,
+    request_builder: &mut RequestBuilder,
+    exporter: &Exporter,
+) {
+    let request = {
+        let metrics = metrics.read().await;
+        request_builder.build(&measurements, |id| metrics.by_id(id))
+    };
+    if let Err(e) = exporter.export(&request).await {
+        log::error!("{} measurements could not be exported: {e:#}", measurements.len());
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// URL of the OTLP/HTTP metrics endpoint.
+    endpoint: String,
+    /// Additional HTTP headers, for instance for authentication.
+    headers: HashMap<String, String>,
+    /// Compression of the requests: `"gzip"` or `"none"`.
+    compression: Compression,
+    /// Value of the `service.name` resource attribute.
+    service_name: String,
+    /// Maximum number of points to accumulate before sending them.
+    max_batch_size: usize,
+    /// Maximum time to wait before sending the accumulated points.
+    #[serde(with = "humantime_serde")]
+    flush_interval: Duration,
+    /// Timeout of each HTTP request.
+    #[serde(with = "humantime_serde")]
+    timeout: Duration,
+    retry: RetryPolicy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            endpoint: String::from("http://localhost:4318/v1/metrics"),
+            headers: HashMap::new(),
+            compression: Compression::Gzip,
+            service_name: String::from("alumet"),
+            max_batch_size: 4096,
+            flush_interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(10),
+            retry: RetryPolicy {
+                max_retries: 5,
+                initial_delay: Duration::from_millis(500),
+                max_delay: Duration::from_secs(30),
+            },
+        }
+    }
+}
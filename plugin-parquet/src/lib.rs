@@ -0,0 +1,96 @@
+mod output;
+mod schema;
+
+use std::{path::PathBuf, time::Duration};
+
+use alumet::plugin::{
+    rust::{deserialize_config, serialize_config, AlumetPlugin},
+    ConfigTable,
+};
+use output::{ParquetOutput, Rotation};
+use parquet::{basic::Compression as ParquetCompression, file::properties::WriterProperties};
+use serde::{Deserialize, Serialize};
+
+pub struct ParquetPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for ParquetPlugin {
+    fn name() -> &'static str {
+        "parquet"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(ParquetPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        let compression = match config.compression {
+            Compression::None => ParquetCompression::UNCOMPRESSED,
+            Compression::Snappy => ParquetCompression::SNAPPY,
+        };
+        let properties = WriterProperties::builder()
+            .set_compression(compression)
+            .set_max_row_group_size(config.row_group_size)
+            .build();
+        let rotation = Rotation {
+            max_file_size: config.max_file_size,
+            max_duration: config.max_file_duration,
+        };
+        let output = ParquetOutput::new(config.output_dir, config.file_prefix, rotation, properties)?;
+        alumet.add_blocking_output(Box::new(output));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Directory where the Parquet files are written.
+    output_dir: PathBuf,
+    /// Prefix of the file names.
+    file_prefix: String,
+    /// Compression of the data pages.
+    compression: Compression,
+    /// Maximum number of rows in a row group. Rows are kept in memory until the row group is complete.
+    row_group_size: usize,
+    /// Start a new file when the current one reaches this size (in bytes).
+    max_file_size: Option<u64>,
+    /// Start a new file when the current one has been opened for this duration.
+    #[serde(with = "humantime_serde")]
+    max_file_duration: Option<Duration>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Compression {
+    None,
+    Snappy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            output_dir: PathBuf::from("alumet-output"),
+            file_prefix: String::from("alumet-measurements"),
+            compression: Compression::Snappy,
+            row_group_size: 65536,
+            max_file_size: Some(128 * 1024 * 1024),
+            max_file_duration: Some(Duration::from_secs(3600)),
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use alumet::{
+    measurement::MeasurementBuffer,
+    metrics::{Metric, RawMetricId},
+    pipeline::{
+        elements::{error::WriteError, output::OutputContext},
+        Output,
+    },
+};
+use anyhow::Context;
+use arrow_schema::SchemaRef;
+use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::schema;
+
+/// When to close the current Parquet file and start a new one.
+pub struct Rotation {
+    /// Maximum size of a file, in bytes.
+    pub max_file_size: Option<u64>,
+    /// Maximum time during which a file is written to.
+    pub max_duration: Option<Duration>,
+}
+
+/// Writes measurements to Parquet files, and starts a new file when the current one
+/// is too big or too old.
+pub struct ParquetOutput {
+    directory: PathBuf,
+    file_prefix: String,
+    rotation: Rotation,
+    properties: WriterProperties,
+    schema: SchemaRef,
+    /// Number of files that have been created, used to generate unique file names.
+    file_count: u64,
+    current: Option<CurrentFile>,
+}
+
+struct CurrentFile {
+    path: PathBuf,
+    writer: ArrowWriter<File>,
+    opened_at: Instant,
+}
+
+impl ParquetOutput {
+    pub fn new(
+        directory: PathBuf,
+        file_prefix: String,
+        rotation: Rotation,
+        properties: WriterProperties,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("could not create the output directory {}", directory.display()))?;
+        Ok(Self {
+            directory,
+            file_prefix,
+            rotation,
+            properties,
+            schema: schema::measurement_schema(),
+            file_count: 0,
+            current: None,
+        })
+    }
+
+    /// Writes the measurements to the current file, rotating it if needed.
+    fn write_measurements<'m>(
+        &mut self,
+        measurements: &MeasurementBuffer,
+        metric_by_id: impl Fn(&RawMetricId) -> Option<&'m Metric>,
+    ) -> anyhow::Result<()> {
+        if measurements.is_empty() {
+            return Ok(());
+        }
+        let batch = schema::to_record_batch(self.schema.clone(), measurements, metric_by_id)?;
+
+        if let Some(max_duration) = self.rotation.max_duration {
+            if self
+                .current
+                .as_ref()
+                .is_some_and(|f| f.opened_at.elapsed() >= max_duration)
+            {
+                self.close_current()?;
+            }
+        }
+        if self.current.is_none() {
+            self.current = Some(self.open_next()?);
+        }
+        let file = self.current.as_mut().unwrap();
+        file.writer.write(&batch)?;
+
+        if let Some(max_size) = self.rotation.max_file_size {
+            let size = file.writer.bytes_written() + file.writer.in_progress_size();
+            if size as u64 >= max_size {
+                self.close_current()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn open_next(&mut self) -> anyhow::Result<CurrentFile> {
+        let path = next_file_path(&self.directory, &self.file_prefix, self.file_count);
+        self.file_count += 1;
+        let file = File::create(&path).with_context(|| format!("could not create {}", path.display()))?;
+        let writer = ArrowWriter::try_new(file, self.schema.clone(), Some(self.properties.clone()))?;
+        log::debug!("Writing measurements to {}", path.display());
+        Ok(CurrentFile {
+            path,
+            writer,
+            opened_at: Instant::now(),
+        })
+    }
+
+    /// Closes the current file, if any. This writes the Parquet footer.
+    fn close_current(&mut self) -> anyhow::Result<()> {
+        if let Some(file) = self.current.take() {
+            file.writer
+                .close()
+                .with_context(|| format!("could not finish writing {}", file.path.display()))?;
+            log::debug!("Closed {}", file.path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Generates the path of a new file, of the form `{prefix}-{date}T{time}-{seq}.parquet`.
+fn next_file_path(directory: &Path, prefix: &str, seq: u64) -> PathBuf {
+    let format = format_description!("[year][month][day]T[hour][minute][second]");
+    let now = OffsetDateTime::now_utc().format(&format).unwrap();
+    directory.join(format!("{prefix}-{now}-{seq:04}.parquet"))
+}
+
+impl Output for ParquetOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        self.write_measurements(measurements, |id| ctx.metrics.by_id(id))?;
+        Ok(())
+    }
+}
+
+impl Drop for ParquetOutput {
+    fn drop(&mut self) {
+        // A Parquet file without its footer cannot be read, make sure that it is written.
+        if let Err(e) = self.close_current() {
+            log::error!("{e:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fs::File,
+        path::PathBuf,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use alumet::{
+        measurement::{
+            MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+        },
+        metrics::{Metric, MetricKind, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use arrow_array::{
+        cast::AsArray,
+        types::{Float64Type, TimestampNanosecondType, UInt64Type},
+        Array, RecordBatch,
+    };
+    use parquet::{arrow::arrow_reader::ParquetRecordBatchReaderBuilder, file::properties::WriterProperties};
+
+    use super::{ParquetOutput, Rotation};
+
+    fn metrics() -> HashMap<RawMetricId, Metric> {
+        HashMap::from([
+            (
+                RawMetricId::from_u64(0),
+                Metric {
+                    name: String::from("energy"),
+                    description: String::new(),
+                    value_type: WrappedMeasurementType::F64,
+                    unit: PrefixedUnit::from(Unit::Joule),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
+                },
+            ),
+            (
+                RawMetricId::from_u64(1),
+                Metric {
+                    name: String::from("memory"),
+                    description: String::new(),
+                    value_type: WrappedMeasurementType::U64,
+                    unit: PrefixedUnit::from(Unit::Byte),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
+                },
+            ),
+        ])
+    }
+
+    fn buffer(t: u64) -> MeasurementBuffer {
+        let timestamp = Timestamp::from(UNIX_EPOCH + Duration::from_secs(t));
+        MeasurementBuffer::from(vec![
+            MeasurementPoint::new_untyped(
+                timestamp,
+                RawMetricId::from_u64(0),
+                Resource::CpuPackage { id: 1 },
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::F64(1.5),
+            )
+            .with_attr("domain", "package"),
+            MeasurementPoint::new_untyped(
+                timestamp,
+                RawMetricId::from_u64(1),
+                Resource::LocalMachine,
+                ResourceConsumer::Process { pid: 7 },
+                WrappedMeasurementValue::U64(4096),
+            ),
+        ])
+    }
+
+    fn read_dir(dir: &std::path::Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+        files.sort();
+        files
+    }
+
+    fn read_parquet(path: &std::path::Path) -> Vec<RecordBatch> {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        reader.map(|b| b.unwrap()).collect()
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let metrics = metrics();
+        let rotation = Rotation {
+            max_file_size: None,
+            max_duration: None,
+        };
+        let mut output = ParquetOutput::new(
+            dir.path().to_owned(),
+            String::from("test"),
+            rotation,
+            WriterProperties::default(),
+        )
+        .unwrap();
+        output.write_measurements(&buffer(1), |id| metrics.get(id)).unwrap();
+        output.write_measurements(&buffer(2), |id| metrics.get(id)).unwrap();
+        drop(output);
+
+        let files = read_dir(dir.path());
+        assert_eq!(files.len(), 1);
+        let name = files[0].file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("test-") && name.ends_with("-0000.parquet"), "{name}");
+
+        let batches = read_parquet(&files[0]);
+        let batch = single_batch(&batches);
+        assert_eq!(batch.num_rows(), 4);
+
+        let timestamps = batch.column(0).as_primitive::<TimestampNanosecondType>();
+        assert_eq!(timestamps.value(0), 1_000_000_000);
+        assert_eq!(timestamps.value(3), 2_000_000_000);
+        let names = batch.column(1).as_string::<i32>();
+        assert_eq!(names.value(0), "energy");
+        assert_eq!(names.value(1), "memory");
+        let values_f64 = batch.column(2).as_primitive::<Float64Type>();
+        let values_u64 = batch.column(3).as_primitive::<UInt64Type>();
+        assert_eq!(values_f64.value(0), 1.5);
+        assert!(values_u64.is_null(0));
+        assert!(values_f64.is_null(1));
+        assert_eq!(values_u64.value(1), 4096);
+        assert_eq!(batch.column(6).as_string::<i32>().value(0), "cpu_package");
+        assert_eq!(batch.column(7).as_string::<i32>().value(0), "1");
+        assert_eq!(batch.column(8).as_string::<i32>().value(1), "process");
+        assert_eq!(batch.column(9).as_string::<i32>().value(1), "7");
+        assert!(batch.column(9).is_null(0));
+
+        let attributes = batch.column(10).as_map();
+        let first = attributes.value(0);
+        assert_eq!(first.column(0).as_string::<i32>().value(0), "domain");
+        assert_eq!(first.column(1).as_string::<i32>().value(0), "package");
+        assert_eq!(attributes.value(1).len(), 0);
+    }
+
+    #[test]
+    fn rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let metrics = metrics();
+        let rotation = Rotation {
+            max_file_size: Some(1),
+            max_duration: None,
+        };
+        let mut output = ParquetOutput::new(
+            dir.path().to_owned(),
+            String::from("test"),
+            rotation,
+            WriterProperties::default(),
+        )
+        .unwrap();
+        for t in 0..3 {
+            output.write_measurements(&buffer(t), |id| metrics.get(id)).unwrap();
+        }
+        drop(output);
+
+        let files = read_dir(dir.path());
+        assert_eq!(files.len(), 3);
+        for f in files {
+            let rows: usize = read_parquet(&f).iter().map(|b| b.num_rows()).sum();
+            assert_eq!(rows, 2);
+        }
+    }
+
+    #[test]
+    fn rotate_by_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        let metrics = metrics();
+        let rotation = Rotation {
+            max_file_size: None,
+            max_duration: Some(Duration::from_millis(20)),
+        };
+        let mut output = ParquetOutput::new(
+            dir.path().to_owned(),
+            String::from("test"),
+            rotation,
+            WriterProperties::default(),
+        )
+        .unwrap();
+        output.write_measurements(&buffer(0), |id| metrics.get(id)).unwrap();
+        output.write_measurements(&buffer(1), |id| metrics.get(id)).unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        output.write_measurements(&buffer(2), |id| metrics.get(id)).unwrap();
+        drop(output);
+
+        let files = read_dir(dir.path());
+        assert_eq!(files.len(), 2);
+        let rows: Vec<usize> = files
+            .iter()
+            .map(|f| read_parquet(f).iter().map(|b| b.num_rows()).sum())
+            .collect();
+        assert_eq!(rows, vec![4, 2]);
+    }
+
+    fn single_batch(batches: &[RecordBatch]) -> RecordBatch {
+        assert_eq!(batches.len(), 1, "expected a single record batch");
+        batches[0].clone()
+    }
+}
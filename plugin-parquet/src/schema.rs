@@ -0,0 +1,123 @@
+//! Arrow schema of the Parquet files, and conversion of measurements to Arrow record batches.
+
+use std::sync::Arc;
+
+use alumet::{
+    measurement::{MeasurementBuffer, WrappedMeasurementValue},
+    metrics::{Metric, RawMetricId},
+};
+use arrow_array::{
+    builder::{BooleanBuilder, Float64Builder, Int64Builder, MapBuilder, StringBuilder, UInt64Builder},
+    ArrayRef, RecordBatch, TimestampNanosecondArray,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+
+/// Returns the schema of the Parquet files.
+///
+/// Each row is a measurement point. The value is stored in `value_f64`, `value_u64`, `value_i64`
+/// or `value_bool`, depending on the type of the metric, and the other value columns are null.
+pub fn measurement_schema() -> SchemaRef {
+    let attribute_entries = Field::new(
+        "entries",
+        DataType::Struct(
+            vec![
+                Field::new("keys", DataType::Utf8, false),
+                Field::new("values", DataType::Utf8, true),
+            ]
+            .into(),
+        ),
+        false,
+    );
+    Arc::new(Schema::new(vec![
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("metric", DataType::Utf8, false),
+        Field::new("value_f64", DataType::Float64, true),
+        Field::new("value_u64", DataType::UInt64, true),
+        Field::new("value_i64", DataType::Int64, true),
+        Field::new("value_bool", DataType::Boolean, true),
+        Field::new("resource_kind", DataType::Utf8, false),
+        Field::new("resource_id", DataType::Utf8, true),
+        Field::new("consumer_kind", DataType::Utf8, false),
+        Field::new("consumer_id", DataType::Utf8, true),
+        Field::new("attributes", DataType::Map(Arc::new(attribute_entries), false), false),
+    ]))
+}
+
+/// Converts a buffer of measurements to a record batch that follows [`measurement_schema`].
+///
+/// Points that belong to an unknown metric are ignored, as well as histograms, which the schema does not support.
+pub fn to_record_batch<'m>(
+    schema: SchemaRef,
+    measurements: &MeasurementBuffer,
+    metric_by_id: impl Fn(&RawMetricId) -> Option<&'m Metric>,
+) -> Result<RecordBatch, ArrowError> {
+    let n = measurements.len();
+    let mut timestamps = Vec::with_capacity(n);
+    let mut metrics = StringBuilder::with_capacity(n, n * 16);
+    let mut values_f64 = Float64Builder::with_capacity(n);
+    let mut values_u64 = UInt64Builder::with_capacity(n);
+    let mut values_i64 = Int64Builder::with_capacity(n);
+    let mut values_bool = BooleanBuilder::with_capacity(n);
+    let mut resource_kinds = StringBuilder::with_capacity(n, n * 8);
+    let mut resource_ids = StringBuilder::with_capacity(n, n * 4);
+    let mut consumer_kinds = StringBuilder::with_capacity(n, n * 8);
+    let mut consumer_ids = StringBuilder::with_capacity(n, n * 4);
+    let mut attributes = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+    let mut skipped_histograms = 0;
+
+    for point in measurements {
+        let Some(metric) = metric_by_id(&point.metric) else {
+            log::warn!("Ignoring point with unknown metric id {:?}", point.metric);
+            continue;
+        };
+        let (value_f64, value_u64, value_i64, value_bool) = match point.value {
+            WrappedMeasurementValue::F64(v) => (Some(v), None, None, None),
+            WrappedMeasurementValue::U64(v) => (None, Some(v), None, None),
+            WrappedMeasurementValue::I64(v) => (None, None, Some(v), None),
+            WrappedMeasurementValue::Bool(v) => (None, None, None, Some(v)),
+            WrappedMeasurementValue::Histogram(_) => {
+                skipped_histograms += 1;
+                continue;
+            }
+        };
+        timestamps.push(point.timestamp.to_unix_nanos());
+        metrics.append_value(&metric.name);
+        values_f64.append_option(value_f64);
+        values_u64.append_option(value_u64);
+        values_i64.append_option(value_i64);
+        values_bool.append_option(value_bool);
+        resource_kinds.append_value(point.resource.kind());
+        resource_ids.append_option(point.resource.id_string());
+        consumer_kinds.append_value(point.consumer.kind());
+        consumer_ids.append_option(point.consumer.id_string());
+        for (key, value) in point.attributes() {
+            attributes.keys().append_value(key);
+            attributes.values().append_value(value.to_string());
+        }
+        attributes.append(true)?;
+    }
+    if skipped_histograms > 0 {
+        log::warn!(
+            "Ignoring {skipped_histograms} histogram points: histograms are not supported by the Parquet output"
+        );
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(TimestampNanosecondArray::from(timestamps).with_timezone("UTC")),
+        Arc::new(metrics.finish()),
+        Arc::new(values_f64.finish()),
+        Arc::new(values_u64.finish()),
+        Arc::new(values_i64.finish()),
+        Arc::new(values_bool.finish()),
+        Arc::new(resource_kinds.finish()),
+        Arc::new(resource_ids.finish()),
+        Arc::new(consumer_kinds.finish()),
+        Arc::new(consumer_ids.finish()),
+        Arc::new(attributes.finish()),
+    ];
+    RecordBatch::try_new(schema, columns)
+}
@@ -7,7 +7,7 @@ use std::{
 
 use alumet::{
     measurement::{MeasurementAccumulator, MeasurementPoint, Timestamp},
-    metrics::{MetricCreationError, TypedMetricId},
+    metrics::{MetricCreationError, MetricKind, TypedMetricId},
     pipeline::{elements::error::PollError, Source},
     plugin::AlumetPluginStart,
     resources::{Resource, ResourceConsumer},
@@ -52,13 +52,24 @@ fn gather_system_info() -> Result<ExplicitSystemInfo, ProcError> {
 impl KernelMetrics {
     pub fn new(alumet: &mut AlumetPluginStart) -> Result<Self, MetricCreationError> {
         Ok(Self {
-            cpu_time: alumet.create_metric("kernel_cpu_time", PrefixedUnit::milli(Unit::Second), "busy CPU time")?,
-            context_switches: alumet.create_metric(
+            cpu_time: alumet.create_metric_with_kind(
+                "kernel_cpu_time",
+                MetricKind::Delta,
+                PrefixedUnit::milli(Unit::Second),
+                "busy CPU time",
+            )?,
+            context_switches: alumet.create_metric_with_kind(
                 "kernel_context_switches",
+                MetricKind::Delta,
                 Unit::Unity,
                 "number of context switches",
             )?,
-            new_forks: alumet.create_metric("kernel_new_forks", Unit::Unity, "number of fork operations")?,
+            new_forks: alumet.create_metric_with_kind(
+                "kernel_new_forks",
+                MetricKind::Delta,
+                Unit::Unity,
+                "number of fork operations",
+            )?,
             n_procs_running: alumet.create_metric(
                 "kernel_n_procs_running",
                 Unit::Unity,
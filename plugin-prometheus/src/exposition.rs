@@ -0,0 +1,675 @@
+//! Prometheus text exposition format.
+//!
+//! See <https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format>.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use alumet::{
+    measurement::{
+        Histogram, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+    },
+    metrics::{Metric, MetricKind, RawMetricId},
+    units::Unit,
+};
+
+/// Labels that are derived from the resource and consumer of each point.
+const RESERVED_LABELS: [&str; 4] = [
+    "resource_kind",
+    "resource_id",
+    "resource_consumer_kind",
+    "resource_consumer_id",
+];
+
+/// Options that control how Alumet metrics are exposed.
+pub struct ExpositionOptions {
+    /// If true, the unit of the metric is appended to its name, e.g. `rapl_consumed_energy_J_total`.
+    pub append_unit_to_metric_name: bool,
+    /// If true, each sample is exported with its timestamp.
+    pub add_timestamp: bool,
+}
+
+/// Type of a Prometheus metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    /// A cumulative value that only increases (except on reset).
+    Counter,
+    /// A value that can arbitrarily go up and down.
+    Gauge,
+    /// Cumulative counts of observations, in buckets.
+    Histogram,
+}
+
+/// Stores the latest samples of every time series, ready to be scraped.
+pub struct MetricStore {
+    options: ExpositionOptions,
+    families: HashMap<RawMetricId, MetricFamily>,
+}
+
+/// All the time series of one metric.
+struct MetricFamily {
+    name: String,
+    help: String,
+    metric_type: MetricType,
+    /// If true, the values are increments that must be added to the current value of the series.
+    accumulate: bool,
+    /// Static labels of the metric, added to every series.
+    labels: Vec<(String, String)>,
+    /// Samples indexed by their serialized labels.
+    series: BTreeMap<String, Sample>,
+}
+
+struct Sample {
+    value: SampleValue,
+    timestamp: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SampleValue {
+    F64(f64),
+    U64(u64),
+    I64(i64),
+    Histogram(Histogram),
+}
+
+impl MetricType {
+    /// Chooses the Prometheus type of an Alumet metric, based on its type and kind.
+    ///
+    /// Counters and deltas are exposed as Prometheus counters. Deltas, such as the energy
+    /// consumed since the previous measurement, are accumulated before being exposed.
+    /// Histograms are accumulated too, and exposed as Prometheus histograms.
+    /// Gauges are exposed as gauges.
+    pub fn of(metric: &Metric) -> MetricType {
+        if metric.value_type == WrappedMeasurementType::Histogram {
+            return MetricType::Histogram;
+        }
+        match metric.kind {
+            MetricKind::Counter | MetricKind::Delta => MetricType::Counter,
+            MetricKind::Gauge => MetricType::Gauge,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+            MetricType::Histogram => "histogram",
+        }
+    }
+}
+
+impl MetricStore {
+    pub fn new(options: ExpositionOptions) -> Self {
+        Self {
+            options,
+            families: HashMap::new(),
+        }
+    }
+
+    /// Updates the store with new measurements.
+    ///
+    /// Gauges and counters keep the latest value of each series, deltas and histograms accumulate the values.
+    /// The definition of the metrics is obtained with `metric_by_id`, usually by
+    /// looking into the [`MetricRegistry`](alumet::metrics::MetricRegistry).
+    pub fn update<'m>(
+        &mut self,
+        measurements: &MeasurementBuffer,
+        metric_by_id: impl Fn(&RawMetricId) -> Option<&'m Metric>,
+    ) {
+        for point in measurements {
+            let family = match self.families.get_mut(&point.metric) {
+                Some(f) => f,
+                None => {
+                    let Some(metric) = metric_by_id(&point.metric) else {
+                        log::warn!("Ignoring point with unknown metric id {:?}", point.metric);
+                        continue;
+                    };
+                    let mut family = MetricFamily::new(metric, &self.options);
+                    family.name = self.deduplicate_name(family.name, &metric.name, family.metric_type);
+                    self.families.entry(point.metric).or_insert(family)
+                }
+            };
+            let labels = serialize_labels(point, &family.labels);
+            let value = match &point.value {
+                WrappedMeasurementValue::F64(v) => SampleValue::F64(*v),
+                WrappedMeasurementValue::U64(v) => SampleValue::U64(*v),
+                WrappedMeasurementValue::I64(v) => SampleValue::I64(*v),
+                WrappedMeasurementValue::Bool(v) => SampleValue::U64(u64::from(*v)),
+                WrappedMeasurementValue::Histogram(h) => SampleValue::Histogram(h.clone()),
+            };
+            match family.series.get_mut(&labels) {
+                Some(sample) if family.accumulate => {
+                    sample.value.add(value);
+                    sample.timestamp = point.timestamp;
+                }
+                Some(sample) => {
+                    sample.value = value;
+                    sample.timestamp = point.timestamp;
+                }
+                None => {
+                    let timestamp = point.timestamp;
+                    family.series.insert(labels, Sample { value, timestamp });
+                }
+            }
+        }
+    }
+
+    /// Returns a name that is not used by any other family.
+    ///
+    /// Different Alumet metrics can have the same Prometheus name once sanitized, for instance
+    /// `a.b` and `a_b`. The metric that comes first keeps the name, the other ones get a suffix
+    /// `_2`, `_3`, etc. (before `_total` for counters).
+    fn deduplicate_name(&self, name: String, metric_name: &str, metric_type: MetricType) -> String {
+        let is_taken = |name: &str| self.families.values().any(|f| f.name == name);
+        if !is_taken(&name) {
+            return name;
+        }
+        let (base, suffix) = match metric_type {
+            MetricType::Counter => (name.strip_suffix("_total").unwrap_or(&name), "_total"),
+            _ => (name.as_str(), ""),
+        };
+        let unique = (2..)
+            .map(|n| format!("{base}_{n}{suffix}"))
+            .find(|candidate| !is_taken(candidate))
+            .unwrap();
+        log::warn!("Metric {metric_name} is exposed as {unique}, because another metric is already exposed as {name}.");
+        unique
+    }
+
+    /// Renders all the samples in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut families: Vec<&MetricFamily> = self.families.values().collect();
+        families.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut res = String::new();
+        for family in families {
+            writeln!(res, "# HELP {} {}", family.name, escape_help(&family.help)).unwrap();
+            writeln!(res, "# TYPE {} {}", family.name, family.metric_type.as_str()).unwrap();
+            for (labels, sample) in &family.series {
+                if let SampleValue::Histogram(h) = &sample.value {
+                    let mut cumulative = 0;
+                    for (bound, count) in h.buckets() {
+                        cumulative += count;
+                        let le = format!("le=\"{}\"", SampleValue::F64(bound));
+                        let labels = if labels.is_empty() {
+                            le
+                        } else {
+                            format!("{labels},{le}")
+                        };
+                        self.render_sample(&mut res, &family.name, "_bucket", &labels, cumulative, sample);
+                    }
+                    self.render_sample(
+                        &mut res,
+                        &family.name,
+                        "_sum",
+                        labels,
+                        SampleValue::F64(h.sum()),
+                        sample,
+                    );
+                    self.render_sample(&mut res, &family.name, "_count", labels, cumulative, sample);
+                } else {
+                    self.render_sample(&mut res, &family.name, "", labels, &sample.value, sample);
+                }
+            }
+        }
+        res
+    }
+
+    /// Writes one line of the text format.
+    fn render_sample(
+        &self,
+        res: &mut String,
+        name: &str,
+        suffix: &str,
+        labels: &str,
+        value: impl std::fmt::Display,
+        sample: &Sample,
+    ) {
+        write!(res, "{name}{suffix}").unwrap();
+        if !labels.is_empty() {
+            write!(res, "{{{labels}}}").unwrap();
+        }
+        write!(res, " {value}").unwrap();
+        if self.options.add_timestamp {
+            let (secs, nanos) = sample.timestamp.to_unix_timestamp();
+            let millis = secs * 1000 + i64::from(nanos) / 1_000_000;
+            write!(res, " {millis}").unwrap();
+        }
+        res.push('\n');
+    }
+}
+
+impl MetricFamily {
+    fn new(metric: &Metric, options: &ExpositionOptions) -> Self {
+        let metric_type = MetricType::of(metric);
+        let mut name = sanitize_name(&metric.name);
+        if options.append_unit_to_metric_name && metric.unit.base_unit != Unit::Unity {
+            name.push('_');
+            name.push_str(&sanitize_name(&metric.unit.unique_name()));
+        }
+        if metric_type == MetricType::Counter && !name.ends_with("_total") {
+            name.push_str("_total");
+        }
+        let help = if metric.unit.base_unit == Unit::Unity {
+            metric.description.clone()
+        } else {
+            format!("{} ({})", metric.description, metric.unit.display_name())
+        };
+        let accumulate = metric_type == MetricType::Histogram || metric.kind == MetricKind::Delta;
+        let labels = metric
+            .labels
+            .iter()
+            .map(|(key, value)| (sanitize_label_name(key), value.clone()))
+            .collect();
+        Self {
+            name,
+            help,
+            metric_type,
+            accumulate,
+            labels,
+            series: BTreeMap::new(),
+        }
+    }
+}
+
+impl SampleValue {
+    /// Accumulates another value into this one.
+    ///
+    /// Histograms are merged bucket by bucket. If the buckets have changed, the new histogram replaces the old one.
+    fn add(&mut self, other: SampleValue) {
+        *self = match (&*self, other) {
+            (SampleValue::U64(a), SampleValue::U64(b)) => SampleValue::U64(a.wrapping_add(b)),
+            (SampleValue::Histogram(a), SampleValue::Histogram(b)) if a.bounds() == b.bounds() => {
+                let counts = a.counts().iter().zip(b.counts()).map(|(x, y)| x + y).collect();
+                let merged = Histogram::from_parts(a.bounds().to_vec(), counts, a.sum() + b.sum());
+                SampleValue::Histogram(merged.expect("the bounds of a valid histogram are valid"))
+            }
+            (_, b @ SampleValue::Histogram(_)) => b,
+            (a, b) => SampleValue::F64(a.as_f64() + b.as_f64()),
+        }
+    }
+
+    fn as_f64(&self) -> f64 {
+        match self {
+            SampleValue::F64(v) => *v,
+            SampleValue::U64(v) => *v as f64,
+            SampleValue::I64(v) => *v as f64,
+            SampleValue::Histogram(h) => h.sum(),
+        }
+    }
+}
+
+impl std::fmt::Display for SampleValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SampleValue::U64(v) => write!(f, "{v}"),
+            SampleValue::I64(v) => write!(f, "{v}"),
+            SampleValue::Histogram(h) => write!(f, "{}", h.sum()),
+            SampleValue::F64(v) if v.is_nan() => f.write_str("NaN"),
+            SampleValue::F64(v) if v.is_infinite() && v.is_sign_positive() => f.write_str("+Inf"),
+            SampleValue::F64(v) if v.is_infinite() => f.write_str("-Inf"),
+            SampleValue::F64(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+/// Serializes the labels of a point: its resource, consumer, attributes and the static labels of its metric.
+///
+/// The attributes and static labels are sorted by key, so that the same set of attributes always
+/// gives the same time series. An attribute replaces the static label that has the same key.
+fn serialize_labels(point: &MeasurementPoint, static_labels: &[(String, String)]) -> String {
+    let mut labels = vec![
+        (String::from("resource_kind"), point.resource.kind().to_owned()),
+        (
+            String::from("resource_id"),
+            point.resource.id_string().unwrap_or_default(),
+        ),
+        (String::from("resource_consumer_kind"), point.consumer.kind().to_owned()),
+        (
+            String::from("resource_consumer_id"),
+            point.consumer.id_string().unwrap_or_default(),
+        ),
+    ];
+    let mut attributes: BTreeMap<String, String> = static_labels
+        .iter()
+        .map(|(key, value)| (reserved_label_alias(key.clone()), value.clone()))
+        .collect();
+    for (key, value) in point.attributes() {
+        let key = reserved_label_alias(sanitize_label_name(key));
+        attributes.insert(key, value.to_string());
+    }
+    labels.extend(attributes);
+
+    let mut res = String::new();
+    for (i, (key, value)) in labels.iter().enumerate() {
+        if i != 0 {
+            res.push(',');
+        }
+        write!(res, "{key}=\"{}\"", escape_label_value(value)).unwrap();
+    }
+    res
+}
+
+/// Renames a label that would conflict with the labels derived from the resource and consumer.
+fn reserved_label_alias(key: String) -> String {
+    if RESERVED_LABELS.contains(&key.as_str()) {
+        format!("alumet_attribute__{key}")
+    } else {
+        key
+    }
+}
+
+/// Replaces the characters that are not allowed in a metric name.
+///
+/// Metric names must match the regex `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+fn sanitize_name(name: &str) -> String {
+    let mut res: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if res.is_empty() || res.starts_with(|c: char| c.is_ascii_digit()) {
+        res.insert(0, '_');
+    }
+    res
+}
+
+/// Replaces the characters that are not allowed in a label name.
+///
+/// Label names must match the regex `[a-zA-Z_][a-zA-Z0-9_]*`, and names that
+/// start with `__` are reserved for internal use by Prometheus.
+fn sanitize_label_name(name: &str) -> String {
+    let mut res: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if res.is_empty() || res.starts_with(|c: char| c.is_ascii_digit()) {
+        res.insert(0, '_');
+    }
+    if res.starts_with("__") {
+        res.insert_str(0, "alumet");
+    }
+    res
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', r"\\").replace('\n', r"\n")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', "\\\"").replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::{
+        measurement::{
+            Histogram, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+        },
+        metrics::{Metric, MetricKind, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use pretty_assertions::assert_eq;
+    use std::{
+        collections::HashMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{escape_label_value, sanitize_label_name, sanitize_name, ExpositionOptions, MetricStore};
+
+    fn metrics() -> HashMap<RawMetricId, Metric> {
+        HashMap::from([
+            (
+                RawMetricId::from_u64(0),
+                Metric {
+                    name: String::from("rapl_consumed_energy"),
+                    description: String::from("Energy consumed"),
+                    value_type: WrappedMeasurementType::F64,
+                    unit: PrefixedUnit::from(Unit::Joule),
+                    kind: MetricKind::Delta,
+                    labels: vec![],
+                },
+            ),
+            (
+                RawMetricId::from_u64(1),
+                Metric {
+                    name: String::from("cpu.temperature"),
+                    description: String::from("Temperature of the CPU"),
+                    value_type: WrappedMeasurementType::U64,
+                    unit: PrefixedUnit::milli(Unit::DegreeCelsius),
+                    kind: MetricKind::Gauge,
+                    labels: vec![],
+                },
+            ),
+            (
+                RawMetricId::from_u64(2),
+                Metric {
+                    name: String::from("bytes_sent"),
+                    description: String::from("Bytes sent on the network"),
+                    value_type: WrappedMeasurementType::U64,
+                    unit: PrefixedUnit::from(Unit::Byte),
+                    kind: MetricKind::Counter,
+                    labels: vec![(String::from("interface"), String::from("eth0"))],
+                },
+            ),
+        ])
+    }
+
+    fn timestamp(secs: u64) -> Timestamp {
+        Timestamp::from(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn render_counters_and_gauges() {
+        let metrics = metrics();
+        let (energy, temp) = (RawMetricId::from_u64(0), RawMetricId::from_u64(1));
+        let mut store = MetricStore::new(ExpositionOptions {
+            append_unit_to_metric_name: false,
+            add_timestamp: true,
+        });
+
+        let mut buf = MeasurementBuffer::new();
+        for (t, value) in [(1, 10.0), (2, 2.5)] {
+            buf.push(MeasurementPoint::new_untyped(
+                timestamp(t),
+                energy,
+                Resource::CpuPackage { id: 0 },
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::F64(value),
+            ));
+        }
+        buf.push(
+            MeasurementPoint::new_untyped(
+                timestamp(2),
+                temp,
+                Resource::LocalMachine,
+                ResourceConsumer::Process { pid: 42 },
+                WrappedMeasurementValue::U64(45000),
+            )
+            .with_attr("sensor", "k10temp \"Tctl\"")
+            .with_attr("resource_kind", "oops"),
+        );
+        buf.push(MeasurementPoint::new_untyped(
+            timestamp(3),
+            temp,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(44000),
+        ));
+        store.update(&buf, |id| metrics.get(id));
+
+        let expected = r#"# HELP cpu_temperature Temperature of the CPU (m°C)
+# TYPE cpu_temperature gauge
+cpu_temperature{resource_kind="local_machine",resource_id="",resource_consumer_kind="local_machine",resource_consumer_id=""} 44000 3000
+cpu_temperature{resource_kind="local_machine",resource_id="",resource_consumer_kind="process",resource_consumer_id="42",alumet_attribute__resource_kind="oops",sensor="k10temp \"Tctl\""} 45000 2000
+# HELP rapl_consumed_energy_total Energy consumed (J)
+# TYPE rapl_consumed_energy_total counter
+rapl_consumed_energy_total{resource_kind="cpu_package",resource_id="0",resource_consumer_kind="local_machine",resource_consumer_id=""} 12.5 2000
+"#;
+        assert_eq!(expected, store.render());
+    }
+
+    #[test]
+    fn append_unit() {
+        let metrics = metrics();
+        let energy = RawMetricId::from_u64(0);
+        let mut store = MetricStore::new(ExpositionOptions {
+            append_unit_to_metric_name: true,
+            add_timestamp: false,
+        });
+        let buf = MeasurementBuffer::from(vec![MeasurementPoint::new_untyped(
+            timestamp(1),
+            energy,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(1.0),
+        )]);
+        store.update(&buf, |id| metrics.get(id));
+        let rendered = store.render();
+        assert!(rendered.contains("# TYPE rapl_consumed_energy_J_total counter\n"));
+        assert!(rendered.ends_with("} 1\n"));
+    }
+
+    #[test]
+    fn render_counter_with_labels() {
+        let metrics = metrics();
+        let mut store = MetricStore::new(ExpositionOptions {
+            append_unit_to_metric_name: false,
+            add_timestamp: false,
+        });
+        let mut buf = MeasurementBuffer::new();
+        for value in [100, 250] {
+            buf.push(MeasurementPoint::new_untyped(
+                timestamp(1),
+                RawMetricId::from_u64(2),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(value),
+            ));
+        }
+        store.update(&buf, |id| metrics.get(id));
+
+        // cumulative counters are not accumulated, the latest value is kept
+        let expected = r#"# HELP bytes_sent_total Bytes sent on the network (B)
+# TYPE bytes_sent_total counter
+bytes_sent_total{resource_kind="local_machine",resource_id="",resource_consumer_kind="local_machine",resource_consumer_id="",interface="eth0"} 250
+"#;
+        assert_eq!(expected, store.render());
+    }
+
+    #[test]
+    fn render_histogram() {
+        let metrics = HashMap::from([(
+            RawMetricId::from_u64(0),
+            Metric {
+                name: String::from("latency"),
+                description: String::from("Latency"),
+                value_type: WrappedMeasurementType::Histogram,
+                unit: PrefixedUnit::from(Unit::Second),
+                kind: MetricKind::Gauge,
+                labels: vec![],
+            },
+        )]);
+        let mut store = MetricStore::new(ExpositionOptions {
+            append_unit_to_metric_name: false,
+            add_timestamp: false,
+        });
+        let mut buf = MeasurementBuffer::new();
+        for values in [vec![0.05, 0.5], vec![2.0]] {
+            let mut h = Histogram::new(vec![0.1, 1.0]).unwrap();
+            for v in values {
+                h.record(v);
+            }
+            buf.push(MeasurementPoint::new_untyped(
+                timestamp(1),
+                RawMetricId::from_u64(0),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::Histogram(h),
+            ));
+        }
+        store.update(&buf, |id| metrics.get(id));
+
+        let labels = r#"resource_kind="local_machine",resource_id="",resource_consumer_kind="local_machine",resource_consumer_id="""#;
+        let expected = format!(
+            r#"# HELP latency Latency (s)
+# TYPE latency histogram
+latency_bucket{{{labels},le="0.1"}} 1
+latency_bucket{{{labels},le="1"}} 2
+latency_bucket{{{labels},le="+Inf"}} 3
+latency_sum{{{labels}}} 2.55
+latency_count{{{labels}}} 3
+"#
+        );
+        assert_eq!(expected, store.render());
+    }
+
+    #[test]
+    fn colliding_names() {
+        let metric = |name: &str, kind| Metric {
+            name: String::from(name),
+            description: String::new(),
+            value_type: WrappedMeasurementType::U64,
+            unit: PrefixedUnit::from(Unit::Unity),
+            kind,
+            labels: vec![],
+        };
+        let metrics = HashMap::from([
+            (RawMetricId::from_u64(0), metric("a.b", MetricKind::Gauge)),
+            (RawMetricId::from_u64(1), metric("a_b", MetricKind::Gauge)),
+            (RawMetricId::from_u64(2), metric("a-b", MetricKind::Gauge)),
+            (RawMetricId::from_u64(3), metric("c.d", MetricKind::Counter)),
+            (RawMetricId::from_u64(4), metric("c_d", MetricKind::Counter)),
+        ]);
+        let mut store = MetricStore::new(ExpositionOptions {
+            append_unit_to_metric_name: false,
+            add_timestamp: false,
+        });
+        let buf = MeasurementBuffer::from(
+            (0..5)
+                .map(|id| {
+                    MeasurementPoint::new_untyped(
+                        timestamp(1),
+                        RawMetricId::from_u64(id),
+                        Resource::LocalMachine,
+                        ResourceConsumer::LocalMachine,
+                        WrappedMeasurementValue::U64(id),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        store.update(&buf, |id| metrics.get(id));
+
+        // each metric has its own family, with a unique name
+        let rendered = store.render();
+        let types: Vec<&str> = rendered.lines().filter(|l| l.starts_with("# TYPE")).collect();
+        assert_eq!(
+            types,
+            vec![
+                "# TYPE a_b gauge",
+                "# TYPE a_b_2 gauge",
+                "# TYPE a_b_3 gauge",
+                "# TYPE c_d_2_total counter",
+                "# TYPE c_d_total counter",
+            ]
+        );
+        assert!(rendered.contains("\na_b_3{resource_kind=\"local_machine\",resource_id=\"\",resource_consumer_kind=\"local_machine\",resource_consumer_id=\"\"} 2\n"));
+        assert!(rendered.contains("\nc_d_2_total{resource_kind=\"local_machine\",resource_id=\"\",resource_consumer_kind=\"local_machine\",resource_consumer_id=\"\"} 4\n"));
+    }
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(sanitize_name("rapl_consumed_energy"), "rapl_consumed_energy");
+        assert_eq!(sanitize_name("a.b-c:d"), "a_b_c:d");
+        assert_eq!(sanitize_name("1abc"), "_1abc");
+        assert_eq!(sanitize_label_name("a:b"), "a_b");
+        assert_eq!(sanitize_label_name("__name__"), "alumet__name__");
+        assert_eq!(escape_label_value("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::{Arc, Mutex};
+
+use alumet::{
+    pipeline::{
+        elements::output::{
+            builder::{AsyncOutputBuildContext, AsyncOutputRegistration},
+            AsyncOutputStream, StreamRecvError,
+        },
+        registry::MetricReader,
+    },
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+};
+use anyhow::Context;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use exposition::{ExpositionOptions, MetricStore};
+
+mod exposition;
+mod server;
+
+/// Exposes the measurements on an HTTP endpoint, in the Prometheus text format.
+pub struct PrometheusPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for PrometheusPlugin {
+    fn name() -> &'static str {
+        "prometheus"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(PrometheusPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+
+        // Bind the socket now to detect configuration errors early.
+        let listener = std::net::TcpListener::bind(&config.address)
+            .with_context(|| format!("Cannot listen on {} for Prometheus scrapes", config.address))?;
+        listener.set_nonblocking(true)?;
+        log::info!(
+            "Prometheus metrics will be available on http://{}/metrics",
+            config.address
+        );
+
+        let store = Arc::new(Mutex::new(MetricStore::new(ExpositionOptions {
+            append_unit_to_metric_name: config.append_unit_to_metric_name,
+            add_timestamp: config.add_timestamp,
+        })));
+
+        alumet.add_async_output_builder(move |ctx: &mut dyn AsyncOutputBuildContext, stream| {
+            // The HTTP server runs in its own task, the output only updates the store.
+            let rt = ctx.async_runtime();
+            let server = {
+                let _guard = rt.enter();
+                let listener = tokio::net::TcpListener::from_std(listener)?;
+                rt.spawn(server::serve(listener, store.clone()))
+            };
+            let output = run_output(stream, ctx.metrics_reader(), store, server);
+            Ok(AsyncOutputRegistration {
+                name: ctx.output_name("scrape_endpoint"),
+                output: Box::pin(output),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Receives the measurements and stores their latest values, until the stream ends.
+async fn run_output(
+    mut stream: AsyncOutputStream,
+    metrics: MetricReader,
+    store: Arc<Mutex<MetricStore>>,
+    server: tokio::task::JoinHandle<anyhow::Result<()>>,
+) -> anyhow::Result<()> {
+    while let Some(received) = stream.0.next().await {
+        match received {
+            Ok(measurements) => {
+                let metrics = metrics.read().await;
+                store.lock().unwrap().update(&measurements, |id| metrics.by_id(id));
+            }
+            Err(StreamRecvError::Lagged(n)) => {
+                log::warn!("Prometheus output is too slow, {n} buffers have been lost");
+            }
+            Err(e) => {
+                log::error!("Prometheus output failed to receive measurements: {e:?}");
+            }
+        }
+    }
+    // No more measurements will come, stop serving them.
+    server.abort();
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Address to listen on, for instance `0.0.0.0:9091`.
+    address: String,
+    /// If true, the unit of each metric is appended to its name.
+    append_unit_to_metric_name: bool,
+    /// If true, the timestamp of each measurement is included in the exposed samples.
+    add_timestamp: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            address: String::from("127.0.0.1:9091"),
+            append_unit_to_metric_name: false,
+            add_timestamp: false,
+        }
+    }
+}
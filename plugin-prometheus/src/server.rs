@@ -0,0 +1,83 @@
+//! Minimal HTTP server that exposes the metrics to Prometheus.
+
+use std::{
+    convert::Infallible,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use http_body_util::Full;
+use hyper::{body::Bytes, header, server::conn::http1, service::service_fn, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+
+use crate::exposition::MetricStore;
+
+/// Content type of the text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// How long to wait before accepting new connections after an error that is not specific to a connection.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+/// Accepts connections and serves the metrics on `/metrics`, until the task is aborted.
+///
+/// An error while accepting a connection does not stop the server.
+pub async fn serve(listener: TcpListener, store: Arc<Mutex<MetricStore>>) -> anyhow::Result<()> {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) if is_connection_error(&e) => {
+                // The client has gone away, this does not affect the other connections.
+                log::debug!("Failed to accept a connection: {e}");
+                continue;
+            }
+            Err(e) => {
+                // Probably a lack of resources (too many open files, for instance): wait for some to be released.
+                log::error!("Failed to accept a connection: {e}");
+                tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                continue;
+            }
+        };
+        log::trace!("New connection from {peer}");
+        let store = store.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| handle_request(req, store.clone()));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log::warn!("Error while serving metrics to {peer}: {e}");
+            }
+        });
+    }
+}
+
+/// Returns true if the error only concerns the connection that was being accepted.
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+    )
+}
+
+async fn handle_request<B>(
+    req: Request<B>,
+    store: Arc<Mutex<MetricStore>>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            let body = store.lock().unwrap().render();
+            Response::builder()
+                .header(header::CONTENT_TYPE, CONTENT_TYPE)
+                .body(Full::new(Bytes::from(body)))
+        }
+        (_, "/metrics") => Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .body(Full::new(Bytes::new())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::new(Bytes::from_static(b"Not Found, try /metrics\n"))),
+    };
+    Ok(response.expect("the response should be valid"))
+}
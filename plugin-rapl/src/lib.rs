@@ -1,6 +1,7 @@
 use std::{path::PathBuf, time::Duration};
 
 use alumet::{
+    metrics::MetricKind,
     pipeline::{trigger, Source},
     plugin::{
         rust::{deserialize_config, serialize_config, AlumetPlugin},
@@ -131,8 +132,9 @@ impl AlumetPlugin for RaplPlugin {
         );
 
         // Create the metric.
-        let metric = alumet.create_metric::<f64>(
+        let metric = alumet.create_metric_with_kind::<f64>(
             "rapl_consumed_energy",
+            MetricKind::Delta,
             Unit::Joule,
             "Energy consumed since the previous measurement, as reported by RAPL.",
         )?;
@@ -0,0 +1,224 @@
+use std::{collections::HashMap, time::Duration};
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    metrics::{Metric, MetricKind, RawMetricId},
+    pipeline::elements::transform::builder::{TransformBuildContext, TransformRegistration},
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use transform::{Derivation, RateTransform};
+
+mod transform;
+mod unit;
+
+pub struct RatePlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for RatePlugin {
+    fn name() -> &'static str {
+        "rate"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(RatePlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        if config.counters.is_empty() {
+            log::warn!("No counter to derive, the rate plugin will do nothing. Add some counters to its config.");
+            return Ok(());
+        }
+
+        // The unit of the derived metrics depends on the unit of the counters, which are registered
+        // by other plugins. Therefore, the derived metrics are registered when the transform is built.
+        alumet.add_transform_builder(move |ctx| {
+            let derivations = register_derived_metrics(ctx, &config.counters)?;
+            let transform = RateTransform::new(derivations, config.keep_counters, config.forget_after);
+            Ok(TransformRegistration {
+                name: ctx.transform_name("rate"),
+                transform: Box::new(transform),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Registers the derived metrics, and returns how to compute them.
+fn register_derived_metrics(
+    ctx: &mut dyn TransformBuildContext,
+    counters: &[CounterConfig],
+) -> anyhow::Result<HashMap<RawMetricId, Derivation>> {
+    let mut derivations = HashMap::with_capacity(counters.len());
+    for counter in counters {
+        // Find the counter and define the derived metric.
+        let (counter_id, metric) = ctx
+            .metric_by_name(&counter.metric)
+            .with_context(|| format!("metric not found: {}", counter.metric))?;
+        if matches!(
+            metric.value_type,
+            WrappedMeasurementType::Bool | WrappedMeasurementType::Histogram
+        ) {
+            return Err(anyhow!(
+                "metric {} has type {}, it cannot be used as a counter",
+                counter.metric,
+                metric.value_type
+            ));
+        }
+        let (derived, factor) = derived_metric(metric, counter);
+        let derived_type = derived.value_type.clone();
+        let derived_metric = ctx
+            .create_metric(derived)
+            .map_err(|e| anyhow!("could not register the metric derived from {}: {e}", counter.metric))?;
+        let derivation = Derivation {
+            mode: counter.mode,
+            derived_metric,
+            derived_type,
+            factor,
+            max_value: counter.max_value,
+        };
+        derivations.insert(counter_id, derivation);
+    }
+    Ok(derivations)
+}
+
+/// Defines the metric derived from a counter.
+fn derived_metric(counter: &Metric, config: &CounterConfig) -> (Metric, f64) {
+    match config.mode {
+        Mode::Rate => {
+            let (unit, factor) = unit::rate_unit(&counter.unit);
+            let metric = Metric {
+                name: config
+                    .derived_metric
+                    .clone()
+                    .unwrap_or_else(|| format!("{}_rate", counter.name)),
+                description: format!("Rate of change per second of {}.", counter.name),
+                value_type: WrappedMeasurementType::F64,
+                unit,
+                kind: MetricKind::Gauge,
+                labels: counter.labels.clone(),
+            };
+            (metric, factor)
+        }
+        Mode::Delta => {
+            let metric = Metric {
+                name: config
+                    .derived_metric
+                    .clone()
+                    .unwrap_or_else(|| format!("{}_delta", counter.name)),
+                description: format!("Difference between two successive values of {}.", counter.name),
+                value_type: counter.value_type.clone(),
+                unit: counter.unit.clone(),
+                kind: MetricKind::Delta,
+                labels: counter.labels.clone(),
+            };
+            (metric, 1.0)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Difference between two successive values, divided by the elapsed time.
+    Rate,
+    /// Difference between two successive values.
+    Delta,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Keep the counters in the measurements, in addition to the derived values.
+    keep_counters: bool,
+    /// Forget the state of a counter when it has not been updated for this duration.
+    #[serde(with = "humantime_serde")]
+    forget_after: Duration,
+    counters: Vec<CounterConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CounterConfig {
+    /// Name of the counter metric.
+    metric: String,
+    mode: Mode,
+    /// Name of the derived metric. By default, `_rate` or `_delta` is appended to the name of the counter.
+    #[serde(default)]
+    derived_metric: Option<String>,
+    /// Maximum value of the counter, after which it wraps around to zero.
+    /// If it is not set, a counter that decreases is considered to have been reset.
+    #[serde(default)]
+    max_value: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keep_counters: true,
+            forget_after: Duration::from_secs(300),
+            counters: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::{
+        measurement::WrappedMeasurementType,
+        metrics::{Metric, MetricKind},
+        units::{PrefixedUnit, Unit},
+    };
+
+    use super::{derived_metric, CounterConfig, Mode};
+
+    #[test]
+    fn derived_metric_definition() {
+        let energy = Metric {
+            name: String::from("energy_counter"),
+            description: String::new(),
+            value_type: WrappedMeasurementType::U64,
+            unit: PrefixedUnit::micro(Unit::Joule),
+            kind: MetricKind::Gauge,
+            labels: vec![],
+        };
+        let mut config = CounterConfig {
+            metric: energy.name.clone(),
+            mode: Mode::Rate,
+            derived_metric: None,
+            max_value: None,
+        };
+        let (rate, factor) = derived_metric(&energy, &config);
+        assert_eq!(rate.name, "energy_counter_rate");
+        assert_eq!(rate.unit, PrefixedUnit::micro(Unit::Watt));
+        assert_eq!(rate.value_type, WrappedMeasurementType::F64);
+        assert_eq!(factor, 1.0);
+
+        config.mode = Mode::Delta;
+        config.derived_metric = Some(String::from("energy"));
+        let (delta, _) = derived_metric(&energy, &config);
+        assert_eq!(delta.name, "energy");
+        assert_eq!(delta.unit, PrefixedUnit::micro(Unit::Joule));
+        assert_eq!(delta.value_type, WrappedMeasurementType::U64);
+    }
+}
@@ -0,0 +1,464 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::{Duration, SystemTime},
+};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::{
+        elements::{error::TransformError, transform::TransformContext},
+        Transform,
+    },
+    plugin::util::{CounterDiff, CounterDiffUpdate},
+    resources::{Resource, ResourceConsumer},
+};
+
+use crate::Mode;
+
+/// How to derive a counter metric.
+pub struct Derivation {
+    pub mode: Mode,
+    /// The metric that holds the derived values.
+    pub derived_metric: RawMetricId,
+    /// The type of the derived values.
+    pub derived_type: WrappedMeasurementType,
+    /// Factor to apply to the per-second rate, to express it in the unit of the derived metric.
+    pub factor: f64,
+    /// The maximum value of the counter, after which it wraps around.
+    /// If `None`, a decreasing counter is considered to have been reset.
+    pub max_value: Option<u64>,
+}
+
+/// Computes the rate (or the delta) of counters.
+pub struct RateTransform {
+    /// The counters to derive, by metric id.
+    derivations: HashMap<RawMetricId, Derivation>,
+    /// Keep the counters in the measurements, in addition to the derived values.
+    keep_counters: bool,
+    /// Forget the series that have not been updated for this duration.
+    forget_after: Duration,
+    series: HashMap<SeriesKey, SeriesState>,
+    last_cleanup: Option<SystemTime>,
+}
+
+/// Identifies a time series of a counter.
+#[derive(PartialEq, Eq, Hash)]
+struct SeriesKey {
+    metric: RawMetricId,
+    resource: Resource,
+    consumer: ResourceConsumer,
+    attributes: Vec<(String, String)>,
+}
+
+struct SeriesState {
+    counter: CounterState,
+    timestamp: Timestamp,
+}
+
+enum CounterState {
+    U64(CounterDiff),
+    F64(f64),
+    I64(i64),
+}
+
+impl RateTransform {
+    pub fn new(derivations: HashMap<RawMetricId, Derivation>, keep_counters: bool, forget_after: Duration) -> Self {
+        Self {
+            derivations,
+            keep_counters,
+            forget_after,
+            series: HashMap::new(),
+            last_cleanup: None,
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        let derivations = &self.derivations;
+        let mut derived_points = Vec::new();
+        let mut latest = None;
+        for point in measurements.iter() {
+            let Some(derivation) = derivations.get(&point.metric) else {
+                continue;
+            };
+            let t = SystemTime::from(point.timestamp);
+            latest = latest.max(Some(t));
+            match self.series.entry(SeriesKey::of(point)) {
+                Entry::Vacant(entry) => {
+                    if let Some(state) = SeriesState::new(&point.value, derivation.max_value, point.timestamp) {
+                        entry.insert(state);
+                    }
+                }
+                Entry::Occupied(mut entry) => {
+                    if let Some(value) = entry.get_mut().update(point, derivation) {
+                        derived_points.push(
+                            MeasurementPoint::new_untyped(
+                                point.timestamp,
+                                derivation.derived_metric,
+                                point.resource.clone(),
+                                point.consumer.clone(),
+                                value,
+                            )
+                            .with_attr_vec(point.attributes().map(|(k, v)| (k.to_owned(), v.clone())).collect()),
+                        );
+                    }
+                }
+            }
+        }
+
+        if !self.keep_counters {
+            measurements.retain(|p| !derivations.contains_key(&p.metric));
+        }
+        for point in derived_points {
+            measurements.push(point);
+        }
+        if let Some(now) = latest {
+            self.forget_old_series(now);
+        }
+    }
+
+    /// Removes the series that have not been updated recently, for instance because the process
+    /// that they were about has terminated.
+    fn forget_old_series(&mut self, now: SystemTime) {
+        let due = self
+            .last_cleanup
+            .is_none_or(|t| now.duration_since(t).unwrap_or_default() >= self.forget_after);
+        if due {
+            let forget_after = self.forget_after;
+            self.series
+                .retain(|_, s| now.duration_since(s.timestamp.into()).unwrap_or_default() < forget_after);
+            self.last_cleanup = Some(now);
+        }
+    }
+}
+
+impl Transform for RateTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+impl SeriesKey {
+    fn of(point: &MeasurementPoint) -> Self {
+        let mut attributes: Vec<(String, String)> =
+            point.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect();
+        attributes.sort_unstable();
+        Self {
+            metric: point.metric,
+            resource: point.resource.clone(),
+            consumer: point.consumer.clone(),
+            attributes,
+        }
+    }
+}
+
+impl SeriesState {
+    /// Creates the state of a counter. Returns `None` if the value is not a number.
+    fn new(value: &WrappedMeasurementValue, max_value: Option<u64>, timestamp: Timestamp) -> Option<Self> {
+        let counter = match value {
+            WrappedMeasurementValue::F64(v) => CounterState::F64(*v),
+            WrappedMeasurementValue::U64(v) => {
+                let mut counter = CounterDiff::with_max_value(max_value.unwrap_or(u64::MAX).max(*v));
+                counter.update(*v);
+                CounterState::U64(counter)
+            }
+            WrappedMeasurementValue::I64(v) => CounterState::I64(*v),
+            WrappedMeasurementValue::Bool(_) | WrappedMeasurementValue::Histogram(_) => return None,
+        };
+        Some(Self { counter, timestamp })
+    }
+
+    /// Updates the state with a new value of the counter, and returns the derived value.
+    ///
+    /// The elapsed time is computed with the monotonic clock if possible, so that the rates
+    /// are not affected by the adjustments of the system time.
+    fn update(&mut self, point: &MeasurementPoint, derivation: &Derivation) -> Option<WrappedMeasurementValue> {
+        let timestamp = point.timestamp;
+        let Some(elapsed) = timestamp.elapsed_since(&self.timestamp) else {
+            log::debug!("Ignoring out-of-order value of counter {:?}", point.metric);
+            return None;
+        };
+        if elapsed.is_zero() {
+            return None;
+        }
+        self.timestamp = timestamp;
+
+        let diff = match (&mut self.counter, &point.value) {
+            (CounterState::U64(counter), WrappedMeasurementValue::U64(v)) if *v <= counter.max_value => {
+                match counter.update(*v) {
+                    CounterDiffUpdate::FirstTime => None,
+                    CounterDiffUpdate::Difference(d) => Some(WrappedMeasurementValue::U64(d)),
+                    CounterDiffUpdate::CorrectedDifference(d) if derivation.max_value.is_some() => {
+                        Some(WrappedMeasurementValue::U64(d))
+                    }
+                    CounterDiffUpdate::CorrectedDifference(_) => None,
+                }
+            }
+            (CounterState::F64(prev), WrappedMeasurementValue::F64(v)) => {
+                let mut d = v - *prev;
+                *prev = *v;
+                if d < 0.0 {
+                    d += derivation.max_value? as f64;
+                }
+                Some(WrappedMeasurementValue::F64(d))
+            }
+            (CounterState::I64(prev), WrappedMeasurementValue::I64(v)) => {
+                // Signed values can legitimately decrease, there is no overflow correction.
+                let d = v.checked_sub(*prev);
+                *prev = *v;
+                Some(WrappedMeasurementValue::I64(d?))
+            }
+            (_, value) => {
+                // The counter has been reset or does not match its previous type, start again.
+                if let Some(state) = SeriesState::new(value, derivation.max_value, timestamp) {
+                    *self = state;
+                }
+                return None;
+            }
+        };
+
+        let diff = diff?;
+        let value = match (derivation.mode, diff) {
+            (Mode::Delta, WrappedMeasurementValue::U64(d))
+                if derivation.derived_type == WrappedMeasurementType::U64 =>
+            {
+                WrappedMeasurementValue::U64(d)
+            }
+            (Mode::Delta, WrappedMeasurementValue::I64(d))
+                if derivation.derived_type == WrappedMeasurementType::I64 =>
+            {
+                WrappedMeasurementValue::I64(d)
+            }
+            (Mode::Delta, d) => WrappedMeasurementValue::F64(as_f64(&d)),
+            (Mode::Rate, d) => WrappedMeasurementValue::F64(as_f64(&d) / elapsed.as_secs_f64() * derivation.factor),
+        };
+        Some(value)
+    }
+}
+
+fn as_f64(value: &WrappedMeasurementValue) -> f64 {
+    value
+        .as_f64()
+        .expect("the difference of two counter values should be a number")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use alumet::{
+        measurement::{
+            ClockDomain, MeasurementBuffer, MeasurementPoint, MonotonicTime, Timestamp, WrappedMeasurementType,
+            WrappedMeasurementValue,
+        },
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{Derivation, RateTransform};
+    use crate::Mode;
+
+    fn energy() -> RawMetricId {
+        RawMetricId::from_u64(0)
+    }
+    fn energy_rate() -> RawMetricId {
+        RawMetricId::from_u64(1)
+    }
+    fn cycles() -> RawMetricId {
+        RawMetricId::from_u64(2)
+    }
+    fn cycles_delta() -> RawMetricId {
+        RawMetricId::from_u64(3)
+    }
+    fn balance() -> RawMetricId {
+        RawMetricId::from_u64(4)
+    }
+    fn balance_delta() -> RawMetricId {
+        RawMetricId::from_u64(5)
+    }
+
+    fn transform(keep_counters: bool) -> RateTransform {
+        let derivations = HashMap::from([
+            (
+                energy(),
+                Derivation {
+                    mode: Mode::Rate,
+                    derived_metric: energy_rate(),
+                    derived_type: WrappedMeasurementType::F64,
+                    factor: 1.0,
+                    max_value: None,
+                },
+            ),
+            (
+                cycles(),
+                Derivation {
+                    mode: Mode::Delta,
+                    derived_metric: cycles_delta(),
+                    derived_type: WrappedMeasurementType::U64,
+                    factor: 1.0,
+                    max_value: Some(1000),
+                },
+            ),
+            (
+                balance(),
+                Derivation {
+                    mode: Mode::Delta,
+                    derived_metric: balance_delta(),
+                    derived_type: WrappedMeasurementType::I64,
+                    factor: 1.0,
+                    max_value: None,
+                },
+            ),
+        ]);
+        RateTransform::new(derivations, keep_counters, Duration::from_secs(60))
+    }
+
+    fn point(t_millis: u64, metric: RawMetricId, pid: u32, value: WrappedMeasurementValue) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_millis(t_millis)),
+            metric,
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid },
+            value,
+        )
+    }
+
+    fn values(buf: &MeasurementBuffer, metric: RawMetricId) -> Vec<(u32, String)> {
+        buf.iter()
+            .filter(|p| p.metric == metric)
+            .map(|p| {
+                let pid = match p.consumer {
+                    ResourceConsumer::Process { pid } => pid,
+                    _ => unreachable!(),
+                };
+                let value = match &p.value {
+                    WrappedMeasurementValue::U64(x) => format!("{x}u"),
+                    WrappedMeasurementValue::I64(x) => format!("{x}i"),
+                    v => v.to_string(),
+                };
+                (pid, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rate_per_series() {
+        let mut t = transform(true);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, energy(), 1, WrappedMeasurementValue::F64(10.0)),
+            point(0, energy(), 2, WrappedMeasurementValue::F64(100.0)),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(buf.len(), 2);
+        assert!(values(&buf, energy_rate()).is_empty());
+
+        let mut buf = MeasurementBuffer::from(vec![
+            point(500, energy(), 1, WrappedMeasurementValue::F64(15.0)),
+            point(2000, energy(), 2, WrappedMeasurementValue::F64(150.0)),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(values(&buf, energy()), vec![(1, "15".into()), (2, "150".into())]);
+        assert_eq!(values(&buf, energy_rate()), vec![(1, "10".into()), (2, "25".into())]);
+    }
+
+    #[test]
+    fn monotonic_clock() {
+        // the system time goes back by 10s between the two points, but the monotonic clock is not affected
+        let point = |t_millis: u64, mono_millis: u64, value: f64| {
+            let mut p = point(t_millis, energy(), 1, WrappedMeasurementValue::F64(value));
+            let mono = MonotonicTime::new(ClockDomain::Boottime, Duration::from_millis(mono_millis));
+            p.timestamp = p.timestamp.with_monotonic(mono);
+            p
+        };
+        let mut t = transform(false);
+        let mut buf = MeasurementBuffer::from(vec![point(20_000, 1000, 10.0)]);
+        t.process(&mut buf);
+        let mut buf = MeasurementBuffer::from(vec![point(10_500, 1500, 15.0)]);
+        t.process(&mut buf);
+        assert_eq!(values(&buf, energy_rate()), vec![(1, "10".into())]);
+    }
+
+    #[test]
+    fn reset_without_max_value() {
+        let mut t = transform(false);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, energy(), 1, WrappedMeasurementValue::F64(10.0)),
+            point(1000, energy(), 1, WrappedMeasurementValue::F64(2.0)),
+            point(2000, energy(), 1, WrappedMeasurementValue::F64(5.0)),
+        ]);
+        t.process(&mut buf);
+        assert!(values(&buf, energy()).is_empty());
+        assert_eq!(values(&buf, energy_rate()), vec![(1, "3".into())]);
+    }
+
+    #[test]
+    fn delta_with_wraparound() {
+        let mut t = transform(false);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, cycles(), 1, WrappedMeasurementValue::U64(900)),
+            point(1000, cycles(), 1, WrappedMeasurementValue::U64(950)),
+            point(2000, cycles(), 1, WrappedMeasurementValue::U64(50)),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(
+            values(&buf, cycles_delta()),
+            vec![(1, "50u".into()), (1, "100u".into())]
+        );
+    }
+
+    #[test]
+    fn signed_delta() {
+        let mut t = transform(false);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, balance(), 1, WrappedMeasurementValue::I64(10)),
+            point(1000, balance(), 1, WrappedMeasurementValue::I64(-5)),
+            point(2000, balance(), 1, WrappedMeasurementValue::I64(-2)),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(
+            values(&buf, balance_delta()),
+            vec![(1, "-15i".into()), (1, "3i".into())]
+        );
+    }
+
+    #[test]
+    fn attributes_are_part_of_the_series() {
+        let mut t = transform(false);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, energy(), 1, WrappedMeasurementValue::F64(0.0)).with_attr("domain", "package"),
+            point(0, energy(), 1, WrappedMeasurementValue::F64(0.0)).with_attr("domain", "dram"),
+            point(1000, energy(), 1, WrappedMeasurementValue::F64(4.0)).with_attr("domain", "package"),
+            point(1000, energy(), 1, WrappedMeasurementValue::F64(1.0)).with_attr("domain", "dram"),
+        ]);
+        t.process(&mut buf);
+        let rates: Vec<(String, String)> = buf
+            .iter()
+            .map(|p| (p.attributes().next().unwrap().1.to_string(), format!("{:?}", p.value)))
+            .collect();
+        assert_eq!(
+            rates,
+            vec![
+                ("package".into(), "F64(4.0)".into()),
+                ("dram".into(), "F64(1.0)".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn forget_old_series() {
+        let mut t = transform(true);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, energy(), 1, WrappedMeasurementValue::F64(0.0)),
+            point(0, energy(), 2, WrappedMeasurementValue::F64(0.0)),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(t.series.len(), 2);
+        let mut buf = MeasurementBuffer::from(vec![point(90_000, energy(), 1, WrappedMeasurementValue::F64(1.0))]);
+        t.process(&mut buf);
+        assert_eq!(t.series.len(), 1);
+    }
+}
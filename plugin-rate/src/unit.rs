@@ -0,0 +1,64 @@
+use alumet::units::{PrefixedUnit, Unit};
+
+/// Returns the unit of the derivative of a counter, and the factor to apply to the
+/// per-second rate to express it in this unit.
+///
+/// For instance, the derivative of an energy in joules is a power in watts.
+pub fn rate_unit(counter_unit: &PrefixedUnit) -> (PrefixedUnit, f64) {
+    let (base_unit, factor) = match &counter_unit.base_unit {
+        Unit::Joule => (Unit::Watt, 1.0),
+        // 1 Wh/s = 3600 W
+        Unit::WattHour => (Unit::Watt, 3600.0),
+        // A time spent per second, for instance a CPU time, is a ratio.
+        Unit::Second => (Unit::Unity, 1.0),
+        // A number of events per second is a frequency.
+        Unit::Unity => (Unit::Hertz, 1.0),
+        unit => {
+            let custom = Unit::Custom {
+                unique_name: format!("{}/s", unit.unique_name()),
+                display_name: format!("{unit}/s"),
+            };
+            (custom, 1.0)
+        }
+    };
+    let unit = PrefixedUnit {
+        base_unit,
+        prefix: counter_unit.prefix.clone(),
+    };
+    (unit, factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::units::{PrefixedUnit, Unit};
+
+    use super::rate_unit;
+
+    #[test]
+    fn derived_units() {
+        assert_eq!(rate_unit(&Unit::Joule.into()), (Unit::Watt.into(), 1.0));
+        assert_eq!(
+            rate_unit(&PrefixedUnit::micro(Unit::Joule)),
+            (PrefixedUnit::micro(Unit::Watt), 1.0)
+        );
+        assert_eq!(
+            rate_unit(&PrefixedUnit::kilo(Unit::WattHour)),
+            (PrefixedUnit::kilo(Unit::Watt), 3600.0)
+        );
+        assert_eq!(
+            rate_unit(&PrefixedUnit::nano(Unit::Second)),
+            (PrefixedUnit::nano(Unit::Unity), 1.0)
+        );
+        assert_eq!(rate_unit(&Unit::Unity.into()), (Unit::Hertz.into(), 1.0));
+
+        let (unit, factor) = rate_unit(&PrefixedUnit::kilo(Unit::Byte));
+        assert_eq!(
+            unit,
+            PrefixedUnit::kilo(Unit::Custom {
+                unique_name: String::from("By/s"),
+                display_name: String::from("B/s"),
+            })
+        );
+        assert_eq!(factor, 1.0);
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, HashMap};
+
+use alumet::{
+    metrics::{Metric, RawMetricId},
+    pipeline::elements::transform::builder::{TransformBuildContext, TransformRegistration},
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+};
+use anyhow::{anyhow, Context};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use rule::{Rule, RuleAction};
+use transform::RelabelTransform;
+
+mod rule;
+mod transform;
+
+pub struct RelabelPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for RelabelPlugin {
+    fn name() -> &'static str {
+        "relabel"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(RelabelPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        if config.rules.is_empty() {
+            log::warn!("No relabeling rule, the relabel plugin will do nothing. Add some rules to its config.");
+            return Ok(());
+        }
+
+        let mut rules = Vec::with_capacity(config.rules.len());
+        for (i, rule) in config.rules.iter().enumerate() {
+            let rule = Rule::compile(rule).with_context(|| format!("invalid relabeling rule #{}", i + 1))?;
+            rules.push(rule);
+        }
+
+        // The new metrics depend on the metrics that are registered by other plugins.
+        // Therefore, they are registered when the transform is built.
+        alumet.add_transform_builder(move |ctx| {
+            for rule in &mut rules {
+                if let RuleAction::RenameMetric {
+                    regex,
+                    replacement,
+                    renamed,
+                } = &mut rule.action
+                {
+                    *renamed = register_renamed_metrics(ctx, &rule.selector.metrics, regex, replacement)?;
+                }
+            }
+            Ok(TransformRegistration {
+                name: ctx.transform_name("relabel"),
+                transform: Box::new(RelabelTransform::new(rules)),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Registers a new metric for each existing metric that is renamed by a rule, and returns the new ids.
+///
+/// Metrics that are registered later, while the pipeline is running, are not renamed.
+fn register_renamed_metrics(
+    ctx: &mut dyn TransformBuildContext,
+    patterns: &[Regex],
+    regex: &Regex,
+    replacement: &str,
+) -> anyhow::Result<HashMap<RawMetricId, RawMetricId>> {
+    let mut sources = Vec::new();
+    let mut new_metrics = Vec::new();
+    for (id, metric) in ctx.metrics().iter() {
+        if !patterns.is_empty() && !patterns.iter().any(|p| p.is_match(&metric.name)) {
+            continue;
+        }
+        let Some(new_name) = rule::replace(regex, &metric.name, replacement) else {
+            continue;
+        };
+        if new_name != metric.name {
+            sources.push(*id);
+            new_metrics.push(Metric {
+                name: new_name,
+                ..metric.clone()
+            });
+        }
+    }
+    if new_metrics.is_empty() {
+        log::warn!("No metric matches the renaming rule {regex}");
+        return Ok(HashMap::new());
+    }
+
+    let mut renamed = HashMap::with_capacity(sources.len());
+    for (source, metric) in sources.into_iter().zip(new_metrics) {
+        let new_id = ctx
+            .create_metric(metric)
+            .map_err(|e| anyhow!("could not rename metric {source:?}: {e}"))?;
+        renamed.insert(source, new_id);
+    }
+    Ok(renamed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Drops the points that do not match the rule.
+    Keep,
+    /// Drops the points that match the rule.
+    Drop,
+    /// Renames the metric of the points with a regex.
+    RenameMetric,
+    /// Sets some attributes to a static value.
+    AddAttributes,
+    DropAttributes,
+    RenameAttributes,
+    /// Rewrites the consumer of the points with a regex.
+    RewriteConsumer,
+}
+
+/// The value of a static attribute.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StaticValue {
+    Bool(bool),
+    U64(u64),
+    F64(f64),
+    String(String),
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// The rules, applied in order.
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    action: Action,
+
+    // Selection of the points: all the conditions must be satisfied.
+    /// Glob patterns on the metric name.
+    #[serde(default)]
+    metrics: Vec<String>,
+    /// Regex on the metric name.
+    #[serde(default)]
+    metrics_regex: Option<String>,
+    #[serde(default)]
+    resource_kinds: Vec<String>,
+    #[serde(default)]
+    consumer_kinds: Vec<String>,
+
+    // Parameters of the actions.
+    /// For `add_attributes`: the attributes to set.
+    #[serde(default)]
+    attributes: BTreeMap<String, StaticValue>,
+    /// For `drop_attributes`: the keys of the attributes to remove.
+    #[serde(default)]
+    keys: Vec<String>,
+    /// For `rename_attributes`: old key -> new key.
+    #[serde(default)]
+    rename: BTreeMap<String, String>,
+    /// For `rename_metric` and `rewrite_consumer`: regex that must match the whole metric name
+    /// or consumer id.
+    #[serde(default)]
+    regex: Option<String>,
+    /// For `rename_metric` and `rewrite_consumer`: the new value, with `$1`, `$name`, ...
+    /// replaced by the groups captured by the regex.
+    #[serde(default)]
+    replacement: Option<String>,
+    /// For `rewrite_consumer`: the new kind of consumer. By default, the kind is not changed.
+    #[serde(default)]
+    new_kind: Option<String>,
+}
@@ -0,0 +1,247 @@
+//! Compiled relabeling rules.
+
+use std::collections::HashMap;
+
+use alumet::{
+    measurement::{AttributeValue, MeasurementPoint},
+    metrics::RawMetricId,
+    resources::ResourceConsumer,
+};
+use anyhow::{anyhow, Context};
+use regex::Regex;
+
+use crate::{Action, RuleConfig, StaticValue};
+
+/// A relabeling rule: an action, applied to the points that match a selector.
+pub struct Rule {
+    pub selector: Selector,
+    pub action: RuleAction,
+}
+
+/// Selects measurement points by metric name, resource kind and consumer kind.
+///
+/// An empty list matches everything.
+pub struct Selector {
+    /// Patterns on the name of the metric.
+    pub metrics: Vec<Regex>,
+    pub resource_kinds: Vec<String>,
+    pub consumer_kinds: Vec<String>,
+}
+
+pub enum RuleAction {
+    /// Drops the points that do not match the selector.
+    Keep,
+    /// Drops the points that match the selector.
+    Drop,
+    /// Moves the points to metrics with another name.
+    RenameMetric {
+        regex: Regex,
+        replacement: String,
+        /// The renamed metrics, by original id.
+        ///
+        /// The new metrics are registered when the transform is built.
+        renamed: HashMap<RawMetricId, RawMetricId>,
+    },
+    AddAttributes(Vec<(String, AttributeValue)>),
+    DropAttributes(Vec<String>),
+    RenameAttributes(Vec<(String, String)>),
+    /// Rewrites the consumer with a regex.
+    RewriteConsumer {
+        regex: Regex,
+        replacement: String,
+        kind: Option<String>,
+    },
+}
+
+impl Rule {
+    pub fn compile(config: &RuleConfig) -> anyhow::Result<Self> {
+        let mut metrics = Vec::with_capacity(config.metrics.len() + 1);
+        for pattern in &config.metrics {
+            metrics.push(glob_to_regex(pattern)?);
+        }
+        if let Some(regex) = &config.metrics_regex {
+            metrics.push(anchored_regex(regex)?);
+        }
+        let selector = Selector {
+            metrics,
+            resource_kinds: config.resource_kinds.clone(),
+            consumer_kinds: config.consumer_kinds.clone(),
+        };
+
+        let action = match config.action {
+            Action::Keep => RuleAction::Keep,
+            Action::Drop => RuleAction::Drop,
+            Action::RenameMetric => RuleAction::RenameMetric {
+                regex: anchored_regex(required(&config.regex, "regex")?)?,
+                replacement: required(&config.replacement, "replacement")?.to_owned(),
+                renamed: HashMap::new(),
+            },
+            Action::AddAttributes => {
+                if config.attributes.is_empty() {
+                    return Err(anyhow!("missing option for action add_attributes: attributes"));
+                }
+                let attributes = config
+                    .attributes
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.to_attribute()))
+                    .collect();
+                RuleAction::AddAttributes(attributes)
+            }
+            Action::DropAttributes => {
+                if config.keys.is_empty() {
+                    return Err(anyhow!("missing option for action drop_attributes: keys"));
+                }
+                RuleAction::DropAttributes(config.keys.clone())
+            }
+            Action::RenameAttributes => {
+                if config.rename.is_empty() {
+                    return Err(anyhow!("missing option for action rename_attributes: rename"));
+                }
+                let rename = config.rename.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                RuleAction::RenameAttributes(rename)
+            }
+            Action::RewriteConsumer => RuleAction::RewriteConsumer {
+                regex: anchored_regex(required(&config.regex, "regex")?)?,
+                replacement: required(&config.replacement, "replacement")?.to_owned(),
+                kind: config.new_kind.clone(),
+            },
+        };
+        Ok(Self { selector, action })
+    }
+}
+
+impl Selector {
+    /// Returns `true` if the point matches the selector.
+    pub fn matches<'m>(&self, point: &MeasurementPoint, metric_name: impl Fn(&RawMetricId) -> Option<&'m str>) -> bool {
+        (self.resource_kinds.is_empty() || self.resource_kinds.iter().any(|k| k == point.resource.kind()))
+            && (self.consumer_kinds.is_empty() || self.consumer_kinds.iter().any(|k| k == point.consumer.kind()))
+            && (self.metrics.is_empty()
+                || metric_name(&point.metric).is_some_and(|name| self.metrics.iter().any(|r| r.is_match(name))))
+    }
+}
+
+impl RuleAction {
+    /// Applies the action to a point that matches the selector of the rule.
+    ///
+    /// `Keep` and `Drop` are handled by the caller.
+    pub fn apply(&self, point: &mut MeasurementPoint) {
+        match self {
+            RuleAction::Keep | RuleAction::Drop => (),
+            RuleAction::RenameMetric { renamed, .. } => {
+                if let Some(new_id) = renamed.get(&point.metric) {
+                    point.metric = *new_id;
+                }
+            }
+            RuleAction::AddAttributes(attributes) => {
+                for (key, value) in attributes {
+                    point.remove_attr(key);
+                    point.add_attr(key.clone(), value.clone());
+                }
+            }
+            RuleAction::DropAttributes(keys) => {
+                for key in keys {
+                    point.remove_attr(key);
+                }
+            }
+            RuleAction::RenameAttributes(rename) => {
+                for (key, new_key) in rename {
+                    if let Some(value) = point.remove_attr(key) {
+                        point.remove_attr(new_key);
+                        point.add_attr(new_key.clone(), value);
+                    }
+                }
+            }
+            RuleAction::RewriteConsumer {
+                regex,
+                replacement,
+                kind,
+            } => {
+                let id = point.consumer.id_string().unwrap_or_default();
+                let Some(new_id) = replace(regex, &id, replacement) else {
+                    return;
+                };
+                let kind = kind.as_deref().unwrap_or(point.consumer.kind());
+                let consumer = if kind == "local_machine" {
+                    Ok(ResourceConsumer::LocalMachine)
+                } else {
+                    ResourceConsumer::parse(kind.to_owned(), new_id)
+                };
+                match consumer {
+                    Ok(consumer) => point.consumer = consumer,
+                    Err(e) => log::debug!("Could not rewrite consumer {:?}: {e}", point.consumer),
+                }
+            }
+        }
+    }
+}
+
+impl StaticValue {
+    fn to_attribute(&self) -> AttributeValue {
+        match self {
+            StaticValue::Bool(b) => AttributeValue::Bool(*b),
+            StaticValue::U64(n) => AttributeValue::U64(*n),
+            StaticValue::F64(x) => AttributeValue::F64(*x),
+            StaticValue::String(s) => AttributeValue::String(s.clone()),
+        }
+    }
+}
+
+/// If `regex` matches `value`, returns the replacement, with the capture groups
+/// (`$1`, `$name`, ...) expanded.
+pub fn replace(regex: &Regex, value: &str, replacement: &str) -> Option<String> {
+    let captures = regex.captures(value)?;
+    let mut res = String::new();
+    captures.expand(replacement, &mut res);
+    Some(res)
+}
+
+/// Compiles a regex that must match the whole string.
+fn anchored_regex(regex: &str) -> anyhow::Result<Regex> {
+    Regex::new(&format!("^(?:{regex})$")).with_context(|| format!("invalid regex: {regex}"))
+}
+
+/// Compiles a glob pattern, where `*` matches any sequence of characters and `?` matches one character.
+fn glob_to_regex(pattern: &str) -> anyhow::Result<Regex> {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).with_context(|| format!("invalid pattern: {pattern}"))
+}
+
+fn required<'a>(option: &'a Option<String>, name: &str) -> anyhow::Result<&'a str> {
+    option
+        .as_deref()
+        .with_context(|| format!("missing option for this action: {name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{anchored_regex, glob_to_regex, replace};
+
+    #[test]
+    fn glob() {
+        let r = glob_to_regex("rapl_*").unwrap();
+        assert!(r.is_match("rapl_consumed_energy"));
+        assert!(!r.is_match("hwmon_rapl_power"));
+        let r = glob_to_regex("cpu?.time").unwrap();
+        assert!(r.is_match("cpu1.time"));
+        assert!(!r.is_match("cpu1_time"));
+        assert!(!r.is_match("cpu12.time"));
+    }
+
+    #[test]
+    fn replacement() {
+        let r = anchored_regex("/kubepods/(?<qos>\\w+)/pod([^/]+)").unwrap();
+        assert_eq!(
+            replace(&r, "/kubepods/burstable/pod1234", "pod-$2 ($qos)"),
+            Some(String::from("pod-1234 (burstable)"))
+        );
+        assert_eq!(replace(&r, "/kubepods/burstable/pod1234/container", "$2"), None);
+    }
+}
@@ -0,0 +1,231 @@
+use alumet::{
+    measurement::MeasurementBuffer,
+    metrics::RawMetricId,
+    pipeline::{
+        elements::{error::TransformError, transform::TransformContext},
+        Transform,
+    },
+};
+
+use crate::rule::{Rule, RuleAction};
+
+/// Filters and relabels the measurements, by applying a list of rules in order.
+pub struct RelabelTransform {
+    rules: Vec<Rule>,
+}
+
+impl RelabelTransform {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self { rules }
+    }
+
+    fn process<'m>(&self, measurements: &mut MeasurementBuffer, metric_name: impl Fn(&RawMetricId) -> Option<&'m str>) {
+        for rule in &self.rules {
+            let selector = &rule.selector;
+            match &rule.action {
+                RuleAction::Keep => measurements.retain(|p| selector.matches(p, &metric_name)),
+                RuleAction::Drop => measurements.retain(|p| !selector.matches(p, &metric_name)),
+                action => {
+                    for point in measurements.iter_mut() {
+                        if selector.matches(point, &metric_name) {
+                            action.apply(point);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Transform for RelabelTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements, |id| ctx.metrics.by_id(id).map(|m| m.name.as_str()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        time::UNIX_EPOCH,
+    };
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::RelabelTransform;
+    use crate::{
+        rule::{Rule, RuleAction},
+        Action, RuleConfig, StaticValue,
+    };
+
+    fn metric_name(id: &RawMetricId) -> Option<&'static str> {
+        match id.as_u64() {
+            0 => Some("rapl_consumed_energy"),
+            1 => Some("cgroup_cpu_usage_user"),
+            2 => Some("energy_package"),
+            _ => None,
+        }
+    }
+
+    fn point(metric: u64, resource: Resource, consumer: ResourceConsumer) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH),
+            RawMetricId::from_u64(metric),
+            resource,
+            consumer,
+            WrappedMeasurementValue::U64(1),
+        )
+    }
+
+    fn rule(action: Action) -> RuleConfig {
+        RuleConfig {
+            action,
+            metrics: Vec::new(),
+            metrics_regex: None,
+            resource_kinds: Vec::new(),
+            consumer_kinds: Vec::new(),
+            attributes: BTreeMap::new(),
+            keys: Vec::new(),
+            rename: BTreeMap::new(),
+            regex: None,
+            replacement: None,
+            new_kind: None,
+        }
+    }
+
+    fn transform(rules: Vec<RuleConfig>) -> RelabelTransform {
+        RelabelTransform::new(rules.iter().map(|r| Rule::compile(r).unwrap()).collect())
+    }
+
+    fn buffer() -> MeasurementBuffer {
+        MeasurementBuffer::from(vec![
+            point(0, Resource::CpuPackage { id: 0 }, ResourceConsumer::LocalMachine).with_attr("domain", "package"),
+            point(0, Resource::Dram { pkg_id: 0 }, ResourceConsumer::LocalMachine).with_attr("domain", "dram"),
+            point(
+                1,
+                Resource::LocalMachine,
+                ResourceConsumer::ControlGroup {
+                    path: "/kubepods/burstable/pod1234".into(),
+                },
+            ),
+            point(1, Resource::LocalMachine, ResourceConsumer::Process { pid: 42 }),
+        ])
+    }
+
+    fn metrics(buf: &MeasurementBuffer) -> Vec<u64> {
+        buf.iter().map(|p| p.metric.as_u64()).collect()
+    }
+
+    #[test]
+    fn keep_and_drop() {
+        let mut keep = rule(Action::Keep);
+        keep.metrics = vec![String::from("rapl_*")];
+        let t = transform(vec![keep]);
+        let mut buf = buffer();
+        t.process(&mut buf, metric_name);
+        assert_eq!(metrics(&buf), vec![0, 0]);
+
+        let mut drop = rule(Action::Drop);
+        drop.resource_kinds = vec![String::from("dram")];
+        let mut drop_processes = rule(Action::Drop);
+        drop_processes.metrics_regex = Some(String::from("cgroup_.+"));
+        drop_processes.consumer_kinds = vec![String::from("process")];
+        let t = transform(vec![drop, drop_processes]);
+        let mut buf = buffer();
+        t.process(&mut buf, metric_name);
+        assert_eq!(metrics(&buf), vec![0, 1]);
+        assert_eq!(buf.iter().nth(1).unwrap().consumer.kind(), "cgroup");
+    }
+
+    #[test]
+    fn attributes() {
+        let mut add = rule(Action::AddAttributes);
+        add.attributes = BTreeMap::from([
+            (String::from("cluster"), StaticValue::String(String::from("g5k"))),
+            (String::from("job_id"), StaticValue::U64(123)),
+        ]);
+        let mut rename = rule(Action::RenameAttributes);
+        rename.rename = BTreeMap::from([(String::from("domain"), String::from("rapl_domain"))]);
+        let mut drop = rule(Action::DropAttributes);
+        drop.keys = vec![String::from("cluster")];
+        drop.consumer_kinds = vec![String::from("process")];
+        let t = transform(vec![add, rename, drop]);
+
+        let mut buf = buffer();
+        t.process(&mut buf, metric_name);
+        let attributes: Vec<String> = buf
+            .iter()
+            .map(|p| {
+                let attrs: Vec<String> = p.attributes().map(|(k, v)| format!("{k}={v}")).collect();
+                attrs.join(",")
+            })
+            .collect();
+        assert_eq!(
+            attributes,
+            vec![
+                "cluster=g5k,job_id=123,rapl_domain=package",
+                "cluster=g5k,job_id=123,rapl_domain=dram",
+                "cluster=g5k,job_id=123",
+                "job_id=123",
+            ]
+        );
+    }
+
+    #[test]
+    fn rewrite_consumer() {
+        let mut rewrite = rule(Action::RewriteConsumer);
+        rewrite.regex = Some(String::from("/kubepods/\\w+/pod(.+)"));
+        rewrite.replacement = Some(String::from("$1"));
+        rewrite.new_kind = Some(String::from("pod"));
+        let mut machine = rule(Action::RewriteConsumer);
+        machine.consumer_kinds = vec![String::from("process")];
+        machine.regex = Some(String::from(".*"));
+        machine.replacement = Some(String::new());
+        machine.new_kind = Some(String::from("local_machine"));
+        let t = transform(vec![rewrite, machine]);
+
+        let mut buf = buffer();
+        t.process(&mut buf, metric_name);
+        let consumers: Vec<ResourceConsumer> = buf.iter().map(|p| p.consumer.clone()).collect();
+        assert_eq!(
+            consumers,
+            vec![
+                ResourceConsumer::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                ResourceConsumer::custom("pod", "1234"),
+                ResourceConsumer::LocalMachine,
+            ]
+        );
+    }
+
+    #[test]
+    fn rename_metric() {
+        let mut rename = rule(Action::RenameMetric);
+        rename.regex = Some(String::from("rapl_consumed_(.*)"));
+        rename.replacement = Some(String::from("${1}_package"));
+        rename.resource_kinds = vec![String::from("cpu_package")];
+        let mut t = transform(vec![rename]);
+        let RuleAction::RenameMetric { renamed, .. } = &mut t.rules[0].action else {
+            unreachable!()
+        };
+        *renamed = HashMap::from([(RawMetricId::from_u64(0), RawMetricId::from_u64(2))]);
+
+        let mut buf = buffer();
+        t.process(&mut buf, metric_name);
+        assert_eq!(metrics(&buf), vec![2, 0, 1, 1]);
+    }
+
+    #[test]
+    fn missing_option() {
+        assert!(Rule::compile(&rule(Action::RenameMetric)).is_err());
+        assert!(Rule::compile(&rule(Action::AddAttributes)).is_err());
+        let mut invalid = rule(Action::Keep);
+        invalid.metrics_regex = Some(String::from("(unclosed"));
+        assert!(Rule::compile(&invalid).is_err());
+    }
+}
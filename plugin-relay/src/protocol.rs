@@ -17,7 +17,7 @@ use crate::serde_impl;
 /// Version number of the current protocol.
 ///
 /// IMPORTANT: you must increase this number when the protocol changes.
-pub const PROTOCOL_VERSION: u32 = 2;
+pub const PROTOCOL_VERSION: u32 = 5;
 
 /// Maximum size (in bytes) of a message body.
 ///
@@ -98,6 +98,8 @@ pub struct Metric {
     pub name: String,
     pub value_type: MetricType,
     pub unit: MetricUnit,
+    pub kind: MetricKind,
+    pub labels: Vec<(String, String)>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -110,6 +112,17 @@ pub struct MetricUnit {
 pub enum MetricType {
     F64,
     U64,
+    I64,
+    Bool,
+    Histogram,
+}
+
+/// The kind of a metric, see [`alumet::metrics::MetricKind`].
+#[derive(Serialize, Deserialize, Debug)]
+pub enum MetricKind {
+    Gauge,
+    Counter,
+    Delta,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -333,6 +346,9 @@ impl From<WrappedMeasurementType> for MetricType {
         match value {
             WrappedMeasurementType::F64 => MetricType::F64,
             WrappedMeasurementType::U64 => MetricType::U64,
+            WrappedMeasurementType::I64 => MetricType::I64,
+            WrappedMeasurementType::Bool => MetricType::Bool,
+            WrappedMeasurementType::Histogram => MetricType::Histogram,
         }
     }
 }
@@ -342,6 +358,29 @@ impl From<MetricType> for WrappedMeasurementType {
         match value {
             MetricType::F64 => WrappedMeasurementType::F64,
             MetricType::U64 => WrappedMeasurementType::U64,
+            MetricType::I64 => WrappedMeasurementType::I64,
+            MetricType::Bool => WrappedMeasurementType::Bool,
+            MetricType::Histogram => WrappedMeasurementType::Histogram,
+        }
+    }
+}
+
+impl From<alumet::metrics::MetricKind> for MetricKind {
+    fn from(value: alumet::metrics::MetricKind) -> Self {
+        match value {
+            alumet::metrics::MetricKind::Gauge => MetricKind::Gauge,
+            alumet::metrics::MetricKind::Counter => MetricKind::Counter,
+            alumet::metrics::MetricKind::Delta => MetricKind::Delta,
+        }
+    }
+}
+
+impl From<MetricKind> for alumet::metrics::MetricKind {
+    fn from(value: MetricKind) -> Self {
+        match value {
+            MetricKind::Gauge => alumet::metrics::MetricKind::Gauge,
+            MetricKind::Counter => alumet::metrics::MetricKind::Counter,
+            MetricKind::Delta => alumet::metrics::MetricKind::Delta,
         }
     }
 }
@@ -354,6 +393,8 @@ impl From<(RawMetricId, alumet::metrics::Metric)> for Metric {
             name: def.name,
             value_type: def.value_type.into(),
             unit: def.unit.into(),
+            kind: def.kind.into(),
+            labels: def.labels,
         }
     }
 }
@@ -1,11 +1,11 @@
-use std::time::{Duration, SystemTime};
+use std::borrow::Cow;
 
 use alumet::{
-    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    measurement::{AttributeValue, Histogram, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
     metrics::RawMetricId,
     resources::{Resource, ResourceConsumer},
 };
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use serde::{ser::SerializeSeq, Deserialize, Serialize};
 
 /// A measurement buffer than can be serialized and deserialized. This type is similar to [`std::borrow::Cow`].
@@ -135,19 +135,17 @@ impl<'a> TryFrom<SerializableMeasurementPoint<'a>> for MeasurementPoint {
     type Error = anyhow::Error;
 
     fn try_from(point: SerializableMeasurementPoint<'a>) -> Result<Self, Self::Error> {
-        let timestamp = SystemTime::UNIX_EPOCH
-            .checked_add(Duration::new(point.timestamp.secs, point.timestamp.nanos))
-            .context("invalid timestamp")?
-            .into();
+        let timestamp =
+            Timestamp::from_unix_timestamp(point.timestamp.secs, point.timestamp.nanos).context("invalid timestamp")?;
         let metric = RawMetricId::from_u64(point.metric_id);
         let resource = Resource::parse(point.resource_kind.to_owned(), point.resource_id)?;
         let consumer = ResourceConsumer::parse(point.consumer_kind.to_owned(), point.consumer_id)?;
-        let value: WrappedMeasurementValue = point.value.into();
+        let value = WrappedMeasurementValue::try_from(point.value)?;
         let attributes = point
             .attributes
             .iter()
-            .map(|(k, v)| (k.to_string(), AttributeValue::from(v)))
-            .collect();
+            .map(|(k, v)| Ok((k.to_string(), AttributeValue::try_from(v)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
         Ok(MeasurementPoint::new_untyped(timestamp, metric, resource, consumer, value).with_attr_vec(attributes))
     }
 }
@@ -158,11 +156,20 @@ pub enum TypedValue<'a> {
     U64(u64),
     Bool(bool),
     Str(&'a str),
+    I64(i64),
+    Histogram(SerializableHistogram<'a>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerializableHistogram<'a> {
+    bounds: Cow<'a, [f64]>,
+    counts: Cow<'a, [u64]>,
+    sum: f64,
 }
 
 #[derive(Serialize, Deserialize)]
 struct UnixTimestamp {
-    secs: u64,
+    secs: i64,
     nanos: u32,
 }
 
@@ -171,17 +178,31 @@ impl<'a> From<&'a WrappedMeasurementValue> for TypedValue<'a> {
         match value {
             WrappedMeasurementValue::F64(v) => TypedValue::F64(*v),
             WrappedMeasurementValue::U64(v) => TypedValue::U64(*v),
+            WrappedMeasurementValue::I64(v) => TypedValue::I64(*v),
+            WrappedMeasurementValue::Bool(v) => TypedValue::Bool(*v),
+            WrappedMeasurementValue::Histogram(h) => TypedValue::Histogram(SerializableHistogram {
+                bounds: Cow::Borrowed(h.bounds()),
+                counts: Cow::Borrowed(h.counts()),
+                sum: h.sum(),
+            }),
         }
     }
 }
 
-impl<'a> From<TypedValue<'a>> for WrappedMeasurementValue {
-    fn from(value: TypedValue<'a>) -> Self {
+impl<'a> TryFrom<TypedValue<'a>> for WrappedMeasurementValue {
+    type Error = anyhow::Error;
+
+    fn try_from(value: TypedValue<'a>) -> Result<Self, Self::Error> {
         match value {
-            TypedValue::F64(v) => WrappedMeasurementValue::F64(v),
-            TypedValue::U64(v) => WrappedMeasurementValue::U64(v),
-            TypedValue::Bool(_) => unreachable!("MeasurementPoint values should never be Bool"),
-            TypedValue::Str(_) => unreachable!("MeasurementPoint values should never be Str"),
+            TypedValue::F64(v) => Ok(WrappedMeasurementValue::F64(v)),
+            TypedValue::U64(v) => Ok(WrappedMeasurementValue::U64(v)),
+            TypedValue::I64(v) => Ok(WrappedMeasurementValue::I64(v)),
+            TypedValue::Bool(v) => Ok(WrappedMeasurementValue::Bool(v)),
+            TypedValue::Histogram(h) => {
+                let h = Histogram::from_parts(h.bounds.into_owned(), h.counts.into_owned(), h.sum)?;
+                Ok(WrappedMeasurementValue::Histogram(h))
+            }
+            TypedValue::Str(_) => Err(anyhow!("MeasurementPoint values should never be Str")),
         }
     }
 }
@@ -193,18 +214,23 @@ impl<'a> From<&'a AttributeValue> for TypedValue<'a> {
             AttributeValue::U64(v) => TypedValue::U64(*v),
             AttributeValue::Bool(v) => TypedValue::Bool(*v),
             AttributeValue::Str(v) => TypedValue::Str(v),
+            AttributeValue::Shared(v) => TypedValue::Str(v),
             AttributeValue::String(v) => TypedValue::Str(v),
         }
     }
 }
 
-impl<'a> From<&'a TypedValue<'a>> for AttributeValue {
-    fn from(value: &'a TypedValue<'a>) -> Self {
+impl<'a> TryFrom<&'a TypedValue<'a>> for AttributeValue {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &'a TypedValue<'a>) -> Result<Self, Self::Error> {
         match value {
-            TypedValue::F64(v) => AttributeValue::F64(*v),
-            TypedValue::U64(v) => AttributeValue::U64(*v),
-            TypedValue::Bool(v) => AttributeValue::Bool(*v),
-            TypedValue::Str(v) => AttributeValue::String(v.to_string()),
+            TypedValue::F64(v) => Ok(AttributeValue::F64(*v)),
+            TypedValue::U64(v) => Ok(AttributeValue::U64(*v)),
+            TypedValue::Bool(v) => Ok(AttributeValue::Bool(*v)),
+            TypedValue::Str(v) => Ok(AttributeValue::String(v.to_string())),
+            TypedValue::I64(_) => Err(anyhow!("attribute values should never be I64")),
+            TypedValue::Histogram(_) => Err(anyhow!("attribute values should never be Histogram")),
         }
     }
 }
@@ -215,3 +241,49 @@ impl<'a> From<&'a Timestamp> for UnixTimestamp {
         Self { secs, nanos }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alumet::{
+        measurement::{Histogram, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::SerdeMeasurementBuffer;
+
+    #[test]
+    fn roundtrip_values() {
+        let mut histogram = Histogram::new(vec![0.5, 1.0]).unwrap();
+        histogram.record(0.7);
+        let values = vec![
+            WrappedMeasurementValue::F64(1.5),
+            WrappedMeasurementValue::U64(42),
+            WrappedMeasurementValue::I64(-7),
+            WrappedMeasurementValue::Bool(true),
+            WrappedMeasurementValue::Histogram(histogram),
+        ];
+        let buf: MeasurementBuffer = values
+            .iter()
+            .map(|v| {
+                MeasurementPoint::new_untyped(
+                    Timestamp::now(),
+                    RawMetricId::from_u64(1),
+                    Resource::LocalMachine,
+                    ResourceConsumer::LocalMachine,
+                    v.clone(),
+                )
+                .with_attr("k", 3)
+            })
+            .collect();
+
+        let bytes = postcard::to_allocvec(&SerdeMeasurementBuffer::Borrowed(&buf)).unwrap();
+        let deserialized: SerdeMeasurementBuffer = postcard::from_bytes(&bytes).unwrap();
+        let deserialized = deserialized.owned();
+        assert_eq!(deserialized.len(), values.len());
+        for (point, expected) in deserialized.iter().zip(values) {
+            assert_eq!(point.value.to_string(), expected.to_string());
+            assert_eq!(point.attributes_len(), 1);
+        }
+    }
+}
@@ -74,6 +74,8 @@ impl TcpSource {
                         description: String::from("remote metric via plugin_relay"),
                         value_type: protocol_metric.value_type.try_into()?,
                         unit: protocol_metric.unit.try_into()?,
+                        kind: protocol_metric.kind.into(),
+                        labels: protocol_metric.labels,
                     };
                     metric_defs.push(alumet_metric);
                     metric_ids.push(protocol_metric.id);
@@ -0,0 +1,126 @@
+use std::{collections::HashMap, time::Duration};
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    metrics::{MetricKind, RawMetricId},
+    pipeline::elements::transform::builder::{TransformBuildContext, TransformRegistration},
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use transform::ResampleTransform;
+
+mod transform;
+
+pub struct ResamplePlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for ResamplePlugin {
+    fn name() -> &'static str {
+        "resample"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(ResamplePlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        if config.metrics.is_empty() {
+            log::warn!("No metric to resample, the resample plugin will do nothing. Add some metrics to its config.");
+            return Ok(());
+        }
+        if config.interval.is_zero() {
+            return Err(anyhow!("the resampling interval must not be zero"));
+        }
+
+        // The kind of the metrics tells how to resample them, but they are registered by other plugins.
+        // Therefore, they are looked up when the transform is built.
+        alumet.add_transform_builder(move |ctx| {
+            let kinds = find_metrics(ctx, &config.metrics)?;
+            let transform = ResampleTransform::new(
+                kinds,
+                config.interval,
+                config.keep_original,
+                config.marker_attribute,
+                config.forget_after,
+            );
+            Ok(TransformRegistration {
+                name: ctx.transform_name("resample"),
+                transform: Box::new(transform),
+            })
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Finds the metrics to resample, and returns their kind.
+fn find_metrics(
+    ctx: &mut dyn TransformBuildContext,
+    metrics: &[String],
+) -> anyhow::Result<HashMap<RawMetricId, MetricKind>> {
+    let mut res = HashMap::with_capacity(metrics.len());
+    for name in metrics {
+        let (id, metric) = ctx
+            .metric_by_name(name)
+            .with_context(|| format!("metric not found: {name}"))?;
+        if matches!(
+            metric.value_type,
+            WrappedMeasurementType::Bool | WrappedMeasurementType::Histogram
+        ) {
+            return Err(anyhow!(
+                "metric {name} has type {}, it cannot be resampled",
+                metric.value_type
+            ));
+        }
+        res.insert(id, metric.kind);
+    }
+    Ok(res)
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Duration between two points of the grid.
+    #[serde(with = "humantime_serde")]
+    interval: Duration,
+    /// Keep the original measurements, in addition to the resampled ones.
+    keep_original: bool,
+    /// Name of the attribute that marks the resampled measurements.
+    marker_attribute: String,
+    /// Forget the state of a series when it has not been updated for this duration.
+    #[serde(with = "humantime_serde")]
+    forget_after: Duration,
+    /// Names of the metrics to resample.
+    metrics: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            keep_original: false,
+            marker_attribute: String::from("interpolated"),
+            forget_after: Duration::from_secs(300),
+            metrics: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::Duration,
+};
+
+use alumet::{
+    measurement::{
+        AttributeKey, AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue,
+    },
+    metrics::{MetricKind, RawMetricId},
+    pipeline::{
+        elements::{error::TransformError, transform::TransformContext},
+        Transform,
+    },
+    resources::{Resource, ResourceConsumer},
+};
+
+/// Resamples the measurements of some metrics to a regular time grid.
+pub struct ResampleTransform {
+    /// The metrics to resample, with their kind.
+    kinds: HashMap<RawMetricId, MetricKind>,
+    /// Duration between two points of the grid, in nanoseconds.
+    interval: i64,
+    /// Keep the original measurements, in addition to the resampled ones.
+    keep_original: bool,
+    /// Attribute added to the resampled measurements.
+    marker: AttributeKey,
+    /// Forget the series that have not been updated for this duration, in nanoseconds.
+    forget_after: i64,
+    series: HashMap<SeriesKey, SeriesState>,
+    last_cleanup: Option<i64>,
+}
+
+/// Identifies a time series.
+#[derive(PartialEq, Eq, Hash)]
+struct SeriesKey {
+    metric: RawMetricId,
+    resource: Resource,
+    consumer: ResourceConsumer,
+    attributes: Vec<(String, String)>,
+}
+
+struct SeriesState {
+    /// Time of the last measurement, in nanoseconds since the Unix epoch.
+    timestamp: i64,
+    /// Value of the last measurement.
+    value: f64,
+    /// For deltas, the amount that has been accumulated in the current interval of the grid.
+    pending: f64,
+}
+
+impl ResampleTransform {
+    pub fn new(
+        kinds: HashMap<RawMetricId, MetricKind>,
+        interval: Duration,
+        keep_original: bool,
+        marker_attribute: String,
+        forget_after: Duration,
+    ) -> Self {
+        Self {
+            kinds,
+            interval: nanos(interval).max(1),
+            keep_original,
+            marker: AttributeKey::from(marker_attribute),
+            forget_after: nanos(forget_after),
+            series: HashMap::new(),
+            last_cleanup: None,
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        let mut resampled = Vec::new();
+        let mut latest = None;
+        for point in measurements.iter() {
+            let Some(kind) = self.kinds.get(&point.metric) else {
+                continue;
+            };
+            let Some(value) = point.value.as_f64() else {
+                continue;
+            };
+            let t = point.timestamp.to_unix_nanos();
+            latest = latest.max(Some(t));
+            let mut emit = |grid_t: i64, v: WrappedMeasurementValue| {
+                let mut p = point.clone();
+                p.timestamp = timestamp(grid_t);
+                p.value = v;
+                p.add_attr(self.marker.clone(), AttributeValue::Bool(true));
+                resampled.push(p);
+            };
+            match self.series.entry(SeriesKey::of(point)) {
+                Entry::Occupied(mut entry) if t - entry.get().timestamp <= self.forget_after => {
+                    let state = entry.get_mut();
+                    if t <= state.timestamp {
+                        log::debug!("Ignoring out-of-order value of {:?}", point.metric);
+                        continue;
+                    }
+                    if *kind == MetricKind::Delta {
+                        state.split(t, value, &point.value, self.interval, &mut emit);
+                    } else {
+                        state.interpolate(t, value, &point.value, self.interval, &mut emit);
+                    }
+                }
+                entry => {
+                    // First value of the series, or the series has been interrupted for a long time: start again.
+                    // A delta covers the time since the previous measurement, which is unknown here, so it is skipped.
+                    // A gauge can be used directly if it is on the grid.
+                    if *kind != MetricKind::Delta && t.rem_euclid(self.interval) == 0 {
+                        emit(t, point.value.clone());
+                    }
+                    let state = SeriesState {
+                        timestamp: t,
+                        value,
+                        pending: 0.0,
+                    };
+                    match entry {
+                        Entry::Occupied(mut e) => {
+                            e.insert(state);
+                        }
+                        Entry::Vacant(e) => {
+                            e.insert(state);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !self.keep_original {
+            measurements.retain(|p| !self.kinds.contains_key(&p.metric));
+        }
+        for point in resampled {
+            measurements.push(point);
+        }
+        if let Some(now) = latest {
+            self.forget_old_series(now);
+        }
+    }
+
+    /// Removes the series that have not been updated recently.
+    fn forget_old_series(&mut self, now: i64) {
+        let due = self.last_cleanup.is_none_or(|t| now - t >= self.forget_after);
+        if due {
+            let forget_after = self.forget_after;
+            self.series.retain(|_, s| now - s.timestamp < forget_after);
+            self.last_cleanup = Some(now);
+        }
+    }
+}
+
+impl Transform for ResampleTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+impl SeriesKey {
+    fn of(point: &MeasurementPoint) -> Self {
+        let mut attributes: Vec<(String, String)> =
+            point.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect();
+        attributes.sort_unstable();
+        Self {
+            metric: point.metric,
+            resource: point.resource.clone(),
+            consumer: point.consumer.clone(),
+            attributes,
+        }
+    }
+}
+
+impl SeriesState {
+    /// Linearly interpolates the value of a gauge (or a counter) at each point of the grid
+    /// between the previous measurement and the new one.
+    fn interpolate(
+        &mut self,
+        t: i64,
+        value: f64,
+        original: &WrappedMeasurementValue,
+        interval: i64,
+        emit: &mut impl FnMut(i64, WrappedMeasurementValue),
+    ) {
+        for grid_t in grid(self.timestamp, t, interval) {
+            let ratio = (grid_t - self.timestamp) as f64 / (t - self.timestamp) as f64;
+            let v = self.value + (value - self.value) * ratio;
+            emit(grid_t, same_type(v.round(), v, original));
+        }
+        self.timestamp = t;
+        self.value = value;
+    }
+
+    /// Splits a delta, which covers the time since the previous measurement, between the intervals of the grid,
+    /// in proportion to their overlap.
+    ///
+    /// Integer deltas are truncated, and the remainder is carried over to the next interval, so that the sum
+    /// of the resampled values is equal to the sum of the original values.
+    fn split(
+        &mut self,
+        t: i64,
+        value: f64,
+        original: &WrappedMeasurementValue,
+        interval: i64,
+        emit: &mut impl FnMut(i64, WrappedMeasurementValue),
+    ) {
+        let span = (t - self.timestamp) as f64;
+        let mut cursor = self.timestamp;
+        for grid_t in grid(self.timestamp, t, interval) {
+            self.pending += value * (grid_t - cursor) as f64 / span;
+            let emitted = match original {
+                WrappedMeasurementValue::F64(_) => self.pending,
+                _ => self.pending.trunc(),
+            };
+            self.pending -= emitted;
+            emit(grid_t, same_type(emitted, emitted, original));
+            cursor = grid_t;
+        }
+        self.pending += value * (t - cursor) as f64 / span;
+        self.timestamp = t;
+        self.value = value;
+    }
+}
+
+/// Returns the points of the grid that are in `]after, until]`.
+fn grid(after: i64, until: i64, interval: i64) -> impl Iterator<Item = i64> {
+    let first = (after.div_euclid(interval) + 1) * interval;
+    (0..)
+        .map(move |i| first + i * interval)
+        .take_while(move |grid_t| *grid_t <= until)
+}
+
+/// Builds a value of the same type as `original`: `integer` for integer types, `float` otherwise.
+fn same_type(integer: f64, float: f64, original: &WrappedMeasurementValue) -> WrappedMeasurementValue {
+    match original {
+        WrappedMeasurementValue::U64(_) => WrappedMeasurementValue::U64(integer.max(0.0) as u64),
+        WrappedMeasurementValue::I64(_) => WrappedMeasurementValue::I64(integer as i64),
+        _ => WrappedMeasurementValue::F64(float),
+    }
+}
+
+fn nanos(d: Duration) -> i64 {
+    i64::try_from(d.as_nanos()).unwrap_or(i64::MAX)
+}
+
+fn timestamp(nanos: i64) -> Timestamp {
+    Timestamp::from_unix_timestamp(nanos.div_euclid(1_000_000_000), nanos.rem_euclid(1_000_000_000) as u32)
+        .expect("the time of the grid should be representable")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::{MetricKind, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::ResampleTransform;
+
+    fn gauge() -> RawMetricId {
+        RawMetricId::from_u64(0)
+    }
+    fn delta() -> RawMetricId {
+        RawMetricId::from_u64(1)
+    }
+
+    fn transform(keep_original: bool) -> ResampleTransform {
+        let kinds = HashMap::from([(gauge(), MetricKind::Gauge), (delta(), MetricKind::Delta)]);
+        ResampleTransform::new(
+            kinds,
+            Duration::from_secs(1),
+            keep_original,
+            String::from("interpolated"),
+            Duration::from_secs(60),
+        )
+    }
+
+    fn point(t_millis: u64, metric: RawMetricId, value: WrappedMeasurementValue) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_millis(t_millis)),
+            metric,
+            Resource::CpuPackage { id: 0 },
+            ResourceConsumer::LocalMachine,
+            value,
+        )
+    }
+
+    /// Returns (time in ms, value, is marked) for each point of the metric.
+    fn values(buf: &MeasurementBuffer, metric: RawMetricId) -> Vec<(i64, String, bool)> {
+        buf.iter()
+            .filter(|p| p.metric == metric)
+            .map(|p| {
+                let marked = p
+                    .attributes()
+                    .any(|(k, v)| k == "interpolated" && v.to_string() == "true");
+                (p.timestamp.to_unix_nanos() / 1_000_000, p.value.to_string(), marked)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn interpolate_gauge() {
+        let mut t = transform(false);
+        let mut buf = MeasurementBuffer::from(vec![point(500, gauge(), WrappedMeasurementValue::F64(10.0))]);
+        t.process(&mut buf);
+        assert!(buf.is_empty());
+
+        let mut buf = MeasurementBuffer::from(vec![point(2500, gauge(), WrappedMeasurementValue::F64(30.0))]);
+        t.process(&mut buf);
+        assert_eq!(
+            values(&buf, gauge()),
+            vec![(1000, "15".into(), true), (2000, "25".into(), true)]
+        );
+
+        // integer gauge, on the grid
+        let mut buf = MeasurementBuffer::from(vec![
+            point(3000, gauge(), WrappedMeasurementValue::U64(7)),
+            point(3000, delta(), WrappedMeasurementValue::U64(7)),
+        ]);
+        buf.iter_mut().for_each(|p| p.add_attr("series", "int"));
+        t.process(&mut buf);
+        assert_eq!(values(&buf, gauge()), vec![(3000, "7".into(), true)]);
+        assert!(values(&buf, delta()).is_empty());
+    }
+
+    #[test]
+    fn split_delta() {
+        let mut t = transform(true);
+        let mut buf = MeasurementBuffer::from(vec![point(500, delta(), WrappedMeasurementValue::U64(100))]);
+        t.process(&mut buf);
+        // the original point is kept, but the first delta cannot be split
+        assert_eq!(values(&buf, delta()), vec![(500, "100".into(), false)]);
+
+        // 30 units between 0.5s and 2s: 10 in ]0.5, 1], 20 in ]1, 2]
+        let mut buf = MeasurementBuffer::from(vec![point(2000, delta(), WrappedMeasurementValue::U64(30))]);
+        t.process(&mut buf);
+        assert_eq!(
+            values(&buf, delta()),
+            vec![
+                (2000, "30".into(), false),
+                (1000, "10".into(), true),
+                (2000, "20".into(), true)
+            ]
+        );
+
+        // 10 units between 2s and 5s: 3.33 per second, the remainder is carried over
+        let mut buf = MeasurementBuffer::from(vec![point(5000, delta(), WrappedMeasurementValue::U64(10))]);
+        t.process(&mut buf);
+        let split: Vec<_> = values(&buf, delta()).into_iter().filter(|(_, _, m)| *m).collect();
+        assert_eq!(
+            split,
+            vec![
+                (3000, "3".into(), true),
+                (4000, "3".into(), true),
+                (5000, "4".into(), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn float_delta_and_gap() {
+        let mut t = transform(false);
+        let mut buf = MeasurementBuffer::from(vec![point(0, delta(), WrappedMeasurementValue::F64(1.0))]);
+        t.process(&mut buf);
+        let mut buf = MeasurementBuffer::from(vec![point(1500, delta(), WrappedMeasurementValue::F64(3.0))]);
+        t.process(&mut buf);
+        assert_eq!(values(&buf, delta()), vec![(1000, "2".into(), true)]);
+
+        // the series has been interrupted for longer than forget_after: start again
+        let mut buf = MeasurementBuffer::from(vec![point(100_000, delta(), WrappedMeasurementValue::F64(3.0))]);
+        t.process(&mut buf);
+        assert!(buf.is_empty());
+    }
+}
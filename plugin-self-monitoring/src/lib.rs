@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use alumet::{
+    metrics::MetricKind,
+    pipeline::trigger::TriggerSpec,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+    units::Unit,
+};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use source::{Metrics, SelfMonitoringSource};
+
+mod process;
+mod source;
+
+pub struct SelfMonitoringPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for SelfMonitoringPlugin {
+    fn name() -> &'static str {
+        "self-monitoring"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(SelfMonitoringPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        if config.poll_interval.is_zero() {
+            return Err(anyhow!("poll_interval must not be zero"));
+        }
+
+        let metrics = Metrics {
+            source_poll_duration: alumet.create_metric(
+                "alumet_source_poll_duration",
+                Unit::Second,
+                "average time spent polling the source",
+            )?,
+            source_poll_jitter: alumet.create_metric(
+                "alumet_source_poll_jitter",
+                Unit::Second,
+                "average difference between the time elapsed between two polls and the interval of the trigger",
+            )?,
+            source_points: alumet.create_metric_with_kind(
+                "alumet_source_points",
+                MetricKind::Delta,
+                Unit::Unity,
+                "number of measurement points produced by the source",
+            )?,
+            source_poll_errors: alumet.create_metric_with_kind(
+                "alumet_source_poll_errors",
+                MetricKind::Delta,
+                Unit::Unity,
+                "number of failed polls, by kind of error",
+            )?,
+            transform_duration: alumet.create_metric(
+                "alumet_transform_duration",
+                Unit::Second,
+                "average time spent applying the transform",
+            )?,
+            transform_errors: alumet.create_metric_with_kind(
+                "alumet_transform_errors",
+                MetricKind::Delta,
+                Unit::Unity,
+                "number of failed applications of the transform",
+            )?,
+            output_write_duration: alumet.create_metric(
+                "alumet_output_write_duration",
+                Unit::Second,
+                "average time spent writing to the output",
+            )?,
+            output_write_errors: alumet.create_metric_with_kind(
+                "alumet_output_write_errors",
+                MetricKind::Delta,
+                Unit::Unity,
+                "number of writes that have failed with a non-fatal error",
+            )?,
+            output_lagged_buffers: alumet.create_metric_with_kind(
+                "alumet_output_lagged_buffers",
+                MetricKind::Delta,
+                Unit::Unity,
+                "number of buffers of measurements lost by the output, because it lagged behind or kept failing",
+            )?,
+            output_dropped_points: alumet.create_metric_with_kind(
+                "alumet_output_dropped_points",
+                MetricKind::Delta,
+                Unit::Unity,
+                "number of measurement points lost by the output",
+            )?,
+            channel_queued: alumet.create_metric(
+                "alumet_channel_queued",
+                Unit::Unity,
+                "number of buffers of measurements waiting in the channel",
+            )?,
+            process: if config.process {
+                Some(process::ProcessMetrics {
+                    cpu_time: alumet.create_metric_with_kind(
+                        "alumet_process_cpu_time",
+                        MetricKind::Delta,
+                        Unit::Second,
+                        "CPU time used by the Alumet agent",
+                    )?,
+                    memory_rss: alumet.create_metric(
+                        "alumet_process_memory_rss",
+                        Unit::Byte,
+                        "resident memory of the Alumet agent",
+                    )?,
+                })
+            } else {
+                None
+            },
+        };
+
+        let source = SelfMonitoringSource::new(alumet.pipeline_telemetry(), metrics);
+        alumet.add_source(Box::new(source), TriggerSpec::at_interval(config.poll_interval));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Time between two measurements of the pipeline.
+    #[serde(with = "humantime_serde")]
+    poll_interval: Duration,
+    /// Also measure the CPU and memory usage of the agent process.
+    process: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(10),
+            process: true,
+        }
+    }
+}
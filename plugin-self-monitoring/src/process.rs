@@ -0,0 +1,36 @@
+//! CPU and memory usage of the agent itself.
+
+use std::time::Duration;
+
+use alumet::metrics::TypedMetricId;
+
+pub struct ProcessMetrics {
+    pub cpu_time: TypedMetricId<f64>,
+    pub memory_rss: TypedMetricId<u64>,
+}
+
+/// Resources used by the current process.
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessUsage {
+    /// Total CPU time, in user and kernel mode, since the process has started.
+    pub cpu_time: Duration,
+    /// Resident set size, in bytes.
+    pub rss_bytes: u64,
+}
+
+#[cfg(target_os = "linux")]
+pub fn current_usage() -> anyhow::Result<ProcessUsage> {
+    let stat = procfs::process::Process::myself()?.stat()?;
+    let ticks = stat.utime + stat.stime;
+    Ok(ProcessUsage {
+        cpu_time: Duration::from_secs_f64(ticks as f64 / procfs::ticks_per_second() as f64),
+        rss_bytes: stat.rss * procfs::page_size(),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn current_usage() -> anyhow::Result<ProcessUsage> {
+    Err(anyhow::anyhow!(
+        "the usage of the process can only be measured on Linux"
+    ))
+}
@@ -0,0 +1,197 @@
+use std::{collections::HashMap, time::Duration};
+
+use alumet::{
+    measurement::{MeasurementAccumulator, MeasurementPoint, MeasurementType, Timestamp},
+    metrics::TypedMetricId,
+    pipeline::{
+        elements::{error::PollError, output::OutputStats},
+        telemetry::{PipelineTelemetry, SourceStats, TransformStats},
+        Source,
+    },
+    resources::{Resource, ResourceConsumer},
+};
+
+use crate::process::{self, ProcessMetrics};
+
+pub struct Metrics {
+    pub source_poll_duration: TypedMetricId<f64>,
+    pub source_poll_jitter: TypedMetricId<f64>,
+    pub source_points: TypedMetricId<u64>,
+    pub source_poll_errors: TypedMetricId<u64>,
+    pub transform_duration: TypedMetricId<f64>,
+    pub transform_errors: TypedMetricId<u64>,
+    pub output_write_duration: TypedMetricId<f64>,
+    pub output_write_errors: TypedMetricId<u64>,
+    pub output_lagged_buffers: TypedMetricId<u64>,
+    pub output_dropped_points: TypedMetricId<u64>,
+    pub channel_queued: TypedMetricId<u64>,
+    pub process: Option<ProcessMetrics>,
+}
+
+/// Turns the telemetry of the pipeline into measurements.
+///
+/// The counters of the pipeline are cumulative, the source publishes what happened since its previous poll.
+pub struct SelfMonitoringSource {
+    telemetry: PipelineTelemetry,
+    metrics: Metrics,
+    prev_sources: HashMap<String, SourceStats>,
+    prev_transforms: HashMap<String, TransformStats>,
+    prev_outputs: HashMap<String, OutputStats>,
+    prev_cpu_time: Option<Duration>,
+}
+
+impl SelfMonitoringSource {
+    pub fn new(telemetry: PipelineTelemetry, metrics: Metrics) -> Self {
+        Self {
+            telemetry,
+            metrics,
+            prev_sources: HashMap::new(),
+            prev_transforms: HashMap::new(),
+            prev_outputs: HashMap::new(),
+            prev_cpu_time: None,
+        }
+    }
+
+    fn poll_sources(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) {
+        for (name, stats) in self.telemetry.sources() {
+            let name = name.to_string();
+            let prev = self.prev_sources.insert(name.clone(), stats).unwrap_or_default();
+            let consumer = ResourceConsumer::custom("alumet_element", name);
+
+            if let Some(duration) = average_secs(stats.poll_time - prev.poll_time, stats.polls - prev.polls) {
+                m.push(point(t, self.metrics.source_poll_duration, &consumer, duration));
+            }
+            if let Some(jitter) = average_secs(stats.jitter - prev.jitter, stats.timed_polls - prev.timed_polls) {
+                m.push(point(t, self.metrics.source_poll_jitter, &consumer, jitter));
+            }
+            m.push(point(
+                t,
+                self.metrics.source_points,
+                &consumer,
+                stats.points - prev.points,
+            ));
+            let errors = [
+                ("can_retry", stats.retryable_errors - prev.retryable_errors),
+                ("fatal", stats.fatal_errors - prev.fatal_errors),
+                ("normal_stop", stats.normal_stops - prev.normal_stops),
+            ];
+            for (kind, n) in errors {
+                m.push(point(t, self.metrics.source_poll_errors, &consumer, n).with_attr("kind", kind));
+            }
+        }
+
+        if let Some(channel) = self.telemetry.source_channel() {
+            let consumer = ResourceConsumer::custom("alumet_channel", "sources");
+            m.push(point(t, self.metrics.channel_queued, &consumer, channel.queued as u64));
+        }
+    }
+
+    fn poll_transforms(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) {
+        for (name, stats) in self.telemetry.transforms() {
+            let name = name.to_string();
+            let prev = self.prev_transforms.insert(name.clone(), stats).unwrap_or_default();
+            let consumer = ResourceConsumer::custom("alumet_element", name);
+
+            if let Some(duration) = average_secs(stats.run_time - prev.run_time, stats.runs - prev.runs) {
+                m.push(point(t, self.metrics.transform_duration, &consumer, duration));
+            }
+            m.push(point(
+                t,
+                self.metrics.transform_errors,
+                &consumer,
+                stats.errors - prev.errors,
+            ));
+        }
+    }
+
+    fn poll_outputs(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) {
+        for (name, stats) in self.telemetry.outputs() {
+            let name = name.to_string();
+            let prev = self.prev_outputs.insert(name.clone(), stats).unwrap_or_default();
+            let consumer = ResourceConsumer::custom("alumet_element", name.clone());
+
+            if let Some(duration) = average_secs(stats.write_time - prev.write_time, stats.writes - prev.writes) {
+                m.push(point(t, self.metrics.output_write_duration, &consumer, duration));
+            }
+            m.push(point(
+                t,
+                self.metrics.output_write_errors,
+                &consumer,
+                stats.retries - prev.retries,
+            ));
+            m.push(point(
+                t,
+                self.metrics.output_lagged_buffers,
+                &consumer,
+                stats.dropped - prev.dropped,
+            ));
+            m.push(point(
+                t,
+                self.metrics.output_dropped_points,
+                &consumer,
+                stats.dropped_points - prev.dropped_points,
+            ));
+            let channel = ResourceConsumer::custom("alumet_channel", name);
+            m.push(point(t, self.metrics.channel_queued, &channel, stats.queued));
+        }
+    }
+
+    fn poll_process(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        let Some(metrics) = &self.metrics.process else {
+            return Ok(());
+        };
+        let usage = process::current_usage().map_err(PollError::CanRetry)?;
+        let consumer = ResourceConsumer::Process {
+            pid: std::process::id(),
+        };
+        if let Some(prev) = self.prev_cpu_time.replace(usage.cpu_time) {
+            m.push(point(
+                t,
+                metrics.cpu_time,
+                &consumer,
+                usage.cpu_time.saturating_sub(prev).as_secs_f64(),
+            ));
+        }
+        m.push(point(t, metrics.memory_rss, &consumer, usage.rss_bytes));
+        Ok(())
+    }
+}
+
+impl Source for SelfMonitoringSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        self.poll_sources(measurements, timestamp);
+        self.poll_transforms(measurements, timestamp);
+        self.poll_outputs(measurements, timestamp);
+        self.poll_process(measurements, timestamp)
+    }
+}
+
+/// Creates a measurement point about an element of the pipeline, or about the agent.
+fn point<T: MeasurementType>(
+    t: Timestamp,
+    metric: TypedMetricId<T>,
+    consumer: &ResourceConsumer,
+    value: T::T,
+) -> MeasurementPoint {
+    MeasurementPoint::new(t, metric, Resource::LocalMachine, consumer.clone(), value)
+}
+
+/// Returns the average of `count` durations that sum to `total`, in seconds, or `None` if there is none.
+fn average_secs(total: Duration, count: u64) -> Option<f64> {
+    (count > 0).then(|| total.as_secs_f64() / count as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::average_secs;
+
+    #[test]
+    fn average() {
+        assert_eq!(average_secs(Duration::ZERO, 0), None);
+        assert_eq!(average_secs(Duration::from_millis(30), 0), None);
+        assert_eq!(average_secs(Duration::from_millis(30), 3), Some(0.01));
+        assert_eq!(average_secs(Duration::from_secs(5), 2), Some(2.5));
+    }
+}
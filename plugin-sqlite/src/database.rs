@@ -0,0 +1,393 @@
+//! Schema of the SQLite database and conversion of the measurements.
+
+use std::{collections::HashMap, path::Path};
+
+use alumet::{
+    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    metrics::{Metric, RawMetricId},
+};
+use anyhow::{anyhow, Context};
+use rusqlite::{params, types::Value, Connection, Transaction};
+
+/// Creates the tables if they don't exist yet.
+///
+/// The `value` column has no declared type, so that SQLite stores the integers and the floats as they are.
+/// The timestamps are stored as a number of nanoseconds since the Unix epoch.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS metrics (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    unit TEXT NOT NULL,
+    type TEXT NOT NULL,
+    description TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS points (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    metric_id INTEGER NOT NULL REFERENCES metrics(id),
+    value NOT NULL,
+    resource_kind TEXT NOT NULL,
+    resource_id TEXT,
+    consumer_kind TEXT NOT NULL,
+    consumer_id TEXT,
+    attributes TEXT
+);
+CREATE INDEX IF NOT EXISTS points_by_metric_and_time ON points(metric_id, timestamp);
+CREATE VIEW IF NOT EXISTS measurements AS
+    SELECT p.timestamp, m.name AS metric, p.value, m.unit, p.resource_kind, p.resource_id,
+           p.consumer_kind, p.consumer_id, p.attributes
+    FROM points p JOIN metrics m ON p.metric_id = m.id;
+";
+
+/// A SQLite database that contains metrics and measurement points.
+pub struct Database {
+    conn: Connection,
+    /// Maps the alumet metric ids to the ids of the `metrics` table.
+    ///
+    /// They are not the same, because the database can be reused by several runs of the agent.
+    metric_rows: HashMap<RawMetricId, i64>,
+}
+
+impl Database {
+    /// Opens the database, creating it if it does not exist.
+    pub fn open(path: &Path, wal: bool) -> anyhow::Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("could not open database {}", path.display()))?;
+        Self::init(conn, wal)
+    }
+
+    fn init(conn: Connection, wal: bool) -> anyhow::Result<Self> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        if wal {
+            // Allows other processes to read the database while we write to it.
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+        }
+        conn.execute_batch(SCHEMA).context("could not create the tables")?;
+        Ok(Self {
+            conn,
+            metric_rows: HashMap::new(),
+        })
+    }
+
+    /// Inserts new metrics and measurements in a single transaction.
+    ///
+    /// If a measurement refers to a metric that has not been inserted yet, its definition
+    /// is looked up with `metric_by_id`.
+    pub fn write<'m>(
+        &mut self,
+        new_metrics: impl IntoIterator<Item = (RawMetricId, Metric)>,
+        measurements: &MeasurementBuffer,
+        metric_by_id: impl Fn(&RawMetricId) -> Option<&'m Metric>,
+    ) -> anyhow::Result<()> {
+        let tx = self.conn.transaction()?;
+        // The new rows are only recorded once the transaction is committed.
+        let mut inserted_metrics = Vec::new();
+        for (id, metric) in new_metrics {
+            let row = upsert_metric(&tx, &metric)?;
+            inserted_metrics.push((id, row));
+        }
+        {
+            let mut insert_point = tx.prepare_cached(
+                "INSERT INTO points (timestamp, metric_id, value, resource_kind, resource_id,
+                                     consumer_kind, consumer_id, attributes)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for point in measurements {
+                let metric_row = match find_metric_row(&self.metric_rows, &inserted_metrics, &point.metric) {
+                    Some(row) => row,
+                    None => {
+                        let metric =
+                            metric_by_id(&point.metric).ok_or_else(|| anyhow!("unknown metric {:?}", point.metric))?;
+                        let row = upsert_metric(&tx, metric)?;
+                        inserted_metrics.push((point.metric, row));
+                        row
+                    }
+                };
+                insert_point.execute(params![
+                    timestamp_nanos(point)?,
+                    metric_row,
+                    sql_value(&point.value),
+                    point.resource.kind(),
+                    point.resource.id_string(),
+                    point.consumer.kind(),
+                    point.consumer.id_string(),
+                    attributes_json(point),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        self.metric_rows.extend(inserted_metrics);
+        Ok(())
+    }
+}
+
+/// Inserts a metric, or updates its definition if a metric with the same name exists.
+/// Returns the id of its row.
+fn upsert_metric(tx: &Transaction, metric: &Metric) -> rusqlite::Result<i64> {
+    tx.prepare_cached(
+        "INSERT INTO metrics (name, unit, type, description) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (name) DO UPDATE SET unit = excluded.unit, type = excluded.type, description = excluded.description
+         RETURNING id",
+    )?
+    .query_row(
+        params![
+            metric.name,
+            metric.unit.unique_name(),
+            metric.value_type.to_string(),
+            metric.description
+        ],
+        |row| row.get(0),
+    )
+}
+
+fn find_metric_row(rows: &HashMap<RawMetricId, i64>, pending: &[(RawMetricId, i64)], id: &RawMetricId) -> Option<i64> {
+    // Look at the pending rows first, because they may redefine a metric.
+    pending
+        .iter()
+        .rev()
+        .find(|(m, _)| m == id)
+        .map(|(_, row)| *row)
+        .or_else(|| rows.get(id).copied())
+}
+
+fn timestamp_nanos(point: &MeasurementPoint) -> anyhow::Result<i64> {
+    let (secs, nanos) = point.timestamp.to_unix_timestamp();
+    secs.checked_mul(1_000_000_000)
+        .and_then(|ns| ns.checked_add(i64::from(nanos)))
+        .ok_or_else(|| anyhow!("timestamp out of range: {:?}", point.timestamp))
+}
+
+fn sql_value(value: &WrappedMeasurementValue) -> Value {
+    match value {
+        WrappedMeasurementValue::F64(x) => Value::Real(*x),
+        // SQLite integers are signed, store the biggest values as floats rather than failing.
+        WrappedMeasurementValue::U64(x) => match i64::try_from(*x) {
+            Ok(x) => Value::Integer(x),
+            Err(_) => Value::Real(*x as f64),
+        },
+        WrappedMeasurementValue::I64(x) => Value::Integer(*x),
+        WrappedMeasurementValue::Bool(b) => Value::Integer(i64::from(*b)),
+        // Histograms are stored as JSON, which can be queried with the JSON functions of SQLite.
+        WrappedMeasurementValue::Histogram(h) => Value::Text(
+            serde_json::json!({
+                "bounds": h.bounds(),
+                "counts": h.counts(),
+                "sum": h.sum(),
+            })
+            .to_string(),
+        ),
+    }
+}
+
+/// Serializes the attributes of the point to a JSON object, or `NULL` if it has none.
+fn attributes_json(point: &MeasurementPoint) -> Option<String> {
+    if point.attributes_len() == 0 {
+        return None;
+    }
+    let map: serde_json::Map<String, serde_json::Value> = point
+        .attributes()
+        .map(|(key, value)| {
+            let value = match value {
+                AttributeValue::F64(x) => serde_json::Value::from(*x),
+                AttributeValue::U64(x) => serde_json::Value::from(*x),
+                AttributeValue::Bool(b) => serde_json::Value::from(*b),
+                AttributeValue::Str(s) => serde_json::Value::from(*s),
+                AttributeValue::Shared(s) => serde_json::Value::from(s.as_ref()),
+                AttributeValue::String(s) => serde_json::Value::from(s.as_str()),
+            };
+            (key.to_owned(), value)
+        })
+        .collect();
+    Some(serde_json::Value::Object(map).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use alumet::{
+        measurement::{
+            MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+        },
+        metrics::{Metric, MetricKind, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use rusqlite::Connection;
+
+    use super::Database;
+
+    fn metric(name: &str, value_type: WrappedMeasurementType, unit: PrefixedUnit) -> Metric {
+        Metric {
+            name: name.to_owned(),
+            description: format!("the {name}"),
+            value_type,
+            unit,
+            kind: MetricKind::Gauge,
+            labels: vec![],
+        }
+    }
+
+    fn point(t: u64, metric: u64, value: WrappedMeasurementValue) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_millis(t)),
+            RawMetricId::from_u64(metric),
+            Resource::CpuPackage { id: 0 },
+            ResourceConsumer::Process { pid: 42 },
+            value,
+        )
+    }
+
+    #[test]
+    fn write_metrics_and_points() {
+        let mut db = Database::init(Connection::open_in_memory().unwrap(), false).unwrap();
+        let energy = metric("energy", WrappedMeasurementType::F64, PrefixedUnit::milli(Unit::Joule));
+        let memory = metric("memory", WrappedMeasurementType::U64, PrefixedUnit::from(Unit::Byte));
+        let late_metrics = HashMap::from([(RawMetricId::from_u64(1), memory)]);
+
+        let buf = MeasurementBuffer::from(vec![
+            point(1500, 0, WrappedMeasurementValue::F64(12.5)).with_attr("domain", "package"),
+            point(2000, 1, WrappedMeasurementValue::U64(1024)),
+        ]);
+        db.write(vec![(RawMetricId::from_u64(0), energy)], &buf, |id| {
+            late_metrics.get(id)
+        })
+        .unwrap();
+
+        let metrics: Vec<(i64, String, String, String, String)> = db
+            .conn
+            .prepare("SELECT id, name, unit, type, description FROM metrics ORDER BY id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            metrics,
+            vec![
+                (1, "energy".into(), "milliJ".into(), "F64".into(), "the energy".into()),
+                (2, "memory".into(), "By".into(), "U64".into(), "the memory".into()),
+            ]
+        );
+
+        type Row = (
+            i64,
+            String,
+            f64,
+            String,
+            Option<String>,
+            String,
+            Option<String>,
+            Option<String>,
+        );
+        let points: Vec<Row> = db
+            .conn
+            .prepare("SELECT timestamp, metric, value, resource_kind, resource_id, consumer_kind, consumer_id, attributes FROM measurements ORDER BY timestamp")
+            .unwrap()
+            .query_map([], |r| {
+                Ok((
+                    r.get(0)?,
+                    r.get(1)?,
+                    r.get(2)?,
+                    r.get(3)?,
+                    r.get(4)?,
+                    r.get(5)?,
+                    r.get(6)?,
+                    r.get(7)?,
+                ))
+            })
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            points,
+            vec![
+                (
+                    1_500_000_000,
+                    "energy".into(),
+                    12.5,
+                    "cpu_package".into(),
+                    Some("0".into()),
+                    "process".into(),
+                    Some("42".into()),
+                    Some(r#"{"domain":"package"}"#.into())
+                ),
+                (
+                    2_000_000_000,
+                    "memory".into(),
+                    1024.0,
+                    "cpu_package".into(),
+                    Some("0".into()),
+                    "process".into(),
+                    Some("42".into()),
+                    None
+                ),
+            ]
+        );
+        let value_type: String = db
+            .conn
+            .query_row("SELECT typeof(value) FROM points WHERE metric_id = 2", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(value_type, "integer");
+    }
+
+    #[test]
+    fn reuse_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.sqlite");
+        let no_metrics: HashMap<RawMetricId, Metric> = HashMap::new();
+        let energy = || metric("energy", WrappedMeasurementType::F64, PrefixedUnit::from(Unit::Joule));
+
+        // The metric ids of alumet can change between two runs, the rows must not.
+        let mut db = Database::open(&path, true).unwrap();
+        let buf = MeasurementBuffer::from(vec![point(0, 0, WrappedMeasurementValue::F64(1.0))]);
+        db.write(vec![(RawMetricId::from_u64(0), energy())], &buf, |id| {
+            no_metrics.get(id)
+        })
+        .unwrap();
+        drop(db);
+
+        let mut db = Database::open(&path, true).unwrap();
+        let buf = MeasurementBuffer::from(vec![point(1, 5, WrappedMeasurementValue::F64(2.0))]);
+        db.write(vec![(RawMetricId::from_u64(5), energy())], &buf, |id| {
+            no_metrics.get(id)
+        })
+        .unwrap();
+
+        let count: (i64, i64) = db
+            .conn
+            .query_row(
+                "SELECT (SELECT count(*) FROM metrics), (SELECT count(*) FROM points WHERE metric_id = 1)",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(count, (1, 2));
+    }
+
+    #[test]
+    fn unknown_metric_rolls_back() {
+        let mut db = Database::init(Connection::open_in_memory().unwrap(), false).unwrap();
+        let no_metrics: HashMap<RawMetricId, Metric> = HashMap::new();
+        let energy = metric("energy", WrappedMeasurementType::F64, PrefixedUnit::from(Unit::Joule));
+        let buf = MeasurementBuffer::from(vec![
+            point(0, 0, WrappedMeasurementValue::F64(1.0)),
+            point(0, 1, WrappedMeasurementValue::F64(1.0)),
+        ]);
+        let res = db.write(vec![(RawMetricId::from_u64(0), energy)], &buf, |id| no_metrics.get(id));
+        assert!(res.is_err());
+
+        let count: i64 = db
+            .conn
+            .query_row(
+                "SELECT (SELECT count(*) FROM metrics) + (SELECT count(*) FROM points)",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 0);
+        assert!(db.metric_rows.is_empty());
+    }
+}
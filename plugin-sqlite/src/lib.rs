@@ -0,0 +1,123 @@
+mod database;
+
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+};
+
+use alumet::{
+    measurement::MeasurementBuffer,
+    metrics::{Metric, RawMetricId},
+    pipeline::{
+        elements::{
+            error::{WriteError, WriteRetry},
+            output::OutputContext,
+        },
+        Output,
+    },
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+};
+use anyhow::Context;
+use database::Database;
+use serde::{Deserialize, Serialize};
+
+pub struct SqlitePlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for SqlitePlugin {
+    fn name() -> &'static str {
+        "sqlite"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(SqlitePlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        let db = Database::open(&config.database_path, config.wal)?;
+
+        // The metrics are sent to the output, which inserts them in the same transaction as the measurements.
+        let (metrics_tx, metrics_rx) = mpsc::channel();
+        alumet.add_blocking_output(Box::new(SqliteOutput {
+            db,
+            new_metrics: metrics_rx,
+        }));
+
+        alumet.on_pre_pipeline_start(move |pre_start| {
+            // register the existing metrics
+            let existing_metrics: Vec<(RawMetricId, Metric)> =
+                pre_start.metrics().iter().map(|(id, def)| (*id, def.clone())).collect();
+            metrics_tx
+                .send(existing_metrics)
+                .context("failed to send the initial metrics to the SQLite output")?;
+
+            // hook to register the late metrics
+            pre_start.add_metric_listener(move |new_metrics| {
+                metrics_tx
+                    .send(new_metrics)
+                    .context("failed to send late metrics to the SQLite output")
+            });
+            Ok(())
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct SqliteOutput {
+    db: Database,
+    new_metrics: Receiver<Vec<(RawMetricId, Metric)>>,
+}
+
+impl Output for SqliteOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        let new_metrics: Vec<_> = self.new_metrics.try_iter().flatten().collect();
+        let res = self.db.write(new_metrics, measurements, |id| ctx.metrics.by_id(id));
+        if let Err(e) = res {
+            // If another process holds a lock on the database, the transaction has been rolled back
+            // and can be attempted again.
+            if let Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) =
+                e.downcast_ref::<rusqlite::Error>().and_then(|e| e.sqlite_error_code())
+            {
+                return Err(e).retry_write();
+            }
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Path to the database file. It is created if it does not exist.
+    database_path: PathBuf,
+    /// Use the write-ahead log, so that the database can be read while Alumet writes to it.
+    wal: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_path: PathBuf::from("alumet-measurements.sqlite"),
+            wal: true,
+        }
+    }
+}